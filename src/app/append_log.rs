@@ -0,0 +1,152 @@
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use gtk4::{self as gtk, glib, prelude::*};
+
+use super::tabs::TabState;
+use super::window::AppState;
+
+/// Typing after this long a pause starts a new timestamped block
+const BLOCK_GAP: Duration = Duration::from_secs(5 * 60);
+
+/// Tag marking text that can no longer be edited in append-only mode.
+pub(super) fn create_lock_tag(buffer: &sourceview5::Buffer) -> gtk::TextTag {
+    let tag = gtk::TextTag::builder()
+        .name("append-locked")
+        .editable(false)
+        .build();
+    buffer.tag_table().add(&tag);
+    tag
+}
+
+impl AppState {
+    pub(super) fn hook_append_only(self: &Rc<Self>) {
+        let weak = Rc::downgrade(self);
+        self.append_toggle.connect_toggled(move |toggle| {
+            if let Some(state) = weak.upgrade() {
+                state.set_append_only(toggle.is_active());
+            }
+        });
+
+        self.append_toggle
+            .set_active(self.settings.borrow().append_only);
+        for tab in self.tabs() {
+            self.reset_tab_append_lock(&tab);
+        }
+    }
+
+    /// Stamp blocks typed into `tab` while the mode is on
+    pub(super) fn hook_append_block(self: &Rc<Self>, tab: &TabState) {
+        // Runs before the default handler, so a header can go in ahead of the new text
+        let weak = Rc::downgrade(self);
+        tab.buffer.connect_insert_text(move |buffer, location, _| {
+            if let Some(state) = weak.upgrade() {
+                state.stamp_new_block(buffer, location);
+            }
+        });
+    }
+
+    fn set_append_only(&self, enabled: bool) {
+        {
+            let mut settings = self.settings.borrow_mut();
+            if settings.append_only == enabled {
+                return;
+            }
+            settings.append_only = enabled;
+            if let Err(err) = settings.save(&self.paths) {
+                log::warn!("Failed to save settings: {err:?}");
+            }
+        }
+        for tab in self.tabs() {
+            self.reset_tab_append_lock(&tab);
+        }
+        if enabled {
+            self.show_toast("Append-only mode: earlier text is locked");
+        }
+    }
+
+    /// Lock everything currently in the buffer (or unlock it when the mode is
+    /// off). Called when the mode changes and when a different document is loaded.
+    pub(super) fn reset_append_lock(&self) {
+        self.reset_tab_append_lock(&self.tab());
+    }
+
+    fn reset_tab_append_lock(&self, tab: &TabState) {
+        let (start, end) = tab.buffer.bounds();
+        tab.buffer.remove_tag(&tab.append_lock_tag, &start, &end);
+        tab.append_block_open.set(false);
+        if !self.settings.borrow().append_only {
+            return;
+        }
+        tab.buffer.apply_tag(&tab.append_lock_tag, &start, &end);
+        tab.buffer.place_cursor(&end);
+        tab.document
+            .view()
+            .scroll_mark_onscreen(&tab.buffer.get_insert());
+    }
+
+    /// Keep the view on the newest entry while in append-only mode.
+    pub(super) fn follow_appended_text(&self) {
+        if self.settings.borrow().append_only && !self.are_completions_suppressed() {
+            let tab = self.tab();
+            tab.document
+                .view()
+                .scroll_mark_onscreen(&tab.buffer.get_insert());
+        }
+    }
+
+    fn stamp_new_block(&self, buffer: &sourceview5::Buffer, location: &mut gtk::TextIter) {
+        let tab = self.tab();
+        // Only typing into the view opens blocks; loads and ghost text don't
+        if !self.settings.borrow().append_only
+            || *buffer != tab.buffer
+            || tab.append_stamping.get()
+            || self.are_completions_suppressed()
+            || !tab.document.view().has_focus()
+            || !location.is_end()
+        {
+            return;
+        }
+
+        let now = Instant::now();
+        let idle = tab
+            .append_last_input
+            .replace(Some(now))
+            .is_none_or(|last| now.duration_since(last) > BLOCK_GAP);
+        if tab.append_block_open.get() && !idle {
+            return;
+        }
+        tab.append_block_open.set(true);
+
+        // Seal the previous block before starting the new one
+        let (start, end) = buffer.bounds();
+        buffer.apply_tag(&tab.append_lock_tag, &start, &end);
+
+        let header = block_header(buffer);
+        let mut end = buffer.end_iter();
+        tab.append_stamping.set(true);
+        buffer.insert(&mut end, &header);
+        tab.append_stamping.set(false);
+        // The header must stay editable along with the text that follows it
+        let header_start = buffer.iter_at_offset(end.offset() - header.chars().count() as i32);
+        buffer.remove_tag(&tab.append_lock_tag, &header_start, &end);
+        *location = buffer.end_iter();
+    }
+}
+
+fn block_header(buffer: &sourceview5::Buffer) -> String {
+    let stamp = glib::DateTime::now_local()
+        .and_then(|now| now.format("%Y-%m-%d %H:%M"))
+        .map(|stamp| stamp.to_string())
+        .unwrap_or_default();
+    // Leave a blank line between blocks
+    let mut last = buffer.end_iter();
+    let separator = if !last.backward_char() {
+        ""
+    } else if last.char() == '\n' {
+        "\n"
+    } else {
+        "\n\n"
+    };
+    format!("{}[{}]\n", separator, stamp)
+}
@@ -1,27 +1,115 @@
 use std::fs;
 use std::path::{Path, PathBuf};
 use std::rc::Rc;
-use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 use adw::prelude::*;
+use anyhow::Context;
+use encoding_rs::Encoding;
 use gtk4::glib::{self, ControlFlow};
 use gtk4::{self as gtk, prelude::*};
 use libadwaita as adw;
 use serde::{Deserialize, Serialize};
 use serde_json;
 
+use super::preferences;
+use super::tabs::TabState;
 use super::window::AppState;
+use crate::document::{LineEnding, derive_display_name};
+use crate::settings::AutosaveMode;
 
 pub(super) const CUSTOM_AUTOSAVE_SENTINEL: u64 = u64::MAX;
 const AUTOSAVE_IDLE_GRACE_SECS: u64 = 2;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub(super) struct AutosaveMetadata {
     pub(super) original_path: Option<String>,
     pub(super) timestamp: u64,
+    /// What the document saves as, so a restored draft keeps its file's
+    /// format; snapshots from before these were recorded have none
+    #[serde(default)]
+    pub(super) encoding: Option<String>,
+    #[serde(default)]
+    pub(super) crlf: bool,
+    #[serde(default)]
+    pub(super) bom: bool,
+}
+
+impl AutosaveMetadata {
+    pub(super) fn encoding(&self) -> Option<&'static Encoding> {
+        self.encoding
+            .as_deref()
+            .and_then(|name| Encoding::for_label(name.as_bytes()))
+    }
+
+    pub(super) fn line_ending(&self) -> LineEnding {
+        if self.crlf {
+            LineEnding::CrLf
+        } else {
+            LineEnding::Lf
+        }
+    }
 }
 
 impl AppState {
+    pub(super) fn hook_autosave_preferences(self: &Rc<Self>) {
+        let weak = Rc::downgrade(self);
+        self.preferences
+            .autosave_combo
+            .connect_selected_notify(move |row: &adw::ComboRow| {
+                if let Some(state) = weak.upgrade() {
+                    let idx = row.selected() as usize;
+                    if let Some((secs, _)) = state.autosave_options.get(idx) {
+                        if *secs == CUSTOM_AUTOSAVE_SENTINEL {
+                            state.prompt_custom_autosave();
+                        } else if *secs != state.settings.borrow().autosave_interval_secs {
+                            state.set_autosave_interval(*secs);
+                        }
+                    }
+                }
+            });
+
+        let weak = Rc::downgrade(self);
+        self.preferences.autosave_idle_switch.connect_active_notify(
+            move |switch_widget: &gtk::Switch| {
+                if let Some(state) = weak.upgrade() {
+                    let active = switch_widget.is_active();
+                    if active == state.settings.borrow().autosave_idle_only {
+                        return;
+                    }
+                    state.set_autosave_idle_only(active);
+                }
+            },
+        );
+
+        let weak = Rc::downgrade(self);
+        self.preferences
+            .autosave_snapshots_spin
+            .connect_value_changed(move |spin| {
+                if let Some(state) = weak.upgrade() {
+                    state.set_autosave_snapshots(spin.value() as usize);
+                }
+            });
+
+        let weak = Rc::downgrade(self);
+        self.preferences
+            .autosave_mode_combo
+            .connect_selected_notify(move |row: &adw::ComboRow| {
+                if let Some(state) = weak.upgrade() {
+                    state.set_autosave_mode(preferences::autosave_mode_from_index(row.selected()));
+                }
+            });
+
+        let weak = Rc::downgrade(self);
+        self.preferences.notifications_switch.connect_active_notify(
+            move |switch_widget: &gtk::Switch| {
+                if let Some(state) = weak.upgrade() {
+                    state.set_desktop_notifications(switch_widget.is_active());
+                }
+            },
+        );
+    }
+
     pub(super) fn set_autosave_interval(self: &Rc<Self>, secs: u64) {
         if secs == CUSTOM_AUTOSAVE_SENTINEL {
             self.prompt_custom_autosave();
@@ -63,6 +151,34 @@ impl AppState {
         }
     }
 
+    pub(super) fn set_autosave_mode(&self, mode: AutosaveMode) {
+        {
+            let mut settings = self.settings.borrow_mut();
+            if settings.autosave_mode == mode {
+                return;
+            }
+            settings.autosave_mode = mode;
+            if let Err(err) = settings.save(&self.paths) {
+                log::warn!("Failed to save settings: {err:?}");
+            }
+        }
+        self.sync_preferences_ui();
+    }
+
+    pub(super) fn set_desktop_notifications(&self, active: bool) {
+        {
+            let mut settings = self.settings.borrow_mut();
+            if settings.desktop_notifications == active {
+                return;
+            }
+            settings.desktop_notifications = active;
+            if let Err(err) = settings.save(&self.paths) {
+                log::warn!("Failed to save settings: {err:?}");
+            }
+        }
+        self.sync_preferences_ui();
+    }
+
     pub(super) fn restart_autosave(self: &Rc<Self>) {
         if let Some(source) = self.autosave_source.borrow_mut().take() {
             // Ignore errors if source was already removed
@@ -86,80 +202,184 @@ impl AppState {
         self.autosave_source.replace(Some(id));
     }
 
-    pub(super) fn run_autosave(&self) {
-        if !self.buffer.is_modified() {
+    /// Snapshot or save every tab with changes that aren't on disk
+    pub(super) fn run_autosave(self: &Rc<Self>) {
+        for tab in self.tabs() {
+            self.autosave_tab(&tab);
+        }
+    }
+
+    fn autosave_tab(self: &Rc<Self>, tab: &Rc<TabState>) {
+        if !tab.buffer.is_modified() {
             return;
         }
         if self.settings.borrow().autosave_idle_only {
-            if let Some(last) = *self.last_edit.borrow() {
+            if let Some(last) = *tab.last_edit.borrow() {
                 if last.elapsed() < Duration::from_secs(AUTOSAVE_IDLE_GRACE_SECS) {
                     // Waiting for idle
                     return;
                 }
             }
         }
-        match self.write_autosave_file() {
+        let write_through = self.settings.borrow().autosave_mode == AutosaveMode::WriteThrough
+            && tab.file_path.borrow().is_some();
+        if write_through {
+            // Suggestions never go into the file; the next run catches up
+            if tab.document.ghost_is_active() {
+                return;
+            }
+            match self.write_through(tab) {
+                Ok(()) => {
+                    tab.last_autosave.set(Some(Instant::now()));
+                    if self.is_current_tab(tab) {
+                        self.update_title();
+                    }
+                }
+                Err(err) => {
+                    log::warn!("Autosave error: {err:?}");
+                    self.notify_desktop("autosave", "Autosave failed", &format!("{err:#}"));
+                }
+            }
+            return;
+        }
+        // Only announce snapshots that captured edits made since the previous one
+        let has_new_edits = match (*tab.last_edit.borrow(), tab.last_autosave.get()) {
+            (Some(edit), Some(saved)) => edit > saved,
+            _ => true,
+        };
+        match self.write_autosave_file(tab) {
             Ok(_timestamp) => {
-                // Autosave success
+                tab.last_autosave.set(Some(Instant::now()));
+                if self.is_current_tab(tab) {
+                    self.update_title();
+                }
+                if has_new_edits {
+                    let name = derive_display_name(&tab.file_path.borrow());
+                    self.notify_desktop(
+                        "autosave",
+                        "Draft autosaved",
+                        &format!("A recovery snapshot of {name} was written"),
+                    );
+                }
             }
             Err(err) => {
                 log::warn!("Autosave error: {err:?}");
+                self.notify_desktop("autosave", "Autosave failed", &err.to_string());
             }
         }
     }
 
-    fn write_autosave_file(&self) -> anyhow::Result<String> {
-        let data = self.document.current_text();
-        let swap_path = self.autosave_path();
+    /// Save `tab` to its file, first copying the version on disk to `<name>.bak`
+    fn write_through(self: &Rc<Self>, tab: &Rc<TabState>) -> anyhow::Result<()> {
+        let Some(path) = tab.file_path.borrow().clone() else {
+            return Ok(());
+        };
+        if path.exists() {
+            let backup = backup_path(&path);
+            fs::copy(&path, &backup)
+                .with_context(|| format!("Failed to back up {}", path.display()))?;
+        }
+        tab.document.save_to_path(&path, self.save_cleanup())?;
+        self.remove_autosave_artifacts(tab);
+        // A fresh monitor, so our own write doesn't come back as an outside change
+        self.watch_file(tab);
+        Ok(())
+    }
+
+    /// Write a new snapshot of `tab`, dropping the oldest past the retention count
+    fn write_autosave_file(&self, tab: &TabState) -> anyhow::Result<String> {
+        let data = tab.document.text_without_ghost();
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        let swap_path = self.paths.autosave_dir.join(snapshot_file_name(
+            &autosave_name(tab),
+            &tab.session_token,
+            now.as_millis() as u64,
+        ));
         let temp = swap_path.with_extension("tmp");
         fs::write(&temp, &data)?;
         fs::rename(&temp, &swap_path)?;
-        let ts = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .map(|d| d.as_secs())
-            .unwrap_or_default();
+        let ts = now.as_secs();
         let metadata = AutosaveMetadata {
-            original_path: self
+            original_path: tab
                 .file_path
                 .borrow()
                 .as_ref()
                 .map(|p| p.display().to_string()),
             timestamp: ts,
+            encoding: Some(tab.document.encoding().name().to_string()),
+            crlf: tab.document.line_ending() == LineEnding::CrLf,
+            bom: tab.document.has_bom(),
         };
         let meta_path = self.autosave_metadata_path(&swap_path);
         fs::write(&meta_path, serde_json::to_string(&metadata)?)?;
+
+        let keep = self.settings.borrow().autosave_snapshots.max(1);
+        for old in self.session_snapshots(&tab.session_token).iter().skip(keep) {
+            self.remove_snapshot(old);
+        }
         Ok(format!("{}s", ts))
     }
 
-    pub(super) fn autosave_path(&self) -> PathBuf {
-        let name = self
-            .file_path
-            .borrow()
-            .as_ref()
-            .and_then(|p| p.file_name().and_then(|o| o.to_str()))
-            .map(|s| s.to_string())
-            .unwrap_or_else(|| "untitled".to_string());
-        let sanitized = name.replace(|c: char| !c.is_ascii_alphanumeric(), "_");
-        self.paths
-            .autosave_dir
-            .join(format!(".{sanitized}-{}.swap", self.session_token))
+    /// Snapshot files written by `session`, newest first
+    pub(super) fn session_snapshots(&self, session: &str) -> Vec<PathBuf> {
+        let Ok(dir) = fs::read_dir(&self.paths.autosave_dir) else {
+            return Vec::new();
+        };
+        let mut snapshots: Vec<(u64, PathBuf)> = dir
+            .flatten()
+            .filter_map(|entry| {
+                let path = entry.path();
+                let (stamp, ours) = {
+                    let (owner, stamp) = parse_snapshot_name(path.file_name()?.to_str()?)?;
+                    (stamp, owner == session)
+                };
+                ours.then_some((stamp, path))
+            })
+            .collect();
+        snapshots.sort_by(|a, b| b.0.cmp(&a.0));
+        snapshots.into_iter().map(|(_, path)| path).collect()
     }
 
     pub(super) fn autosave_metadata_path(&self, swap_path: &Path) -> PathBuf {
         swap_path.with_extension("meta")
     }
 
-    pub(super) fn remove_autosave_artifacts(&self) {
-        let swap = self.autosave_path();
-        if swap.exists() {
-            let _ = fs::remove_file(&swap);
+    pub(super) fn remove_snapshot(&self, swap_path: &Path) {
+        if swap_path.exists() {
+            if let Err(err) = fs::remove_file(swap_path) {
+                log::warn!("Failed to delete swap file {:?}: {:?}", swap_path, err);
+            }
         }
-        let meta = self.autosave_metadata_path(&swap);
+        let meta = self.autosave_metadata_path(swap_path);
         if meta.exists() {
             let _ = fs::remove_file(&meta);
         }
     }
 
+    pub(super) fn remove_autosave_artifacts(&self, tab: &TabState) {
+        tab.last_autosave.set(None);
+        for swap in self.session_snapshots(&tab.session_token) {
+            self.remove_snapshot(&swap);
+        }
+    }
+
+    pub(super) fn set_autosave_snapshots(&self, count: usize) {
+        {
+            let mut settings = self.settings.borrow_mut();
+            if settings.autosave_snapshots == count {
+                return;
+            }
+            settings.autosave_snapshots = count;
+            if let Err(err) = settings.save(&self.paths) {
+                log::warn!("Failed to save settings: {err:?}");
+            }
+        }
+        // Trimmed to the new count on each tab's next snapshot
+        self.sync_preferences_ui();
+    }
+
     pub(super) fn autosave_description(&self, secs: u64) -> String {
         if let Some((_, label)) = self.autosave_options.iter().find(|(v, _)| *v == secs) {
             label.to_string()
@@ -177,6 +397,17 @@ impl AppState {
         self.preferences
             .autosave_idle_switch
             .set_active(self.settings.borrow().autosave_idle_only);
+        self.preferences
+            .autosave_snapshots_spin
+            .set_value(self.settings.borrow().autosave_snapshots as f64);
+        self.preferences
+            .autosave_mode_combo
+            .set_selected(
+                preferences::autosave_mode_index(&self.settings.borrow().autosave_mode) as u32,
+            );
+        self.preferences
+            .notifications_switch
+            .set_active(self.settings.borrow().desktop_notifications);
     }
 
     pub(super) fn find_interval_index(&self, secs: u64) -> Option<usize> {
@@ -231,3 +462,85 @@ impl AppState {
         dialog.show();
     }
 }
+
+/// `notes.md` is backed up as `notes.md.bak`
+fn backup_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".bak");
+    PathBuf::from(name)
+}
+
+/// The document's file name made safe for snapshot names; `untitled` if it has none
+fn autosave_name(tab: &TabState) -> String {
+    let name = tab
+        .file_path
+        .borrow()
+        .as_ref()
+        .and_then(|p| p.file_name().and_then(|o| o.to_str()))
+        .map(|s| s.to_string())
+        .unwrap_or_else(|| "untitled".to_string());
+    name.replace(|c: char| !c.is_ascii_alphanumeric(), "_")
+}
+
+/// `.notes_md-<session>.<stamp_ms>.swap`
+fn snapshot_file_name(name: &str, session: &str, stamp_ms: u64) -> String {
+    format!(".{name}-{session}.{stamp_ms}.swap")
+}
+
+/// The session token and time stamp in a snapshot's file name. Snapshots
+/// written before they were rotated (`.notes_md-<session>.swap`) have stamp 0.
+pub(super) fn parse_snapshot_name(file_name: &str) -> Option<(&str, u64)> {
+    let stem = file_name.strip_suffix(".swap")?;
+    let (stem, stamp) = match stem.rsplit_once('.') {
+        Some((rest, stamp)) if !rest.is_empty() => match stamp.parse() {
+            Ok(stamp) => (rest, stamp),
+            Err(_) => (stem, 0),
+        },
+        _ => (stem, 0),
+    };
+    // Names are sanitized to alphanumerics and `_`, so the first dash ends them
+    let (_, session) = stem.split_once('-')?;
+    Some((session, stamp))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_snapshot_names_round_trip() {
+        let session = "6f1c2a9e-3b4d-4e5f-8a7b-0c1d2e3f4a5b";
+        let name = snapshot_file_name("notes_md", session, 1_700_000_000_123);
+        assert_eq!(
+            parse_snapshot_name(&name),
+            Some((session, 1_700_000_000_123))
+        );
+    }
+
+    #[test]
+    fn test_parse_snapshot_name_reads_unrotated_names() {
+        assert_eq!(
+            parse_snapshot_name(".untitled-6f1c2a9e-3b4d.swap"),
+            Some(("6f1c2a9e-3b4d", 0))
+        );
+        assert_eq!(parse_snapshot_name(".untitled-abc.meta"), None);
+        assert_eq!(parse_snapshot_name("stray.swap"), None);
+    }
+
+    #[test]
+    fn test_metadata_keeps_the_file_format() {
+        let metadata: AutosaveMetadata = serde_json::from_str(
+            r#"{"original_path":null,"timestamp":1,"encoding":"UTF-16LE","crlf":true,"bom":true}"#,
+        )
+        .unwrap();
+        assert_eq!(metadata.encoding(), Some(encoding_rs::UTF_16LE));
+        assert_eq!(metadata.line_ending(), LineEnding::CrLf);
+
+        // Snapshots written before the format was recorded
+        let old: AutosaveMetadata =
+            serde_json::from_str(r#"{"original_path":null,"timestamp":1}"#).unwrap();
+        assert_eq!(old.encoding(), None);
+        assert_eq!(old.line_ending(), LineEnding::Lf);
+        assert!(!old.bom);
+    }
+}
@@ -0,0 +1,432 @@
+//! Every command the window offers, and the Ctrl+Shift+P palette that
+//! searches them. Adding a command here makes it show up in the palette.
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use gtk4::{self as gtk, gdk, glib::Propagation, prelude::*};
+use libadwaita as adw;
+
+use super::editor_settings::FONT_SCALE_STEP;
+use super::window::AppState;
+
+pub(super) struct Command {
+    pub(super) title: &'static str,
+    /// Shortcut as shown to the user, for commands that have one
+    pub(super) shortcut: Option<&'static str>,
+    run: fn(&Rc<AppState>),
+}
+
+pub(super) const COMMANDS: &[Command] = &[
+    Command {
+        title: "New Window",
+        shortcut: None,
+        run: |state| {
+            if let Some(app) = state
+                .window()
+                .application()
+                .and_downcast::<adw::Application>()
+            {
+                if let Err(err) = crate::app::build_ui(&app) {
+                    log::error!("Failed to spawn new window: {:?}", err);
+                }
+            }
+        },
+    },
+    Command {
+        title: "New Tab",
+        shortcut: Some("Ctrl+T"),
+        run: |state| {
+            state.open_tab();
+        },
+    },
+    Command {
+        title: "Close Tab",
+        shortcut: Some("Ctrl+W"),
+        run: |state| state.close_current_tab(),
+    },
+    Command {
+        title: "Open File…",
+        shortcut: None,
+        run: |state| state.open_document_dialog(),
+    },
+    Command {
+        title: "Save",
+        shortcut: None,
+        run: |state| state.save_action(),
+    },
+    Command {
+        title: "Save As…",
+        shortcut: None,
+        run: |state| state.save_as_dialog(),
+    },
+    Command {
+        title: "Export as HTML…",
+        shortcut: None,
+        run: |state| state.export_html_dialog(),
+    },
+    Command {
+        title: "Print…",
+        shortcut: Some("Ctrl+P"),
+        run: |state| state.print_document(),
+    },
+    Command {
+        title: "Copy Full Path",
+        shortcut: None,
+        run: |state| state.copy_file_path(),
+    },
+    Command {
+        title: "Open Containing Folder",
+        shortcut: None,
+        run: |state| state.open_containing_folder(),
+    },
+    Command {
+        title: "Undo",
+        shortcut: Some("Ctrl+Z"),
+        run: |state| state.undo(),
+    },
+    Command {
+        title: "Redo",
+        shortcut: Some("Ctrl+Shift+Z"),
+        run: |state| state.redo(),
+    },
+    Command {
+        title: "Duplicate Line",
+        shortcut: Some("Ctrl+D"),
+        run: |state| state.edit_lines(super::editing::duplicate_lines),
+    },
+    Command {
+        title: "Move Line Up",
+        shortcut: Some("Alt+Up"),
+        run: |state| state.edit_lines(|buffer| super::editing::move_lines(buffer, true)),
+    },
+    Command {
+        title: "Move Line Down",
+        shortcut: Some("Alt+Down"),
+        run: |state| state.edit_lines(|buffer| super::editing::move_lines(buffer, false)),
+    },
+    Command {
+        title: "Toggle Comment",
+        shortcut: Some("Ctrl+/"),
+        run: |state| state.toggle_comment(),
+    },
+    Command {
+        title: "Find",
+        shortcut: Some("Ctrl+F"),
+        run: |state| state.show_search_panel(false),
+    },
+    Command {
+        title: "Find & Replace",
+        shortcut: Some("Ctrl+Shift+F"),
+        run: |state| state.show_search_panel(true),
+    },
+    Command {
+        title: "Go to Line…",
+        shortcut: Some("Ctrl+G"),
+        run: |state| state.show_goto_line_dialog(),
+    },
+    Command {
+        title: "Toggle Word Wrap",
+        shortcut: None,
+        // Through the switch, so Preferences stays in step
+        run: |state| {
+            let wrap = state.settings.borrow().wrap_text;
+            state.preferences.wrap_switch.set_active(!wrap);
+        },
+    },
+    Command {
+        title: "Toggle Line Numbers",
+        shortcut: None,
+        run: |state| {
+            let shown = state.settings.borrow().show_line_numbers;
+            state.preferences.line_numbers_switch.set_active(!shown);
+        },
+    },
+    Command {
+        title: "Toggle Markdown Preview",
+        shortcut: None,
+        run: |state| {
+            let active = state.preview_toggle.is_active();
+            state.preview_toggle.set_active(!active);
+        },
+    },
+    Command {
+        title: "Toggle Append-Only Mode",
+        shortcut: None,
+        run: |state| {
+            let active = state.append_toggle.is_active();
+            state.append_toggle.set_active(!active);
+        },
+    },
+    Command {
+        title: "Switch Line Endings",
+        shortcut: None,
+        run: |state| state.toggle_line_ending(),
+    },
+    Command {
+        title: "Zoom In",
+        shortcut: Some("Ctrl++"),
+        run: |state| state.zoom_editor(FONT_SCALE_STEP),
+    },
+    Command {
+        title: "Zoom Out",
+        shortcut: Some("Ctrl+-"),
+        run: |state| state.zoom_editor(-FONT_SCALE_STEP),
+    },
+    Command {
+        title: "Reset Zoom",
+        shortcut: Some("Ctrl+0"),
+        run: |state| state.reset_editor_zoom(),
+    },
+    Command {
+        title: "Request Completion",
+        shortcut: Some("Ctrl+Space"),
+        run: |state| state.request_llm_completion(),
+    },
+    Command {
+        title: "Summarize Document",
+        shortcut: None,
+        run: |state| state.summarize_document(),
+    },
+    Command {
+        title: "Download Model",
+        shortcut: None,
+        // The default model for the device completions run on
+        run: |state| {
+            let model_ref = {
+                let settings = state.settings.borrow();
+                if settings.llm.force_cpu_only || state.gpus.is_empty() {
+                    settings.llm.default_cpu_model.clone()
+                } else {
+                    settings.llm.default_gpu_model.clone()
+                }
+            };
+            state.download_llm_model(model_ref);
+        },
+    },
+    Command {
+        title: "Show Logs",
+        shortcut: None,
+        run: |state| state.show_logs(),
+    },
+    Command {
+        title: "Preferences",
+        shortcut: None,
+        run: |state| state.preferences.window.present(),
+    },
+];
+
+/// How well `query` matches `title`, or `None` if it doesn't. The query's
+/// characters must appear in order; runs of adjacent characters and matches at
+/// word starts rank higher.
+pub(super) fn fuzzy_score(query: &str, title: &str) -> Option<u32> {
+    let mut score = 0;
+    let mut title_chars = title.char_indices();
+    let mut previous_match: Option<usize> = None;
+    for wanted in query.chars().filter(|c| !c.is_whitespace()) {
+        let wanted = wanted.to_lowercase().next().unwrap_or(wanted);
+        loop {
+            let (index, c) = title_chars.next()?;
+            if c.to_lowercase().next() != Some(wanted) {
+                continue;
+            }
+            score += 1;
+            if previous_match.is_some_and(|previous| previous + 1 == index) {
+                score += 4;
+            }
+            let word_start = title[..index]
+                .chars()
+                .next_back()
+                .is_none_or(|before| !before.is_alphanumeric());
+            if word_start {
+                score += 3;
+            }
+            previous_match = Some(index);
+            break;
+        }
+    }
+    Some(score)
+}
+
+/// Commands matching `query`, best first. An empty query lists them all in
+/// registry order.
+fn matching_commands(query: &str) -> Vec<&'static Command> {
+    let mut matches: Vec<(u32, &'static Command)> = COMMANDS
+        .iter()
+        .filter_map(|command| fuzzy_score(query, command.title).map(|score| (score, command)))
+        .collect();
+    // Stable, so equal scores keep the registry order
+    matches.sort_by(|a, b| b.0.cmp(&a.0));
+    matches.into_iter().map(|(_, command)| command).collect()
+}
+
+impl AppState {
+    /// Ctrl+Shift+P: search every command by name and run the one picked
+    pub(super) fn show_command_palette(self: &Rc<Self>) {
+        let entry = gtk::SearchEntry::builder()
+            .placeholder_text("Type a command…")
+            .build();
+        let list = gtk::ListBox::builder()
+            .selection_mode(gtk::SelectionMode::Browse)
+            .build();
+        let scroller = gtk::ScrolledWindow::builder()
+            .hscrollbar_policy(gtk::PolicyType::Never)
+            .min_content_height(320)
+            .vexpand(true)
+            .child(&list)
+            .build();
+
+        let content = gtk::Box::builder()
+            .orientation(gtk::Orientation::Vertical)
+            .spacing(6)
+            .margin_start(12)
+            .margin_end(12)
+            .margin_top(12)
+            .margin_bottom(12)
+            .build();
+        content.append(&entry);
+        content.append(&scroller);
+
+        let window = adw::Window::builder()
+            .title("Commands")
+            .transient_for(&self.window())
+            .modal(true)
+            .default_width(420)
+            .content(&content)
+            .build();
+
+        // What each row runs, in list order
+        let shown: Rc<RefCell<Vec<&'static Command>>> = Rc::new(RefCell::new(Vec::new()));
+        let fill = {
+            let list = list.clone();
+            let shown = shown.clone();
+            move |query: &str| {
+                list.remove_all();
+                let matches = matching_commands(query);
+                for command in &matches {
+                    list.append(&command_row(command));
+                }
+                list.select_row(list.row_at_index(0).as_ref());
+                shown.replace(matches);
+            }
+        };
+        fill("");
+        entry.connect_search_changed(move |entry| fill(&entry.text()));
+
+        let weak = Rc::downgrade(self);
+        let weak_window = window.downgrade();
+        let shown_rows = shown.clone();
+        let run_row = move |index: i32| {
+            let command = usize::try_from(index)
+                .ok()
+                .and_then(|index| shown_rows.borrow().get(index).copied());
+            if let Some(window) = weak_window.upgrade() {
+                window.close();
+            }
+            if let (Some(state), Some(command)) = (weak.upgrade(), command) {
+                (command.run)(&state);
+            }
+        };
+        let run_row = Rc::new(run_row);
+
+        {
+            let run_row = run_row.clone();
+            list.connect_row_activated(move |_, row| run_row(row.index()));
+        }
+        {
+            let list = list.clone();
+            entry.connect_activate(move |_| {
+                if let Some(row) = list.selected_row() {
+                    run_row(row.index());
+                }
+            });
+        }
+
+        // Arrow keys move through the list while typing continues in the entry
+        let keys = gtk::EventControllerKey::new();
+        keys.set_propagation_phase(gtk::PropagationPhase::Capture);
+        {
+            let list = list.clone();
+            keys.connect_key_pressed(move |_, key, _, _| {
+                let step = match key {
+                    gdk::Key::Down => 1,
+                    gdk::Key::Up => -1,
+                    _ => return Propagation::Proceed,
+                };
+                let current = list.selected_row().map_or(0, |row| row.index());
+                if let Some(row) = list.row_at_index(current + step) {
+                    list.select_row(Some(&row));
+                }
+                Propagation::Stop
+            });
+        }
+        entry.add_controller(keys);
+
+        let weak_window = window.downgrade();
+        entry.connect_stop_search(move |_| {
+            if let Some(window) = weak_window.upgrade() {
+                window.close();
+            }
+        });
+
+        window.present();
+        entry.grab_focus();
+    }
+}
+
+fn command_row(command: &Command) -> gtk::ListBoxRow {
+    let title = gtk::Label::builder()
+        .label(command.title)
+        .xalign(0.0)
+        .hexpand(true)
+        .build();
+    let row_box = gtk::Box::builder()
+        .orientation(gtk::Orientation::Horizontal)
+        .spacing(12)
+        .margin_start(6)
+        .margin_end(6)
+        .margin_top(6)
+        .margin_bottom(6)
+        .build();
+    row_box.append(&title);
+    if let Some(shortcut) = command.shortcut {
+        let shortcut = gtk::Label::new(Some(shortcut));
+        shortcut.add_css_class("dim-label");
+        row_box.append(&shortcut);
+    }
+    gtk::ListBoxRow::builder().child(&row_box).build()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fuzzy_score_requires_characters_in_order() {
+        assert!(fuzzy_score("sva", "Save As…").is_some());
+        assert!(fuzzy_score("SAVE", "Save As…").is_some());
+        assert!(fuzzy_score("avs", "Save As…").is_none());
+        assert_eq!(fuzzy_score("", "Save"), Some(0));
+    }
+
+    #[test]
+    fn test_fuzzy_score_prefers_word_starts_and_runs() {
+        let word_starts = fuzzy_score("gl", "Go to Line…").unwrap();
+        let scattered = fuzzy_score("gl", "Toggle Word Wrap").unwrap();
+        assert!(word_starts > scattered);
+
+        let run = fuzzy_score("save", "Save").unwrap();
+        let spread = fuzzy_score("save", "Switch Line Endings Above").unwrap_or(0);
+        assert!(run > spread);
+    }
+
+    #[test]
+    fn test_every_command_matches_its_own_title() {
+        for command in COMMANDS {
+            assert_eq!(
+                matching_commands(command.title).first().map(|c| c.title),
+                Some(command.title)
+            );
+        }
+    }
+}
@@ -1,7 +1,19 @@
+use super::editing;
+use super::preferences;
+use super::tabs::TabState;
 use super::window::AppState;
+use crate::document::Document;
+use crate::llm::filter::{self, CompletionFilter};
+use crate::llm::{CompletionProfile, CompletionScope, FimTemplate, LlmManager, context};
+use crate::settings::TabAction;
+use gtk4::glib::{self, ControlFlow};
 use gtk4::prelude::*;
+use gtk4::{self as gtk, gdk};
 use libadwaita as adw;
 use std::rc::Rc;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Instant;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum CompletionTrigger {
@@ -9,6 +21,29 @@ pub enum CompletionTrigger {
     Automatic,
 }
 
+/// Tokens a completion may take. Mid-text (FIM) completions only fill a small
+/// gap, and automatic ones shrink to stay within the latency target; manual
+/// requests otherwise get the full budget.
+fn completion_budget(manager: &LlmManager, is_fim: bool, trigger: CompletionTrigger) -> usize {
+    let max_tokens = if is_fim {
+        std::cmp::min(50, manager.config().max_completion_tokens)
+    } else {
+        manager.config().max_completion_tokens
+    };
+    if trigger == CompletionTrigger::Automatic {
+        manager.adaptive_token_budget(max_tokens)
+    } else {
+        max_tokens
+    }
+}
+
+/// Messages from a completion worker to the main thread
+enum CompletionUpdate {
+    /// Text generated so far, while the model is still running
+    Partial(String),
+    Done(anyhow::Result<String>),
+}
+
 impl AppState {
     pub(super) fn are_completions_suppressed(&self) -> bool {
         self.completion_suppression_depth.get() > 0
@@ -25,6 +60,16 @@ impl AppState {
         result
     }
 
+    /// With completion diagnostics on, show why no suggestion appeared
+    /// instead of leaving the status bar silent.
+    pub(super) fn explain_missing_completion(&self, reason: &str) {
+        log::debug!("No completion shown: {}", reason);
+        if self.settings.borrow().llm.explain_completions {
+            self.status_label
+                .set_text(&format!("No suggestion: {}", reason));
+        }
+    }
+
     pub(super) fn request_llm_completion_with_generation(
         self: &Rc<Self>,
         trigger: CompletionTrigger,
@@ -35,6 +80,8 @@ impl AppState {
             return;
         }
 
+        self.reset_idle_unload_timer();
+
         // Mark completion as in-flight
         if trigger == CompletionTrigger::Manual {
             self.manual_completion_inflight.set(true);
@@ -45,11 +92,28 @@ impl AppState {
         }
 
         // Get the completion context (text before cursor)
-        let context = self.completion_context();
+        let mut context = self.completion_context();
+        // A manual request in a blank document seeds the model with the starter prompt
+        if trigger == CompletionTrigger::Manual && context.trim().is_empty() {
+            if let Some(starter) = self.starter_prompt() {
+                log::info!("Empty document, using starter prompt");
+                context = starter;
+            }
+        }
+        // Remember the selection being rewritten so the result is only applied to it
+        let rewrite_selection = if self.rewrites_selection() {
+            self.tab()
+                .buffer
+                .selection_bounds()
+                .map(|(start, end)| (start.offset(), end.offset()))
+        } else {
+            None
+        };
 
         // Skip if context is empty
         if trigger == CompletionTrigger::Automatic && context.is_empty() {
             self.auto_completion_running.set(false);
+            self.explain_missing_completion("no text around the cursor");
             return;
         }
 
@@ -65,20 +129,36 @@ impl AppState {
         );
 
         // Prepare for background work
+        let (filter, regenerate_blocked, stop_at_sentence_end) = {
+            let settings = self.settings.borrow();
+            (
+                CompletionFilter::new(&settings.llm.completion_blocklist),
+                settings.llm.regenerate_blocked,
+                settings.llm.stop_at_sentence_end,
+            )
+        };
+        let thread_filter = filter.clone();
         let llm_manager = self.llm_manager.clone();
-        let completion_generation = self.completion_generation.clone();
+        let cancel = self.completion_cancel.borrow().clone();
 
-        // Determine if this is a FIM (fill-in-the-middle) request
-        let is_fim = context.contains("<｜fim▁begin｜>");
+        // Determine if this is a FIM (fill-in-the-middle) request.
+        // Selection rewrites use the FIM layout but may need the full token budget.
+        let is_fim = FimTemplate::detect(&context).is_some() && rewrite_selection.is_none();
 
         // Use a channel to communicate between threads
-        let (tx, rx) = std::sync::mpsc::channel::<anyhow::Result<String>>();
+        let (tx, rx) = std::sync::mpsc::channel::<CompletionUpdate>();
 
-        // Spawn thread to request completion
-        std::thread::spawn(move || {
+        // An identical request finished recently: answer from the cache right
+        // away. A busy manager is left to the worker, which checks too.
+        let cached = self.llm_manager.try_lock().ok().and_then(|manager| {
+            manager.cached_completion(&context, completion_budget(&manager, is_fim, trigger))
+        });
+        let cached_tx = tx.clone();
+
+        let worker = move || {
             let result = (|| -> anyhow::Result<String> {
                 // Check if stale BEFORE trying to lock (avoid wasting mutex time)
-                if generation != completion_generation.get() {
+                if cancel.load(Ordering::Relaxed) {
                     log::info!(
                         "Completion request {} is stale, aborting before inference",
                         generation
@@ -91,7 +171,7 @@ impl AppState {
                     .map_err(|e| anyhow::anyhow!("Failed to lock LLM manager: {}", e))?;
 
                 // Double-check after acquiring lock (in case it changed while waiting)
-                if generation != completion_generation.get() {
+                if cancel.load(Ordering::Relaxed) {
                     log::info!(
                         "Completion request {} became stale while waiting for lock, aborting",
                         generation
@@ -101,14 +181,33 @@ impl AppState {
                     ));
                 }
 
-                // Get max tokens from settings, but use smaller limit for FIM (mid-text) completion
-                let max_tokens = if is_fim {
-                    // FIM completions should be short - just filling a small gap
-                    // Use max 50 tokens or settings value, whichever is smaller
-                    std::cmp::min(50, manager.config().max_completion_tokens)
-                } else {
-                    manager.config().max_completion_tokens
-                };
+                let max_tokens = completion_budget(&manager, is_fim, trigger);
+
+                // A remote request doesn't need the manager, and holding it for
+                // the length of the request would stall every other model task
+                if let Some(config) = manager.remote_config() {
+                    if let Some(completion) = manager.cached_completion(&context, max_tokens) {
+                        return Ok(completion);
+                    }
+                    drop(manager);
+                    log::info!(
+                        "Sending remote request for generation {} (FIM={}, max_tokens={})",
+                        generation,
+                        is_fim,
+                        max_tokens
+                    );
+                    let request =
+                        || LlmManager::complete_remote(&config, &context, max_tokens, &cancel);
+                    let mut completion = request()?;
+                    if regenerate_blocked && thread_filter.blocked_by(&completion).is_some() {
+                        log::info!("Completion matched the blocklist, requesting another");
+                        completion = request()?;
+                    }
+                    if let Ok(manager) = llm_manager.lock() {
+                        manager.cache_completion(&context, max_tokens, &completion);
+                    }
+                    return Ok(completion);
+                }
 
                 log::info!(
                     "Running inference for generation {} (FIM={}, max_tokens={})",
@@ -116,13 +215,40 @@ impl AppState {
                     is_fim,
                     max_tokens
                 );
-                // Call the complete method
-                let completion = manager.complete(&context, max_tokens)?;
+                // Stream tokens to the main thread; the cancel token stops
+                // generation once the request goes stale. Only the first
+                // sentence is shown when stopping at sentence ends, so stop
+                // generating once it is complete.
+                let partial_tx = tx.clone();
+                let mut streamed = String::new();
+                let mut completion =
+                    manager.complete_cached_streaming(&context, max_tokens, &cancel, |piece| {
+                        streamed.push_str(piece);
+                        let _ = partial_tx.send(CompletionUpdate::Partial(streamed.clone()));
+                        !(stop_at_sentence_end && filter::has_complete_sentence(&streamed))
+                    })?;
+                if regenerate_blocked && !thread_filter.is_empty() {
+                    if let Some(pattern) = thread_filter.blocked_by(&completion) {
+                        log::info!("Completion matched blocklist {:?}, regenerating", pattern);
+                        // Bypass the cache, which would hand back the blocked text
+                        completion = manager.complete_cancellable(&context, max_tokens, &cancel)?;
+                        manager.cache_completion(&context, max_tokens, &completion);
+                    }
+                }
                 Ok(completion)
             })();
 
-            let _ = tx.send(result);
-        });
+            let _ = tx.send(CompletionUpdate::Done(result));
+        };
+        match cached {
+            Some(completion) => {
+                let _ = cached_tx.send(CompletionUpdate::Done(Ok(completion)));
+            }
+            // Spawn thread to request completion
+            None => {
+                std::thread::spawn(worker);
+            }
+        }
 
         // Set up receiver on main thread
         let weak = Rc::downgrade(self);
@@ -132,8 +258,17 @@ impl AppState {
                 return gtk4::glib::ControlFlow::Break;
             }
 
-            // Try to receive result
-            match rx.try_recv() {
+            // Drain everything the worker sent; only the newest partial text matters
+            let mut latest_partial = None;
+            let received = loop {
+                match rx.try_recv() {
+                    Ok(CompletionUpdate::Partial(text)) => latest_partial = Some(text),
+                    Ok(CompletionUpdate::Done(result)) => break Ok(result),
+                    Err(err) => break Err(err),
+                }
+            };
+
+            match received {
                 Ok(result) => {
                     if let Some(state) = weak.upgrade() {
                         // Clear completion flags regardless of staleness
@@ -142,9 +277,12 @@ impl AppState {
                         } else {
                             state.auto_completion_running.set(false);
                         }
+                        // The first completion loads the model if nothing preloaded it
+                        state.update_loaded_model_row();
 
                         // Check if this request is still current
                         if generation != state.completion_generation.get() {
+                            state.explain_missing_completion("the text changed while generating");
                             return gtk4::glib::ControlFlow::Break;
                         }
 
@@ -156,23 +294,75 @@ impl AppState {
                                 } else {
                                     completion_text
                                 };
+                                let completion_text = if stop_at_sentence_end {
+                                    filter::truncate_at_sentence_end(&completion_text).to_string()
+                                } else {
+                                    completion_text
+                                };
+
+                                if let Some(bounds) = rewrite_selection {
+                                    let current = state
+                                        .tab()
+                                        .buffer
+                                        .selection_bounds()
+                                        .map(|(start, end)| (start.offset(), end.offset()));
+                                    if current != Some(bounds) {
+                                        log::info!("Selection changed, dropping rewrite");
+                                        state.status_label.set_text("");
+                                        state.explain_missing_completion("the selection changed");
+                                        return gtk4::glib::ControlFlow::Break;
+                                    }
+                                }
 
-                                if !completion_text.trim().is_empty() {
+                                if let Some(pattern) = filter.blocked_by(&completion_text) {
+                                    log::info!("Completion discarded by blocklist {:?}", pattern);
+                                    state.with_suppressed_completion(|| {
+                                        state.tab().document.dismiss_ghost_text()
+                                    });
+                                    state.status_label.set_text("Suggestion blocked by filter");
+                                    state.explain_missing_completion(&format!(
+                                        "blocked by filter {pattern}"
+                                    ));
+                                } else if !completion_text.trim().is_empty() {
                                     log::info!(
                                         "Completion generated: {} chars",
                                         completion_text.len()
                                     );
+                                    let reveal = state.settings.borrow().llm.reveal_ghost_text;
                                     // Show the completion as ghost text
                                     state.with_suppressed_completion(|| {
-                                        state.document.insert_ghost_text(&completion_text);
+                                        if rewrite_selection.is_some() {
+                                            state
+                                                .tab()
+                                                .document
+                                                .insert_ghost_replacement(&completion_text);
+                                        } else {
+                                            state
+                                                .tab()
+                                                .document
+                                                .insert_ghost_text(&completion_text);
+                                        }
                                     });
-                                    state.status_label.set_text(
-                                        "Suggestion ready (Tab to accept, Esc to dismiss)",
-                                    );
+                                    if reveal {
+                                        state.tab().document.reveal_ghost_text();
+                                    }
+                                    if rewrite_selection.is_some() {
+                                        state.status_label.set_text(
+                                            "Rewrite ready (Tab to replace selection, Esc to dismiss)",
+                                        );
+                                    } else {
+                                        state.status_label.set_text(
+                                            "Suggestion ready (Tab to accept, Esc to dismiss)",
+                                        );
+                                    }
                                 } else {
                                     log::info!("Completion was empty");
+                                    state.with_suppressed_completion(|| {
+                                        state.tab().document.dismiss_ghost_text()
+                                    });
                                     // Don't annoy user with "No completion generated"
                                     state.status_label.set_text("");
+                                    state.explain_missing_completion("the model returned nothing");
                                 }
                             }
                             Err(err) => {
@@ -181,8 +371,12 @@ impl AppState {
                                 if err_msg.contains("Request cancelled") {
                                     log::debug!("Completion cancelled: {}", err);
                                     state.status_label.set_text("");
+                                    state.explain_missing_completion("cancelled");
                                 } else {
                                     log::warn!("LLM completion failed: {}", err);
+                                    state.with_suppressed_completion(|| {
+                                        state.tab().document.dismiss_ghost_text()
+                                    });
                                     // Show error in status for all completions
                                     state
                                         .status_label
@@ -205,7 +399,20 @@ impl AppState {
                     gtk4::glib::ControlFlow::Break
                 }
                 Err(std::sync::mpsc::TryRecvError::Empty) => {
-                    // Not ready yet, keep polling
+                    // Not finished yet; show what has been generated so far
+                    if let (Some(state), Some(partial)) = (weak.upgrade(), latest_partial) {
+                        // Rewrites replace the selection, so they are only shown once complete
+                        if rewrite_selection.is_none()
+                            && generation == state.completion_generation.get()
+                        {
+                            state.show_partial_completion(
+                                &partial,
+                                &filter,
+                                is_fim,
+                                stop_at_sentence_end,
+                            );
+                        }
+                    }
                     gtk4::glib::ControlFlow::Continue
                 }
                 Err(std::sync::mpsc::TryRecvError::Disconnected) => {
@@ -223,7 +430,81 @@ impl AppState {
         });
     }
 
-    pub(super) fn preload_llm_model(self: &Rc<Self>) {
+    /// Restart the countdown after which an idle local model is unloaded.
+    pub(super) fn reset_idle_unload_timer(self: &Rc<Self>) {
+        if let Some(source) = self.idle_unload_source.borrow_mut().take() {
+            source.remove();
+        }
+        let secs = self.settings.borrow().llm.unload_after_idle_secs;
+        if secs == 0 {
+            return;
+        }
+        let weak = Rc::downgrade(self);
+        let source = gtk4::glib::timeout_add_seconds_local_once(
+            secs.min(u32::MAX as u64) as u32,
+            move || {
+                if let Some(state) = weak.upgrade() {
+                    state.idle_unload_source.borrow_mut().take();
+                    state.unload_idle_model();
+                }
+            },
+        );
+        self.idle_unload_source.borrow_mut().replace(source);
+    }
+
+    fn unload_idle_model(self: &Rc<Self>) {
+        // A completion holds the manager for its whole run, so a failed
+        // try-lock means one is in flight; check again after another interval
+        let in_flight = self.manual_completion_inflight.get() || self.auto_completion_running.get();
+        let Some(manager) = self.lock_llm_manager().filter(|_| !in_flight) else {
+            self.reset_idle_unload_timer();
+            return;
+        };
+        if !manager.is_model_loaded() {
+            return;
+        }
+        log::info!("No completions requested for a while, unloading the model");
+        manager.unload_model();
+        drop(manager);
+        self.update_loaded_model_row();
+        // The next request reloads the model through ensure_model_loaded
+        self.llm_status_label.show();
+        self.llm_status_label.set_text("LLM unloaded (idle)");
+        let label = self.llm_status_label.clone();
+        gtk4::glib::timeout_add_seconds_local_once(3, move || {
+            label.hide();
+        });
+    }
+
+    /// Show the text a completion has produced so far as ghost text. The
+    /// finished completion replaces it once generation ends.
+    fn show_partial_completion(
+        &self,
+        partial: &str,
+        filter: &CompletionFilter,
+        is_fim: bool,
+        stop_at_sentence_end: bool,
+    ) {
+        let partial = if is_fim { partial.trim_end() } else { partial };
+        let partial = if stop_at_sentence_end {
+            filter::truncate_at_sentence_end(partial)
+        } else {
+            partial
+        };
+        // Don't flash text the filter would discard at the end anyway
+        if partial.trim().is_empty() || filter.blocked_by(partial).is_some() {
+            return;
+        }
+        self.with_suppressed_completion(|| self.tab().document.insert_ghost_text(partial));
+        if self.settings.borrow().llm.reveal_ghost_text {
+            self.tab().document.reveal_ghost_text();
+        }
+    }
+
+    /// Load the configured model in the background. With `reload`, the current
+    /// settings are pushed to the manager and any loaded model is dropped first so
+    /// a model switch is paid for now instead of on the next completion.
+    pub(super) fn preload_llm_model(self: &Rc<Self>, reload: bool) {
         // Show spinner and start it
         self.llm_spinner.show();
         self.llm_spinner.start();
@@ -231,20 +512,28 @@ impl AppState {
         self.llm_status_label.set_text("Loading LLM...");
 
         let llm_manager = self.llm_manager.clone();
-        let (tx, rx) = std::sync::mpsc::channel::<anyhow::Result<()>>();
+        let reload_config = reload.then(|| self.settings.borrow().llm.clone());
+        // Carries the GPU layer count loading fell back to, if it had to
+        let (tx, rx) = std::sync::mpsc::channel::<anyhow::Result<Option<i32>>>();
 
         // Spawn a background thread to preload the model
         std::thread::spawn(move || {
             log::info!("Starting background LLM model preload...");
-            let result = (|| -> anyhow::Result<()> {
-                let manager = llm_manager
+            let result = (|| -> anyhow::Result<Option<i32>> {
+                let mut manager = llm_manager
                     .lock()
                     .map_err(|e| anyhow::anyhow!("Failed to lock LLM manager: {}", e))?;
 
+                if let Some(config) = reload_config {
+                    log::info!("Reloading LLM with updated preferences");
+                    manager.update_config(config);
+                    manager.unload_model();
+                }
+
                 // Trigger model loading by requesting a dummy completion
                 // This will download and load the model if needed
                 let _ = manager.complete("test", 1)?;
-                Ok(())
+                Ok(manager.take_gpu_layer_fallback())
             })();
 
             let _ = tx.send(result);
@@ -270,7 +559,7 @@ impl AppState {
                     spinner.hide();
 
                     match result {
-                        Ok(()) => {
+                        Ok(fallback) => {
                             log::info!("LLM model preloaded successfully");
                             status_label.set_text("LLM ready");
                             // Hide the label after a few seconds
@@ -281,8 +570,23 @@ impl AppState {
 
                             // If user has typed something while loading, trigger completion
                             if let Some(weak_state) = weak_for_trigger.upgrade() {
-                                // Check if there's text in the buffer
-                                if weak_state.buffer.char_count() > 0 {
+                                // A preloaded model that is never used still gets unloaded
+                                weak_state.reset_idle_unload_timer();
+                                weak_state.update_loaded_model_row();
+                                if let Some(layers) = fallback {
+                                    let message = format!(
+                                        "The model didn't fit on the GPU; offloaded {} layers instead",
+                                        layers
+                                    );
+                                    weak_state.show_toast(&message);
+                                    if reload {
+                                        weak_state.show_preferences_toast(&message);
+                                    }
+                                }
+                                if reload {
+                                    weak_state.show_preferences_toast("Model loaded");
+                                } else if weak_state.tab().buffer.char_count() > 0 {
+                                    // Check if there's text in the buffer
                                     log::info!(
                                         "User was typing during LLM load, triggering auto-completion"
                                     );
@@ -296,6 +600,16 @@ impl AppState {
                             log::warn!("Failed to preload LLM model: {}", err);
                             status_label.set_text("LLM unavailable");
                             // Keep the error visible
+                            if let Some(state) = weak_for_trigger.upgrade() {
+                                // A reload unloads the old model before failing
+                                state.update_loaded_model_row();
+                                if reload {
+                                    state.show_preferences_toast(&format!(
+                                        "Failed to load model: {}",
+                                        err
+                                    ));
+                                }
+                            }
                         }
                     }
                     gtk4::glib::ControlFlow::Break
@@ -316,4 +630,564 @@ impl AppState {
             }
         });
     }
+
+    /// Show what the GGUF file says about the model in memory, or that there
+    /// is none
+    pub(super) fn update_loaded_model_row(&self) {
+        let Some(manager) = self.lock_llm_manager() else {
+            return;
+        };
+        let row = &self.preferences.loaded_model_row;
+        let Some(path) = manager.loaded_model_path() else {
+            row.set_title("Loaded Model");
+            row.set_subtitle(preferences::NO_MODEL_LOADED);
+            return;
+        };
+        let file_name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        match manager.loaded_model_info() {
+            Some(info) => {
+                row.set_title(info.name.as_deref().unwrap_or(&file_name));
+                row.set_subtitle(&info.summary());
+            }
+            None => {
+                row.set_title("Loaded Model");
+                row.set_subtitle(&file_name);
+            }
+        }
+    }
+
+    /// Probe how many layers of the current model fit on the selected GPU and
+    /// remember the result for that device.
+    pub(super) fn auto_tune_gpu_layers(self: &Rc<Self>) {
+        let Some(device) = self
+            .settings
+            .borrow()
+            .llm
+            .gpu_tuning_key()
+            .map(str::to_string)
+        else {
+            self.show_preferences_toast("Select a GPU accelerator to auto-tune");
+            return;
+        };
+
+        self.preferences.auto_tune_button.set_sensitive(false);
+        self.preferences
+            .gpu_layers_row
+            .set_subtitle("Auto-tuning… this can take a minute");
+        self.llm_spinner.show();
+        self.llm_spinner.start();
+        self.llm_status_label.show();
+        self.llm_status_label.set_text("Tuning GPU layers...");
+
+        let llm_manager = self.llm_manager.clone();
+        let config = self.settings.borrow().llm.clone();
+        let (tx, rx) = std::sync::mpsc::channel::<anyhow::Result<i32>>();
+
+        std::thread::spawn(move || {
+            let result = (|| -> anyhow::Result<i32> {
+                let mut manager = llm_manager
+                    .lock()
+                    .map_err(|e| anyhow::anyhow!("Failed to lock LLM manager: {}", e))?;
+                manager.update_config(config);
+                manager.auto_tune_gpu_layers()
+            })();
+            let _ = tx.send(result);
+        });
+
+        let weak = Rc::downgrade(self);
+        gtk4::glib::timeout_add_local(std::time::Duration::from_millis(200), move || {
+            let Some(state) = weak.upgrade() else {
+                return gtk4::glib::ControlFlow::Break;
+            };
+            let result = match rx.try_recv() {
+                Ok(result) => result,
+                Err(std::sync::mpsc::TryRecvError::Empty) => {
+                    return gtk4::glib::ControlFlow::Continue;
+                }
+                Err(std::sync::mpsc::TryRecvError::Disconnected) => {
+                    Err(anyhow::anyhow!("Auto-tune stopped unexpectedly"))
+                }
+            };
+
+            state.llm_spinner.stop();
+            state.llm_spinner.hide();
+            match result {
+                Ok(layers) => {
+                    log::info!("Auto-tuned GPU {} to {} layers", device, layers);
+                    state.llm_status_label.set_text("LLM ready");
+                    state.record_tuned_gpu_layers(&device, layers);
+                    state.update_loaded_model_row();
+                    state.show_preferences_toast(&format!(
+                        "GPU auto-tune complete: {} layers",
+                        layers
+                    ));
+                }
+                Err(err) => {
+                    log::warn!("GPU auto-tune failed: {}", err);
+                    state.llm_status_label.set_text("LLM unavailable");
+                    // Restore the subtitle and button state
+                    state.sync_llm_preferences();
+                    state.show_preferences_toast(&format!("Auto-tune failed: {}", err));
+                }
+            }
+            gtk4::glib::ControlFlow::Break
+        });
+    }
+
+    pub(super) fn install_completion_shortcuts(self: &Rc<Self>, tab: &TabState) {
+        let controller = gtk::EventControllerKey::new();
+        // Set to CAPTURE phase so we intercept Tab before TextView's default handler
+        controller.set_propagation_phase(gtk::PropagationPhase::Capture);
+        let weak = Rc::downgrade(self);
+        controller.connect_key_pressed(move |_, keyval, _, state| {
+            if let Some(app) = weak.upgrade() {
+                if state.contains(gdk::ModifierType::CONTROL_MASK) && keyval == gdk::Key::space {
+                    app.request_llm_completion();
+                    return glib::Propagation::Stop;
+                }
+
+                // Take undo/redo from the TextView so a suggestion is dropped
+                // first, and the line shortcuts before its own bindings for them
+                if state.contains(gdk::ModifierType::CONTROL_MASK) {
+                    let shift = state.contains(gdk::ModifierType::SHIFT_MASK);
+                    match keyval {
+                        gdk::Key::z if !shift => {
+                            app.undo();
+                            return glib::Propagation::Stop;
+                        }
+                        gdk::Key::Z | gdk::Key::z | gdk::Key::y | gdk::Key::Y => {
+                            app.redo();
+                            return glib::Propagation::Stop;
+                        }
+                        gdk::Key::d | gdk::Key::D if !shift => {
+                            app.edit_lines(editing::duplicate_lines);
+                            return glib::Propagation::Stop;
+                        }
+                        // The view binds Ctrl+/ to select all
+                        gdk::Key::slash => {
+                            app.toggle_comment();
+                            return glib::Propagation::Stop;
+                        }
+                        _ => {}
+                    }
+                } else if state.contains(gdk::ModifierType::ALT_MASK)
+                    && matches!(keyval, gdk::Key::Up | gdk::Key::Down)
+                {
+                    let up = keyval == gdk::Key::Up;
+                    app.edit_lines(|buffer| editing::move_lines(buffer, up));
+                    return glib::Propagation::Stop;
+                }
+
+                // Log Tab presses to debug
+                if keyval == gdk::Key::Tab {
+                    log::info!(
+                        "Tab key pressed, ghost_is_active={}",
+                        app.tab().document.ghost_is_active()
+                    );
+                }
+
+                if app.tab().document.ghost_is_active() {
+                    match keyval {
+                        gdk::Key::Right if state.contains(gdk::ModifierType::CONTROL_MASK) => {
+                            app.accept_completion_with(Document::accept_ghost_word);
+                            return glib::Propagation::Stop;
+                        }
+                        // Shift+Tab usually arrives as ISO_Left_Tab
+                        gdk::Key::ISO_Left_Tab | gdk::Key::Tab
+                            if state.contains(gdk::ModifierType::SHIFT_MASK) =>
+                        {
+                            app.accept_completion_with(Document::accept_ghost_line);
+                            return glib::Propagation::Stop;
+                        }
+                        gdk::Key::Tab => {
+                            log::info!("Accepting ghost text completion");
+                            app.accept_current_completion();
+                            return glib::Propagation::Stop;
+                        }
+                        gdk::Key::Escape => {
+                            log::info!("Escape key pressed with active ghost text");
+                            app.cancel_current_completion();
+                            return glib::Propagation::Stop;
+                        }
+                        // Drop the suggestion before the edit lands so the
+                        // edit doesn't bury its undo step
+                        _ => {
+                            if is_textual_key(keyval, state)
+                                || matches!(
+                                    keyval,
+                                    gdk::Key::BackSpace | gdk::Key::Return | gdk::Key::KP_Enter
+                                )
+                            {
+                                app.cancel_current_completion();
+                            }
+                        }
+                    }
+                } else if keyval == gdk::Key::Tab
+                    && !state.intersects(
+                        gdk::ModifierType::CONTROL_MASK
+                            | gdk::ModifierType::SHIFT_MASK
+                            | gdk::ModifierType::ALT_MASK,
+                    )
+                    && !app.tab().buffer.has_selection()
+                {
+                    return app.handle_plain_tab();
+                }
+            }
+
+            glib::Propagation::Proceed
+        });
+        tab.document.view().add_controller(controller);
+
+        // Moving the cursor with the mouse doesn't go through the key handler,
+        // so clicks away from a suggestion are caught here before the view
+        // places the cursor
+        let click = gtk::GestureClick::new();
+        click.set_button(0);
+        click.set_propagation_phase(gtk::PropagationPhase::Capture);
+        let weak = Rc::downgrade(self);
+        click.connect_pressed(move |_, _, x, y| {
+            let Some(app) = weak.upgrade() else {
+                return;
+            };
+            if !app.tab().document.ghost_is_active() || !app.settings.borrow().llm.dismiss_on_click
+            {
+                return;
+            }
+            let view = app.tab().document.view();
+            let (bx, by) =
+                view.window_to_buffer_coords(gtk::TextWindowType::Widget, x as i32, y as i32);
+            let on_ghost = view
+                .iter_at_location(bx, by)
+                .is_some_and(|iter| app.tab().document.ghost_contains(&iter));
+            if !on_ghost {
+                app.cancel_current_completion();
+            }
+        });
+        tab.document.view().add_controller(click);
+    }
+
+    /// Tab with nothing to accept: indent or complete, depending on the setting
+    fn handle_plain_tab(self: &Rc<Self>) -> glib::Propagation {
+        let action = self.settings.borrow().tab_action;
+        match action {
+            // The view indents with spaces itself when the setting asks for them
+            TabAction::InsertTab | TabAction::InsertSpaces => glib::Propagation::Proceed,
+            TabAction::Complete => {
+                self.request_llm_completion();
+                glib::Propagation::Stop
+            }
+        }
+    }
+
+    pub(super) fn handle_text_change(self: &Rc<Self>) {
+        if self.are_completions_suppressed() {
+            return;
+        }
+
+        // Check for deletions/undo to avoid triggering on backspace or Ctrl+Z
+        let current_count = self.tab().buffer.char_count();
+        let last_count = self.tab().last_char_count.get();
+        self.tab().last_char_count.set(current_count);
+
+        // Only trigger completion on NET INSERTIONS (current > last)
+        // Don't trigger on deletions (current < last) or replacements (current == last)
+        if current_count <= last_count {
+            // User deleted text or replaced - don't trigger auto-completion
+            self.cancel_completion_debounce();
+            self.completion_wait_start.set(None);
+            self.manual_completion_inflight.set(false);
+            self.with_suppressed_completion(|| self.tab().document.dismiss_ghost_text());
+            // Stop any suggestion that is still streaming in
+            self.bump_completion_generation();
+            return;
+        }
+
+        self.cancel_completion_debounce();
+        self.manual_completion_inflight.set(false);
+        self.with_suppressed_completion(|| self.tab().document.dismiss_ghost_text());
+        let generation = self.bump_completion_generation();
+
+        // Large single insertions are almost always pastes; suggesting right after
+        // them wastes inference, so wait for the next small edit or a manual trigger
+        let bulk_limit = self.settings.borrow().llm.bulk_insert_chars;
+        let inserted = (current_count - last_count) as usize;
+        if bulk_limit > 0 && inserted > bulk_limit {
+            log::debug!(
+                "Skipping auto-completion after bulk insert of {} chars",
+                inserted
+            );
+            return;
+        }
+
+        if self.cursor_on_long_line() {
+            log::debug!("Skipping auto-completion on a very long line");
+            return;
+        }
+
+        // In trigger mode, stay quiet unless the edit ended with a trigger character
+        if !self.typed_trigger_char() {
+            return;
+        }
+
+        self.schedule_auto_completion(generation);
+    }
+
+    /// Whether the character just before the cursor allows an auto-completion.
+    /// Always true unless trigger-only mode is enabled.
+    fn typed_trigger_char(&self) -> bool {
+        let settings = self.settings.borrow();
+        if !settings.llm.trigger_only {
+            return true;
+        }
+        let mut iter = self
+            .tab()
+            .buffer
+            .iter_at_offset(self.tab().buffer.cursor_position());
+        if !iter.backward_char() || !settings.llm.trigger_chars.contains(iter.char()) {
+            return false;
+        }
+        if settings.llm.completion_profile == CompletionProfile::SentenceDrafting {
+            // Drafting continues with the next sentence, so the space must follow a finished one
+            return iter.backward_char() && matches!(iter.char(), '.' | '!' | '?');
+        }
+        true
+    }
+
+    pub(super) fn schedule_auto_completion(self: &Rc<Self>, generation: u64) {
+        if self.manual_completion_inflight.get()
+            || !self.settings.borrow().llm.auto_completion_enabled
+        {
+            return;
+        }
+
+        // ALWAYS cancel old debounce and schedule new one when content changes
+        self.cancel_completion_debounce();
+
+        // Typing that never pauses still gets a suggestion once the max wait is up
+        let started = self
+            .completion_wait_start
+            .get()
+            .unwrap_or_else(Instant::now);
+        self.completion_wait_start.set(Some(started));
+        let delay = self
+            .settings
+            .borrow()
+            .llm
+            .completion_delay(started.elapsed());
+
+        let weak = Rc::downgrade(self);
+        let source = glib::timeout_add_local(delay, move || {
+            if let Some(state) = weak.upgrade() {
+                // Clear the stored source ID since we're about to complete
+                state.completion_debounce.borrow_mut().take();
+                state.completion_wait_start.set(None);
+
+                if state.manual_completion_inflight.get() {
+                    return ControlFlow::Break;
+                }
+
+                state.request_llm_completion_with_generation(
+                    CompletionTrigger::Automatic,
+                    generation,
+                );
+            }
+            ControlFlow::Break
+        });
+        self.completion_debounce.borrow_mut().replace(source);
+    }
+
+    pub(super) fn cancel_completion_debounce(&self) {
+        if let Some(source) = self.completion_debounce.borrow_mut().take() {
+            // Ignore errors if source was already removed
+            let _ = source.remove();
+        }
+    }
+
+    pub(super) fn bump_completion_generation(&self) -> u64 {
+        let next = self.completion_generation.get().wrapping_add(1);
+        self.completion_generation.set(next);
+        // Stop workers from older generations, even mid-generation
+        self.completion_cancel
+            .replace(Arc::new(AtomicBool::new(false)))
+            .store(true, Ordering::Relaxed);
+        next
+    }
+
+    pub(super) fn request_llm_completion(self: &Rc<Self>) {
+        if self.cursor_on_long_line() {
+            self.show_toast("Suggestions are disabled on very long lines");
+            return;
+        }
+        let context = self.completion_context();
+        if context.trim().is_empty() && self.starter_prompt().is_none() {
+            let toast = adw::Toast::new("Type some text before requesting a completion.");
+            toast.set_timeout(5);
+            self.toast_overlay.add_toast(toast);
+            return;
+        }
+
+        let generation = self.bump_completion_generation();
+        self.request_llm_completion_with_generation(CompletionTrigger::Manual, generation);
+    }
+
+    /// Check the document for lines long enough to make wrapping and
+    /// suggestions sluggish (e.g. minified files) and adapt the editor.
+    pub(super) fn detect_long_lines(&self) {
+        let mut iter = self.tab().buffer.start_iter();
+        let mut long = false;
+        loop {
+            if iter.chars_in_line() > LONG_LINE_CHARS {
+                long = true;
+                break;
+            }
+            if !iter.forward_line() {
+                break;
+            }
+        }
+        if self.tab().long_lines.replace(long) != long {
+            self.apply_editor_settings();
+        }
+        if long {
+            self.show_toast("Very long lines: wrapping is off and suggestions are limited");
+        }
+    }
+
+    fn cursor_on_long_line(&self) -> bool {
+        self.tab()
+            .buffer
+            .iter_at_offset(self.tab().buffer.cursor_position())
+            .chars_in_line()
+            > LONG_LINE_CHARS
+    }
+
+    pub(super) fn completion_context(&self) -> String {
+        let buffer = self.tab().document.buffer();
+        let (strategy, lines, prefix_chars, suffix_chars, include_filename, fim_template) = {
+            let settings = self.settings.borrow();
+            (
+                settings.llm.context_strategy,
+                settings.llm.context_lines,
+                settings.llm.context_prefix_chars,
+                settings.llm.context_suffix_chars,
+                settings.llm.include_filename,
+                settings.llm.resolved_fim_template(),
+            )
+        };
+        // When rewriting a selection, the selected text is the "hole" the model fills
+        let (hole_start, hole_end) = self.completion_bounds();
+
+        // Get prefix (text before cursor), widened to a line start so the
+        // strategy can tell full lines from partial ones. Offsets are used
+        // instead of walking char by char, and the widening is skipped on
+        // huge lines so it can't pull in the whole line.
+        let mut prefix_start =
+            buffer.iter_at_offset((hole_start.offset() - prefix_chars as i32).max(0));
+        if prefix_start.line_offset() <= prefix_chars as i32 {
+            prefix_start.set_line_offset(0);
+        }
+        let raw_prefix = buffer.text(&prefix_start, &hole_start, true);
+        let mut prefix =
+            context::clip_prefix(&raw_prefix, strategy, prefix_chars, lines).to_string();
+
+        // Get suffix (text after cursor), widened to a line end
+        let mut suffix_end = buffer.iter_at_offset(hole_end.offset() + suffix_chars as i32);
+        if !suffix_end.ends_line()
+            && suffix_end.chars_in_line() - suffix_end.line_offset() <= suffix_chars as i32
+        {
+            suffix_end.forward_to_line_end();
+        }
+        let raw_suffix = buffer.text(&hole_end, &suffix_end, true);
+        let suffix = context::clip_suffix(&raw_suffix, strategy, suffix_chars, lines).to_string();
+
+        // Name the file at the top of the prompt. A blank document is left
+        // blank so the starter prompt still kicks in.
+        if include_filename && !(prefix.trim().is_empty() && suffix.trim().is_empty()) {
+            if let Some(header) = self
+                .tab()
+                .file_path
+                .borrow()
+                .as_deref()
+                .and_then(context::file_header)
+            {
+                prefix.insert_str(0, &header);
+            }
+        }
+
+        // Wrap in the model family's FIM markers; the model generates what
+        // goes in the hole between prefix and suffix
+        fim_template.build_prompt(&prefix, &suffix)
+    }
+
+    /// The configured starter prompt, if any, used in place of an empty context
+    pub(super) fn starter_prompt(&self) -> Option<String> {
+        let prompt = self.settings.borrow().llm.starter_prompt.trim().to_string();
+        if prompt.is_empty() {
+            None
+        } else {
+            Some(prompt)
+        }
+    }
+
+    /// Whether a completion request should rewrite the current selection
+    pub(super) fn rewrites_selection(&self) -> bool {
+        self.settings.borrow().llm.completion_scope == CompletionScope::Selection
+            && self.tab().buffer.has_selection()
+    }
+
+    fn completion_bounds(&self) -> (gtk::TextIter, gtk::TextIter) {
+        if self.rewrites_selection() {
+            if let Some(bounds) = self.tab().buffer.selection_bounds() {
+                return bounds;
+            }
+        }
+        let cursor_iter = self
+            .tab()
+            .buffer
+            .iter_at_offset(self.tab().buffer.cursor_position());
+        (cursor_iter.clone(), cursor_iter)
+    }
+
+    fn accept_current_completion(self: &Rc<Self>) {
+        self.accept_completion_with(Document::accept_ghost_text);
+    }
+
+    /// Accept all or part of the suggestion with `accept`
+    fn accept_completion_with(self: &Rc<Self>, accept: fn(&Document) -> bool) {
+        log::info!("Accepting ghost text completion");
+        let mut accepted = false;
+        self.with_suppressed_completion(|| {
+            accepted = accept(&self.tab().document);
+        });
+        if accepted {
+            log::info!("Ghost text accepted successfully");
+            self.tab().last_edit.replace(Some(Instant::now()));
+            self.update_title();
+            self.status_label.set_text("Completion accepted");
+            // Bump generation to invalidate any in-flight completions, but don't schedule new one
+            // User should continue typing before we offer another suggestion
+            self.bump_completion_generation();
+        } else {
+            log::warn!("No ghost text to accept");
+        }
+    }
+
+    fn cancel_current_completion(&self) {
+        // Also stops a suggestion that is still streaming in
+        self.bump_completion_generation();
+        self.with_suppressed_completion(|| self.tab().document.dismiss_ghost_text());
+        self.status_label.set_text("Suggestion dismissed");
+    }
+}
+
+/// Lines longer than this disable wrapping and completions
+const LONG_LINE_CHARS: i32 = 10_000;
+
+fn is_textual_key(key: gdk::Key, state: gdk::ModifierType) -> bool {
+    if state.intersects(gdk::ModifierType::CONTROL_MASK | gdk::ModifierType::ALT_MASK) {
+        return false;
+    }
+    key.to_unicode().map(|ch| !ch.is_control()).unwrap_or(false)
 }
@@ -0,0 +1,333 @@
+//! Line editing shortcuts: Ctrl+D duplicates, Alt+Up/Down moves lines and
+//! Ctrl+/ comments them out. Each is a single undo step.
+
+use std::rc::Rc;
+
+use gtk4::{self as gtk, prelude::*};
+use sourceview5::prelude::*;
+
+use crate::document::Document;
+
+use super::window::AppState;
+
+/// First and last line the cursor or selection covers. A selection ending at
+/// the very start of a line doesn't take that line along.
+fn selected_lines(buffer: &gtk::TextBuffer) -> (i32, i32) {
+    let (start, end) = buffer.selection_bounds().unwrap_or_else(|| {
+        let cursor = buffer.iter_at_mark(&buffer.get_insert());
+        (cursor, cursor)
+    });
+    let last = if end.line() > start.line() && end.starts_line() {
+        end.line() - 1
+    } else {
+        end.line()
+    };
+    (start.line(), last)
+}
+
+fn line_start(buffer: &gtk::TextBuffer, line: i32) -> gtk::TextIter {
+    buffer
+        .iter_at_line(line)
+        .unwrap_or_else(|| buffer.end_iter())
+}
+
+/// End of `line`, before its line break
+fn line_end(buffer: &gtk::TextBuffer, line: i32) -> gtk::TextIter {
+    let mut end = line_start(buffer, line);
+    if !end.ends_line() {
+        end.forward_to_line_end();
+    }
+    end
+}
+
+/// Where the selection bound and cursor are, as (line, offset) pairs
+fn selection_positions(buffer: &gtk::TextBuffer) -> [(i32, i32); 2] {
+    [buffer.selection_bound(), buffer.get_insert()].map(|mark| {
+        let iter = buffer.iter_at_mark(&mark);
+        (iter.line(), iter.line_offset())
+    })
+}
+
+fn restore_selection(buffer: &gtk::TextBuffer, positions: [(i32, i32); 2], line_shift: i32) {
+    let [bound, insert] = positions.map(|(line, offset)| {
+        buffer
+            .iter_at_line_offset(line + line_shift, offset)
+            .unwrap_or_else(|| line_end(buffer, line + line_shift))
+    });
+    buffer.select_range(&insert, &bound);
+}
+
+/// Copy the selection after itself, or the cursor's line below itself. The
+/// copy ends up selected, or the cursor on the copied line.
+pub(super) fn duplicate_lines(buffer: &gtk::TextBuffer) {
+    buffer.begin_user_action();
+    if let Some((start, mut end)) = buffer.selection_bounds() {
+        let text = buffer.text(&start, &end, false);
+        let offset = end.offset();
+        buffer.insert(&mut end, &text);
+        buffer.select_range(&end, &buffer.iter_at_offset(offset));
+    } else {
+        let positions = selection_positions(buffer);
+        let line = positions[1].0;
+        let text = buffer.text(&line_start(buffer, line), &line_end(buffer, line), false);
+        buffer.insert(&mut line_end(buffer, line), &format!("\n{text}"));
+        restore_selection(buffer, positions, 1);
+    }
+    buffer.end_user_action();
+}
+
+/// Swap the selected lines with the line above (`up`) or below them. Nothing
+/// happens at the start or end of the document.
+pub(super) fn move_lines(buffer: &gtk::TextBuffer, up: bool) {
+    let (first, last) = selected_lines(buffer);
+    if (up && first == 0) || (!up && last + 1 >= buffer.line_count()) {
+        return;
+    }
+    let positions = selection_positions(buffer);
+    let block = buffer.text(&line_start(buffer, first), &line_end(buffer, last), false);
+    let (region_first, region_last, replacement) = if up {
+        let above = buffer.text(
+            &line_start(buffer, first - 1),
+            &line_end(buffer, first - 1),
+            false,
+        );
+        (first - 1, last, format!("{block}\n{above}"))
+    } else {
+        let below = buffer.text(
+            &line_start(buffer, last + 1),
+            &line_end(buffer, last + 1),
+            false,
+        );
+        (first, last + 1, format!("{below}\n{block}"))
+    };
+
+    buffer.begin_user_action();
+    let mut start = line_start(buffer, region_first);
+    let mut end = line_end(buffer, region_last);
+    buffer.delete(&mut start, &mut end);
+    buffer.insert(&mut start, &replacement);
+    restore_selection(buffer, positions, if up { -1 } else { 1 });
+    buffer.end_user_action();
+}
+
+/// Comment out the selected lines with `prefix`, or uncomment them if every
+/// non-blank one already is.
+pub(super) fn toggle_comment(buffer: &gtk::TextBuffer, prefix: &str) {
+    let (first, last) = selected_lines(buffer);
+    let lines: Vec<(i32, String)> = (first..=last)
+        .map(|line| {
+            let text = buffer.text(&line_start(buffer, line), &line_end(buffer, line), false);
+            (line, text.to_string())
+        })
+        .filter(|(_, text)| !text.trim().is_empty())
+        .collect();
+    if lines.is_empty() {
+        return;
+    }
+    let commented = lines
+        .iter()
+        .all(|(_, text)| text.trim_start().starts_with(prefix));
+
+    buffer.begin_user_action();
+    for (line, text) in &lines {
+        let indent = text.chars().take_while(|c| c.is_whitespace()).count() as i32;
+        let mut start = line_start(buffer, *line);
+        start.forward_chars(indent);
+        if commented {
+            let rest = &text.trim_start()[prefix.len()..];
+            let width = prefix.chars().count() + usize::from(rest.starts_with(' '));
+            let mut end = start;
+            end.forward_chars(width as i32);
+            buffer.delete(&mut start, &mut end);
+        } else {
+            buffer.insert(&mut start, &format!("{prefix} "));
+        }
+    }
+    buffer.end_user_action();
+}
+
+impl AppState {
+    /// Ctrl+/: the prefix comes from the highlighting language, with `#` for
+    /// plain text and languages that only have block comments
+    pub(super) fn toggle_comment(&self) {
+        let prefix = self
+            .tab()
+            .buffer
+            .language()
+            .and_then(|language| language.metadata("line-comment-start"))
+            .map_or_else(|| "#".to_string(), |prefix| prefix.to_string());
+        self.edit_lines(|buffer| toggle_comment(buffer, &prefix));
+    }
+
+    /// Run a line edit on the current tab, unless append-only mode forbids
+    /// changing what is already written.
+    pub(super) fn edit_lines(&self, edit: impl FnOnce(&gtk::TextBuffer)) {
+        if self.settings.borrow().append_only {
+            self.show_toast("Written lines can't be changed in append-only mode");
+            return;
+        }
+        let tab = self.tab();
+        self.with_suppressed_completion(|| {
+            tab.document.dismiss_ghost_text();
+            edit(tab.buffer.upcast_ref::<gtk::TextBuffer>());
+        });
+        tab.document
+            .view()
+            .scroll_mark_onscreen(&tab.buffer.get_insert());
+    }
+
+    pub(super) fn show_goto_line_dialog(self: &Rc<Self>) {
+        let dialog = gtk::Dialog::builder()
+            .transient_for(&self.window())
+            .modal(true)
+            .title("Go to Line")
+            .build();
+        dialog.add_button("Cancel", gtk::ResponseType::Cancel);
+        dialog.add_button("Go", gtk::ResponseType::Accept);
+        dialog.set_default_response(gtk::ResponseType::Accept);
+
+        let entry = gtk::Entry::builder()
+            .placeholder_text("Line number")
+            .input_purpose(gtk::InputPurpose::Digits)
+            .activates_default(true)
+            .build();
+        entry.set_margin_top(12);
+        entry.set_margin_bottom(12);
+        entry.set_margin_start(12);
+        entry.set_margin_end(12);
+        dialog.content_area().append(&entry);
+        entry.grab_focus();
+
+        let weak = Rc::downgrade(self);
+        let entry_clone = entry.clone();
+        dialog.connect_response(move |dialog, response| {
+            if response == gtk::ResponseType::Accept {
+                if let Some(state) = weak.upgrade() {
+                    let text = entry_clone.text();
+                    if let Ok(mut line) = text.trim().parse::<i32>() {
+                        if line <= 0 {
+                            line = 1;
+                        }
+                        let total = state.tab().buffer.line_count().max(1);
+                        if line > total {
+                            line = total;
+                        }
+                        if let Some(mut iter) = state.tab().buffer.iter_at_line(line - 1) {
+                            state.tab().buffer.place_cursor(&iter);
+                            let view = state.tab().document.view();
+                            view.scroll_to_iter(&mut iter, 0.1, false, 0.0, 0.0);
+                            state
+                                .status_label
+                                .set_text(&format!("Line {} of {}", line, total));
+                        }
+                    } else {
+                        state.status_label.set_text("Enter a valid line number");
+                    }
+                }
+            }
+            dialog.close();
+        });
+
+        dialog.show();
+    }
+
+    pub(super) fn undo(&self) {
+        self.step_history(Document::undo);
+    }
+
+    pub(super) fn redo(&self) {
+        self.step_history(Document::redo);
+    }
+
+    /// Undo or redo with `step`. Text brought back this way isn't typing, so
+    /// it doesn't prompt a new suggestion.
+    fn step_history(&self, step: fn(&Document) -> bool) {
+        self.bump_completion_generation();
+        self.with_suppressed_completion(|| self.tab().document.dismiss_ghost_text());
+        if step(&self.tab().document) {
+            self.cancel_completion_debounce();
+            self.completion_wait_start.set(None);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn buffer_with(text: &str, cursor: i32) -> Option<gtk::TextBuffer> {
+        if gtk4::init().is_err() {
+            eprintln!("Skipping GTK test due to missing display");
+            return None;
+        }
+        let buffer = gtk::TextBuffer::new(None);
+        buffer.set_text(text);
+        buffer.place_cursor(&buffer.iter_at_offset(cursor));
+        Some(buffer)
+    }
+
+    fn text(buffer: &gtk::TextBuffer) -> String {
+        let (start, end) = buffer.bounds();
+        buffer.text(&start, &end, false).to_string()
+    }
+
+    #[test]
+    fn test_duplicate_line() {
+        let Some(buffer) = buffer_with("one\ntwo", 5) else {
+            return;
+        };
+        duplicate_lines(&buffer);
+        assert_eq!(text(&buffer), "one\ntwo\ntwo");
+        let cursor = buffer.iter_at_mark(&buffer.get_insert());
+        assert_eq!((cursor.line(), cursor.line_offset()), (2, 1));
+    }
+
+    #[test]
+    fn test_duplicate_selection() {
+        let Some(buffer) = buffer_with("abc", 0) else {
+            return;
+        };
+        buffer.select_range(&buffer.iter_at_offset(0), &buffer.iter_at_offset(2));
+        duplicate_lines(&buffer);
+        assert_eq!(text(&buffer), "ababc");
+    }
+
+    #[test]
+    fn test_move_lines_swaps_with_neighbor() {
+        let Some(buffer) = buffer_with("one\ntwo\nthree", 5) else {
+            return;
+        };
+        move_lines(&buffer, true);
+        assert_eq!(text(&buffer), "two\none\nthree");
+        move_lines(&buffer, false);
+        move_lines(&buffer, false);
+        assert_eq!(text(&buffer), "one\nthree\ntwo");
+        let cursor = buffer.iter_at_mark(&buffer.get_insert());
+        assert_eq!((cursor.line(), cursor.line_offset()), (2, 1));
+    }
+
+    #[test]
+    fn test_toggle_comment() {
+        let Some(buffer) = buffer_with("  a\n\n  # b", 0) else {
+            return;
+        };
+        buffer.select_range(&buffer.start_iter(), &buffer.end_iter());
+        toggle_comment(&buffer, "#");
+        assert_eq!(text(&buffer), "  # a\n\n  # # b");
+        buffer.select_range(&buffer.start_iter(), &buffer.end_iter());
+        toggle_comment(&buffer, "#");
+        assert_eq!(text(&buffer), "  a\n\n  # b");
+    }
+
+    #[test]
+    fn test_move_lines_at_document_edges_does_nothing() {
+        let Some(buffer) = buffer_with("one\ntwo", 0) else {
+            return;
+        };
+        move_lines(&buffer, true);
+        assert_eq!(text(&buffer), "one\ntwo");
+        buffer.place_cursor(&buffer.end_iter());
+        move_lines(&buffer, false);
+        assert_eq!(text(&buffer), "one\ntwo");
+    }
+}
@@ -0,0 +1,711 @@
+//! Editor preferences: fonts, zoom, colors and the text view options
+
+use std::rc::Rc;
+
+use adw::prelude::*;
+use gtk4::glib::Propagation;
+use gtk4::{self as gtk, gdk, prelude::*};
+use libadwaita as adw;
+use sourceview5::prelude::*;
+
+use crate::document::SaveCleanup;
+use crate::settings::TabAction;
+
+use super::preferences;
+use super::tabs::TabState;
+use super::window::AppState;
+
+impl AppState {
+    pub(super) fn apply_editor_settings(&self) {
+        self.apply_style();
+        let font_css = {
+            let settings = self.settings.borrow();
+            editor_font_css(&settings.editor_font, settings.font_scale)
+        };
+        self.editor_css.load_from_data(&font_css);
+        let ghost_color = self.ghost_text_color();
+        self.preferences.ghost_color_button.set_rgba(&ghost_color);
+        let settings = self.settings.borrow();
+
+        for tab in self.tabs() {
+            tab.document.set_ghost_color(&ghost_color);
+            let view = tab.document.view();
+            view.set_show_line_numbers(settings.show_line_numbers);
+            view.set_tab_width(settings.tab_width);
+            view.set_insert_spaces_instead_of_tabs(settings.insert_spaces);
+            view.set_auto_indent(settings.auto_indent);
+            view.set_highlight_current_line(settings.highlight_current_line);
+            tab.buffer
+                .set_highlight_matching_brackets(settings.highlight_matching_brackets);
+            view.set_show_line_marks(settings.show_whitespace);
+
+            // Wrapping a multi-megabyte line stalls layout, so long-line files never wrap
+            if settings.wrap_text && !tab.long_lines.get() {
+                view.set_wrap_mode(gtk::WrapMode::WordChar);
+            } else {
+                view.set_wrap_mode(gtk::WrapMode::None);
+            }
+        }
+    }
+
+    /// Apply the light/dark preference to the window and pick the matching
+    /// editor color scheme
+    fn apply_style(&self) {
+        let (follow_system, dark, scheme_id) = {
+            let settings = self.settings.borrow();
+            (
+                settings.follow_system_style,
+                settings.dark_style,
+                settings.style_scheme.clone(),
+            )
+        };
+        let style_manager = adw::StyleManager::default();
+        style_manager.set_color_scheme(if follow_system {
+            adw::ColorScheme::Default
+        } else if dark {
+            adw::ColorScheme::ForceDark
+        } else {
+            adw::ColorScheme::ForceLight
+        });
+        let manager = sourceview5::StyleSchemeManager::default();
+        let chosen = if scheme_id.is_empty() {
+            None
+        } else {
+            let scheme = manager.scheme(&scheme_id);
+            if scheme.is_none() {
+                // Uninstalled since it was picked
+                log::warn!("Style scheme {:?} not found, using the default", scheme_id);
+            }
+            scheme
+        };
+        let scheme = chosen.or_else(|| {
+            manager.scheme(if style_manager.is_dark() {
+                "Adwaita-dark"
+            } else {
+                "Adwaita"
+            })
+        });
+        for tab in self.tabs() {
+            tab.buffer.set_style_scheme(scheme.as_ref());
+        }
+    }
+
+    fn set_follow_system_style(&self, follow: bool) {
+        {
+            let mut settings = self.settings.borrow_mut();
+            if settings.follow_system_style == follow {
+                return;
+            }
+            settings.follow_system_style = follow;
+        }
+        self.save_settings();
+        self.apply_editor_settings();
+    }
+
+    /// The font the font picker starts from
+    fn editor_font_or_default(&self) -> String {
+        let current = self.settings.borrow().editor_font.clone();
+        if current.is_empty() {
+            "Monospace 11".to_string()
+        } else {
+            current
+        }
+    }
+
+    #[cfg(feature = "v4_10")]
+    fn choose_editor_font(self: &Rc<Self>) {
+        let dialog = gtk::FontDialog::builder()
+            .title("Editor Font")
+            .modal(true)
+            .build();
+        let current = gtk::pango::FontDescription::from_string(&self.editor_font_or_default());
+        let weak = Rc::downgrade(self);
+        dialog.choose_font(
+            Some(&self.preferences.window),
+            Some(&current),
+            None::<&gtk::gio::Cancellable>,
+            move |result| {
+                // Dismissing the dialog is an error too
+                if let (Some(state), Ok(font)) = (weak.upgrade(), result) {
+                    state.set_editor_font(font.to_string());
+                }
+            },
+        );
+    }
+
+    /// Without the `v4_10` feature, the font chooser dialog GTK 4.10 deprecated
+    #[cfg(not(feature = "v4_10"))]
+    fn choose_editor_font(self: &Rc<Self>) {
+        let dialog =
+            gtk::FontChooserDialog::new(Some("Editor Font"), Some(&self.preferences.window));
+        dialog.set_modal(true);
+        dialog.set_font(&self.editor_font_or_default());
+        let weak = Rc::downgrade(self);
+        dialog.connect_response(move |dialog, response| {
+            if response == gtk::ResponseType::Ok {
+                if let (Some(state), Some(font)) = (weak.upgrade(), dialog.font()) {
+                    state.set_editor_font(font.to_string());
+                }
+            }
+            dialog.close();
+        });
+        dialog.present();
+    }
+
+    /// Grow or shrink the editor text by `step` of the base size
+    pub(super) fn zoom_editor(&self, step: f64) {
+        let scale = self.settings.borrow().font_scale + step;
+        self.set_font_scale(scale);
+    }
+
+    pub(super) fn reset_editor_zoom(&self) {
+        self.set_font_scale(1.0);
+    }
+
+    /// Ctrl+scroll zooms the editor
+    pub(super) fn install_zoom_scroll(self: &Rc<Self>, tab: &TabState) {
+        let scroll_controller = gtk::EventControllerScroll::new(
+            gtk::EventControllerScrollFlags::VERTICAL | gtk::EventControllerScrollFlags::DISCRETE,
+        );
+        let weak = Rc::downgrade(self);
+        scroll_controller.connect_scroll(move |controller, _, dy| {
+            let Some(state) = weak.upgrade() else {
+                return Propagation::Proceed;
+            };
+            if !controller
+                .current_event_state()
+                .contains(gdk::ModifierType::CONTROL_MASK)
+                || dy == 0.0
+            {
+                return Propagation::Proceed;
+            }
+            state.zoom_editor(if dy < 0.0 {
+                FONT_SCALE_STEP
+            } else {
+                -FONT_SCALE_STEP
+            });
+            Propagation::Stop
+        });
+        tab.document.view().add_controller(scroll_controller);
+    }
+
+    fn set_font_scale(&self, scale: f64) {
+        // Rounded so repeated steps don't drift away from round percentages
+        let scale = ((scale * 100.0).round() / 100.0).clamp(FONT_SCALE_MIN, FONT_SCALE_MAX);
+        {
+            let mut settings = self.settings.borrow_mut();
+            if settings.font_scale == scale {
+                return;
+            }
+            settings.font_scale = scale;
+        }
+        self.save_settings();
+        self.apply_editor_settings();
+        self.status_label
+            .set_text(&format!("Zoom {}%", (scale * 100.0).round()));
+    }
+
+    fn set_editor_font(&self, font: String) {
+        {
+            let mut settings = self.settings.borrow_mut();
+            if settings.editor_font == font {
+                return;
+            }
+            settings.editor_font = font;
+        }
+        self.save_settings();
+        let font = self.settings.borrow().editor_font.clone();
+        self.preferences
+            .font_row
+            .set_subtitle(&preferences::font_label(&font));
+        self.preferences
+            .font_reset_button
+            .set_sensitive(!font.is_empty());
+        self.apply_editor_settings();
+    }
+
+    fn set_style_scheme(&self, scheme_id: String) {
+        {
+            let mut settings = self.settings.borrow_mut();
+            if settings.style_scheme == scheme_id {
+                return;
+            }
+            settings.style_scheme = scheme_id;
+        }
+        self.save_settings();
+        self.apply_editor_settings();
+    }
+
+    fn set_dark_style(&self, dark: bool) {
+        {
+            let mut settings = self.settings.borrow_mut();
+            if settings.dark_style == dark {
+                return;
+            }
+            settings.dark_style = dark;
+        }
+        self.save_settings();
+        self.apply_editor_settings();
+    }
+
+    /// The configured suggestion color, or the default for the current light/dark style
+    fn ghost_text_color(&self) -> gdk::RGBA {
+        let configured = self.settings.borrow().ghost_text_color.clone();
+        if !configured.is_empty() {
+            match gdk::RGBA::parse(configured.as_str()) {
+                Ok(color) => return color,
+                Err(_) => log::warn!("Ignoring invalid suggestion color {:?}", configured),
+            }
+        }
+        // Dim enough to read as a suggestion, bright enough to stay legible
+        let default = if adw::StyleManager::default().is_dark() {
+            GHOST_COLOR_DARK
+        } else {
+            GHOST_COLOR_LIGHT
+        };
+        gdk::RGBA::parse(default).expect("default suggestion color is valid")
+    }
+
+    fn set_ghost_text_color(&self, color: String) {
+        {
+            let mut settings = self.settings.borrow_mut();
+            if settings.ghost_text_color == color {
+                return;
+            }
+            settings.ghost_text_color = color;
+        }
+        self.save_settings();
+        let custom = !self.settings.borrow().ghost_text_color.is_empty();
+        self.preferences
+            .ghost_color_reset_button
+            .set_sensitive(custom);
+        self.apply_editor_settings();
+    }
+
+    pub(super) fn hook_editor_preferences(self: &Rc<Self>) {
+        let weak = Rc::downgrade(self);
+        self.preferences.font_button.connect_clicked(move |_| {
+            if let Some(state) = weak.upgrade() {
+                state.choose_editor_font();
+            }
+        });
+
+        let weak = Rc::downgrade(self);
+        self.preferences
+            .font_reset_button
+            .connect_clicked(move |_| {
+                if let Some(state) = weak.upgrade() {
+                    state.set_editor_font(String::new());
+                }
+            });
+
+        let weak = Rc::downgrade(self);
+        self.preferences
+            .follow_system_style_switch
+            .connect_state_set(move |_, active| {
+                if let Some(state) = weak.upgrade() {
+                    state.set_follow_system_style(active);
+                }
+                Propagation::Proceed
+            });
+
+        let weak = Rc::downgrade(self);
+        self.preferences
+            .style_scheme_combo
+            .connect_selected_notify(move |row| {
+                if let Some(state) = weak.upgrade() {
+                    state.set_style_scheme(preferences::style_scheme_from_index(row.selected()));
+                }
+            });
+
+        let weak = Rc::downgrade(self);
+        self.preferences
+            .dark_style_switch
+            .connect_state_set(move |_, active| {
+                if let Some(state) = weak.upgrade() {
+                    state.set_dark_style(active);
+                }
+                Propagation::Proceed
+            });
+
+        let weak = Rc::downgrade(self);
+        self.preferences
+            .ghost_color_button
+            .connect_color_set(move |button| {
+                if let Some(state) = weak.upgrade() {
+                    state.set_ghost_text_color(preferences::color_to_hex(&button.rgba()));
+                }
+            });
+
+        let weak = Rc::downgrade(self);
+        self.preferences
+            .ghost_color_reset_button
+            .connect_clicked(move |_| {
+                if let Some(state) = weak.upgrade() {
+                    state.set_ghost_text_color(String::new());
+                }
+            });
+
+        // The editor scheme and default suggestion color follow the light/dark style
+        let weak = Rc::downgrade(self);
+        adw::StyleManager::default().connect_dark_notify(move |_| {
+            if let Some(state) = weak.upgrade() {
+                state.apply_editor_settings();
+            }
+        });
+
+        let weak = Rc::downgrade(self);
+        self.preferences
+            .line_numbers_switch
+            .connect_state_set(move |_, active| {
+                if let Some(state) = weak.upgrade() {
+                    state.set_show_line_numbers(active);
+                }
+                Propagation::Proceed
+            });
+
+        let weak = Rc::downgrade(self);
+        self.preferences
+            .tab_width_spin
+            .connect_value_changed(move |spin| {
+                if let Some(state) = weak.upgrade() {
+                    state.set_tab_width(spin.value() as u32);
+                }
+            });
+
+        let weak = Rc::downgrade(self);
+        self.preferences
+            .insert_spaces_switch
+            .connect_state_set(move |_, active| {
+                if let Some(state) = weak.upgrade() {
+                    state.set_insert_spaces(active);
+                }
+                Propagation::Proceed
+            });
+
+        let weak = Rc::downgrade(self);
+        self.preferences
+            .auto_indent_switch
+            .connect_state_set(move |_, active| {
+                if let Some(state) = weak.upgrade() {
+                    state.set_auto_indent(active);
+                }
+                Propagation::Proceed
+            });
+
+        let weak = Rc::downgrade(self);
+        self.preferences
+            .current_line_switch
+            .connect_state_set(move |_, active| {
+                if let Some(state) = weak.upgrade() {
+                    state.set_highlight_current_line(active);
+                }
+                Propagation::Proceed
+            });
+
+        let weak = Rc::downgrade(self);
+        self.preferences
+            .matching_brackets_switch
+            .connect_state_set(move |_, active| {
+                if let Some(state) = weak.upgrade() {
+                    state.set_highlight_matching_brackets(active);
+                }
+                Propagation::Proceed
+            });
+
+        let weak = Rc::downgrade(self);
+        self.preferences
+            .whitespace_switch
+            .connect_state_set(move |_, active| {
+                if let Some(state) = weak.upgrade() {
+                    state.set_show_whitespace(active);
+                }
+                Propagation::Proceed
+            });
+
+        let weak = Rc::downgrade(self);
+        self.preferences
+            .wrap_switch
+            .connect_state_set(move |_, active| {
+                if let Some(state) = weak.upgrade() {
+                    state.set_wrap_text(active);
+                }
+                Propagation::Proceed
+            });
+
+        let weak = Rc::downgrade(self);
+        self.preferences
+            .reopen_last_file_switch
+            .connect_state_set(move |_, active| {
+                if let Some(state) = weak.upgrade() {
+                    state.set_reopen_last_file(active);
+                }
+                Propagation::Proceed
+            });
+
+        let weak = Rc::downgrade(self);
+        self.preferences
+            .confirm_overwrite_switch
+            .connect_state_set(move |_, active| {
+                if let Some(state) = weak.upgrade() {
+                    state.set_confirm_overwrite(active);
+                }
+                Propagation::Proceed
+            });
+
+        let weak = Rc::downgrade(self);
+        self.preferences
+            .final_newline_switch
+            .connect_state_set(move |_, active| {
+                if let Some(state) = weak.upgrade() {
+                    state.set_ensure_final_newline(active);
+                }
+                Propagation::Proceed
+            });
+
+        let weak = Rc::downgrade(self);
+        self.preferences
+            .trim_whitespace_switch
+            .connect_state_set(move |_, active| {
+                if let Some(state) = weak.upgrade() {
+                    state.set_trim_trailing_whitespace(active);
+                }
+                Propagation::Proceed
+            });
+
+        let weak = Rc::downgrade(self);
+        self.preferences
+            .max_recent_spin
+            .connect_value_changed(move |spin| {
+                if let Some(state) = weak.upgrade() {
+                    state.set_max_recent_files(spin.value() as usize);
+                }
+            });
+
+        let weak = Rc::downgrade(self);
+        self.preferences
+            .pause_monitor_switch
+            .connect_state_set(move |_, active| {
+                if let Some(state) = weak.upgrade() {
+                    state.set_pause_monitor_in_background(active);
+                }
+                Propagation::Proceed
+            });
+
+        let weak = Rc::downgrade(self);
+        self.preferences
+            .tab_action_combo
+            .connect_selected_notify(move |row| {
+                if let Some(state) = weak.upgrade() {
+                    state.set_tab_action(preferences::tab_action_from_index(row.selected()));
+                }
+            });
+    }
+
+    fn set_tab_action(&self, action: TabAction) {
+        {
+            let mut settings = self.settings.borrow_mut();
+            if settings.tab_action == action {
+                return;
+            }
+            settings.tab_action = action;
+        }
+        self.save_settings();
+    }
+
+    /// Cleanups for saving to the document's file; autosave drafts don't get them
+    pub(super) fn save_cleanup(&self) -> SaveCleanup {
+        let settings = self.settings.borrow();
+        SaveCleanup {
+            final_newline: settings.ensure_final_newline,
+            trim_trailing_whitespace: settings.trim_trailing_whitespace_on_save,
+        }
+    }
+
+    fn set_ensure_final_newline(&self, enabled: bool) {
+        {
+            let mut settings = self.settings.borrow_mut();
+            if settings.ensure_final_newline == enabled {
+                return;
+            }
+            settings.ensure_final_newline = enabled;
+        }
+        self.save_settings();
+    }
+
+    fn set_trim_trailing_whitespace(&self, enabled: bool) {
+        {
+            let mut settings = self.settings.borrow_mut();
+            if settings.trim_trailing_whitespace_on_save == enabled {
+                return;
+            }
+            settings.trim_trailing_whitespace_on_save = enabled;
+        }
+        self.save_settings();
+    }
+
+    fn set_reopen_last_file(&self, reopen: bool) {
+        {
+            let mut settings = self.settings.borrow_mut();
+            if settings.reopen_last_file == reopen {
+                return;
+            }
+            settings.reopen_last_file = reopen;
+        }
+        self.save_settings();
+    }
+
+    fn set_confirm_overwrite(&self, confirm: bool) {
+        {
+            let mut settings = self.settings.borrow_mut();
+            if settings.confirm_overwrite == confirm {
+                return;
+            }
+            settings.confirm_overwrite = confirm;
+        }
+        self.save_settings();
+    }
+
+    fn set_show_line_numbers(&self, show: bool) {
+        {
+            let mut settings = self.settings.borrow_mut();
+            if settings.show_line_numbers == show {
+                return;
+            }
+            settings.show_line_numbers = show;
+        }
+        self.save_settings();
+        self.apply_editor_settings();
+    }
+
+    fn set_tab_width(&self, width: u32) {
+        {
+            let mut settings = self.settings.borrow_mut();
+            if settings.tab_width == width {
+                return;
+            }
+            settings.tab_width = width;
+        }
+        self.save_settings();
+        self.apply_editor_settings();
+    }
+
+    fn set_insert_spaces(&self, spaces: bool) {
+        {
+            let mut settings = self.settings.borrow_mut();
+            if settings.insert_spaces == spaces {
+                return;
+            }
+            settings.insert_spaces = spaces;
+        }
+        self.save_settings();
+        self.apply_editor_settings();
+    }
+
+    fn set_auto_indent(&self, auto_indent: bool) {
+        {
+            let mut settings = self.settings.borrow_mut();
+            if settings.auto_indent == auto_indent {
+                return;
+            }
+            settings.auto_indent = auto_indent;
+        }
+        self.save_settings();
+        self.apply_editor_settings();
+    }
+
+    fn set_highlight_current_line(&self, highlight: bool) {
+        {
+            let mut settings = self.settings.borrow_mut();
+            if settings.highlight_current_line == highlight {
+                return;
+            }
+            settings.highlight_current_line = highlight;
+        }
+        self.save_settings();
+        self.apply_editor_settings();
+    }
+
+    fn set_highlight_matching_brackets(&self, highlight: bool) {
+        {
+            let mut settings = self.settings.borrow_mut();
+            if settings.highlight_matching_brackets == highlight {
+                return;
+            }
+            settings.highlight_matching_brackets = highlight;
+        }
+        self.save_settings();
+        self.apply_editor_settings();
+    }
+
+    fn set_show_whitespace(&self, show: bool) {
+        {
+            let mut settings = self.settings.borrow_mut();
+            if settings.show_whitespace == show {
+                return;
+            }
+            settings.show_whitespace = show;
+        }
+        self.save_settings();
+        self.apply_editor_settings();
+    }
+
+    fn set_wrap_text(&self, wrap: bool) {
+        {
+            let mut settings = self.settings.borrow_mut();
+            if settings.wrap_text == wrap {
+                return;
+            }
+            settings.wrap_text = wrap;
+        }
+        self.save_settings();
+        self.apply_editor_settings();
+    }
+}
+
+/// CSS class on the editor view that the font rules target
+pub(super) const EDITOR_CSS_CLASS: &str = "wispnote-editor";
+
+/// Editor zoom limits and the change per Ctrl+Plus/Minus
+const FONT_SCALE_MIN: f64 = 0.5;
+const FONT_SCALE_MAX: f64 = 3.0;
+pub(super) const FONT_SCALE_STEP: f64 = 0.1;
+
+/// CSS applying the Pango font description `font`, zoomed by `scale`, to the
+/// editor view. An empty description keeps the system monospace font.
+fn editor_font_css(font: &str, scale: f64) -> String {
+    use gtk::glib::translate::IntoGlib;
+
+    let zoom = if scale == 1.0 {
+        String::new()
+    } else {
+        format!("font-size: {}%;", (scale * 100.0).round())
+    };
+    if font.is_empty() {
+        if zoom.is_empty() {
+            return String::new();
+        }
+        return format!(".{EDITOR_CSS_CLASS} {{ {zoom} }}");
+    }
+    let desc = gtk::pango::FontDescription::from_string(font);
+    let mut rules = Vec::new();
+    if let Some(family) = desc.family() {
+        rules.push(format!("font-family: \"{}\";", family.replace('"', "\\\"")));
+    }
+    if desc.size() > 0 {
+        let size = desc.size() as f64 / gtk::pango::SCALE as f64 * scale;
+        let unit = if desc.is_size_absolute() { "px" } else { "pt" };
+        rules.push(format!("font-size: {size:.1}{unit};"));
+    } else if !zoom.is_empty() {
+        rules.push(zoom);
+    }
+    rules.push(format!("font-weight: {};", desc.weight().into_glib()));
+    if desc.style() == gtk::pango::Style::Italic {
+        rules.push("font-style: italic;".to_string());
+    }
+    format!(".{EDITOR_CSS_CLASS} {{ {} }}", rules.join(" "))
+}
+
+/// Suggestion colors used until one is picked in Preferences
+const GHOST_COLOR_LIGHT: &str = "#707070";
+const GHOST_COLOR_DARK: &str = "#9a9a9a";
@@ -0,0 +1,256 @@
+//! Export the current tab as a standalone HTML page. Markdown is rendered;
+//! anything else is kept as preformatted text in the style scheme's colors.
+
+use std::fs;
+use std::path::Path;
+use std::rc::Rc;
+
+use anyhow::{Context, Result};
+use gtk4::{self as gtk, glib::translate::IntoGlib, prelude::*};
+use sourceview5::prelude::*;
+
+use crate::document::derive_display_name;
+use crate::markdown;
+
+use super::window::AppState;
+
+impl AppState {
+    /// Ask where to write the HTML, suggesting the document's name
+    pub(super) fn export_html_dialog(self: &Rc<Self>) {
+        let dialog = gtk::FileChooserDialog::builder()
+            .title("Export as HTML")
+            .transient_for(&self.window())
+            .modal(true)
+            .action(gtk::FileChooserAction::Save)
+            .build();
+        dialog.add_button("Cancel", gtk::ResponseType::Cancel);
+        dialog.add_button("Export", gtk::ResponseType::Accept);
+        let filter = gtk::FileFilter::new();
+        filter.set_name(Some("HTML"));
+        filter.add_mime_type("text/html");
+        filter.add_pattern("*.html");
+        dialog.add_filter(&filter);
+        let path = self.tab().file_path.borrow().clone();
+        dialog.set_current_name(&html_file_name(path.as_deref()));
+
+        let weak = Rc::downgrade(self);
+        dialog.connect_response(move |dialog, response| {
+            if response == gtk::ResponseType::Accept {
+                if let (Some(state), Some(path)) =
+                    (weak.upgrade(), dialog.file().and_then(|file| file.path()))
+                {
+                    match state.export_html(&path) {
+                        Ok(()) => state.show_toast(&format!(
+                            "Exported to {}",
+                            derive_display_name(&Some(path))
+                        )),
+                        Err(err) => state.present_error("Failed to export", &err.to_string()),
+                    }
+                }
+            }
+            dialog.close();
+        });
+        dialog.show();
+    }
+
+    /// Write the current tab to `path` as HTML. The buffer is only read; a
+    /// showing suggestion stays but isn't exported.
+    fn export_html(&self, path: &Path) -> Result<()> {
+        let tab = self.tab();
+        let file_path = tab.file_path.borrow().clone();
+        let title = derive_display_name(&file_path);
+
+        let colors = scheme_colors(&tab.buffer);
+        let body = if file_path.as_deref().is_some_and(is_markdown) {
+            markdown::to_html(&tab.document.text_without_ghost())
+        } else {
+            // Search matches and the matching bracket are tags too; switch
+            // them off so only the syntax colors are left
+            let search_highlight = tab.search_context.is_highlight();
+            let bracket_highlight = tab.buffer.is_highlight_matching_brackets();
+            tab.search_context.set_highlight(false);
+            tab.buffer.set_highlight_matching_brackets(false);
+            let body = highlighted_pre(&tab.buffer, &tab.document.ghost_tag());
+            tab.search_context.set_highlight(search_highlight);
+            tab.buffer
+                .set_highlight_matching_brackets(bracket_highlight);
+            body
+        };
+        fs::write(path, html_page(&title, &colors, &body))
+            .with_context(|| format!("Failed to write {}", path.display()))
+    }
+}
+
+/// The page's foreground and background, from the scheme's text style
+#[derive(Debug, Default)]
+struct PageColors {
+    foreground: Option<String>,
+    background: Option<String>,
+}
+
+fn scheme_colors(buffer: &sourceview5::Buffer) -> PageColors {
+    let Some(style) = buffer
+        .style_scheme()
+        .and_then(|scheme| scheme.style("text"))
+    else {
+        return PageColors::default();
+    };
+    let color = |name: &str| {
+        style
+            .property::<bool>(&format!("{name}-set"))
+            .then(|| style.property::<Option<String>>(name))
+            .flatten()
+    };
+    PageColors {
+        foreground: color("foreground"),
+        background: color("background"),
+    }
+}
+
+/// The buffer as a `<pre>` block, with the colors and weights its syntax
+/// highlighting tags apply written inline. Text tagged `ghost` is left out.
+fn highlighted_pre(buffer: &sourceview5::Buffer, ghost: &gtk::TextTag) -> String {
+    let (start, end) = buffer.bounds();
+    buffer.ensure_highlight(&start, &end);
+
+    let mut out = String::from("<pre>");
+    let mut iter = start;
+    while iter < end {
+        let mut next = iter;
+        next.forward_to_tag_toggle(None::<&gtk::TextTag>);
+        if iter.has_tag(ghost) {
+            iter = next;
+            continue;
+        }
+        let text = markdown::escape(&buffer.text(&iter, &next, false));
+        let style = tag_css(&iter.tags());
+        if style.is_empty() {
+            out.push_str(&text);
+        } else {
+            out.push_str(&format!("<span style=\"{style}\">{text}</span>"));
+        }
+        iter = next;
+    }
+    out.push_str("</pre>\n");
+    out
+}
+
+/// Inline CSS for `tags`, in priority order so later tags win. Only the
+/// highlighting engine's tags count; those are unnamed, while the editor's
+/// own tags (the append-only lock, a pending rewrite) all have names.
+fn tag_css(tags: &[gtk::TextTag]) -> String {
+    let mut color = None;
+    let mut background = None;
+    let mut bold = None;
+    let mut italic = None;
+    for tag in tags.iter().filter(|tag| tag.name().is_none()) {
+        if tag.is_foreground_set() {
+            color = tag.foreground_rgba();
+        }
+        if tag.is_background_set() {
+            background = tag.background_rgba();
+        }
+        if tag.is_weight_set() {
+            bold = Some(tag.weight() >= gtk::pango::Weight::Bold.into_glib());
+        }
+        if tag.is_style_set() {
+            italic = Some(tag.style() == gtk::pango::Style::Italic);
+        }
+    }
+
+    let mut css = Vec::new();
+    if let Some(color) = color {
+        css.push(format!("color: {color}"));
+    }
+    if let Some(background) = background {
+        css.push(format!("background-color: {background}"));
+    }
+    if bold == Some(true) {
+        css.push("font-weight: bold".to_string());
+    }
+    if italic == Some(true) {
+        css.push("font-style: italic".to_string());
+    }
+    css.join("; ")
+}
+
+fn is_markdown(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| matches!(ext.to_lowercase().as_str(), "md" | "markdown"))
+}
+
+/// `notes.md` becomes `notes.html`; an untitled document is `Untitled.html`
+fn html_file_name(path: Option<&Path>) -> String {
+    let stem = path
+        .and_then(|path| path.file_stem())
+        .and_then(|stem| stem.to_str())
+        .unwrap_or("Untitled");
+    format!("{stem}.html")
+}
+
+fn html_page(title: &str, colors: &PageColors, body: &str) -> String {
+    let mut style = String::from("body { max-width: 50em; margin: 2em auto; padding: 0 1em;");
+    if let Some(foreground) = &colors.foreground {
+        style.push_str(&format!(" color: {foreground};"));
+    }
+    if let Some(background) = &colors.background {
+        style.push_str(&format!(" background-color: {background};"));
+    }
+    style.push_str(" }\npre { white-space: pre-wrap; }");
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+         <title>{}</title>\n<style>\n{style}\n</style>\n</head>\n<body>\n{body}</body>\n</html>\n",
+        markdown::escape(title)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_html_file_name() {
+        assert_eq!(
+            html_file_name(Some(Path::new("/tmp/notes.md"))),
+            "notes.html"
+        );
+        assert_eq!(
+            html_file_name(Some(Path::new("/tmp/Makefile"))),
+            "Makefile.html"
+        );
+        assert_eq!(html_file_name(None), "Untitled.html");
+    }
+
+    #[test]
+    fn test_tag_css_ignores_named_tags() {
+        if gtk4::init().is_err() {
+            eprintln!("Skipping GTK test due to missing display");
+            return;
+        }
+        let syntax = gtk::TextTag::builder()
+            .foreground("#ff0000")
+            .weight(gtk::pango::Weight::Bold.into_glib())
+            .build();
+        let ghost = gtk::TextTag::builder()
+            .name("llm-ghost")
+            .foreground("#888888")
+            .style(gtk::pango::Style::Italic)
+            .build();
+        let css = tag_css(&[syntax, ghost]);
+        assert!(css.starts_with("color: rgb(255,0,0)"));
+        assert!(css.ends_with("font-weight: bold"));
+    }
+
+    #[test]
+    fn test_html_page_escapes_title_and_applies_colors() {
+        let colors = PageColors {
+            foreground: Some("#000000".to_string()),
+            background: Some("#ffffff".to_string()),
+        };
+        let page = html_page("a<b", &colors, "<p>hi</p>\n");
+        assert!(page.contains("<title>a&lt;b</title>"));
+        assert!(page.contains("color: #000000; background-color: #ffffff;"));
+        assert!(page.contains("<body>\n<p>hi</p>\n</body>"));
+    }
+}
@@ -0,0 +1,174 @@
+//! Watching the open files for changes made by other programs
+
+use std::fs;
+use std::rc::Rc;
+
+use gtk4::{self as gtk, gio, glib, prelude::*};
+
+use super::recent;
+use super::tabs::TabState;
+use super::window::AppState;
+
+impl AppState {
+    pub(super) fn watch_active_file(self: &Rc<Self>) {
+        self.watch_file(&self.tab());
+    }
+
+    pub(super) fn watch_file(self: &Rc<Self>, tab: &Rc<TabState>) {
+        tab.file_monitor.borrow_mut().take();
+        tab.external_change_pending.set(false);
+        tab.paused_monitor_mtime.borrow_mut().take();
+        if let Some(path) = tab.file_path.borrow().clone() {
+            // Watch the real target so symlinked files report changes to the file itself
+            let file = gio::File::for_path(recent::canonical_path(&path));
+            match file.monitor_file(gio::FileMonitorFlags::NONE, None::<&gio::Cancellable>) {
+                Ok(monitor) => {
+                    let weak = Rc::downgrade(self);
+                    let weak_tab = Rc::downgrade(tab);
+                    monitor.connect_changed(move |_, _, _, event| {
+                        if matches!(
+                            event,
+                            gio::FileMonitorEvent::Changed
+                                | gio::FileMonitorEvent::ChangesDoneHint
+                                | gio::FileMonitorEvent::Deleted
+                        ) {
+                            if let (Some(state), Some(tab)) = (weak.upgrade(), weak_tab.upgrade()) {
+                                state.handle_external_change(&tab);
+                            }
+                        }
+                    });
+                    tab.file_monitor.replace(Some(monitor));
+                }
+                Err(err) => log::warn!("Failed to watch file: {err:?}"),
+            }
+        }
+    }
+
+    /// Drop the monitors once the window has been in the background for a while,
+    /// remembering each file's modification time to compare on resume.
+    pub(super) fn schedule_monitor_pause(self: &Rc<Self>) {
+        self.cancel_monitor_pause();
+        if !self.settings.borrow().pause_monitor_in_background {
+            return;
+        }
+        let weak = Rc::downgrade(self);
+        let source = glib::timeout_add_seconds_local_once(MONITOR_IDLE_SECS, move || {
+            let Some(state) = weak.upgrade() else {
+                return;
+            };
+            state.monitor_pause_source.borrow_mut().take();
+            if state.window().is_active() {
+                return;
+            }
+            log::debug!("Window idle in background, pausing file monitors");
+            for tab in state.tabs() {
+                if tab.file_monitor.borrow_mut().take().is_none() {
+                    continue;
+                }
+                let mtime = tab
+                    .file_path
+                    .borrow()
+                    .as_ref()
+                    .and_then(|path| fs::metadata(path).and_then(|meta| meta.modified()).ok());
+                tab.paused_monitor_mtime.replace(Some(mtime));
+            }
+        });
+        self.monitor_pause_source.borrow_mut().replace(source);
+    }
+
+    /// Re-arm paused monitors and report changes that happened while they were off.
+    pub(super) fn resume_file_monitor(self: &Rc<Self>) {
+        self.cancel_monitor_pause();
+        for tab in self.tabs() {
+            let Some(paused_mtime) = tab.paused_monitor_mtime.borrow_mut().take() else {
+                continue;
+            };
+            let current_mtime = tab
+                .file_path
+                .borrow()
+                .as_ref()
+                .and_then(|path| fs::metadata(path).and_then(|meta| meta.modified()).ok());
+            self.watch_file(&tab);
+            if current_mtime != paused_mtime {
+                log::info!("File changed while its monitor was paused");
+                self.handle_external_change(&tab);
+            }
+        }
+    }
+
+    fn cancel_monitor_pause(&self) {
+        if let Some(source) = self.monitor_pause_source.borrow_mut().take() {
+            let _ = source.remove();
+        }
+    }
+
+    pub(super) fn stop_file_monitor(&self) {
+        let tab = self.tab();
+        tab.file_monitor.borrow_mut().take();
+        tab.external_change_pending.set(false);
+    }
+
+    fn handle_external_change(self: &Rc<Self>, tab: &Rc<TabState>) {
+        if tab.external_change_pending.replace(true) {
+            return;
+        }
+        // Show the tab the question is about
+        self.select_tab(tab);
+        let weak = Rc::downgrade(self);
+        let weak_tab = Rc::downgrade(tab);
+        let dialog = gtk::MessageDialog::builder()
+            .transient_for(&self.window())
+            .modal(true)
+            .text("File changed on disk")
+            .secondary_text("The file was modified outside Wispnote. Reload it?")
+            .build();
+        dialog.add_button("Keep My Changes", gtk::ResponseType::Cancel);
+        dialog.add_button("Reload", gtk::ResponseType::Accept);
+        dialog.connect_response(move |dialog: &gtk::MessageDialog, response| {
+            if let (Some(state), Some(tab)) = (weak.upgrade(), weak_tab.upgrade()) {
+                if response == gtk::ResponseType::Accept {
+                    state.reload_from_disk(&tab);
+                } else {
+                    tab.external_change_pending.set(false);
+                }
+            }
+            dialog.close();
+        });
+        dialog.show();
+    }
+
+    fn reload_from_disk(self: &Rc<Self>, tab: &Rc<TabState>) {
+        self.select_tab(tab);
+        if let Some(path) = tab.file_path.borrow().clone() {
+            match tab.document.load_from_path(&path) {
+                Ok(_) => {
+                    tab.buffer.set_modified(false);
+                    self.reset_append_lock();
+                    self.detect_long_lines();
+                    self.update_title();
+                    self.status_label.set_text("Reloaded from disk");
+                    self.watch_file(tab);
+                }
+                Err(err) => self.present_error("Failed to reload", &err.to_string()),
+            }
+        }
+        tab.external_change_pending.set(false);
+    }
+
+    pub(super) fn set_pause_monitor_in_background(self: &Rc<Self>, pause: bool) {
+        {
+            let mut settings = self.settings.borrow_mut();
+            if settings.pause_monitor_in_background == pause {
+                return;
+            }
+            settings.pause_monitor_in_background = pause;
+        }
+        self.save_settings();
+        if !pause {
+            self.resume_file_monitor();
+        }
+    }
+}
+
+/// Seconds in the background before a window's file monitor is paused
+const MONITOR_IDLE_SECS: u32 = 60;
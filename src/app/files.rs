@@ -0,0 +1,339 @@
+//! Opening and saving files, and the file format controls in the status bar
+
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use adw::prelude::*;
+use gtk4::{self as gtk, gio, glib, prelude::*};
+use libadwaita as adw;
+
+use crate::document::LineEnding;
+
+use super::recent;
+use super::window::AppState;
+
+impl AppState {
+    pub(super) fn open_document_dialog(self: &Rc<Self>) {
+        let dialog = gtk::FileChooserDialog::builder()
+            .title("Open File")
+            .transient_for(&self.window())
+            .modal(true)
+            .action(gtk::FileChooserAction::Open)
+            .build();
+        dialog.add_button("Cancel", gtk::ResponseType::Cancel);
+        dialog.add_button("Open", gtk::ResponseType::Accept);
+        Self::attach_file_filters(&dialog);
+        let weak = Rc::downgrade(self);
+        dialog.connect_response(move |dialog, response| {
+            if response == gtk::ResponseType::Accept {
+                if let Some(state) = weak.upgrade() {
+                    if let Some(file) = dialog.file() {
+                        if let Some(path) = file.path() {
+                            state.open_path(&path);
+                        } else {
+                            state.present_error(
+                                "Unsupported file",
+                                "Location is not on the local filesystem",
+                            );
+                        }
+                    }
+                }
+            }
+            dialog.close();
+        });
+        dialog.show();
+    }
+
+    pub(super) fn save_action(self: &Rc<Self>) {
+        if self.tab().file_path.borrow().is_some() {
+            if let Err(err) = self.write_current_file() {
+                self.present_error("Save failed", &err.to_string());
+            }
+        } else {
+            self.save_as_dialog();
+        }
+    }
+
+    fn write_current_file(self: &Rc<Self>) -> anyhow::Result<()> {
+        let tab = self.tab();
+        let path = tab
+            .file_path
+            .borrow()
+            .clone()
+            .ok_or_else(|| anyhow::anyhow!("No file selected"))?;
+        tab.document.save_to_path(&path, self.save_cleanup())?;
+        self.remove_autosave_artifacts(&tab);
+        self.record_recent_file(&path);
+        self.watch_active_file();
+        self.update_title();
+        Ok(())
+    }
+
+    pub(super) fn save_as_dialog(self: &Rc<Self>) {
+        let dialog = gtk::FileChooserDialog::builder()
+            .title("Save File As")
+            .transient_for(&self.window())
+            .modal(true)
+            .action(gtk::FileChooserAction::Save)
+            .build();
+        dialog.add_button("Cancel", gtk::ResponseType::Cancel);
+        dialog.add_button("Save", gtk::ResponseType::Accept);
+        Self::attach_file_filters(&dialog);
+        let weak = Rc::downgrade(self);
+        dialog.connect_response(move |dialog, response| {
+            if response == gtk::ResponseType::Accept {
+                if let Some(state) = weak.upgrade() {
+                    if let Some(file) = dialog.file() {
+                        if let Some(path) = file.path() {
+                            state.confirm_overwrite_then(path);
+                        } else {
+                            state.present_error(
+                                "Unsupported file",
+                                "Location is not on the local filesystem",
+                            );
+                        }
+                    }
+                }
+            }
+            dialog.close();
+        });
+        dialog.show();
+    }
+
+    /// Save to `path` chosen in Save As, asking first if that would replace a
+    /// different existing file. Some portal choosers don't confirm on their own.
+    fn confirm_overwrite_then(self: &Rc<Self>, path: PathBuf) {
+        let is_current = self
+            .tab()
+            .file_path
+            .borrow()
+            .as_ref()
+            .is_some_and(|current| {
+                recent::canonical_path(current) == recent::canonical_path(&path)
+            });
+        if !self.settings.borrow().confirm_overwrite || !path.exists() || is_current {
+            self.save_to_new_path(&path);
+            return;
+        }
+
+        let name = path
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_else(|| path.display().to_string());
+        let dialog = gtk::MessageDialog::builder()
+            .transient_for(&self.window())
+            .modal(true)
+            .text(format!("Replace “{}”?", name))
+            .secondary_text(
+                "A file with this name already exists. Replacing it will overwrite its contents.",
+            )
+            .build();
+        dialog.add_button("Cancel", gtk::ResponseType::Cancel);
+        let replace = dialog.add_button("Replace", gtk::ResponseType::Accept);
+        replace.add_css_class("destructive-action");
+        let weak = Rc::downgrade(self);
+        dialog.connect_response(move |dialog, response| {
+            if response == gtk::ResponseType::Accept {
+                if let Some(state) = weak.upgrade() {
+                    state.save_to_new_path(&path);
+                }
+            }
+            dialog.close();
+        });
+        dialog.show();
+    }
+
+    fn save_to_new_path(self: &Rc<Self>, path: &Path) {
+        let tab = self.tab();
+        match tab.document.save_to_path(path, self.save_cleanup()) {
+            Ok(_) => {
+                tab.file_path.replace(Some(path.to_path_buf()));
+                self.write_session_lock(&tab);
+                self.detect_language(&tab);
+                self.remove_autosave_artifacts(&tab);
+                self.record_recent_file(path);
+                self.watch_active_file();
+                self.update_title();
+                self.run_autosave();
+            }
+            Err(err) => self.present_error("Failed to save", &err.to_string()),
+        }
+    }
+
+    /// Switch the document between LF and CRLF line endings
+    pub(super) fn toggle_line_ending(&self) {
+        let line_ending = match self.tab().document.line_ending() {
+            LineEnding::Lf => LineEnding::CrLf,
+            LineEnding::CrLf => LineEnding::Lf,
+        };
+        self.tab().document.set_line_ending(line_ending);
+        self.update_title();
+    }
+
+    /// Popover listing encodings: picking one converts the document to it
+    /// on the next save, the reopen button re-reads the file as it instead
+    pub(super) fn show_encoding_menu(self: &Rc<Self>) {
+        let list = gtk::ListBox::builder()
+            .selection_mode(gtk::SelectionMode::None)
+            .css_classes(["boxed-list"])
+            .build();
+        let can_reopen = self.tab().file_path.borrow().is_some();
+        let popover = gtk::Popover::builder().build();
+        for &encoding in ENCODINGS {
+            let item = adw::ActionRow::builder()
+                .title(encoding.name())
+                .activatable(true)
+                .build();
+            if encoding == self.tab().document.encoding() {
+                item.add_prefix(&gtk::Image::from_icon_name("object-select-symbolic"));
+            }
+            let reopen = gtk::Button::builder()
+                .icon_name("document-revert-symbolic")
+                .tooltip_text("Reopen the File With This Encoding")
+                .valign(gtk::Align::Center)
+                .sensitive(can_reopen)
+                .css_classes(["flat"])
+                .build();
+            let weak = Rc::downgrade(self);
+            let weak_popover = popover.downgrade();
+            reopen.connect_clicked(move |_| {
+                if let Some(popover) = weak_popover.upgrade() {
+                    popover.popdown();
+                }
+                if let Some(state) = weak.upgrade() {
+                    state.reopen_with_encoding(encoding);
+                }
+            });
+            item.add_suffix(&reopen);
+            list.append(&item);
+        }
+        let scroller = gtk::ScrolledWindow::builder()
+            .child(&list)
+            .hscrollbar_policy(gtk::PolicyType::Never)
+            .min_content_width(240)
+            .max_content_height(360)
+            .propagate_natural_height(true)
+            .build();
+        popover.set_child(Some(&scroller));
+        popover.set_parent(&self.encoding_button);
+        popover.connect_closed(|popover| {
+            // Unparenting inside the signal handler upsets GTK; wait until it's done
+            let popover = popover.clone();
+            glib::idle_add_local_once(move || popover.unparent());
+        });
+
+        let weak = Rc::downgrade(self);
+        let weak_popover = popover.downgrade();
+        list.connect_row_activated(move |_, item| {
+            if let (Some(state), Some(encoding)) = (
+                weak.upgrade(),
+                ENCODINGS.get(item.index() as usize).copied(),
+            ) {
+                state.tab().document.set_encoding(encoding);
+                state.update_title();
+            }
+            if let Some(popover) = weak_popover.upgrade() {
+                popover.popdown();
+            }
+        });
+        popover.popup();
+    }
+
+    /// Re-read the open file as `encoding`, for when detection guessed wrong
+    fn reopen_with_encoding(self: &Rc<Self>, encoding: &'static encoding_rs::Encoding) {
+        let Some(path) = self.tab().file_path.borrow().clone() else {
+            return;
+        };
+        if self.tab().buffer.is_modified() {
+            self.show_toast("Save or undo your changes before reopening the file");
+            return;
+        }
+        match self
+            .tab()
+            .document
+            .load_from_path_with(&path, Some(encoding))
+        {
+            Ok(_) => {
+                self.reset_append_lock();
+                self.detect_long_lines();
+                self.update_title();
+                self.status_label
+                    .set_text(&format!("Reopened as {}", encoding.name()));
+            }
+            Err(err) => self.present_error("Failed to reopen", &format!("{err:#}")),
+        }
+    }
+
+    /// The current tab's file, made absolute. Untitled documents have none,
+    /// which is said with a toast.
+    fn current_file_path(&self) -> Option<PathBuf> {
+        let path = self.tab().file_path.borrow().clone();
+        if path.is_none() {
+            self.show_toast("This document hasn't been saved to a file yet");
+        }
+        path.map(|path| recent::canonical_path(&path))
+    }
+
+    pub(super) fn copy_file_path(&self) {
+        if let Some(path) = self.current_file_path() {
+            self.window()
+                .clipboard()
+                .set_text(&path.display().to_string());
+            self.show_toast("Path copied");
+        }
+    }
+
+    /// Show the folder the current file is in, in the default file manager
+    pub(super) fn open_containing_folder(&self) {
+        let Some(folder) = self
+            .current_file_path()
+            .and_then(|path| path.parent().map(Path::to_path_buf))
+        else {
+            return;
+        };
+        let uri = gio::File::for_path(&folder).uri();
+        let context = self.window().display().app_launch_context();
+        if let Err(err) = gio::AppInfo::launch_default_for_uri(&uri, Some(&context)) {
+            self.present_error("Failed to open folder", &err.to_string());
+        }
+    }
+
+    fn attach_file_filters(dialog: &gtk::FileChooserDialog) {
+        let text_filter = gtk::FileFilter::new();
+        text_filter.set_name(Some("Text files"));
+        text_filter.add_mime_type("text/plain");
+        text_filter.add_pattern("*.txt");
+        dialog.add_filter(&text_filter);
+
+        let md_filter = gtk::FileFilter::new();
+        md_filter.set_name(Some("Markdown"));
+        md_filter.add_mime_type("text/markdown");
+        md_filter.add_pattern("*.md");
+        md_filter.add_pattern("*.markdown");
+        dialog.add_filter(&md_filter);
+
+        let all_filter = gtk::FileFilter::new();
+        all_filter.set_name(Some("All files"));
+        all_filter.add_pattern("*");
+        dialog.add_filter(&all_filter);
+        dialog.set_filter(&text_filter);
+    }
+}
+
+/// Encodings offered in the status bar, most common first. The `_INIT`
+/// values are used because a static can't read the `UTF_8`-style statics.
+static ENCODINGS: &[&encoding_rs::Encoding] = &[
+    &encoding_rs::UTF_8_INIT,
+    &encoding_rs::UTF_16LE_INIT,
+    &encoding_rs::UTF_16BE_INIT,
+    &encoding_rs::WINDOWS_1252_INIT,
+    &encoding_rs::ISO_8859_15_INIT,
+    &encoding_rs::ISO_8859_2_INIT,
+    &encoding_rs::WINDOWS_1251_INIT,
+    &encoding_rs::KOI8_R_INIT,
+    &encoding_rs::SHIFT_JIS_INIT,
+    &encoding_rs::EUC_JP_INIT,
+    &encoding_rs::GBK_INIT,
+    &encoding_rs::BIG5_INIT,
+    &encoding_rs::EUC_KR_INIT,
+];
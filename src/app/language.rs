@@ -0,0 +1,236 @@
+//! Syntax highlighting language of each tab: guessed from the file name and
+//! contents, or picked from the status bar. A pick is remembered per file.
+
+use std::path::Path;
+use std::rc::Rc;
+
+use gtk4::{self as gtk, gio, glib, prelude::*};
+use libadwaita as adw;
+use sourceview5::prelude::*;
+
+use crate::state_store::WindowState;
+
+use super::recent;
+use super::tabs::TabState;
+use super::window::AppState;
+
+/// Language id stored for files picked as plain text
+const PLAIN_TEXT: &str = "";
+
+/// How much of the start of a document content sniffing looks at
+const SNIFF_CHARS: i32 = 4096;
+
+/// Guess from the file name, falling back to the contents (a shebang line,
+/// say) for files without a telling extension.
+fn guess_language(path: Option<&Path>, head: &str) -> Option<sourceview5::Language> {
+    let (content_type, _) = gio::content_type_guess(path, head.as_bytes());
+    sourceview5::LanguageManager::default().guess_language(path, Some(&content_type))
+}
+
+fn language_by_id(id: &str) -> Option<sourceview5::Language> {
+    if id == PLAIN_TEXT {
+        return None;
+    }
+    sourceview5::LanguageManager::default().language(id)
+}
+
+fn override_key(path: &Path) -> String {
+    recent::canonical_path(path).display().to_string()
+}
+
+impl AppState {
+    /// Pick `tab`'s language again after its file changed: the user's choice
+    /// for the file if there is one, a guess otherwise.
+    pub(super) fn detect_language(&self, tab: &TabState) {
+        let path = tab.file_path.borrow().clone();
+        let chosen = path.as_deref().and_then(|path| {
+            self.window_state
+                .borrow()
+                .language_overrides
+                .get(&override_key(path))
+                .cloned()
+        });
+        let language = match chosen {
+            Some(id) => language_by_id(&id),
+            None => {
+                let start = tab.buffer.start_iter();
+                let end = tab.buffer.iter_at_offset(SNIFF_CHARS);
+                guess_language(path.as_deref(), &tab.buffer.text(&start, &end, false))
+            }
+        };
+        tab.buffer.set_language(language.as_ref());
+        self.update_language_button();
+    }
+
+    pub(super) fn update_language_button(&self) {
+        let name = self
+            .tab()
+            .buffer
+            .language()
+            .map(|language| language.name().to_string());
+        self.language_button
+            .set_label(name.as_deref().unwrap_or("Plain Text"));
+    }
+
+    /// Use `id` for the current tab, or go back to guessing with `None`. For a
+    /// saved file the choice is stored so it applies when it is opened again.
+    fn choose_language(&self, id: Option<&str>) {
+        let tab = self.tab();
+        let path = tab.file_path.borrow().clone();
+        match (path, id) {
+            (Some(path), _) => {
+                {
+                    let key = override_key(&path);
+                    let mut store = self.window_state.borrow_mut();
+                    // Other windows store their choices too; start from what is on disk
+                    if let Ok(saved) = WindowState::load(&self.paths) {
+                        store.language_overrides = saved.language_overrides;
+                    }
+                    match id {
+                        Some(id) => store.language_overrides.insert(key, id.to_string()),
+                        None => store.language_overrides.remove(&key),
+                    };
+                    if let Err(err) = store.save(&self.paths) {
+                        log::warn!("Failed to save window state: {err:?}");
+                    }
+                }
+                self.detect_language(&tab);
+            }
+            // Nothing to remember an untitled document's choice by
+            (None, Some(id)) => {
+                tab.buffer.set_language(language_by_id(id).as_ref());
+                self.update_language_button();
+            }
+            (None, None) => self.detect_language(&tab),
+        }
+    }
+
+    pub(super) fn show_language_menu(self: &Rc<Self>) {
+        let manager = sourceview5::LanguageManager::default();
+        let mut languages: Vec<sourceview5::Language> = manager
+            .language_ids()
+            .iter()
+            .filter_map(|id| manager.language(id))
+            .filter(|language| !language.is_hidden())
+            .collect();
+        languages.sort_by_key(|language| language.name().to_lowercase());
+
+        let tab = self.tab();
+        let current = tab.buffer.language().map(|language| language.id());
+        let automatic = tab.file_path.borrow().as_deref().is_none_or(|path| {
+            !self
+                .window_state
+                .borrow()
+                .language_overrides
+                .contains_key(&override_key(path))
+        });
+
+        // What each row picks, in list order
+        let mut choices: Vec<(Option<String>, String)> = vec![
+            (None, "Automatic".to_string()),
+            (Some(PLAIN_TEXT.to_string()), "Plain Text".to_string()),
+        ];
+        choices.extend(
+            languages
+                .iter()
+                .map(|language| (Some(language.id().to_string()), language.name().to_string())),
+        );
+
+        let list = gtk::ListBox::builder()
+            .selection_mode(gtk::SelectionMode::None)
+            .css_classes(["boxed-list"])
+            .build();
+        for (id, name) in &choices {
+            let item = adw::ActionRow::builder()
+                .title(name.as_str())
+                .activatable(true)
+                .build();
+            let selected = match id {
+                None => automatic,
+                Some(_) if automatic => false,
+                Some(id) if id == PLAIN_TEXT => current.is_none(),
+                Some(id) => current.as_deref() == Some(id.as_str()),
+            };
+            if selected {
+                item.add_prefix(&gtk::Image::from_icon_name("object-select-symbolic"));
+            }
+            list.append(&item);
+        }
+
+        let entry = gtk::SearchEntry::builder()
+            .placeholder_text("Search languages…")
+            .build();
+        {
+            let entry = entry.clone();
+            list.set_filter_func(move |row| {
+                let query = entry.text().to_lowercase();
+                row.downcast_ref::<adw::ActionRow>()
+                    .is_some_and(|row| row.title().to_lowercase().contains(&query))
+            });
+        }
+        {
+            let list = list.clone();
+            entry.connect_search_changed(move |_| list.invalidate_filter());
+        }
+
+        let scroller = gtk::ScrolledWindow::builder()
+            .child(&list)
+            .hscrollbar_policy(gtk::PolicyType::Never)
+            .min_content_width(240)
+            .max_content_height(360)
+            .propagate_natural_height(true)
+            .build();
+        let content = gtk::Box::builder()
+            .orientation(gtk::Orientation::Vertical)
+            .spacing(6)
+            .build();
+        content.append(&entry);
+        content.append(&scroller);
+
+        let popover = gtk::Popover::builder().child(&content).build();
+        popover.set_parent(&self.language_button);
+        popover.connect_closed(|popover| {
+            // Unparenting inside the signal handler upsets GTK; wait until it's done
+            let popover = popover.clone();
+            glib::idle_add_local_once(move || popover.unparent());
+        });
+
+        let weak = Rc::downgrade(self);
+        let weak_popover = popover.downgrade();
+        list.connect_row_activated(move |_, item| {
+            let choice = usize::try_from(item.index())
+                .ok()
+                .and_then(|index| choices.get(index));
+            if let (Some(state), Some((id, _))) = (weak.upgrade(), choice) {
+                state.choose_language(id.as_deref());
+            }
+            if let Some(popover) = weak_popover.upgrade() {
+                popover.popdown();
+            }
+        });
+        popover.popup();
+        entry.grab_focus();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_guess_language() {
+        if gtk4::init().is_err() {
+            eprintln!("Skipping GTK test due to missing display");
+            return;
+        }
+        let language = |path: Option<&str>, head: &str| {
+            guess_language(path.map(Path::new), head).map(|language| language.id().to_string())
+        };
+        assert_eq!(language(Some("main.rs"), ""), Some("rust".to_string()));
+        assert_eq!(
+            language(Some("build"), "#!/bin/sh\necho hi\n"),
+            Some("sh".to_string())
+        );
+        assert!(language_by_id(PLAIN_TEXT).is_none());
+    }
+}
@@ -0,0 +1,1630 @@
+//! LLM preferences and checking that the configured model is usable
+
+use std::rc::Rc;
+use std::sync::mpsc;
+
+use adw::prelude::*;
+use gtk4::glib::{self, ControlFlow, Propagation};
+use gtk4::{self as gtk, prelude::*};
+use libadwaita as adw;
+
+use crate::llm::{
+    CompletionProfile, CompletionScope, ContextStrategy, FimTemplate, LlmManager, LlmReadiness,
+    LlmSettings, ProviderKind,
+};
+use crate::secrets;
+
+use super::preferences;
+use super::window::AppState;
+
+impl AppState {
+    pub(super) fn sync_llm_preferences(&self) {
+        let (
+            provider,
+            idx,
+            endpoint,
+            remote_model,
+            override_model,
+            model_path,
+            gpu_idx,
+            gpu_model,
+            cpu_model,
+            max_tokens,
+            completion_scope,
+        ) = {
+            let settings = self.settings.borrow();
+            let provider = settings.llm.provider;
+            let idx = preferences::provider_index(&provider);
+            let endpoint = settings.llm.endpoint.clone();
+            let remote_model = settings.llm.remote_model.clone();
+            let override_model = settings.llm.override_model_path;
+            let model_path = settings.llm.local_model_path.clone();
+            let gpu_idx = if settings.llm.force_cpu_only {
+                0
+            } else if let Some(ref device) = settings.llm.preferred_device {
+                self.gpus
+                    .iter()
+                    .position(|g| &g.id == device)
+                    .map(|i| i + 1)
+                    .unwrap_or(0)
+            } else {
+                0
+            };
+            let gpu_model = settings.llm.default_gpu_model.clone();
+            let cpu_model = settings.llm.default_cpu_model.clone();
+            let max_tokens = settings.llm.max_completion_tokens;
+            let completion_scope = settings.llm.completion_scope;
+            (
+                provider,
+                idx,
+                endpoint,
+                remote_model,
+                override_model,
+                model_path,
+                gpu_idx,
+                gpu_model,
+                cpu_model,
+                max_tokens,
+                completion_scope,
+            )
+        };
+
+        self.preferences.llm_provider_combo.set_selected(idx as u32);
+        self.preferences
+            .llm_endpoint_row
+            .set_visible(provider != ProviderKind::Local);
+        self.preferences.llm_endpoint_row.set_text(&endpoint);
+        self.preferences
+            .remote_model_row
+            .set_visible(provider != ProviderKind::Local);
+        self.preferences.remote_model_row.set_text(&remote_model);
+        self.preferences
+            .test_connection_button
+            .set_visible(provider != ProviderKind::Local);
+        self.preferences
+            .connection_check_row
+            .set_visible(provider != ProviderKind::Local);
+        let check_connection = self.settings.borrow().llm.check_connection_on_startup;
+        self.preferences
+            .connection_check_switch
+            .set_active(check_connection);
+        let api_key = self.settings.borrow().llm.api_key().to_string();
+        self.preferences.api_key_row.set_text(&api_key);
+        let hf_token = self.settings.borrow().llm.hf_token().to_string();
+        self.preferences.hf_token_row.set_text(&hf_token);
+        self.preferences
+            .override_model_switch
+            .set_active(override_model);
+        self.preferences.llm_model_row.set_sensitive(override_model);
+        self.preferences.llm_model_row.set_text(&model_path);
+        self.preferences.gpu_combo.set_selected(gpu_idx as u32);
+        self.preferences.gpu_model_row.set_text(&gpu_model);
+        self.preferences.cpu_model_row.set_text(&cpu_model);
+        self.preferences
+            .max_tokens_spin
+            .set_value(max_tokens as f64);
+        let context_size = self.settings.borrow().llm.context_size;
+        self.preferences
+            .context_size_spin
+            .set_value(context_size as f64);
+        let sampling = self.settings.borrow().llm.sampling();
+        self.preferences
+            .temperature_spin
+            .set_value(sampling.temperature as f64);
+        self.preferences.top_p_spin.set_value(sampling.top_p as f64);
+        self.preferences.top_k_spin.set_value(sampling.top_k as f64);
+        let use_repeat_penalty = self.settings.borrow().llm.use_repeat_penalty;
+        self.preferences
+            .repeat_penalty_switch
+            .set_active(use_repeat_penalty);
+        let repeat_penalty = self.settings.borrow().llm.repeat_penalty;
+        self.preferences
+            .repeat_penalty_spin
+            .set_value(repeat_penalty as f64);
+        let penalty_last_n = self.settings.borrow().llm.penalty_last_n;
+        self.preferences
+            .penalty_last_n_spin
+            .set_value(penalty_last_n as f64);
+        let frequency_penalty = self.settings.borrow().llm.frequency_penalty;
+        self.preferences
+            .frequency_penalty_spin
+            .set_value(frequency_penalty as f64);
+        let presence_penalty = self.settings.borrow().llm.presence_penalty;
+        self.preferences
+            .presence_penalty_spin
+            .set_value(presence_penalty as f64);
+        let seed = self.settings.borrow().llm.seed;
+        self.preferences
+            .seed_row
+            .set_text(&preferences::seed_text(seed));
+        let cache_size = self.settings.borrow().llm.completion_cache_size;
+        self.preferences
+            .cache_size_spin
+            .set_value(cache_size as f64);
+        let latency_target_ms = self.settings.borrow().llm.latency_target_ms;
+        self.preferences
+            .latency_target_spin
+            .set_value(latency_target_ms as f64);
+        let idle_unload_secs = self.settings.borrow().llm.unload_after_idle_secs;
+        self.preferences
+            .idle_unload_spin
+            .set_value((idle_unload_secs / 60) as f64);
+        self.preferences
+            .completion_scope_combo
+            .set_selected(preferences::completion_scope_index(&completion_scope) as u32);
+        let (context_strategy, context_lines) = {
+            let settings = self.settings.borrow();
+            (settings.llm.context_strategy, settings.llm.context_lines)
+        };
+        self.preferences
+            .context_strategy_combo
+            .set_selected(preferences::context_strategy_index(&context_strategy) as u32);
+        self.preferences
+            .context_lines_spin
+            .set_value(context_lines as f64);
+        self.preferences
+            .context_lines_spin
+            .set_sensitive(context_strategy == ContextStrategy::Lines);
+        let (prefix_chars, suffix_chars) = {
+            let settings = self.settings.borrow();
+            (
+                settings.llm.context_prefix_chars,
+                settings.llm.context_suffix_chars,
+            )
+        };
+        self.preferences
+            .context_prefix_spin
+            .set_value(prefix_chars as f64);
+        self.preferences
+            .context_suffix_spin
+            .set_value(suffix_chars as f64);
+        let fim_template = self.settings.borrow().llm.fim_template;
+        self.preferences
+            .fim_template_combo
+            .set_selected(preferences::fim_template_index(&fim_template) as u32);
+        let (completion_profile, stop_at_sentence_end) = {
+            let settings = self.settings.borrow();
+            (
+                settings.llm.completion_profile,
+                settings.llm.stop_at_sentence_end,
+            )
+        };
+        self.preferences
+            .completion_profile_combo
+            .set_selected(preferences::completion_profile_index(&completion_profile) as u32);
+        self.preferences
+            .stop_at_sentence_switch
+            .set_active(stop_at_sentence_end);
+        let include_filename = self.settings.borrow().llm.include_filename;
+        self.preferences
+            .include_filename_switch
+            .set_active(include_filename);
+        let (debounce_ms, max_wait_ms) = {
+            let settings = self.settings.borrow();
+            (
+                settings.llm.completion_debounce_ms,
+                settings.llm.completion_max_wait_ms,
+            )
+        };
+        self.preferences
+            .completion_debounce_spin
+            .set_value(debounce_ms as f64);
+        self.preferences
+            .completion_max_wait_spin
+            .set_value(max_wait_ms as f64);
+        let auto_completion_enabled = self.settings.borrow().llm.auto_completion_enabled;
+        self.preferences
+            .auto_completion_switch
+            .set_active(auto_completion_enabled);
+        let (trigger_only, trigger_chars) = {
+            let settings = self.settings.borrow();
+            (
+                settings.llm.trigger_only,
+                settings.llm.trigger_chars.clone(),
+            )
+        };
+        self.preferences
+            .trigger_only_switch
+            .set_active(trigger_only);
+        self.preferences
+            .trigger_chars_row
+            .set_text(&preferences::escape_trigger_chars(&trigger_chars));
+        self.preferences
+            .trigger_chars_row
+            .set_sensitive(trigger_only);
+        let bulk_insert_chars = self.settings.borrow().llm.bulk_insert_chars;
+        self.preferences
+            .bulk_insert_spin
+            .set_value(bulk_insert_chars as f64);
+        let (gpu_layers_subtitle, can_tune) = {
+            let settings = self.settings.borrow();
+            (
+                preferences::gpu_layers_subtitle(&settings.llm),
+                settings.llm.gpu_tuning_key().is_some(),
+            )
+        };
+        self.preferences
+            .gpu_layers_row
+            .set_subtitle(&gpu_layers_subtitle);
+        let gpu_layers = self.settings.borrow().llm.gpu_layers;
+        self.preferences
+            .gpu_layers_spin
+            .set_value(gpu_layers as f64);
+        self.preferences.gpu_layers_spin.set_sensitive(can_tune);
+        self.preferences.auto_tune_button.set_sensitive(can_tune);
+        let starter_prompt = self.settings.borrow().llm.starter_prompt.clone();
+        self.preferences
+            .starter_prompt_row
+            .set_text(&starter_prompt);
+        let blocklist = self.settings.borrow().llm.completion_blocklist.join("\n");
+        self.preferences
+            .blocklist_view
+            .buffer()
+            .set_text(&blocklist);
+        let regenerate_blocked = self.settings.borrow().llm.regenerate_blocked;
+        self.preferences
+            .regenerate_blocked_switch
+            .set_active(regenerate_blocked);
+        let reveal_ghost_text = self.settings.borrow().llm.reveal_ghost_text;
+        self.preferences
+            .reveal_ghost_switch
+            .set_active(reveal_ghost_text);
+        let dismiss_on_click = self.settings.borrow().llm.dismiss_on_click;
+        self.preferences
+            .dismiss_on_click_switch
+            .set_active(dismiss_on_click);
+        let explain_completions = self.settings.borrow().llm.explain_completions;
+        self.preferences
+            .explain_completions_switch
+            .set_active(explain_completions);
+        self.sync_models_dir_row();
+    }
+
+    pub(super) fn hook_llm_preferences(self: &Rc<Self>) {
+        let weak = Rc::downgrade(self);
+        self.preferences
+            .llm_provider_combo
+            .connect_selected_notify(move |row| {
+                if let Some(state) = weak.upgrade() {
+                    let provider = preferences::provider_from_index(row.selected());
+                    state.update_llm_provider(provider);
+                }
+            });
+
+        let weak = Rc::downgrade(self);
+        self.preferences
+            .llm_endpoint_row
+            .connect_changed(move |entry: &adw::EntryRow| {
+                if let Some(state) = weak.upgrade() {
+                    state.update_llm_endpoint(entry.text().to_string());
+                }
+            });
+
+        let weak = Rc::downgrade(self);
+        self.preferences
+            .remote_model_row
+            .connect_changed(move |entry: &adw::EntryRow| {
+                if let Some(state) = weak.upgrade() {
+                    state.update_remote_model(entry.text().to_string());
+                }
+            });
+
+        let weak = Rc::downgrade(self);
+        self.preferences
+            .test_connection_button
+            .connect_clicked(move |_| {
+                if let Some(state) = weak.upgrade() {
+                    state.test_llm_connection();
+                }
+            });
+
+        let weak = Rc::downgrade(self);
+        self.preferences
+            .connection_check_switch
+            .connect_active_notify(move |switch| {
+                if let Some(state) = weak.upgrade() {
+                    state.update_check_connection_on_startup(switch.is_active());
+                }
+            });
+
+        let state = Rc::clone(self);
+        let weak = Rc::downgrade(self);
+        self.preferences
+            .override_model_switch
+            .connect_state_set(move |_, active| {
+                if let Some(state) = weak.upgrade() {
+                    state.update_override_model(active);
+                }
+                Propagation::Proceed
+            });
+
+        let weak = Rc::downgrade(self);
+        self.preferences
+            .llm_model_row
+            .connect_changed(move |entry: &adw::EntryRow| {
+                if let Some(state) = weak.upgrade() {
+                    state.update_llm_local_model(entry.text().to_string());
+                }
+            });
+
+        let weak = Rc::downgrade(self);
+        self.preferences
+            .gpu_combo
+            .connect_selected_notify(move |row| {
+                if let Some(state) = weak.upgrade() {
+                    let idx = row.selected();
+                    state.update_gpu_selection(idx);
+                }
+            });
+
+        let weak = Rc::downgrade(self);
+        self.preferences
+            .gpu_model_row
+            .connect_changed(move |entry: &adw::EntryRow| {
+                if let Some(state) = weak.upgrade() {
+                    state.update_gpu_model(entry.text().to_string());
+                }
+            });
+
+        let weak = Rc::downgrade(self);
+        self.preferences
+            .cpu_model_row
+            .connect_changed(move |entry: &adw::EntryRow| {
+                if let Some(state) = weak.upgrade() {
+                    state.update_cpu_model(entry.text().to_string());
+                }
+            });
+
+        let weak = Rc::downgrade(self);
+        self.preferences
+            .gpu_download_button
+            .connect_clicked(move |_| {
+                if let Some(state) = weak.upgrade() {
+                    let model_ref = state.preferences.gpu_model_row.text().trim().to_string();
+                    if model_ref.is_empty() {
+                        let toast =
+                            adw::Toast::new("Enter a GPU model reference before downloading.");
+                        toast.set_timeout(6);
+                        state.toast_overlay.add_toast(toast);
+                    } else {
+                        state.download_llm_model(model_ref);
+                    }
+                }
+            });
+
+        let weak = Rc::downgrade(self);
+        self.preferences
+            .cpu_download_button
+            .connect_clicked(move |_| {
+                if let Some(state) = weak.upgrade() {
+                    let model_ref = state.preferences.cpu_model_row.text().trim().to_string();
+                    if model_ref.is_empty() {
+                        let toast =
+                            adw::Toast::new("Enter a CPU model reference before downloading.");
+                        toast.set_timeout(6);
+                        state.toast_overlay.add_toast(toast);
+                    } else {
+                        state.download_llm_model(model_ref);
+                    }
+                }
+            });
+
+        let weak = Rc::downgrade(self);
+        self.preferences
+            .max_tokens_spin
+            .connect_value_changed(move |spin| {
+                if let Some(state) = weak.upgrade() {
+                    let value = spin.value() as usize;
+                    state.update_max_completion_tokens(value);
+                }
+            });
+
+        let weak = Rc::downgrade(self);
+        self.preferences
+            .context_size_spin
+            .connect_value_changed(move |spin| {
+                if let Some(state) = weak.upgrade() {
+                    state.update_context_size(spin.value() as u32);
+                }
+            });
+
+        let weak = Rc::downgrade(self);
+        self.preferences
+            .temperature_spin
+            .connect_value_changed(move |spin| {
+                if let Some(state) = weak.upgrade() {
+                    state.update_temperature(spin.value() as f32);
+                }
+            });
+
+        let weak = Rc::downgrade(self);
+        self.preferences
+            .top_p_spin
+            .connect_value_changed(move |spin| {
+                if let Some(state) = weak.upgrade() {
+                    state.update_top_p(spin.value() as f32);
+                }
+            });
+
+        let weak = Rc::downgrade(self);
+        self.preferences
+            .top_k_spin
+            .connect_value_changed(move |spin| {
+                if let Some(state) = weak.upgrade() {
+                    state.update_top_k(spin.value() as i32);
+                }
+            });
+
+        let weak = Rc::downgrade(self);
+        self.preferences
+            .repeat_penalty_switch
+            .connect_active_notify(move |switch| {
+                if let Some(state) = weak.upgrade() {
+                    state.update_use_repeat_penalty(switch.is_active());
+                }
+            });
+
+        let weak = Rc::downgrade(self);
+        self.preferences
+            .repeat_penalty_spin
+            .connect_value_changed(move |spin| {
+                if let Some(state) = weak.upgrade() {
+                    state.update_repeat_penalty(spin.value() as f32);
+                }
+            });
+
+        let weak = Rc::downgrade(self);
+        self.preferences
+            .penalty_last_n_spin
+            .connect_value_changed(move |spin| {
+                if let Some(state) = weak.upgrade() {
+                    state.update_penalty_last_n(spin.value() as i32);
+                }
+            });
+
+        let weak = Rc::downgrade(self);
+        self.preferences
+            .frequency_penalty_spin
+            .connect_value_changed(move |spin| {
+                if let Some(state) = weak.upgrade() {
+                    state.update_frequency_penalty(spin.value() as f32);
+                }
+            });
+
+        let weak = Rc::downgrade(self);
+        self.preferences
+            .presence_penalty_spin
+            .connect_value_changed(move |spin| {
+                if let Some(state) = weak.upgrade() {
+                    state.update_presence_penalty(spin.value() as f32);
+                }
+            });
+
+        let weak = Rc::downgrade(self);
+        self.preferences
+            .seed_row
+            .connect_changed(move |entry: &adw::EntryRow| {
+                let Some(state) = weak.upgrade() else {
+                    return;
+                };
+                match preferences::parse_seed(&entry.text()) {
+                    Ok(seed) => {
+                        entry.remove_css_class("error");
+                        state.update_seed(seed);
+                    }
+                    // Keep the last valid seed until the entry makes sense again
+                    Err(_) => entry.add_css_class("error"),
+                }
+            });
+
+        let weak = Rc::downgrade(self);
+        self.preferences
+            .cache_size_spin
+            .connect_value_changed(move |spin| {
+                if let Some(state) = weak.upgrade() {
+                    state.update_completion_cache_size(spin.value() as usize);
+                }
+            });
+
+        let weak = Rc::downgrade(self);
+        self.preferences
+            .latency_target_spin
+            .connect_value_changed(move |spin| {
+                if let Some(state) = weak.upgrade() {
+                    state.update_latency_target(spin.value() as u64);
+                }
+            });
+
+        let weak = Rc::downgrade(self);
+        self.preferences.api_key_row.connect_apply(move |row| {
+            if let Some(state) = weak.upgrade() {
+                state.update_api_key(row.text().trim().to_string());
+            }
+        });
+
+        let weak = Rc::downgrade(self);
+        self.preferences.hf_token_row.connect_apply(move |row| {
+            if let Some(state) = weak.upgrade() {
+                state.update_hf_token(row.text().trim().to_string());
+            }
+        });
+
+        let weak = Rc::downgrade(self);
+        self.preferences
+            .idle_unload_spin
+            .connect_value_changed(move |spin| {
+                if let Some(state) = weak.upgrade() {
+                    state.update_unload_after_idle(spin.value() as u64 * 60);
+                }
+            });
+
+        let weak = Rc::downgrade(self);
+        self.preferences
+            .completion_scope_combo
+            .connect_selected_notify(move |row| {
+                if let Some(state) = weak.upgrade() {
+                    let scope = preferences::completion_scope_from_index(row.selected());
+                    state.update_completion_scope(scope);
+                }
+            });
+
+        let weak = Rc::downgrade(self);
+        self.preferences
+            .fim_template_combo
+            .connect_selected_notify(move |row| {
+                if let Some(state) = weak.upgrade() {
+                    let template = preferences::fim_template_from_index(row.selected());
+                    state.update_fim_template(template);
+                }
+            });
+
+        let weak = Rc::downgrade(self);
+        self.preferences
+            .context_strategy_combo
+            .connect_selected_notify(move |row| {
+                if let Some(state) = weak.upgrade() {
+                    let strategy = preferences::context_strategy_from_index(row.selected());
+                    state.update_context_strategy(strategy);
+                }
+            });
+
+        let weak = Rc::downgrade(self);
+        self.preferences
+            .context_lines_spin
+            .connect_value_changed(move |spin| {
+                if let Some(state) = weak.upgrade() {
+                    state.update_context_lines(spin.value() as usize);
+                }
+            });
+
+        let weak = Rc::downgrade(self);
+        self.preferences
+            .context_prefix_spin
+            .connect_value_changed(move |spin| {
+                if let Some(state) = weak.upgrade() {
+                    state.update_context_chars(Some(spin.value() as usize), None);
+                }
+            });
+
+        let weak = Rc::downgrade(self);
+        self.preferences
+            .context_suffix_spin
+            .connect_value_changed(move |spin| {
+                if let Some(state) = weak.upgrade() {
+                    state.update_context_chars(None, Some(spin.value() as usize));
+                }
+            });
+
+        let weak = Rc::downgrade(self);
+        self.preferences
+            .completion_profile_combo
+            .connect_selected_notify(move |row| {
+                if let Some(state) = weak.upgrade() {
+                    let profile = preferences::completion_profile_from_index(row.selected());
+                    state.update_completion_profile(profile);
+                }
+            });
+
+        let weak = Rc::downgrade(self);
+        self.preferences
+            .stop_at_sentence_switch
+            .connect_active_notify(move |switch| {
+                if let Some(state) = weak.upgrade() {
+                    state.update_stop_at_sentence_end(switch.is_active());
+                }
+            });
+
+        let weak = Rc::downgrade(self);
+        self.preferences
+            .include_filename_switch
+            .connect_active_notify(move |switch| {
+                if let Some(state) = weak.upgrade() {
+                    state.update_include_filename(switch.is_active());
+                }
+            });
+
+        let weak = Rc::downgrade(self);
+        self.preferences
+            .completion_debounce_spin
+            .connect_value_changed(move |spin| {
+                if let Some(state) = weak.upgrade() {
+                    state.update_completion_debounce(spin.value() as u64);
+                }
+            });
+
+        let weak = Rc::downgrade(self);
+        self.preferences
+            .completion_max_wait_spin
+            .connect_value_changed(move |spin| {
+                if let Some(state) = weak.upgrade() {
+                    state.update_completion_max_wait(spin.value() as u64);
+                }
+            });
+
+        let weak = Rc::downgrade(self);
+        self.preferences
+            .auto_completion_switch
+            .connect_active_notify(move |switch| {
+                if let Some(state) = weak.upgrade() {
+                    state.update_auto_completion_enabled(switch.is_active());
+                }
+            });
+
+        let weak = Rc::downgrade(self);
+        self.preferences
+            .trigger_only_switch
+            .connect_active_notify(move |switch| {
+                if let Some(state) = weak.upgrade() {
+                    state.update_trigger_only(switch.is_active());
+                }
+            });
+
+        let weak = Rc::downgrade(self);
+        self.preferences
+            .trigger_chars_row
+            .connect_changed(move |entry: &adw::EntryRow| {
+                if let Some(state) = weak.upgrade() {
+                    state.update_trigger_chars(preferences::unescape_trigger_chars(&entry.text()));
+                }
+            });
+
+        let weak = Rc::downgrade(self);
+        self.preferences
+            .bulk_insert_spin
+            .connect_value_changed(move |spin| {
+                if let Some(state) = weak.upgrade() {
+                    state.update_bulk_insert_chars(spin.value() as usize);
+                }
+            });
+
+        let weak = Rc::downgrade(self);
+        self.preferences
+            .starter_prompt_row
+            .connect_changed(move |entry: &adw::EntryRow| {
+                if let Some(state) = weak.upgrade() {
+                    state.update_starter_prompt(entry.text().to_string());
+                }
+            });
+
+        let weak = Rc::downgrade(self);
+        self.preferences
+            .blocklist_view
+            .buffer()
+            .connect_changed(move |buffer| {
+                if let Some(state) = weak.upgrade() {
+                    let text = buffer.text(&buffer.start_iter(), &buffer.end_iter(), false);
+                    let patterns = text
+                        .lines()
+                        .filter(|line| !line.trim().is_empty())
+                        .map(str::to_string)
+                        .collect();
+                    state.update_completion_blocklist(patterns);
+                }
+            });
+
+        let weak = Rc::downgrade(self);
+        self.preferences
+            .regenerate_blocked_switch
+            .connect_active_notify(move |switch| {
+                if let Some(state) = weak.upgrade() {
+                    state.update_regenerate_blocked(switch.is_active());
+                }
+            });
+
+        let weak = Rc::downgrade(self);
+        self.preferences
+            .reveal_ghost_switch
+            .connect_active_notify(move |switch| {
+                if let Some(state) = weak.upgrade() {
+                    state.update_reveal_ghost_text(switch.is_active());
+                }
+            });
+
+        let weak = Rc::downgrade(self);
+        self.preferences
+            .dismiss_on_click_switch
+            .connect_active_notify(move |switch| {
+                if let Some(state) = weak.upgrade() {
+                    state.update_dismiss_on_click(switch.is_active());
+                }
+            });
+
+        let weak = Rc::downgrade(self);
+        self.preferences
+            .explain_completions_switch
+            .connect_active_notify(move |switch| {
+                if let Some(state) = weak.upgrade() {
+                    state.update_explain_completions(switch.is_active());
+                }
+            });
+
+        let weak = Rc::downgrade(self);
+        self.preferences
+            .load_model_button
+            .connect_clicked(move |_| {
+                if let Some(state) = weak.upgrade() {
+                    state.show_preferences_toast("Loading model…");
+                    state.preload_llm_model(true);
+                }
+            });
+
+        let weak = Rc::downgrade(self);
+        self.preferences
+            .gpu_layers_spin
+            .connect_value_changed(move |spin| {
+                if let Some(state) = weak.upgrade() {
+                    state.update_gpu_layers(spin.value() as i32);
+                }
+            });
+
+        let weak = Rc::downgrade(self);
+        self.preferences.auto_tune_button.connect_clicked(move |_| {
+            if let Some(state) = weak.upgrade() {
+                state.auto_tune_gpu_layers();
+            }
+        });
+
+        let weak = Rc::downgrade(self);
+        self.preferences
+            .reset_defaults_button
+            .connect_clicked(move |_| {
+                if let Some(state) = weak.upgrade() {
+                    let defaults = LlmSettings::default();
+                    // Updating text triggers the change signals which update settings
+                    state
+                        .preferences
+                        .gpu_model_row
+                        .set_text(&defaults.default_gpu_model);
+                    state
+                        .preferences
+                        .cpu_model_row
+                        .set_text(&defaults.default_cpu_model);
+                    state
+                        .preferences
+                        .max_tokens_spin
+                        .set_value(defaults.max_completion_tokens as f64);
+
+                    let toast = adw::Toast::new("LLM settings reset to defaults.");
+                    toast.set_timeout(3);
+                    state.toast_overlay.add_toast(toast);
+                }
+            });
+    }
+
+    fn update_llm_provider(&self, provider: ProviderKind) {
+        {
+            let mut settings = self.settings.borrow_mut();
+            if settings.llm.provider == provider {
+                return;
+            }
+            settings.llm.set_provider(provider);
+            // Each provider has its own key
+            settings.llm.keyring_api_key = secrets::load_api_key(provider);
+        }
+        self.save_settings();
+        self.refresh_llm_manager_config();
+        self.sync_llm_preferences();
+    }
+
+    /// Save the API key for the current provider, in the keyring when possible
+    fn update_api_key(&self, key: String) {
+        let provider = self.settings.borrow().llm.provider;
+        if self.settings.borrow().llm.api_key() == key {
+            return;
+        }
+        let stored = secrets::store_api_key(provider, &key);
+        {
+            let mut settings = self.settings.borrow_mut();
+            if stored.is_ok() {
+                settings.llm.keyring_api_key = Some(key).filter(|key| !key.is_empty());
+                // Drop any copy left in the config file from before
+                settings.llm.plaintext_api_key.clear();
+            } else {
+                settings.llm.keyring_api_key = None;
+                settings.llm.plaintext_api_key = key;
+            }
+        }
+        match stored {
+            Ok(()) => self.show_preferences_toast("API key saved to the system keyring"),
+            Err(err) => {
+                log::warn!("{err:#}");
+                self.show_preferences_toast("No system keyring; API key saved in the config file");
+            }
+        }
+        self.save_settings();
+        self.refresh_llm_manager_config();
+    }
+
+    /// Save the Hugging Face token, in the keyring when possible
+    fn update_hf_token(&self, token: String) {
+        if self.settings.borrow().llm.hf_token() == token {
+            return;
+        }
+        let stored = secrets::store_hf_token(&token);
+        {
+            let mut settings = self.settings.borrow_mut();
+            if stored.is_ok() {
+                settings.llm.keyring_hf_token = Some(token).filter(|token| !token.is_empty());
+                settings.llm.plaintext_hf_token.clear();
+            } else {
+                settings.llm.keyring_hf_token = None;
+                settings.llm.plaintext_hf_token = token;
+            }
+        }
+        match stored {
+            Ok(()) => self.show_preferences_toast("Token saved to the system keyring"),
+            Err(err) => {
+                log::warn!("{err:#}");
+                self.show_preferences_toast("No system keyring; token saved in the config file");
+            }
+        }
+        self.save_settings();
+        self.refresh_llm_manager_config();
+    }
+
+    fn update_llm_endpoint(&self, endpoint: String) {
+        {
+            let mut settings = self.settings.borrow_mut();
+            if settings.llm.endpoint == endpoint {
+                return;
+            }
+            settings.llm.endpoint = endpoint;
+        }
+        self.save_settings();
+        self.refresh_llm_manager_config();
+    }
+
+    fn update_remote_model(&self, model: String) {
+        {
+            let mut settings = self.settings.borrow_mut();
+            if settings.llm.remote_model == model {
+                return;
+            }
+            settings.llm.remote_model = model;
+        }
+        self.save_settings();
+        self.refresh_llm_manager_config();
+    }
+
+    fn update_llm_local_model(&self, path: String) {
+        {
+            let mut settings = self.settings.borrow_mut();
+            if settings.llm.local_model_path == path {
+                return;
+            }
+            settings.llm.local_model_path = path;
+        }
+        self.save_settings();
+        self.refresh_llm_manager_config();
+    }
+
+    fn update_override_model(&self, active: bool) {
+        {
+            let mut settings = self.settings.borrow_mut();
+            if settings.llm.override_model_path == active {
+                return;
+            }
+            settings.llm.override_model_path = active;
+        }
+        self.save_settings();
+        self.refresh_llm_manager_config();
+        self.sync_llm_preferences();
+    }
+
+    fn update_gpu_selection(&self, idx: u32) {
+        {
+            let mut settings = self.settings.borrow_mut();
+            if idx == 0 {
+                settings.llm.force_cpu_only = true;
+                settings.llm.preferred_device = None;
+            } else {
+                settings.llm.force_cpu_only = false;
+                let gpu_idx = (idx as usize) - 1;
+                if let Some(gpu) = self.gpus.get(gpu_idx) {
+                    settings.llm.preferred_device = Some(gpu.id.clone());
+                }
+            }
+        }
+        self.save_settings();
+        self.refresh_llm_manager_config();
+        self.sync_llm_preferences();
+    }
+
+    /// Takes effect the next time the model loads
+    fn update_gpu_layers(&self, layers: i32) {
+        {
+            let mut settings = self.settings.borrow_mut();
+            if settings.llm.gpu_layers == layers {
+                return;
+            }
+            settings.llm.gpu_layers = layers;
+        }
+        self.save_settings();
+        self.refresh_llm_manager_config();
+        self.sync_llm_preferences();
+    }
+
+    pub(super) fn record_tuned_gpu_layers(&self, device: &str, layers: i32) {
+        self.settings
+            .borrow_mut()
+            .llm
+            .tuned_gpu_layers
+            .insert(device.to_string(), layers);
+        self.save_settings();
+        self.refresh_llm_manager_config();
+        self.sync_llm_preferences();
+    }
+
+    fn update_gpu_model(&self, model: String) {
+        {
+            let mut settings = self.settings.borrow_mut();
+            if settings.llm.default_gpu_model == model {
+                return;
+            }
+            settings.llm.default_gpu_model = model;
+        }
+        self.save_settings();
+        self.refresh_llm_manager_config();
+    }
+
+    fn update_cpu_model(&self, model: String) {
+        {
+            let mut settings = self.settings.borrow_mut();
+            if settings.llm.default_cpu_model == model {
+                return;
+            }
+            settings.llm.default_cpu_model = model;
+        }
+        self.save_settings();
+        self.refresh_llm_manager_config();
+    }
+
+    fn update_max_completion_tokens(&self, tokens: usize) {
+        {
+            let mut settings = self.settings.borrow_mut();
+            if settings.llm.max_completion_tokens == tokens {
+                return;
+            }
+            settings.llm.max_completion_tokens = tokens;
+            settings.llm.completion_profile = CompletionProfile::Custom;
+        }
+        self.save_settings();
+        self.refresh_llm_manager_config();
+    }
+
+    fn update_completion_cache_size(&self, size: usize) {
+        {
+            let mut settings = self.settings.borrow_mut();
+            if settings.llm.completion_cache_size == size {
+                return;
+            }
+            settings.llm.completion_cache_size = size;
+        }
+        self.save_settings();
+        self.refresh_llm_manager_config();
+    }
+
+    fn update_context_size(&self, tokens: u32) {
+        {
+            let mut settings = self.settings.borrow_mut();
+            if settings.llm.context_size == tokens {
+                return;
+            }
+            settings.llm.context_size = tokens;
+        }
+        self.save_settings();
+        self.refresh_llm_manager_config();
+    }
+
+    fn update_temperature(&self, temperature: f32) {
+        {
+            let mut settings = self.settings.borrow_mut();
+            if settings.llm.temperature == temperature {
+                return;
+            }
+            settings.llm.temperature = temperature;
+        }
+        self.save_settings();
+        self.refresh_llm_manager_config();
+    }
+
+    fn update_top_p(&self, top_p: f32) {
+        {
+            let mut settings = self.settings.borrow_mut();
+            if settings.llm.top_p == top_p {
+                return;
+            }
+            settings.llm.top_p = top_p;
+        }
+        self.save_settings();
+        self.refresh_llm_manager_config();
+    }
+
+    fn update_top_k(&self, top_k: i32) {
+        {
+            let mut settings = self.settings.borrow_mut();
+            if settings.llm.top_k == top_k {
+                return;
+            }
+            settings.llm.top_k = top_k;
+        }
+        self.save_settings();
+        self.refresh_llm_manager_config();
+    }
+
+    fn update_use_repeat_penalty(&self, enabled: bool) {
+        {
+            let mut settings = self.settings.borrow_mut();
+            if settings.llm.use_repeat_penalty == enabled {
+                return;
+            }
+            settings.llm.use_repeat_penalty = enabled;
+        }
+        self.save_settings();
+        self.refresh_llm_manager_config();
+    }
+
+    fn update_repeat_penalty(&self, penalty: f32) {
+        {
+            let mut settings = self.settings.borrow_mut();
+            if settings.llm.repeat_penalty == penalty {
+                return;
+            }
+            settings.llm.repeat_penalty = penalty;
+        }
+        self.save_settings();
+        self.refresh_llm_manager_config();
+    }
+
+    fn update_penalty_last_n(&self, last_n: i32) {
+        {
+            let mut settings = self.settings.borrow_mut();
+            if settings.llm.penalty_last_n == last_n {
+                return;
+            }
+            settings.llm.penalty_last_n = last_n;
+        }
+        self.save_settings();
+        self.refresh_llm_manager_config();
+    }
+
+    fn update_frequency_penalty(&self, penalty: f32) {
+        {
+            let mut settings = self.settings.borrow_mut();
+            if settings.llm.frequency_penalty == penalty {
+                return;
+            }
+            settings.llm.frequency_penalty = penalty;
+        }
+        self.save_settings();
+        self.refresh_llm_manager_config();
+    }
+
+    fn update_presence_penalty(&self, penalty: f32) {
+        {
+            let mut settings = self.settings.borrow_mut();
+            if settings.llm.presence_penalty == penalty {
+                return;
+            }
+            settings.llm.presence_penalty = penalty;
+        }
+        self.save_settings();
+        self.refresh_llm_manager_config();
+    }
+
+    fn update_seed(&self, seed: Option<u64>) {
+        {
+            let mut settings = self.settings.borrow_mut();
+            if settings.llm.seed == seed {
+                return;
+            }
+            settings.llm.seed = seed;
+        }
+        self.save_settings();
+        self.refresh_llm_manager_config();
+    }
+
+    fn update_latency_target(&self, millis: u64) {
+        {
+            let mut settings = self.settings.borrow_mut();
+            if settings.llm.latency_target_ms == millis {
+                return;
+            }
+            settings.llm.latency_target_ms = millis;
+        }
+        self.save_settings();
+        self.refresh_llm_manager_config();
+    }
+
+    fn update_unload_after_idle(self: &Rc<Self>, secs: u64) {
+        {
+            let mut settings = self.settings.borrow_mut();
+            if settings.llm.unload_after_idle_secs == secs {
+                return;
+            }
+            settings.llm.unload_after_idle_secs = secs;
+        }
+        self.save_settings();
+        self.refresh_llm_manager_config();
+        self.reset_idle_unload_timer();
+    }
+
+    fn update_completion_scope(&self, scope: CompletionScope) {
+        {
+            let mut settings = self.settings.borrow_mut();
+            if settings.llm.completion_scope == scope {
+                return;
+            }
+            settings.llm.completion_scope = scope;
+        }
+        self.save_settings();
+        self.refresh_llm_manager_config();
+    }
+
+    fn update_fim_template(&self, template: FimTemplate) {
+        {
+            let mut settings = self.settings.borrow_mut();
+            if settings.llm.fim_template == template {
+                return;
+            }
+            settings.llm.fim_template = template;
+        }
+        self.save_settings();
+    }
+
+    fn update_context_strategy(&self, strategy: ContextStrategy) {
+        {
+            let mut settings = self.settings.borrow_mut();
+            if settings.llm.context_strategy == strategy {
+                return;
+            }
+            settings.llm.context_strategy = strategy;
+        }
+        self.save_settings();
+        self.preferences
+            .context_lines_spin
+            .set_sensitive(strategy == ContextStrategy::Lines);
+    }
+
+    fn update_context_lines(&self, lines: usize) {
+        {
+            let mut settings = self.settings.borrow_mut();
+            if settings.llm.context_lines == lines {
+                return;
+            }
+            settings.llm.context_lines = lines;
+        }
+        self.save_settings();
+    }
+
+    /// Update the character budget before and/or after the cursor
+    fn update_context_chars(&self, prefix_chars: Option<usize>, suffix_chars: Option<usize>) {
+        {
+            let mut settings = self.settings.borrow_mut();
+            let prefix_chars = prefix_chars.unwrap_or(settings.llm.context_prefix_chars);
+            let suffix_chars = suffix_chars.unwrap_or(settings.llm.context_suffix_chars);
+            if settings.llm.context_prefix_chars == prefix_chars
+                && settings.llm.context_suffix_chars == suffix_chars
+            {
+                return;
+            }
+            settings.llm.context_prefix_chars = prefix_chars;
+            settings.llm.context_suffix_chars = suffix_chars;
+        }
+        self.save_settings();
+    }
+
+    fn update_completion_profile(&self, profile: CompletionProfile) {
+        {
+            let mut settings = self.settings.borrow_mut();
+            if settings.llm.completion_profile == profile {
+                return;
+            }
+            settings.llm.apply_profile(profile);
+        }
+        self.save_settings();
+        self.refresh_llm_manager_config();
+        // Show the knobs the profile just changed
+        self.sync_llm_preferences();
+    }
+
+    fn update_stop_at_sentence_end(&self, enabled: bool) {
+        {
+            let mut settings = self.settings.borrow_mut();
+            if settings.llm.stop_at_sentence_end == enabled {
+                return;
+            }
+            settings.llm.stop_at_sentence_end = enabled;
+            settings.llm.completion_profile = CompletionProfile::Custom;
+        }
+        self.save_settings();
+    }
+
+    fn update_include_filename(&self, enabled: bool) {
+        {
+            let mut settings = self.settings.borrow_mut();
+            if settings.llm.include_filename == enabled {
+                return;
+            }
+            settings.llm.include_filename = enabled;
+        }
+        self.save_settings();
+    }
+
+    fn update_completion_debounce(&self, debounce_ms: u64) {
+        let max_wait_ms = {
+            let mut settings = self.settings.borrow_mut();
+            if settings.llm.completion_debounce_ms == debounce_ms {
+                return;
+            }
+            settings.llm.completion_debounce_ms = debounce_ms;
+            settings.llm.completion_max_wait_ms
+        };
+        self.save_settings();
+        // The max wait can't be shorter than the pause it cuts short
+        if max_wait_ms != 0 && max_wait_ms < debounce_ms {
+            self.preferences
+                .completion_max_wait_spin
+                .set_value(debounce_ms as f64);
+        }
+    }
+
+    fn update_completion_max_wait(&self, max_wait_ms: u64) {
+        let debounce_ms = self.settings.borrow().llm.completion_debounce_ms;
+        if max_wait_ms != 0 && max_wait_ms < debounce_ms {
+            // Goes through this handler again with the clamped value
+            self.preferences
+                .completion_max_wait_spin
+                .set_value(debounce_ms as f64);
+            return;
+        }
+        {
+            let mut settings = self.settings.borrow_mut();
+            if settings.llm.completion_max_wait_ms == max_wait_ms {
+                return;
+            }
+            settings.llm.completion_max_wait_ms = max_wait_ms;
+        }
+        self.save_settings();
+    }
+
+    fn update_auto_completion_enabled(&self, enabled: bool) {
+        {
+            let mut settings = self.settings.borrow_mut();
+            if settings.llm.auto_completion_enabled == enabled {
+                return;
+            }
+            settings.llm.auto_completion_enabled = enabled;
+        }
+        self.save_settings();
+        if !enabled {
+            self.cancel_completion_debounce();
+        }
+        self.show_preferences_toast(if enabled {
+            "Suggestions appear while typing"
+        } else {
+            "Suggestions only appear on Ctrl+Space"
+        });
+    }
+
+    fn update_trigger_only(&self, enabled: bool) {
+        {
+            let mut settings = self.settings.borrow_mut();
+            if settings.llm.trigger_only == enabled {
+                return;
+            }
+            settings.llm.trigger_only = enabled;
+            settings.llm.completion_profile = CompletionProfile::Custom;
+        }
+        self.save_settings();
+        self.preferences.trigger_chars_row.set_sensitive(enabled);
+    }
+
+    fn update_trigger_chars(&self, chars: String) {
+        {
+            let mut settings = self.settings.borrow_mut();
+            if settings.llm.trigger_chars == chars {
+                return;
+            }
+            settings.llm.trigger_chars = chars;
+            settings.llm.completion_profile = CompletionProfile::Custom;
+        }
+        self.save_settings();
+    }
+
+    fn update_bulk_insert_chars(&self, chars: usize) {
+        {
+            let mut settings = self.settings.borrow_mut();
+            if settings.llm.bulk_insert_chars == chars {
+                return;
+            }
+            settings.llm.bulk_insert_chars = chars;
+        }
+        self.save_settings();
+    }
+
+    fn update_completion_blocklist(&self, patterns: Vec<String>) {
+        {
+            let mut settings = self.settings.borrow_mut();
+            if settings.llm.completion_blocklist == patterns {
+                return;
+            }
+            settings.llm.completion_blocklist = patterns;
+        }
+        self.save_settings();
+    }
+
+    fn update_regenerate_blocked(&self, enabled: bool) {
+        {
+            let mut settings = self.settings.borrow_mut();
+            if settings.llm.regenerate_blocked == enabled {
+                return;
+            }
+            settings.llm.regenerate_blocked = enabled;
+        }
+        self.save_settings();
+    }
+
+    fn update_reveal_ghost_text(&self, enabled: bool) {
+        {
+            let mut settings = self.settings.borrow_mut();
+            if settings.llm.reveal_ghost_text == enabled {
+                return;
+            }
+            settings.llm.reveal_ghost_text = enabled;
+        }
+        self.save_settings();
+    }
+
+    fn update_dismiss_on_click(&self, enabled: bool) {
+        {
+            let mut settings = self.settings.borrow_mut();
+            if settings.llm.dismiss_on_click == enabled {
+                return;
+            }
+            settings.llm.dismiss_on_click = enabled;
+        }
+        self.save_settings();
+    }
+
+    fn update_check_connection_on_startup(&self, enabled: bool) {
+        {
+            let mut settings = self.settings.borrow_mut();
+            if settings.llm.check_connection_on_startup == enabled {
+                return;
+            }
+            settings.llm.check_connection_on_startup = enabled;
+        }
+        self.save_settings();
+    }
+
+    fn update_explain_completions(&self, enabled: bool) {
+        {
+            let mut settings = self.settings.borrow_mut();
+            if settings.llm.explain_completions == enabled {
+                return;
+            }
+            settings.llm.explain_completions = enabled;
+        }
+        self.save_settings();
+    }
+
+    fn update_starter_prompt(&self, prompt: String) {
+        {
+            let mut settings = self.settings.borrow_mut();
+            if settings.llm.starter_prompt == prompt {
+                return;
+            }
+            settings.llm.starter_prompt = prompt;
+        }
+        self.save_settings();
+    }
+
+    pub(super) fn check_llm_readiness(self: &Rc<Self>) {
+        // Skip if user disabled the check
+        if self.settings.borrow().skip_llm_startup_check {
+            return;
+        }
+
+        let readiness = self
+            .lock_llm_manager()
+            .map(|mgr| mgr.check_readiness())
+            .unwrap_or(LlmReadiness::LocalBackendUnavailable);
+
+        if readiness != LlmReadiness::Ready {
+            self.show_llm_setup_dialog(readiness);
+            return;
+        }
+
+        // Only ask a remote service whether it answers when the user wants that
+        let config = self.settings.borrow().llm.clone();
+        if config.provider == ProviderKind::Local || !config.check_connection_on_startup {
+            return;
+        }
+        let weak = Rc::downgrade(self);
+        self.probe_llm_connection(config, move |result| {
+            if let (Some(state), Err(err)) = (weak.upgrade(), result) {
+                log::warn!("LLM connection check failed: {err:#}");
+                state.show_llm_setup_dialog(LlmReadiness::NeedsValidConnection {
+                    error: format!("{err:#}"),
+                });
+            }
+        });
+    }
+
+    /// Preferences' Test Connection button. Tests what is in the rows, so an
+    /// API key that hasn't been applied yet counts too.
+    fn test_llm_connection(self: &Rc<Self>) {
+        self.preferences.test_connection_button.set_sensitive(false);
+        self.show_preferences_toast("Testing connection…");
+        let mut config = self.settings.borrow().llm.clone();
+        config.provider =
+            preferences::provider_from_index(self.preferences.llm_provider_combo.selected());
+        config.endpoint = self.preferences.llm_endpoint_row.text().to_string();
+        config.remote_model = self.preferences.remote_model_row.text().to_string();
+        config.keyring_api_key = Some(self.preferences.api_key_row.text().to_string());
+        let weak = Rc::downgrade(self);
+        self.probe_llm_connection(config, move |result| {
+            let Some(state) = weak.upgrade() else {
+                return;
+            };
+            state.preferences.test_connection_button.set_sensitive(true);
+            match result {
+                Ok(()) => state.show_preferences_toast("Connection works"),
+                Err(err) => {
+                    log::warn!("LLM connection test failed: {err:#}");
+                    state.show_preferences_toast(&format!("Connection failed: {err:#}"));
+                }
+            }
+        });
+    }
+
+    /// Run `LlmManager::test_connection` off the main thread and hand the
+    /// result to `done` back on it
+    fn probe_llm_connection(
+        &self,
+        config: LlmSettings,
+        done: impl FnOnce(anyhow::Result<()>) + 'static,
+    ) {
+        let (tx, rx) = mpsc::channel::<anyhow::Result<()>>();
+        std::thread::spawn(move || {
+            let _ = tx.send(LlmManager::test_connection(&config));
+        });
+        let mut done = Some(done);
+        glib::timeout_add_local(std::time::Duration::from_millis(200), move || {
+            let result = match rx.try_recv() {
+                Ok(result) => result,
+                Err(mpsc::TryRecvError::Empty) => return ControlFlow::Continue,
+                Err(mpsc::TryRecvError::Disconnected) => {
+                    Err(anyhow::anyhow!("Connection test stopped unexpectedly"))
+                }
+            };
+            if let Some(done) = done.take() {
+                done(result);
+            }
+            ControlFlow::Break
+        });
+    }
+
+    fn show_llm_setup_dialog(self: &Rc<Self>, readiness: LlmReadiness) {
+        let dialog = gtk::Dialog::builder()
+            .transient_for(&self.window())
+            .modal(true)
+            .title("LLM Setup")
+            .build();
+
+        let (message, action_label) = match &readiness {
+            LlmReadiness::NeedsDownload { model_ref } => (
+                format!(
+                    "Your LLM configuration uses local inference, but the model is not downloaded yet.\n\n\
+                    Model: {}\n\n\
+                    Would you like to download it now?",
+                    model_ref
+                ),
+                Some("Download Model"),
+            ),
+            LlmReadiness::LocalBackendUnavailable => (
+                "Wispnote could not initialize its bundled llama.cpp backend for local inference.\n\n\
+                (Development build hint) If you're running from source, make sure the llama.cpp shared libraries and GPU/CPU drivers it depends on are available; otherwise, switch to a remote provider in Preferences."
+                    .to_string(),
+                Some("Open Preferences"),
+            ),
+            LlmReadiness::NeedsEndpoint => (
+                "Your LLM provider requires an endpoint URL, but none is configured.\n\n\
+                Please configure your LLM settings in Preferences."
+                    .to_string(),
+                Some("Open Preferences"),
+            ),
+            LlmReadiness::NeedsValidConnection { error } => (
+                format!(
+                    "Wispnote couldn't reach your LLM service, so completions won't work yet.\n\n\
+                    {}\n\n\
+                    Please check the endpoint, model name and API key in Preferences.",
+                    error
+                ),
+                Some("Open Preferences"),
+            ),
+            LlmReadiness::Ready => return, // Should never reach here
+        };
+
+        // Build content
+        let vbox = gtk::Box::new(gtk::Orientation::Vertical, 12);
+        vbox.set_margin_top(12);
+        vbox.set_margin_bottom(12);
+        vbox.set_margin_start(12);
+        vbox.set_margin_end(12);
+
+        let label = gtk::Label::new(Some(&message));
+        label.set_wrap(true);
+        label.set_max_width_chars(50);
+        vbox.append(&label);
+
+        // Add checkbox for "Don't show again"
+        let checkbox = gtk::CheckButton::with_label("Don't show this dialog again");
+        vbox.append(&checkbox);
+
+        dialog.content_area().append(&vbox);
+
+        dialog.add_button("Later", gtk::ResponseType::Cancel);
+
+        if let Some(label) = action_label {
+            dialog.add_button(label, gtk::ResponseType::Accept);
+            dialog.set_default_response(gtk::ResponseType::Accept);
+        }
+
+        let weak = Rc::downgrade(self);
+        let readiness_clone = readiness.clone();
+        dialog.connect_response(move |dialog, response| {
+            if let Some(state) = weak.upgrade() {
+                // Save "don't show again" preference
+                if checkbox.is_active() {
+                    let mut settings = state.settings.borrow_mut();
+                    settings.skip_llm_startup_check = true;
+                    drop(settings);
+                    state.save_settings();
+                }
+
+                if response == gtk::ResponseType::Accept {
+                    match &readiness_clone {
+                        LlmReadiness::NeedsDownload { model_ref } => {
+                            state.download_llm_model(model_ref.clone());
+                        }
+                        _ => {
+                            // Open preferences
+                            state.preferences.window.present();
+                        }
+                    }
+                }
+            }
+            dialog.close();
+        });
+
+        dialog.show();
+    }
+
+    pub(super) fn refresh_llm_manager_config(&self) {
+        if let Some(mut manager) = self.lock_llm_manager() {
+            manager.update_config(self.settings.borrow().llm.clone());
+        }
+    }
+}
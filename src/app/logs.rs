@@ -0,0 +1,135 @@
+use std::rc::Rc;
+
+use gtk4::{self as gtk, glib, prelude::*};
+use libadwaita as adw;
+
+use super::window::AppState;
+use crate::logging::{self, LogVerbosity};
+
+impl AppState {
+    /// Open a window listing recent log messages, refreshed while it is open
+    pub(super) fn show_logs(self: &Rc<Self>) {
+        let buffer = gtk::TextBuffer::new(None);
+        let view = gtk::TextView::builder()
+            .buffer(&buffer)
+            .editable(false)
+            .cursor_visible(false)
+            .monospace(true)
+            .wrap_mode(gtk::WrapMode::WordChar)
+            .top_margin(8)
+            .bottom_margin(8)
+            .left_margin(8)
+            .right_margin(8)
+            .build();
+        let scroller = gtk::ScrolledWindow::builder()
+            .hexpand(true)
+            .vexpand(true)
+            .child(&view)
+            .build();
+
+        let copy_btn = gtk::Button::builder()
+            .icon_name("edit-copy-symbolic")
+            .tooltip_text("Copy Logs")
+            .build();
+        let clear_btn = gtk::Button::builder()
+            .icon_name("edit-clear-all-symbolic")
+            .tooltip_text("Clear Logs")
+            .build();
+        let labels: Vec<&str> = LogVerbosity::ALL.iter().map(|v| v.label()).collect();
+        let current = logging::verbosity();
+        let level_dropdown = gtk::DropDown::from_strings(&labels);
+        level_dropdown.set_tooltip_text(Some("Detail Level"));
+        level_dropdown.set_selected(
+            LogVerbosity::ALL
+                .iter()
+                .position(|v| *v == current)
+                .unwrap_or(0) as u32,
+        );
+        let weak = Rc::downgrade(self);
+        level_dropdown.connect_selected_notify(move |dropdown| {
+            if let (Some(state), Some(verbosity)) = (
+                weak.upgrade(),
+                LogVerbosity::ALL.get(dropdown.selected() as usize),
+            ) {
+                state.update_log_verbosity(*verbosity);
+            }
+        });
+
+        let header = adw::HeaderBar::new();
+        header.pack_start(&level_dropdown);
+        header.pack_end(&copy_btn);
+        header.pack_end(&clear_btn);
+
+        let content = gtk::Box::new(gtk::Orientation::Vertical, 0);
+        content.append(&header);
+        content.append(&scroller);
+
+        let window = adw::Window::builder()
+            .title("Logs")
+            .transient_for(&self.window())
+            .default_width(720)
+            .default_height(480)
+            .content(&content)
+            .build();
+
+        let copy_view = view.clone();
+        copy_btn.connect_clicked(move |_| {
+            let buffer = copy_view.buffer();
+            let text = buffer.text(&buffer.start_iter(), &buffer.end_iter(), false);
+            copy_view.clipboard().set_text(&text);
+        });
+        clear_btn.connect_clicked(|_| logging::clear());
+
+        render_logs(&view, &buffer);
+        let mut shown_total = logging::total();
+        let weak_view = view.downgrade();
+        glib::timeout_add_local(std::time::Duration::from_secs(1), move || {
+            let Some(view) = weak_view.upgrade() else {
+                return glib::ControlFlow::Break;
+            };
+            let total = logging::total();
+            if total != shown_total {
+                shown_total = total;
+                render_logs(&view, &view.buffer());
+            }
+            glib::ControlFlow::Continue
+        });
+
+        window.present();
+    }
+
+    fn update_log_verbosity(&self, verbosity: LogVerbosity) {
+        let mut settings = self.settings.borrow_mut();
+        if settings.log_verbosity == verbosity {
+            return;
+        }
+        settings.log_verbosity = verbosity;
+        logging::set_verbosity(verbosity);
+        log::info!("Log detail set to {}", verbosity.label());
+        if let Err(err) = settings.save(&self.paths) {
+            log::warn!("Failed to save settings: {err:?}");
+        }
+    }
+}
+
+fn render_logs(view: &gtk::TextView, buffer: &gtk::TextBuffer) {
+    let entries = logging::recent();
+    let text = if entries.is_empty() {
+        "No log messages yet.".to_string()
+    } else {
+        entries
+            .iter()
+            .map(|entry| {
+                format!(
+                    "{} {:<5} [{}] {}",
+                    entry.time, entry.level, entry.target, entry.message
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    };
+    buffer.set_text(&text);
+    // Keep the newest messages in view
+    let mut end = buffer.end_iter();
+    view.scroll_to_iter(&mut end, 0.0, false, 0.0, 0.0);
+}
@@ -1,9 +1,25 @@
+pub mod append_log;
 pub mod autosave;
+pub mod commands;
 pub mod completion;
+pub mod editing;
+pub mod editor_settings;
+pub mod export;
+pub mod file_monitor;
+pub mod files;
+pub mod language;
+pub mod llm_settings;
+pub mod logs;
+pub mod models;
 pub mod preferences;
+pub mod preview;
+pub mod print;
 pub mod recent;
 pub mod recovery;
 pub mod search;
+pub mod session_lock;
+pub mod summary;
+pub mod tabs;
 pub mod window;
 
-pub use window::build_ui;
+pub use window::{build_ui, open_files};
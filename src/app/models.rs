@@ -0,0 +1,894 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, mpsc};
+
+use gtk4::glib::ControlFlow;
+use gtk4::{self as gtk, glib, prelude::*};
+use libadwaita as adw;
+
+use super::preferences;
+use super::window::AppState;
+use crate::llm::{
+    DownloadCancelled, DownloadPhase, DownloadProgress, DownloadedModel, HuggingFaceModel,
+    ModelDownloader, RepoFile,
+};
+
+impl AppState {
+    pub(super) fn hook_models_dir(self: &Rc<Self>) {
+        let weak = Rc::downgrade(self);
+        self.preferences
+            .models_dir_button
+            .connect_clicked(move |_| {
+                if let Some(state) = weak.upgrade() {
+                    state.choose_models_dir();
+                }
+            });
+
+        let weak = Rc::downgrade(self);
+        self.preferences
+            .models_dir_reset_button
+            .connect_clicked(move |_| {
+                if let Some(state) = weak.upgrade() {
+                    state.set_models_dir(None);
+                }
+            });
+
+        let weak = Rc::downgrade(self);
+        self.preferences
+            .scan_models_button
+            .connect_clicked(move |_| {
+                if let Some(state) = weak.upgrade() {
+                    state.choose_scan_folder();
+                }
+            });
+
+        let weak = Rc::downgrade(self);
+        self.preferences
+            .import_model_button
+            .connect_clicked(move |_| {
+                if let Some(state) = weak.upgrade() {
+                    state.choose_model_to_import();
+                }
+            });
+
+        let weak = Rc::downgrade(self);
+        self.preferences
+            .gpu_files_button
+            .connect_clicked(move |btn| {
+                if let Some(state) = weak.upgrade() {
+                    let row = state.preferences.gpu_model_row.clone();
+                    state.show_repo_files(&row, btn);
+                }
+            });
+
+        let weak = Rc::downgrade(self);
+        self.preferences
+            .cpu_files_button
+            .connect_clicked(move |btn| {
+                if let Some(state) = weak.upgrade() {
+                    let row = state.preferences.cpu_model_row.clone();
+                    state.show_repo_files(&row, btn);
+                }
+            });
+
+        self.refresh_downloaded_models();
+        self.warn_if_models_dir_unwritable();
+    }
+
+    /// A custom models folder may live on a drive that isn't mounted anymore.
+    fn warn_if_models_dir_unwritable(&self) {
+        if self.settings.borrow().llm.models_dir_override.is_none() {
+            return;
+        }
+        let dir = self.model_downloader.borrow().models_dir().to_path_buf();
+        if let Err(err) = ModelDownloader::ensure_writable(&dir) {
+            log::warn!("Models folder is not usable: {err:#}");
+            let toast = adw::Toast::new(&format!(
+                "Models folder {} isn't writable; choose another one in Preferences",
+                dir.display()
+            ));
+            toast.set_timeout(10);
+            self.toast_overlay.add_toast(toast);
+        }
+    }
+
+    /// Rebuild the list of downloaded models and the disk usage summary.
+    pub(super) fn refresh_downloaded_models(self: &Rc<Self>) {
+        let list = &self.preferences.downloaded_models_list;
+        while let Some(child) = list.first_child() {
+            list.remove(&child);
+        }
+
+        let models = self.model_downloader.borrow().list_downloaded();
+        let in_use = {
+            let settings = self.settings.borrow();
+            settings
+                .llm
+                .override_model_path
+                .then(|| PathBuf::from(&settings.llm.local_model_path))
+        };
+        let total: u64 = models.iter().map(|model| model.size).sum();
+        self.preferences
+            .downloaded_models_group
+            .set_description(Some(&format!(
+                "{} model{}, {} on disk",
+                models.len(),
+                if models.len() == 1 { "" } else { "s" },
+                glib::format_size(total)
+            )));
+
+        for model in models {
+            let mut subtitle = glib::format_size(model.size).to_string();
+            if model.sha256.is_none() {
+                subtitle.push_str(" · not verified");
+            }
+            if is_linked(&model.path) {
+                subtitle.push_str(" · linked");
+            }
+            let row = adw::ActionRow::builder()
+                .title(model.name.as_str())
+                .subtitle(subtitle)
+                .activatable(true)
+                .tooltip_text("Use this model")
+                .build();
+            if in_use.as_deref() == Some(model.path.as_path()) {
+                row.add_prefix(&gtk::Image::from_icon_name("object-select-symbolic"));
+            }
+            let weak = Rc::downgrade(self);
+            let path = model.path.clone();
+            row.connect_activated(move |_| {
+                if let Some(state) = weak.upgrade() {
+                    state.use_local_model_file(&path);
+                }
+            });
+            let delete_button = gtk::Button::builder()
+                .icon_name("user-trash-symbolic")
+                .valign(gtk::Align::Center)
+                .tooltip_text("Delete model")
+                .css_classes(["flat"])
+                .build();
+            let weak = Rc::downgrade(self);
+            delete_button.connect_clicked(move |_| {
+                if let Some(state) = weak.upgrade() {
+                    state.confirm_delete_model(model.clone());
+                }
+            });
+            row.add_suffix(&delete_button);
+            list.append(&row);
+        }
+    }
+
+    fn confirm_delete_model(self: &Rc<Self>, model: DownloadedModel) {
+        let dialog = gtk::MessageDialog::builder()
+            .transient_for(&self.preferences.window)
+            .modal(true)
+            .text(format!("Delete {}?", model.name))
+            .secondary_text(if is_linked(&model.path) {
+                "Only the link is removed; the file it points to is kept.".to_string()
+            } else {
+                format!(
+                    "This frees {}. The model has to be downloaded again to use it.",
+                    glib::format_size(model.size)
+                )
+            })
+            .build();
+        dialog.add_button("Cancel", gtk::ResponseType::Cancel);
+        let delete = dialog.add_button("Delete", gtk::ResponseType::Accept);
+        delete.add_css_class("destructive-action");
+
+        let weak = Rc::downgrade(self);
+        dialog.connect_response(move |dialog, response| {
+            if response == gtk::ResponseType::Accept {
+                if let Some(state) = weak.upgrade() {
+                    state.delete_model(&model);
+                }
+            }
+            dialog.close();
+        });
+        dialog.show();
+    }
+
+    fn delete_model(self: &Rc<Self>, model: &DownloadedModel) {
+        // Don't pull the file out from under the loaded model
+        {
+            let Some(manager) = self.lock_llm_manager() else {
+                self.show_preferences_toast("The model is busy; try again in a moment");
+                return;
+            };
+            if manager.loaded_model_path().as_deref() == Some(model.path.as_path()) {
+                manager.unload_model();
+                self.llm_status_label.set_text("LLM unloaded");
+            }
+        }
+        self.update_loaded_model_row();
+
+        let result = self.model_downloader.borrow().delete_model(&model.name);
+        match result {
+            Ok(()) => {
+                self.show_preferences_toast(&format!("Deleted {}", model.name));
+            }
+            Err(err) => {
+                log::warn!("Failed to delete model: {err:?}");
+                self.present_error("Failed to delete model", &format!("{err:#}"));
+            }
+        }
+        self.refresh_downloaded_models();
+        self.check_llm_readiness();
+    }
+
+    /// Look up the GGUF files in the repo typed into `row` and offer them in a
+    /// popover, so the exact quantization can be picked instead of guessed.
+    fn show_repo_files(self: &Rc<Self>, row: &adw::EntryRow, button: &gtk::Button) {
+        let text = row.text();
+        let Some(repo) = HuggingFaceModel::repo_of(&text) else {
+            self.show_preferences_toast("Enter a repository like owner/repo first");
+            return;
+        };
+        // Keep a revision the user typed
+        let revision = text
+            .split(':')
+            .next()
+            .and_then(|left| left.split_once('@'))
+            .map(|(_, revision)| revision.trim().to_string());
+        let token = self.settings.borrow().llm.hf_token().trim().to_string();
+        button.set_sensitive(false);
+
+        let (tx, rx) = mpsc::channel::<anyhow::Result<Vec<RepoFile>>>();
+        let lookup_repo = repo.clone();
+        std::thread::spawn(move || {
+            let token = Some(token.as_str()).filter(|token| !token.is_empty());
+            let _ = tx.send(HuggingFaceModel::list_gguf_files(&lookup_repo, token));
+        });
+
+        let weak = Rc::downgrade(self);
+        let row = row.clone();
+        let button = button.clone();
+        glib::timeout_add_local(std::time::Duration::from_millis(100), move || {
+            let result = match rx.try_recv() {
+                Ok(result) => result,
+                Err(mpsc::TryRecvError::Empty) => return glib::ControlFlow::Continue,
+                Err(mpsc::TryRecvError::Disconnected) => {
+                    button.set_sensitive(true);
+                    return glib::ControlFlow::Break;
+                }
+            };
+            button.set_sensitive(true);
+            let Some(state) = weak.upgrade() else {
+                return glib::ControlFlow::Break;
+            };
+            match result {
+                Ok(files) if files.is_empty() => {
+                    state.show_preferences_toast(&format!("{} has no .gguf files", repo));
+                }
+                Ok(files) => {
+                    let base = match &revision {
+                        Some(revision) => format!("{}@{}", repo, revision),
+                        None => repo.clone(),
+                    };
+                    present_repo_files(&row, &button, &base, files);
+                }
+                Err(err) => {
+                    log::warn!("Failed to list files in {}: {err:#}", repo);
+                    state.show_preferences_toast(&format!("Couldn't list files: {err:#}"));
+                }
+            }
+            glib::ControlFlow::Break
+        });
+    }
+
+    fn choose_scan_folder(self: &Rc<Self>) {
+        let dialog = gtk::FileChooserDialog::builder()
+            .title("Scan Folder for Models")
+            .transient_for(&self.preferences.window)
+            .modal(true)
+            .action(gtk::FileChooserAction::SelectFolder)
+            .build();
+        dialog.add_button("Cancel", gtk::ResponseType::Cancel);
+        dialog.add_button("Scan", gtk::ResponseType::Accept);
+        let weak = Rc::downgrade(self);
+        dialog.connect_response(move |dialog, response| {
+            if response == gtk::ResponseType::Accept {
+                if let Some(state) = weak.upgrade() {
+                    if let Some(path) = dialog.file().and_then(|file| file.path()) {
+                        state.present_scanned_models(&path);
+                    }
+                }
+            }
+            dialog.close();
+        });
+        dialog.present();
+    }
+
+    fn choose_model_to_import(self: &Rc<Self>) {
+        let dialog = gtk::FileChooserDialog::builder()
+            .title("Import Model")
+            .transient_for(&self.preferences.window)
+            .modal(true)
+            .action(gtk::FileChooserAction::Open)
+            .build();
+        dialog.add_button("Cancel", gtk::ResponseType::Cancel);
+        dialog.add_button("Import", gtk::ResponseType::Accept);
+        let filter = gtk::FileFilter::new();
+        filter.set_name(Some("GGUF models"));
+        filter.add_pattern("*.gguf");
+        dialog.add_filter(&filter);
+        let weak = Rc::downgrade(self);
+        dialog.connect_response(move |dialog, response| {
+            if response == gtk::ResponseType::Accept {
+                if let Some(state) = weak.upgrade() {
+                    if let Some(path) = dialog.file().and_then(|file| file.path()) {
+                        state.import_model(path, false);
+                    }
+                }
+            }
+            dialog.close();
+        });
+        dialog.present();
+    }
+
+    /// Bring `source` into the models folder in the background: copied, or
+    /// linked when `link` is set. A linked model comes from a scan where it was
+    /// already chosen, so it's used right away; a copy is only offered.
+    fn import_model(self: &Rc<Self>, source: PathBuf, link: bool) {
+        let downloader = self.model_downloader.borrow().clone();
+        self.preferences.import_model_button.set_sensitive(false);
+        self.show_preferences_toast(if link {
+            "Adding model…"
+        } else {
+            "Importing model…"
+        });
+
+        let (tx, rx) = mpsc::channel::<anyhow::Result<PathBuf>>();
+        std::thread::spawn(move || {
+            let result = if link {
+                downloader.register_model(&source)
+            } else {
+                downloader.import_model(&source)
+            };
+            let _ = tx.send(result);
+        });
+
+        let weak = Rc::downgrade(self);
+        glib::timeout_add_local(std::time::Duration::from_millis(200), move || {
+            let Some(state) = weak.upgrade() else {
+                return glib::ControlFlow::Break;
+            };
+            let result = match rx.try_recv() {
+                Ok(result) => result,
+                Err(mpsc::TryRecvError::Empty) => return glib::ControlFlow::Continue,
+                Err(mpsc::TryRecvError::Disconnected) => {
+                    state.preferences.import_model_button.set_sensitive(true);
+                    return glib::ControlFlow::Break;
+                }
+            };
+            state.preferences.import_model_button.set_sensitive(true);
+            match result {
+                Ok(path) if link => {
+                    state.refresh_downloaded_models();
+                    state.use_local_model_file(&path);
+                }
+                Ok(path) => {
+                    state.refresh_downloaded_models();
+                    let name = path
+                        .file_name()
+                        .map(|name| name.to_string_lossy().to_string())
+                        .unwrap_or_default();
+                    let toast = adw::Toast::new(&format!("Imported {}", name));
+                    toast.set_button_label(Some("Use Model"));
+                    toast.set_timeout(10);
+                    let weak = Rc::downgrade(&state);
+                    toast.connect_button_clicked(move |_| {
+                        if let Some(state) = weak.upgrade() {
+                            state.use_local_model_file(&path);
+                        }
+                    });
+                    state.preferences.window.add_toast(toast);
+                }
+                Err(err) => {
+                    log::warn!("Failed to import model: {err:?}");
+                    state.present_error("Failed to import model", &format!("{err:#}"));
+                }
+            }
+            glib::ControlFlow::Break
+        });
+    }
+
+    /// List the GGUF files in `dir` and add the chosen one to the models folder
+    /// as the model to use.
+    fn present_scanned_models(self: &Rc<Self>, dir: &Path) {
+        let models = ModelDownloader::new(dir.to_path_buf()).list_models();
+        if models.is_empty() {
+            self.show_preferences_toast("No .gguf models found in that folder");
+            return;
+        }
+
+        let list = gtk::ListBox::builder()
+            .selection_mode(gtk::SelectionMode::Single)
+            .css_classes(["boxed-list"])
+            .build();
+        for model in &models {
+            let name = model
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_default();
+            let size = fs::metadata(model)
+                .map(|meta| glib::format_size(meta.len()).to_string())
+                .unwrap_or_default();
+            let row = adw::ActionRow::builder().title(name).subtitle(size).build();
+            list.append(&row);
+        }
+        list.select_row(list.row_at_index(0).as_ref());
+        let scroller = gtk::ScrolledWindow::builder()
+            .child(&list)
+            .min_content_height(160)
+            .max_content_height(320)
+            .propagate_natural_height(true)
+            .build();
+
+        let dialog = gtk::MessageDialog::builder()
+            .transient_for(&self.preferences.window)
+            .modal(true)
+            .text(format!(
+                "Found {} model{}",
+                models.len(),
+                if models.len() == 1 { "" } else { "s" }
+            ))
+            .secondary_text("Choose the model to use for local completions.")
+            .build();
+        dialog.message_area().append(&scroller);
+        dialog.add_button("Cancel", gtk::ResponseType::Cancel);
+        dialog.add_button("Use Model", gtk::ResponseType::Accept);
+        dialog.set_default_response(gtk::ResponseType::Accept);
+
+        let weak = Rc::downgrade(self);
+        dialog.connect_response(move |dialog, response| {
+            if response == gtk::ResponseType::Accept {
+                let chosen = list
+                    .selected_row()
+                    .and_then(|row| models.get(row.index() as usize));
+                if let (Some(state), Some(model)) = (weak.upgrade(), chosen) {
+                    state.import_model(model.clone(), true);
+                }
+            }
+            dialog.close();
+        });
+        dialog.show();
+    }
+
+    /// Point the custom model path at `model`. Going through the widgets lets
+    /// their change handlers update and save the settings.
+    fn use_local_model_file(self: &Rc<Self>, model: &Path) {
+        self.preferences.override_model_switch.set_active(true);
+        self.preferences
+            .llm_model_row
+            .set_text(&model.display().to_string());
+        log::info!("Using model {}", model.display());
+        self.show_preferences_toast("Model selected; it loads on the next completion");
+        self.refresh_downloaded_models();
+    }
+
+    fn choose_models_dir(self: &Rc<Self>) {
+        let dialog = gtk::FileChooserDialog::builder()
+            .title("Choose Models Folder")
+            .transient_for(&self.preferences.window)
+            .modal(true)
+            .action(gtk::FileChooserAction::SelectFolder)
+            .build();
+        dialog.add_button("Cancel", gtk::ResponseType::Cancel);
+        dialog.add_button("Select", gtk::ResponseType::Accept);
+        let weak = Rc::downgrade(self);
+        dialog.connect_response(move |dialog, response| {
+            if response == gtk::ResponseType::Accept {
+                if let Some(state) = weak.upgrade() {
+                    if let Some(path) = dialog.file().and_then(|file| file.path()) {
+                        state.set_models_dir(Some(path));
+                    }
+                }
+            }
+            dialog.close();
+        });
+        dialog.present();
+    }
+
+    /// Switch the models directory; `None` goes back to the default one.
+    fn set_models_dir(self: &Rc<Self>, dir: Option<PathBuf>) {
+        // Choosing the default folder explicitly is the same as resetting
+        let dir = dir.filter(|dir| *dir != self.paths.models_dir);
+        let new_dir = dir.clone().unwrap_or_else(|| self.paths.models_dir.clone());
+        let old_dir = self.model_downloader.borrow().models_dir().to_path_buf();
+        if new_dir == old_dir {
+            return;
+        }
+        if let Err(err) = ModelDownloader::ensure_writable(&new_dir) {
+            log::warn!("Rejected models folder: {err:#}");
+            self.show_preferences_toast(&format!("Can't use this folder: {err:#}"));
+            return;
+        }
+
+        self.settings.borrow_mut().llm.models_dir_override = dir;
+        if let Err(err) = self.settings.borrow().save(&self.paths) {
+            log::warn!("Failed to save settings: {err:?}");
+        }
+        self.refresh_llm_manager_config();
+        let downloader = ModelDownloader::new(new_dir);
+        let found = downloader.list_models().len();
+        let old_models = self
+            .model_downloader
+            .replace(downloader)
+            .list_models()
+            .len();
+        self.sync_models_dir_row();
+        self.refresh_downloaded_models();
+
+        let toast = adw::Toast::new(&format!(
+            "Models folder changed ({} model{} found)",
+            found,
+            if found == 1 { "" } else { "s" }
+        ));
+        // Offer to bring the existing models along instead of downloading them again
+        if old_models > 0 {
+            toast.set_button_label(Some("Move Models Here"));
+            let weak = Rc::downgrade(self);
+            toast.connect_button_clicked(move |_| {
+                if let Some(state) = weak.upgrade() {
+                    state.move_models_from(old_dir.clone());
+                }
+            });
+            toast.set_timeout(10);
+        }
+        self.preferences.window.add_toast(toast);
+    }
+
+    pub(super) fn sync_models_dir_row(&self) {
+        let settings = self.settings.borrow();
+        self.preferences
+            .models_dir_row
+            .set_subtitle(&preferences::models_dir_subtitle(&settings.llm));
+        self.preferences
+            .models_dir_reset_button
+            .set_sensitive(settings.llm.models_dir_override.is_some());
+    }
+
+    /// Move models from `old_dir` into the current models directory in the background.
+    fn move_models_from(self: &Rc<Self>, old_dir: PathBuf) {
+        let source = ModelDownloader::new(old_dir);
+        let dest = self.model_downloader.borrow().models_dir().to_path_buf();
+        self.show_preferences_toast("Moving models…");
+
+        let (tx, rx) = mpsc::channel::<anyhow::Result<usize>>();
+        std::thread::spawn(move || {
+            let _ = tx.send(source.move_models_to(&dest));
+        });
+
+        let weak = Rc::downgrade(self);
+        glib::timeout_add_local(std::time::Duration::from_millis(200), move || {
+            let Some(state) = weak.upgrade() else {
+                return glib::ControlFlow::Break;
+            };
+            let result = match rx.try_recv() {
+                Ok(result) => result,
+                Err(mpsc::TryRecvError::Empty) => return glib::ControlFlow::Continue,
+                Err(mpsc::TryRecvError::Disconnected) => return glib::ControlFlow::Break,
+            };
+            match result {
+                Ok(moved) => {
+                    log::info!("Moved {} models to the new folder", moved);
+                    state.show_preferences_toast(&format!("Moved {} models", moved));
+                    state.refresh_downloaded_models();
+                }
+                Err(err) => {
+                    log::warn!("Failed to move models: {err:?}");
+                    state.present_error("Failed to move models", &format!("{err:#}"));
+                }
+            }
+            glib::ControlFlow::Break
+        });
+    }
+
+    fn show_download_banner(&self, title: &str) {
+        self.download_title.replace(Some(title.to_string()));
+        self.download_label
+            .set_text(&format!("{} — preparing", title));
+        self.download_progress.set_fraction(0.0);
+        self.download_progress.set_text(Some("Preparing download…"));
+        self.download_revealer.set_reveal_child(true);
+    }
+
+    fn update_download_progress(&self, progress: DownloadProgress) {
+        let base = self
+            .download_title
+            .borrow()
+            .clone()
+            .unwrap_or_else(|| "Model download".into());
+
+        match progress.phase {
+            DownloadPhase::Preparing => {
+                self.download_label
+                    .set_text(&format!("{} — preparing", base));
+                self.download_progress.pulse();
+                self.download_progress.set_text(Some("Preparing download…"));
+            }
+            DownloadPhase::VerifyingExisting => {
+                self.download_label
+                    .set_text(&format!("{} — verifying existing file", base));
+                self.update_progress_bar(progress);
+            }
+            DownloadPhase::Retrying { attempt, of } => {
+                self.download_label
+                    .set_text(&format!("{} — retrying ({}/{})…", base, attempt, of));
+                self.download_progress
+                    .set_text(Some("Connection lost, retrying…"));
+            }
+            DownloadPhase::Resuming => {
+                let resumed_at = progress
+                    .total
+                    .filter(|total| *total > 0)
+                    .map(|total| {
+                        format!(
+                            " at {:.0}%",
+                            progress.downloaded as f64 / total as f64 * 100.0
+                        )
+                    })
+                    .unwrap_or_default();
+                self.download_label
+                    .set_text(&format!("{} — resuming{}", base, resumed_at));
+                self.update_progress_bar(progress);
+            }
+            DownloadPhase::Downloading => {
+                self.download_label
+                    .set_text(&format!("{} — downloading", base));
+                self.update_progress_bar(progress);
+            }
+            DownloadPhase::Finished => {
+                self.download_label
+                    .set_text(&format!("{} — finishing", base));
+                self.download_progress.set_fraction(1.0);
+                self.download_progress.set_text(Some("Download complete"));
+            }
+        }
+    }
+
+    fn hide_download_banner(&self) {
+        self.download_revealer.set_reveal_child(false);
+        self.download_title.replace(None);
+        self.download_cancel.replace(None);
+    }
+
+    pub(super) fn cancel_download(&self) {
+        if let Some(cancel) = self.download_cancel.borrow().as_ref() {
+            cancel.store(true, Ordering::Relaxed);
+            let title = self.download_title.borrow().clone().unwrap_or_default();
+            self.download_label
+                .set_text(&format!("{} — cancelling", title));
+        }
+    }
+
+    fn update_progress_bar(&self, progress: DownloadProgress) {
+        if let Some(total) = progress.total.filter(|t| *t > 0) {
+            let fraction = (progress.downloaded as f64 / total as f64).clamp(0.0, 1.0);
+            self.download_progress.set_fraction(fraction);
+            let rate = progress
+                .speed_bps
+                .map(|bps| {
+                    let mut rate = format!(" — {}/s", human_readable_bytes(bps));
+                    if bps > 0 {
+                        let remaining = total.saturating_sub(progress.downloaded) / bps;
+                        rate.push_str(&format!(", ~{} left", format_eta(remaining)));
+                    }
+                    rate
+                })
+                .unwrap_or_default();
+            self.download_progress.set_text(Some(&format!(
+                "{:.1}% ({} / {}){}",
+                fraction * 100.0,
+                human_readable_bytes(progress.downloaded),
+                human_readable_bytes(total),
+                rate
+            )));
+        } else {
+            self.download_progress.pulse();
+            let rate = progress
+                .speed_bps
+                .map(|bps| format!(" — {}/s", human_readable_bytes(bps)))
+                .unwrap_or_default();
+            self.download_progress.set_text(Some(&format!(
+                "{} downloaded{}",
+                human_readable_bytes(progress.downloaded),
+                rate
+            )));
+        }
+    }
+
+    pub(super) fn download_llm_model(self: &Rc<Self>, model_ref: String) {
+        let trimmed = model_ref.trim();
+        if trimmed.is_empty() {
+            let toast = adw::Toast::new("Specify a model reference before downloading.");
+            toast.set_timeout(6);
+            self.toast_overlay.add_toast(toast);
+            return;
+        }
+
+        let parsed_model = match HuggingFaceModel::parse(trimmed) {
+            Ok(model) => model,
+            Err(err) => {
+                let error_toast =
+                    adw::Toast::new(&format!("Invalid model reference '{}': {}", trimmed, err));
+                error_toast.set_timeout(10);
+                self.toast_overlay.add_toast(error_toast);
+                self.status_label
+                    .set_text(&format!("Invalid model reference: {}", err));
+                return;
+            }
+        };
+
+        let model_name = parsed_model.filename();
+        self.show_download_banner(&model_name);
+
+        enum DownloadMsg {
+            Progress(DownloadProgress),
+            Finished(anyhow::Result<PathBuf>),
+        }
+
+        let mut downloader = self.model_downloader.borrow().clone();
+        downloader.set_hf_token(self.settings.borrow().llm.hf_token());
+        let (sender, receiver) = mpsc::channel::<DownloadMsg>();
+        let cancel = Arc::new(AtomicBool::new(false));
+        self.download_cancel.replace(Some(cancel.clone()));
+
+        std::thread::spawn(move || {
+            let thread_sender = sender.clone();
+            let result = downloader.download_with_progress(&parsed_model, &cancel, |progress| {
+                let _ = thread_sender.send(DownloadMsg::Progress(progress));
+            });
+            let _ = thread_sender.send(DownloadMsg::Finished(result));
+        });
+
+        let weak = Rc::downgrade(self);
+        glib::timeout_add_local(std::time::Duration::from_millis(50), move || match receiver
+            .try_recv()
+        {
+            Ok(DownloadMsg::Progress(progress)) => {
+                if let Some(state) = weak.upgrade() {
+                    state.update_download_progress(progress);
+                }
+                ControlFlow::Continue
+            }
+            Ok(DownloadMsg::Finished(result)) => {
+                if let Some(state) = weak.upgrade() {
+                    state.hide_download_banner();
+                    match result {
+                        Ok(path) => {
+                            let success_toast = adw::Toast::new(&format!(
+                                "Model downloaded successfully: {}",
+                                path.display()
+                            ));
+                            success_toast.set_timeout(5);
+                            state.toast_overlay.add_toast(success_toast);
+                            state.status_label.set_text("Model ready for use");
+                            state.refresh_downloaded_models();
+                            state.notify_desktop(
+                                "model-download",
+                                "Model download finished",
+                                &path.display().to_string(),
+                            );
+                        }
+                        Err(err) if err.is::<DownloadCancelled>() => {
+                            let toast = adw::Toast::new("Download cancelled");
+                            toast.set_timeout(4);
+                            state.toast_overlay.add_toast(toast);
+                            state.status_label.set_text("Download cancelled");
+                        }
+                        Err(err) => {
+                            state.notify_desktop(
+                                "model-download",
+                                "Model download failed",
+                                &err.to_string(),
+                            );
+                            let error_toast =
+                                adw::Toast::new(&format!("Failed to download model: {}", err));
+                            error_toast.set_timeout(10);
+                            state.toast_overlay.add_toast(error_toast);
+                            state
+                                .status_label
+                                .set_text(&format!("Download failed: {}", err));
+                        }
+                    }
+                }
+                ControlFlow::Break
+            }
+            Err(mpsc::TryRecvError::Empty) => ControlFlow::Continue,
+            Err(mpsc::TryRecvError::Disconnected) => {
+                if let Some(state) = weak.upgrade() {
+                    state.hide_download_banner();
+                    state
+                        .status_label
+                        .set_text("Download interrupted unexpectedly");
+                }
+                ControlFlow::Break
+            }
+        });
+    }
+}
+
+/// Popover under `button` listing `files`; choosing one writes `base:file` into `row`.
+fn present_repo_files(row: &adw::EntryRow, button: &gtk::Button, base: &str, files: Vec<RepoFile>) {
+    let list = gtk::ListBox::builder()
+        .selection_mode(gtk::SelectionMode::None)
+        .css_classes(["boxed-list"])
+        .build();
+    for file in &files {
+        let size = file
+            .size
+            .map(|size| glib::format_size(size).to_string())
+            .unwrap_or_default();
+        let item = adw::ActionRow::builder()
+            .title(file.name.as_str())
+            .subtitle(size)
+            .activatable(true)
+            .build();
+        list.append(&item);
+    }
+    let scroller = gtk::ScrolledWindow::builder()
+        .child(&list)
+        .hscrollbar_policy(gtk::PolicyType::Never)
+        .min_content_width(320)
+        .max_content_height(360)
+        .propagate_natural_height(true)
+        .build();
+
+    let popover = gtk::Popover::builder().child(&scroller).build();
+    popover.set_parent(button);
+    popover.connect_closed(|popover| {
+        // Unparenting inside the signal handler upsets GTK; wait until it's done
+        let popover = popover.clone();
+        glib::idle_add_local_once(move || popover.unparent());
+    });
+
+    let row = row.clone();
+    let base = base.to_string();
+    let weak_popover = popover.downgrade();
+    list.connect_row_activated(move |_, item| {
+        if let Some(file) = files.get(item.index() as usize) {
+            // The row's change handler saves the setting
+            row.set_text(&format!("{}:{}", base, file.name));
+        }
+        if let Some(popover) = weak_popover.upgrade() {
+            popover.popdown();
+        }
+    });
+    popover.popup();
+}
+
+/// Whether `path` is a link to a model kept elsewhere, from a folder scan
+fn is_linked(path: &Path) -> bool {
+    fs::symlink_metadata(path).is_ok_and(|meta| meta.is_symlink())
+}
+
+fn human_readable_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KB", "MB", "GB", "TB"];
+    if bytes == 0 {
+        return "0 B".into();
+    }
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{bytes} {}", UNITS[unit])
+    } else {
+        format!("{value:.1} {}", UNITS[unit])
+    }
+}
+
+/// Rough time left, e.g. "45s", "5m" or "1h 20m"
+fn format_eta(secs: u64) -> String {
+    match secs {
+        0..60 => format!("{secs}s"),
+        60..3600 => format!("{}m", secs / 60),
+        _ => format!("{}h {}m", secs / 3600, secs % 3600 / 60),
+    }
+}
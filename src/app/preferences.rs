@@ -2,26 +2,107 @@ use gtk4::{self as gtk, glib};
 use libadwaita::prelude::*;
 use libadwaita::{self as adw};
 
-use crate::llm::{GpuDevice, LlmSettings, ProviderKind};
-use crate::settings::Settings;
+use crate::llm::{
+    ALL_GPU_LAYERS, CompletionProfile, CompletionScope, ContextStrategy, FimTemplate, GpuDevice,
+    LlmSettings, ProviderKind,
+};
+use crate::settings::{AutosaveMode, Settings, TabAction};
 
 pub(super) struct PreferencesUi {
     pub window: adw::PreferencesWindow,
     pub autosave_combo: adw::ComboRow,
     pub autosave_idle_switch: gtk::Switch,
+    pub autosave_mode_combo: adw::ComboRow,
+    pub autosave_snapshots_spin: gtk::SpinButton,
+    pub notifications_switch: gtk::Switch,
     pub llm_provider_combo: adw::ComboRow,
     pub llm_endpoint_row: adw::EntryRow,
+    pub remote_model_row: adw::EntryRow,
+    pub test_connection_button: gtk::Button,
+    pub connection_check_row: adw::ActionRow,
+    pub connection_check_switch: gtk::Switch,
     pub override_model_switch: gtk::Switch,
     pub llm_model_row: adw::EntryRow,
     pub gpu_combo: adw::ComboRow,
     pub gpu_model_row: adw::EntryRow,
+    pub gpu_files_button: gtk::Button,
     pub gpu_download_button: gtk::Button,
     pub cpu_model_row: adw::EntryRow,
+    pub cpu_files_button: gtk::Button,
     pub cpu_download_button: gtk::Button,
     pub reset_defaults_button: gtk::Button,
+    pub load_model_button: gtk::Button,
+    pub models_dir_row: adw::ActionRow,
+    pub models_dir_button: gtk::Button,
+    pub models_dir_reset_button: gtk::Button,
+    pub scan_models_button: gtk::Button,
+    pub import_model_button: gtk::Button,
+    pub downloaded_models_group: adw::PreferencesGroup,
+    pub downloaded_models_list: gtk::ListBox,
+    pub gpu_layers_row: adw::ActionRow,
+    pub gpu_layers_spin: gtk::SpinButton,
+    pub auto_tune_button: gtk::Button,
+    pub loaded_model_row: adw::ActionRow,
     pub max_tokens_spin: gtk::SpinButton,
+    pub context_size_spin: gtk::SpinButton,
+    pub temperature_spin: gtk::SpinButton,
+    pub top_p_spin: gtk::SpinButton,
+    pub top_k_spin: gtk::SpinButton,
+    pub repeat_penalty_switch: gtk::Switch,
+    pub repeat_penalty_spin: gtk::SpinButton,
+    pub penalty_last_n_spin: gtk::SpinButton,
+    pub frequency_penalty_spin: gtk::SpinButton,
+    pub presence_penalty_spin: gtk::SpinButton,
+    pub seed_row: adw::EntryRow,
+    pub cache_size_spin: gtk::SpinButton,
+    pub latency_target_spin: gtk::SpinButton,
+    pub idle_unload_spin: gtk::SpinButton,
+    pub api_key_row: adw::PasswordEntryRow,
+    pub hf_token_row: adw::PasswordEntryRow,
+    pub auto_completion_switch: gtk::Switch,
+    pub completion_profile_combo: adw::ComboRow,
+    pub completion_scope_combo: adw::ComboRow,
+    pub context_strategy_combo: adw::ComboRow,
+    pub context_lines_spin: gtk::SpinButton,
+    pub context_prefix_spin: gtk::SpinButton,
+    pub context_suffix_spin: gtk::SpinButton,
+    pub fim_template_combo: adw::ComboRow,
+    pub bulk_insert_spin: gtk::SpinButton,
+    pub completion_debounce_spin: gtk::SpinButton,
+    pub completion_max_wait_spin: gtk::SpinButton,
+    pub trigger_only_switch: gtk::Switch,
+    pub trigger_chars_row: adw::EntryRow,
+    pub stop_at_sentence_switch: gtk::Switch,
+    pub include_filename_switch: gtk::Switch,
+    pub starter_prompt_row: adw::EntryRow,
+    pub blocklist_view: gtk::TextView,
+    pub regenerate_blocked_switch: gtk::Switch,
+    pub reveal_ghost_switch: gtk::Switch,
+    pub dismiss_on_click_switch: gtk::Switch,
+    pub explain_completions_switch: gtk::Switch,
+    pub font_row: adw::ActionRow,
+    pub font_button: gtk::Button,
+    pub font_reset_button: gtk::Button,
+    pub line_numbers_switch: gtk::Switch,
     pub whitespace_switch: gtk::Switch,
     pub wrap_switch: gtk::Switch,
+    pub tab_action_combo: adw::ComboRow,
+    pub confirm_overwrite_switch: gtk::Switch,
+    pub pause_monitor_switch: gtk::Switch,
+    pub final_newline_switch: gtk::Switch,
+    pub trim_whitespace_switch: gtk::Switch,
+    pub max_recent_spin: gtk::SpinButton,
+    pub reopen_last_file_switch: gtk::Switch,
+    pub tab_width_spin: gtk::SpinButton,
+    pub insert_spaces_switch: gtk::Switch,
+    pub auto_indent_switch: gtk::Switch,
+    pub current_line_switch: gtk::Switch,
+    pub matching_brackets_switch: gtk::Switch,
+    pub follow_system_style_switch: gtk::Switch,
+    pub dark_style_switch: gtk::Switch,
+    pub style_scheme_combo: adw::ComboRow,
+    pub ghost_color_button: gtk::ColorButton,
+    pub ghost_color_reset_button: gtk::Button,
 }
 
 pub(super) fn build_preferences(
@@ -52,32 +133,161 @@ pub(super) fn build_preferences(
     autosave_idle_row.add_suffix(&autosave_idle_switch);
     autosave_idle_row.set_activatable_widget(Some(&autosave_idle_switch));
 
+    let mode_names: Vec<&'static str> = AUTOSAVE_MODES.iter().map(|(_, name)| *name).collect();
+    let autosave_mode_combo = adw::ComboRow::builder()
+        .title("Save To")
+        .subtitle("Saving to the file keeps its previous version as a .bak")
+        .model(&gtk::StringList::new(mode_names.as_slice()))
+        .selected(autosave_mode_index(&settings.autosave_mode) as u32)
+        .build();
+
+    let autosave_snapshots_row = adw::ActionRow::builder()
+        .title("Snapshots Kept")
+        .subtitle("Older recovery snapshots help when a bad edit was already autosaved")
+        .build();
+    let autosave_snapshots_spin = gtk::SpinButton::builder()
+        .adjustment(&gtk::Adjustment::new(
+            settings.autosave_snapshots as f64,
+            1.0,
+            50.0,
+            1.0,
+            5.0,
+            0.0,
+        ))
+        .valign(gtk::Align::Center)
+        .build();
+    autosave_snapshots_row.add_suffix(&autosave_snapshots_spin);
+
     let autosave_group = adw::PreferencesGroup::builder().title("Behavior").build();
     autosave_group.add(&autosave_combo);
+    autosave_group.add(&autosave_mode_combo);
+    autosave_group.add(&autosave_snapshots_row);
     autosave_group.add(&autosave_idle_row);
 
+    let notifications_switch = gtk::Switch::builder()
+        .valign(gtk::Align::Center)
+        .active(settings.desktop_notifications)
+        .build();
+    let notifications_row = adw::ActionRow::builder()
+        .title("Desktop Notifications")
+        .subtitle("Report autosaves, recovery and downloads while in the background")
+        .build();
+    notifications_row.add_suffix(&notifications_switch);
+    notifications_row.set_activatable_widget(Some(&notifications_switch));
+
+    let notifications_group = adw::PreferencesGroup::builder()
+        .title("Notifications")
+        .build();
+    notifications_group.add(&notifications_row);
+
     let autosave_page = adw::PreferencesPage::builder()
         .title("Autosave")
         .icon_name("document-save-symbolic")
         .build();
     autosave_page.add(&autosave_group);
+    autosave_page.add(&notifications_group);
 
-    let (editor_page, whitespace_switch, wrap_switch) = build_editor_page(settings);
-    let (
-        llm_page,
+    let EditorPage {
+        page: editor_page,
+        font_row,
+        font_button,
+        font_reset_button,
+        line_numbers_switch,
+        whitespace_switch,
+        wrap_switch,
+        tab_action_combo,
+        confirm_overwrite_switch,
+        pause_monitor_switch,
+        final_newline_switch,
+        trim_whitespace_switch,
+        max_recent_spin,
+        reopen_last_file_switch,
+        tab_width_spin,
+        insert_spaces_switch,
+        auto_indent_switch,
+        current_line_switch,
+        matching_brackets_switch,
+    } = build_editor_page(settings);
+    let LlmPage {
+        page: llm_page,
         llm_provider_combo,
         llm_endpoint_row,
+        remote_model_row,
+        test_connection_button,
+        connection_check_row,
+        connection_check_switch,
         override_model_switch,
         llm_model_row,
         gpu_combo,
         gpu_model_row,
+        gpu_files_button,
         gpu_download_button,
         cpu_model_row,
+        cpu_files_button,
         cpu_download_button,
         reset_defaults_button,
+        load_model_button,
+        models_dir_row,
+        models_dir_button,
+        models_dir_reset_button,
+        scan_models_button,
+        import_model_button,
+        downloaded_models_group,
+        downloaded_models_list,
+        gpu_layers_row,
+        gpu_layers_spin,
+        auto_tune_button,
+        loaded_model_row,
         max_tokens_spin,
-    ) = build_llm_page(&settings.llm, gpus);
-    let theming_page = build_theming_page();
+        context_size_spin,
+        temperature_spin,
+        top_p_spin,
+        top_k_spin,
+        repeat_penalty_switch,
+        repeat_penalty_spin,
+        penalty_last_n_spin,
+        frequency_penalty_spin,
+        presence_penalty_spin,
+        seed_row,
+        cache_size_spin,
+        latency_target_spin,
+        idle_unload_spin,
+        api_key_row,
+        hf_token_row,
+    } = build_llm_page(&settings.llm, gpus);
+    let CompletionGroup {
+        group: completion_group,
+        auto_completion_switch,
+        completion_profile_combo,
+        completion_scope_combo,
+        context_strategy_combo,
+        context_lines_spin,
+        context_prefix_spin,
+        context_suffix_spin,
+        fim_template_combo,
+        bulk_insert_spin,
+        completion_debounce_spin,
+        completion_max_wait_spin,
+        trigger_only_switch,
+        trigger_chars_row,
+        stop_at_sentence_switch,
+        include_filename_switch,
+        starter_prompt_row,
+        blocklist_view,
+        regenerate_blocked_switch,
+        reveal_ghost_switch,
+        dismiss_on_click_switch,
+        explain_completions_switch,
+    } = build_completion_group(&settings.llm);
+    llm_page.add(&completion_group);
+    let ThemingPage {
+        page: theming_page,
+        follow_system_style_switch,
+        dark_style_switch,
+        style_scheme_combo,
+        ghost_color_button,
+        ghost_color_reset_button,
+    } = build_theming_page(settings);
     // Shortcuts page removed for now as it was empty/placeholder
 
     let window = adw::PreferencesWindow::builder()
@@ -94,37 +304,159 @@ pub(super) fn build_preferences(
         window,
         autosave_combo,
         autosave_idle_switch,
+        autosave_mode_combo,
+        autosave_snapshots_spin,
+        notifications_switch,
         llm_provider_combo,
         llm_endpoint_row,
+        remote_model_row,
+        test_connection_button,
+        connection_check_row,
+        connection_check_switch,
         override_model_switch,
         llm_model_row,
         gpu_combo,
         gpu_model_row,
+        gpu_files_button,
         gpu_download_button,
         cpu_model_row,
+        cpu_files_button,
         cpu_download_button,
         reset_defaults_button,
+        load_model_button,
+        models_dir_row,
+        models_dir_button,
+        models_dir_reset_button,
+        scan_models_button,
+        import_model_button,
+        downloaded_models_group,
+        downloaded_models_list,
+        gpu_layers_row,
+        gpu_layers_spin,
+        auto_tune_button,
+        loaded_model_row,
         max_tokens_spin,
+        context_size_spin,
+        temperature_spin,
+        top_p_spin,
+        top_k_spin,
+        repeat_penalty_switch,
+        repeat_penalty_spin,
+        penalty_last_n_spin,
+        frequency_penalty_spin,
+        presence_penalty_spin,
+        seed_row,
+        cache_size_spin,
+        latency_target_spin,
+        idle_unload_spin,
+        api_key_row,
+        hf_token_row,
+        auto_completion_switch,
+        completion_profile_combo,
+        completion_scope_combo,
+        context_strategy_combo,
+        context_lines_spin,
+        context_prefix_spin,
+        context_suffix_spin,
+        fim_template_combo,
+        bulk_insert_spin,
+        completion_debounce_spin,
+        completion_max_wait_spin,
+        trigger_only_switch,
+        trigger_chars_row,
+        stop_at_sentence_switch,
+        include_filename_switch,
+        starter_prompt_row,
+        blocklist_view,
+        regenerate_blocked_switch,
+        reveal_ghost_switch,
+        dismiss_on_click_switch,
+        explain_completions_switch,
+        font_row,
+        font_button,
+        font_reset_button,
+        line_numbers_switch,
         whitespace_switch,
         wrap_switch,
+        tab_action_combo,
+        confirm_overwrite_switch,
+        pause_monitor_switch,
+        final_newline_switch,
+        trim_whitespace_switch,
+        max_recent_spin,
+        reopen_last_file_switch,
+        tab_width_spin,
+        insert_spaces_switch,
+        auto_indent_switch,
+        current_line_switch,
+        matching_brackets_switch,
+        follow_system_style_switch,
+        dark_style_switch,
+        style_scheme_combo,
+        ghost_color_button,
+        ghost_color_reset_button,
     }
 }
 
-fn build_editor_page(settings: &Settings) -> (adw::PreferencesPage, gtk::Switch, gtk::Switch) {
+/// Widgets on the Editor page the window reads and updates
+struct EditorPage {
+    page: adw::PreferencesPage,
+    font_row: adw::ActionRow,
+    font_button: gtk::Button,
+    font_reset_button: gtk::Button,
+    line_numbers_switch: gtk::Switch,
+    whitespace_switch: gtk::Switch,
+    wrap_switch: gtk::Switch,
+    tab_action_combo: adw::ComboRow,
+    confirm_overwrite_switch: gtk::Switch,
+    pause_monitor_switch: gtk::Switch,
+    final_newline_switch: gtk::Switch,
+    trim_whitespace_switch: gtk::Switch,
+    max_recent_spin: gtk::SpinButton,
+    reopen_last_file_switch: gtk::Switch,
+    tab_width_spin: gtk::SpinButton,
+    insert_spaces_switch: gtk::Switch,
+    auto_indent_switch: gtk::Switch,
+    current_line_switch: gtk::Switch,
+    matching_brackets_switch: gtk::Switch,
+}
+
+fn build_editor_page(settings: &Settings) -> EditorPage {
     let page = adw::PreferencesPage::builder()
         .title("Editor")
         .icon_name("accessories-text-editor-symbolic")
         .build();
     let group = adw::PreferencesGroup::builder().title("Appearance").build();
 
-    // Font selection (simplified placeholder for now, ideally GtkFontDialog)
     let font_row = adw::ActionRow::builder()
         .title("Font")
-        .subtitle("System default")
+        .subtitle(font_label(&settings.editor_font))
+        .build();
+    let font_reset_button = gtk::Button::builder()
+        .icon_name("edit-undo-symbolic")
+        .tooltip_text("Use System Font")
+        .valign(gtk::Align::Center)
+        .sensitive(!settings.editor_font.is_empty())
         .build();
-    font_row.add_suffix(&gtk::Button::with_label("Select…")); // Keeping simple for now
+    font_reset_button.add_css_class("flat");
+    let font_button = gtk::Button::builder()
+        .label("Select…")
+        .valign(gtk::Align::Center)
+        .build();
+    font_row.add_suffix(&font_reset_button);
+    font_row.add_suffix(&font_button);
+    font_row.set_activatable_widget(Some(&font_button));
     group.add(&font_row);
 
+    let line_numbers_row = adw::ActionRow::builder().title("Show Line Numbers").build();
+    let line_numbers_switch = gtk::Switch::builder()
+        .valign(gtk::Align::Center)
+        .active(settings.show_line_numbers)
+        .build();
+    line_numbers_row.add_suffix(&line_numbers_switch);
+    line_numbers_row.set_activatable_widget(Some(&line_numbers_switch));
+    group.add(&line_numbers_row);
+
     let whitespace_row = adw::ActionRow::builder().title("Show Whitespace").build();
     let whitespace_switch = gtk::Switch::builder()
         .valign(gtk::Align::Center)
@@ -143,27 +475,219 @@ fn build_editor_page(settings: &Settings) -> (adw::PreferencesPage, gtk::Switch,
     wrap_row.set_activatable_widget(Some(&wrap_switch));
     group.add(&wrap_row);
 
+    let current_line_row = adw::ActionRow::builder()
+        .title("Highlight Current Line")
+        .build();
+    let current_line_switch = gtk::Switch::builder()
+        .valign(gtk::Align::Center)
+        .active(settings.highlight_current_line)
+        .build();
+    current_line_row.add_suffix(&current_line_switch);
+    current_line_row.set_activatable_widget(Some(&current_line_switch));
+    group.add(&current_line_row);
+
+    let matching_brackets_row = adw::ActionRow::builder()
+        .title("Highlight Matching Brackets")
+        .build();
+    let matching_brackets_switch = gtk::Switch::builder()
+        .valign(gtk::Align::Center)
+        .active(settings.highlight_matching_brackets)
+        .build();
+    matching_brackets_row.add_suffix(&matching_brackets_switch);
+    matching_brackets_row.set_activatable_widget(Some(&matching_brackets_switch));
+    group.add(&matching_brackets_row);
+
     page.add(&group);
-    (page, whitespace_switch, wrap_switch)
+
+    let indent_group = adw::PreferencesGroup::builder()
+        .title("Indentation")
+        .build();
+    let (tab_width_row, tab_width_spin) = spin_row(
+        "Tab Width",
+        Some("Columns a tab character spans"),
+        gtk::Adjustment::new(settings.tab_width as f64, 1.0, 16.0, 1.0, 2.0, 0.0),
+    );
+    indent_group.add(&tab_width_row);
+
+    let insert_spaces_row = adw::ActionRow::builder()
+        .title("Indent With Spaces")
+        .subtitle("Tab inserts spaces up to the next tab stop")
+        .build();
+    let insert_spaces_switch = gtk::Switch::builder()
+        .valign(gtk::Align::Center)
+        .active(settings.insert_spaces)
+        .build();
+    insert_spaces_row.add_suffix(&insert_spaces_switch);
+    insert_spaces_row.set_activatable_widget(Some(&insert_spaces_switch));
+    indent_group.add(&insert_spaces_row);
+
+    let auto_indent_row = adw::ActionRow::builder()
+        .title("Auto Indent")
+        .subtitle("New lines start at the previous line's indentation")
+        .build();
+    let auto_indent_switch = gtk::Switch::builder()
+        .valign(gtk::Align::Center)
+        .active(settings.auto_indent)
+        .build();
+    auto_indent_row.add_suffix(&auto_indent_switch);
+    auto_indent_row.set_activatable_widget(Some(&auto_indent_switch));
+    indent_group.add(&auto_indent_row);
+    page.add(&indent_group);
+
+    let behavior_group = adw::PreferencesGroup::builder().title("Behavior").build();
+    let tab_action_names: Vec<&'static str> = TAB_ACTIONS.iter().map(|(_, name)| *name).collect();
+    let tab_action_list = gtk::StringList::new(tab_action_names.as_slice());
+    let tab_action_combo = adw::ComboRow::builder()
+        .title("Tab Without a Suggestion")
+        .subtitle("With a suggestion showing, Tab always accepts it")
+        .model(&tab_action_list)
+        .selected(tab_action_index(&settings.tab_action) as u32)
+        .build();
+    behavior_group.add(&tab_action_combo);
+    page.add(&behavior_group);
+
+    let files_group = adw::PreferencesGroup::builder().title("Files").build();
+    let confirm_overwrite_row = adw::ActionRow::builder()
+        .title("Confirm Overwrite")
+        .subtitle("Ask before Save As replaces an existing file")
+        .build();
+    let confirm_overwrite_switch = gtk::Switch::builder()
+        .valign(gtk::Align::Center)
+        .active(settings.confirm_overwrite)
+        .build();
+    confirm_overwrite_row.add_suffix(&confirm_overwrite_switch);
+    confirm_overwrite_row.set_activatable_widget(Some(&confirm_overwrite_switch));
+    files_group.add(&confirm_overwrite_row);
+
+    let pause_monitor_row = adw::ActionRow::builder()
+        .title("Pause File Watching in Background")
+        .subtitle("Saves system watch slots; changes are checked when the window regains focus")
+        .build();
+    let pause_monitor_switch = gtk::Switch::builder()
+        .valign(gtk::Align::Center)
+        .active(settings.pause_monitor_in_background)
+        .build();
+    pause_monitor_row.add_suffix(&pause_monitor_switch);
+    pause_monitor_row.set_activatable_widget(Some(&pause_monitor_switch));
+    files_group.add(&pause_monitor_row);
+
+    let final_newline_row = adw::ActionRow::builder()
+        .title("Insert Final Newline")
+        .subtitle("End saved files with a line break")
+        .build();
+    let final_newline_switch = gtk::Switch::builder()
+        .valign(gtk::Align::Center)
+        .active(settings.ensure_final_newline)
+        .build();
+    final_newline_row.add_suffix(&final_newline_switch);
+    final_newline_row.set_activatable_widget(Some(&final_newline_switch));
+    files_group.add(&final_newline_row);
+
+    let trim_whitespace_row = adw::ActionRow::builder()
+        .title("Trim Trailing Whitespace")
+        .subtitle("Remove spaces and tabs at line ends when saving")
+        .build();
+    let trim_whitespace_switch = gtk::Switch::builder()
+        .valign(gtk::Align::Center)
+        .active(settings.trim_trailing_whitespace_on_save)
+        .build();
+    trim_whitespace_row.add_suffix(&trim_whitespace_switch);
+    trim_whitespace_row.set_activatable_widget(Some(&trim_whitespace_switch));
+    files_group.add(&trim_whitespace_row);
+
+    let (max_recent_row, max_recent_spin) = spin_row(
+        "Recent Files",
+        Some("How many files the Recent Files list remembers; 0 turns it off"),
+        gtk::Adjustment::new(settings.max_recent_files as f64, 0.0, 50.0, 1.0, 5.0, 0.0),
+    );
+    files_group.add(&max_recent_row);
+
+    let reopen_last_file_row = adw::ActionRow::builder()
+        .title("Reopen Last File")
+        .subtitle("Otherwise a notification offers to reopen it on start")
+        .build();
+    let reopen_last_file_switch = gtk::Switch::builder()
+        .valign(gtk::Align::Center)
+        .active(settings.reopen_last_file)
+        .build();
+    reopen_last_file_row.add_suffix(&reopen_last_file_switch);
+    reopen_last_file_row.set_activatable_widget(Some(&reopen_last_file_switch));
+    files_group.add(&reopen_last_file_row);
+    page.add(&files_group);
+
+    EditorPage {
+        page,
+        font_row,
+        font_button,
+        font_reset_button,
+        line_numbers_switch,
+        whitespace_switch,
+        wrap_switch,
+        tab_action_combo,
+        confirm_overwrite_switch,
+        pause_monitor_switch,
+        final_newline_switch,
+        trim_whitespace_switch,
+        max_recent_spin,
+        reopen_last_file_switch,
+        tab_width_spin,
+        insert_spaces_switch,
+        auto_indent_switch,
+        current_line_switch,
+        matching_brackets_switch,
+    }
 }
 
-fn build_llm_page(
-    llm: &LlmSettings,
-    gpus: &[GpuDevice],
-) -> (
-    adw::PreferencesPage,
-    adw::ComboRow,
-    adw::EntryRow,
-    gtk::Switch,
-    adw::EntryRow,
-    adw::ComboRow,
-    adw::EntryRow,
-    gtk::Button,
-    adw::EntryRow,
-    gtk::Button,
-    gtk::Button,
-    gtk::SpinButton,
-) {
+/// Widgets on the AI page the window reads and updates, apart from the completion group
+struct LlmPage {
+    page: adw::PreferencesPage,
+    llm_provider_combo: adw::ComboRow,
+    llm_endpoint_row: adw::EntryRow,
+    remote_model_row: adw::EntryRow,
+    test_connection_button: gtk::Button,
+    connection_check_row: adw::ActionRow,
+    connection_check_switch: gtk::Switch,
+    override_model_switch: gtk::Switch,
+    llm_model_row: adw::EntryRow,
+    gpu_combo: adw::ComboRow,
+    gpu_model_row: adw::EntryRow,
+    gpu_files_button: gtk::Button,
+    gpu_download_button: gtk::Button,
+    cpu_model_row: adw::EntryRow,
+    cpu_files_button: gtk::Button,
+    cpu_download_button: gtk::Button,
+    reset_defaults_button: gtk::Button,
+    load_model_button: gtk::Button,
+    models_dir_row: adw::ActionRow,
+    models_dir_button: gtk::Button,
+    models_dir_reset_button: gtk::Button,
+    scan_models_button: gtk::Button,
+    import_model_button: gtk::Button,
+    downloaded_models_group: adw::PreferencesGroup,
+    downloaded_models_list: gtk::ListBox,
+    gpu_layers_row: adw::ActionRow,
+    gpu_layers_spin: gtk::SpinButton,
+    auto_tune_button: gtk::Button,
+    loaded_model_row: adw::ActionRow,
+    max_tokens_spin: gtk::SpinButton,
+    context_size_spin: gtk::SpinButton,
+    temperature_spin: gtk::SpinButton,
+    top_p_spin: gtk::SpinButton,
+    top_k_spin: gtk::SpinButton,
+    repeat_penalty_switch: gtk::Switch,
+    repeat_penalty_spin: gtk::SpinButton,
+    penalty_last_n_spin: gtk::SpinButton,
+    frequency_penalty_spin: gtk::SpinButton,
+    presence_penalty_spin: gtk::SpinButton,
+    seed_row: adw::EntryRow,
+    cache_size_spin: gtk::SpinButton,
+    latency_target_spin: gtk::SpinButton,
+    idle_unload_spin: gtk::SpinButton,
+    api_key_row: adw::PasswordEntryRow,
+    hf_token_row: adw::PasswordEntryRow,
+}
+
+fn build_llm_page(llm: &LlmSettings, gpus: &[GpuDevice]) -> LlmPage {
     let page = adw::PreferencesPage::builder()
         .title("AI Assistant")
         .icon_name("sparkles-symbolic")
@@ -190,6 +714,35 @@ fn build_llm_page(
     endpoint_row.set_visible(llm.provider != ProviderKind::Local);
     provider_group.add(&endpoint_row);
 
+    let remote_model_row = adw::EntryRow::builder()
+        .title("Model Name")
+        .text(&llm.remote_model)
+        .build();
+    remote_model_row.set_visible(llm.provider != ProviderKind::Local);
+    provider_group.add(&remote_model_row);
+
+    let test_connection_button = gtk::Button::builder()
+        .label("Test Connection")
+        .tooltip_text("Ask the service for its model list")
+        .margin_top(12)
+        .css_classes(["flat"])
+        .visible(llm.provider != ProviderKind::Local)
+        .build();
+    provider_group.add(&test_connection_button);
+
+    let connection_check_row = adw::ActionRow::builder()
+        .title("Test Connection at Startup")
+        .subtitle("Warn when the service can't be reached")
+        .visible(llm.provider != ProviderKind::Local)
+        .build();
+    let connection_check_switch = gtk::Switch::builder()
+        .valign(gtk::Align::Center)
+        .active(llm.check_connection_on_startup)
+        .build();
+    connection_check_row.add_suffix(&connection_check_switch);
+    connection_check_row.set_activatable_widget(Some(&connection_check_switch));
+    provider_group.add(&connection_check_row);
+
     let local_group = adw::PreferencesGroup::builder()
         .title("Local Inference")
         .description("Configure onboard GGUF models.")
@@ -216,11 +769,70 @@ fn build_llm_page(
     llm_model_row.set_sensitive(llm.override_model_path);
     local_group.add(&llm_model_row);
 
+    let scan_models_row = adw::ActionRow::builder()
+        .title("Existing Models")
+        .subtitle("Pick a .gguf file from a folder downloaded with other tools")
+        .build();
+    let scan_models_button = gtk::Button::builder()
+        .label("Scan Folder…")
+        .valign(gtk::Align::Center)
+        .build();
+    scan_models_row.add_suffix(&scan_models_button);
+    local_group.add(&scan_models_row);
+
+    let import_model_row = adw::ActionRow::builder()
+        .title("Import Model")
+        .subtitle("Copy a .gguf file into the models folder so it's verified and listed")
+        .build();
+    let import_model_button = gtk::Button::builder()
+        .label("Import…")
+        .valign(gtk::Align::Center)
+        .build();
+    import_model_row.add_suffix(&import_model_button);
+    local_group.add(&import_model_row);
+
+    let models_dir_row = adw::ActionRow::builder()
+        .title("Models Folder")
+        .subtitle(models_dir_subtitle(llm))
+        .build();
+    let models_dir_reset_button = gtk::Button::builder()
+        .icon_name("edit-undo-symbolic")
+        .valign(gtk::Align::Center)
+        .tooltip_text("Use the default folder")
+        .css_classes(["flat"])
+        .sensitive(llm.models_dir_override.is_some())
+        .build();
+    let models_dir_button = gtk::Button::builder()
+        .label("Choose…")
+        .valign(gtk::Align::Center)
+        .build();
+    models_dir_row.add_suffix(&models_dir_reset_button);
+    models_dir_row.add_suffix(&models_dir_button);
+    local_group.add(&models_dir_row);
+
+    // Filled in by the window, which keeps it up to date
+    let downloaded_models_group = adw::PreferencesGroup::builder()
+        .title("Downloaded Models")
+        .build();
+    let downloaded_models_list = gtk::ListBox::builder()
+        .selection_mode(gtk::SelectionMode::None)
+        .css_classes(["boxed-list"])
+        .build();
+    downloaded_models_list.set_placeholder(Some(
+        &gtk::Label::builder()
+            .label("No models downloaded yet")
+            .css_classes(["dim-label"])
+            .margin_top(12)
+            .margin_bottom(12)
+            .build(),
+    ));
+    downloaded_models_group.add(&downloaded_models_list);
+
     // Hardware Acceleration
     let device_group = adw::PreferencesGroup::builder().title("Hardware").build();
 
     let gpu_names: Vec<String> = std::iter::once("CPU Only".to_string())
-        .chain(gpus.iter().map(|g| g.name.clone()))
+        .chain(gpus.iter().map(GpuDevice::label))
         .collect();
     let gpu_strings: Vec<&str> = gpu_names.iter().map(|s| s.as_str()).collect();
     let gpu_list = gtk::StringList::new(gpu_strings.as_slice());
@@ -253,6 +865,13 @@ fn build_llm_page(
         .tooltip_text("Download default model")
         .css_classes(["flat"])
         .build();
+    let gpu_files_button = gtk::Button::builder()
+        .icon_name("view-list-symbolic")
+        .valign(gtk::Align::Center)
+        .tooltip_text("Choose a file from the repository")
+        .css_classes(["flat"])
+        .build();
+    gpu_model_row.add_suffix(&gpu_files_button);
     gpu_model_row.add_suffix(&gpu_download_button);
     device_group.add(&gpu_model_row);
 
@@ -266,9 +885,43 @@ fn build_llm_page(
         .tooltip_text("Download default model")
         .css_classes(["flat"])
         .build();
+    let cpu_files_button = gtk::Button::builder()
+        .icon_name("view-list-symbolic")
+        .valign(gtk::Align::Center)
+        .tooltip_text("Choose a file from the repository")
+        .css_classes(["flat"])
+        .build();
+    cpu_model_row.add_suffix(&cpu_files_button);
     cpu_model_row.add_suffix(&cpu_download_button);
     device_group.add(&cpu_model_row);
 
+    let gpu_layers_row = adw::ActionRow::builder()
+        .title("GPU Layers")
+        .subtitle(gpu_layers_subtitle(llm))
+        .build();
+    // The top of the range means all layers, or the auto-tuned count
+    let gpu_layers_spin = gtk::SpinButton::builder()
+        .adjustment(&gtk::Adjustment::new(
+            llm.gpu_layers as f64,
+            0.0,
+            ALL_GPU_LAYERS as f64,
+            1.0,
+            8.0,
+            0.0,
+        ))
+        .valign(gtk::Align::Center)
+        .tooltip_text("Fewer layers fit smaller GPUs; the rest run on the CPU")
+        .build();
+    gpu_layers_row.add_suffix(&gpu_layers_spin);
+    let auto_tune_button = gtk::Button::builder()
+        .label("Auto-tune GPU")
+        .valign(gtk::Align::Center)
+        .tooltip_text("Find how many layers fit on the selected GPU")
+        .sensitive(llm.gpu_tuning_key().is_some())
+        .build();
+    gpu_layers_row.add_suffix(&auto_tune_button);
+    device_group.add(&gpu_layers_row);
+
     let reset_defaults_button = gtk::Button::builder()
         .label("Reset to Defaults")
         .margin_top(12)
@@ -277,6 +930,21 @@ fn build_llm_page(
         .build();
     local_group.add(&reset_defaults_button);
 
+    // Model changes otherwise only apply lazily on the next completion
+    let load_model_button = gtk::Button::builder()
+        .label("Apply & Load Now")
+        .margin_bottom(12)
+        .css_classes(["suggested-action"])
+        .build();
+    local_group.add(&load_model_button);
+
+    let loaded_model_row = adw::ActionRow::builder()
+        .title("Loaded Model")
+        .subtitle(NO_MODEL_LOADED)
+        .subtitle_selectable(true)
+        .build();
+    local_group.add(&loaded_model_row);
+
     local_group.add(&device_group);
 
     let advanced_group = adw::PreferencesGroup::builder().title("Generation").build();
@@ -296,35 +964,810 @@ fn build_llm_page(
     max_tokens_row.add_suffix(&max_tokens_spin);
     advanced_group.add(&max_tokens_row);
 
+    let context_size_row = adw::ActionRow::builder()
+        .title("Context Window (tokens)")
+        .subtitle("Longer text before the cursor is trimmed to fit; larger windows use more memory")
+        .build();
+    let context_size_spin = gtk::SpinButton::builder()
+        .adjustment(&gtk::Adjustment::new(
+            llm.context_size as f64,
+            512.0,
+            32_768.0,
+            512.0,
+            2048.0,
+            0.0,
+        ))
+        .valign(gtk::Align::Center)
+        .build();
+    context_size_row.add_suffix(&context_size_spin);
+    advanced_group.add(&context_size_row);
+
+    let temperature_row = adw::ActionRow::builder()
+        .title("Temperature")
+        .subtitle("Higher values vary suggestions more; 0 always picks the likeliest text")
+        .build();
+    let temperature_spin = gtk::SpinButton::builder()
+        .adjustment(&gtk::Adjustment::new(
+            llm.temperature as f64,
+            0.0,
+            2.0,
+            0.05,
+            0.1,
+            0.0,
+        ))
+        .digits(2)
+        .valign(gtk::Align::Center)
+        .build();
+    temperature_row.add_suffix(&temperature_spin);
+    advanced_group.add(&temperature_row);
+
+    let top_p_row = adw::ActionRow::builder()
+        .title("Top P")
+        .subtitle("Sample only from the most likely tokens covering this probability")
+        .build();
+    let top_p_spin = gtk::SpinButton::builder()
+        .adjustment(&gtk::Adjustment::new(
+            llm.top_p as f64,
+            0.0,
+            1.0,
+            0.05,
+            0.1,
+            0.0,
+        ))
+        .digits(2)
+        .valign(gtk::Align::Center)
+        .build();
+    top_p_row.add_suffix(&top_p_spin);
+    advanced_group.add(&top_p_row);
+
+    let top_k_row = adw::ActionRow::builder()
+        .title("Top K")
+        .subtitle("Sample only from this many candidates; 0 turns the limit off")
+        .build();
+    let top_k_spin = gtk::SpinButton::builder()
+        .adjustment(&gtk::Adjustment::new(
+            llm.top_k as f64,
+            0.0,
+            200.0,
+            1.0,
+            10.0,
+            0.0,
+        ))
+        .valign(gtk::Align::Center)
+        .build();
+    top_k_row.add_suffix(&top_k_spin);
+    advanced_group.add(&top_k_row);
+
+    let repeat_penalty_row = adw::ActionRow::builder()
+        .title("Penalize Repetition")
+        .subtitle("Keep small models from repeating the same words over and over")
+        .build();
+    let repeat_penalty_switch = gtk::Switch::builder()
+        .valign(gtk::Align::Center)
+        .active(llm.use_repeat_penalty)
+        .build();
+    repeat_penalty_row.add_suffix(&repeat_penalty_switch);
+    repeat_penalty_row.set_activatable_widget(Some(&repeat_penalty_switch));
+    advanced_group.add(&repeat_penalty_row);
+
+    let repeat_penalty_value_row = adw::ActionRow::builder()
+        .title("Repeat Penalty")
+        .subtitle("How strongly recent tokens are discouraged; 1 has no effect")
+        .build();
+    let repeat_penalty_spin = gtk::SpinButton::builder()
+        .adjustment(&gtk::Adjustment::new(
+            llm.repeat_penalty as f64,
+            1.0,
+            2.0,
+            0.05,
+            0.1,
+            0.0,
+        ))
+        .digits(2)
+        .valign(gtk::Align::Center)
+        .build();
+    repeat_penalty_value_row.add_suffix(&repeat_penalty_spin);
+    advanced_group.add(&repeat_penalty_value_row);
+
+    let penalty_last_n_row = adw::ActionRow::builder()
+        .title("Penalty Window (tokens)")
+        .subtitle("How many recent tokens count as repeats")
+        .build();
+    let penalty_last_n_spin = gtk::SpinButton::builder()
+        .adjustment(&gtk::Adjustment::new(
+            llm.penalty_last_n as f64,
+            0.0,
+            2048.0,
+            8.0,
+            64.0,
+            0.0,
+        ))
+        .valign(gtk::Align::Center)
+        .build();
+    penalty_last_n_row.add_suffix(&penalty_last_n_spin);
+    advanced_group.add(&penalty_last_n_row);
+
+    let frequency_penalty_row = adw::ActionRow::builder()
+        .title("Frequency Penalty")
+        .subtitle("Grows with every repeat of a token")
+        .build();
+    let frequency_penalty_spin = gtk::SpinButton::builder()
+        .adjustment(&gtk::Adjustment::new(
+            llm.frequency_penalty as f64,
+            0.0,
+            2.0,
+            0.05,
+            0.1,
+            0.0,
+        ))
+        .digits(2)
+        .valign(gtk::Align::Center)
+        .build();
+    frequency_penalty_row.add_suffix(&frequency_penalty_spin);
+    advanced_group.add(&frequency_penalty_row);
+
+    let presence_penalty_row = adw::ActionRow::builder()
+        .title("Presence Penalty")
+        .subtitle("Applied once to any token that already appeared")
+        .build();
+    let presence_penalty_spin = gtk::SpinButton::builder()
+        .adjustment(&gtk::Adjustment::new(
+            llm.presence_penalty as f64,
+            0.0,
+            2.0,
+            0.05,
+            0.1,
+            0.0,
+        ))
+        .digits(2)
+        .valign(gtk::Align::Center)
+        .build();
+    presence_penalty_row.add_suffix(&presence_penalty_spin);
+    advanced_group.add(&presence_penalty_row);
+
+    let seed_row = adw::EntryRow::builder()
+        .title("Seed (empty = random)")
+        .text(seed_text(llm.seed))
+        .input_purpose(gtk::InputPurpose::Digits)
+        .build();
+    advanced_group.add(&seed_row);
+
+    for row in [
+        &repeat_penalty_value_row,
+        &penalty_last_n_row,
+        &frequency_penalty_row,
+        &presence_penalty_row,
+    ] {
+        repeat_penalty_switch
+            .bind_property("active", row, "sensitive")
+            .sync_create()
+            .build();
+    }
+
+    let cache_size_row = adw::ActionRow::builder()
+        .title("Cached Completions")
+        .subtitle("Reuse results for identical context; 0 turns caching off")
+        .build();
+    let cache_size_spin = gtk::SpinButton::builder()
+        .adjustment(&gtk::Adjustment::new(
+            llm.completion_cache_size as f64,
+            0.0,
+            256.0,
+            1.0,
+            8.0,
+            0.0,
+        ))
+        .valign(gtk::Align::Center)
+        .build();
+    cache_size_row.add_suffix(&cache_size_spin);
+    advanced_group.add(&cache_size_row);
+
+    let latency_target_row = adw::ActionRow::builder()
+        .title("Latency Target (ms)")
+        .subtitle(
+            "Shorten automatic suggestions on slow hardware to finish in time; 0 turns it off",
+        )
+        .build();
+    let latency_target_spin = gtk::SpinButton::builder()
+        .adjustment(&gtk::Adjustment::new(
+            llm.latency_target_ms as f64,
+            0.0,
+            10_000.0,
+            50.0,
+            100.0,
+            0.0,
+        ))
+        .valign(gtk::Align::Center)
+        .build();
+    latency_target_row.add_suffix(&latency_target_spin);
+    advanced_group.add(&latency_target_row);
+
+    let idle_unload_row = adw::ActionRow::builder()
+        .title("Unload When Idle (minutes)")
+        .subtitle("Free the model's memory after a while without suggestions; 0 keeps it loaded")
+        .build();
+    let idle_unload_spin = gtk::SpinButton::builder()
+        .adjustment(&gtk::Adjustment::new(
+            (llm.unload_after_idle_secs / 60) as f64,
+            0.0,
+            240.0,
+            1.0,
+            10.0,
+            0.0,
+        ))
+        .valign(gtk::Align::Center)
+        .build();
+    idle_unload_row.add_suffix(&idle_unload_spin);
+    advanced_group.add(&idle_unload_row);
+
     // Credentials
-    let secrets_group = adw::PreferencesGroup::builder().title("Security").build();
-    let token_row = adw::PasswordEntryRow::builder().title("API Key").build();
-    secrets_group.add(&token_row);
+    let secrets_group = adw::PreferencesGroup::builder()
+        .title("Security")
+        .description("Keys are kept in the system keyring when one is available")
+        .build();
+    let api_key_row = adw::PasswordEntryRow::builder()
+        .title("API Key")
+        .text(llm.api_key())
+        .show_apply_button(true)
+        .build();
+    secrets_group.add(&api_key_row);
+    let hf_token_row = adw::PasswordEntryRow::builder()
+        .title("Hugging Face Token")
+        .text(llm.hf_token())
+        .show_apply_button(true)
+        .build();
+    secrets_group.add(&hf_token_row);
 
     page.add(&provider_group);
     page.add(&local_group);
+    page.add(&downloaded_models_group);
     page.add(&advanced_group);
     page.add(&secrets_group);
 
-    (
+    LlmPage {
         page,
-        provider_row,
-        endpoint_row,
+        llm_provider_combo: provider_row,
+        llm_endpoint_row: endpoint_row,
+        remote_model_row,
+        test_connection_button,
+        connection_check_row,
+        connection_check_switch,
         override_model_switch,
         llm_model_row,
         gpu_combo,
         gpu_model_row,
+        gpu_files_button,
         gpu_download_button,
         cpu_model_row,
+        cpu_files_button,
         cpu_download_button,
         reset_defaults_button,
+        load_model_button,
+        models_dir_row,
+        models_dir_button,
+        models_dir_reset_button,
+        scan_models_button,
+        import_model_button,
+        downloaded_models_group,
+        downloaded_models_list,
+        gpu_layers_row,
+        gpu_layers_spin,
+        auto_tune_button,
+        loaded_model_row,
         max_tokens_spin,
-    )
+        context_size_spin,
+        temperature_spin,
+        top_p_spin,
+        top_k_spin,
+        repeat_penalty_switch,
+        repeat_penalty_spin,
+        penalty_last_n_spin,
+        frequency_penalty_spin,
+        presence_penalty_spin,
+        seed_row,
+        cache_size_spin,
+        latency_target_spin,
+        idle_unload_spin,
+        api_key_row,
+        hf_token_row,
+    }
+}
+
+pub(super) fn seed_text(seed: Option<u64>) -> String {
+    seed.map(|seed| seed.to_string()).unwrap_or_default()
+}
+
+/// `Ok(None)` for an empty entry, `Err` when the text isn't a seed
+pub(super) fn parse_seed(text: &str) -> Result<Option<u64>, std::num::ParseIntError> {
+    let text = text.trim();
+    if text.is_empty() {
+        Ok(None)
+    } else {
+        text.parse().map(Some)
+    }
+}
+
+pub(super) fn models_dir_subtitle(llm: &LlmSettings) -> String {
+    match &llm.models_dir_override {
+        Some(dir) => dir.display().to_string(),
+        None => "Default (app data folder)".to_string(),
+    }
+}
+
+pub(super) fn gpu_layers_subtitle(llm: &LlmSettings) -> String {
+    if llm.gpu_tuning_key().is_none() {
+        "Not used when running on the CPU".into()
+    } else if llm.gpu_layers != ALL_GPU_LAYERS {
+        format!("{} layers offloaded", llm.gpu_layers)
+    } else if let Some(layers) = llm.tuned_layers() {
+        format!("Auto-tuned: {} layers offloaded", layers)
+    } else {
+        "Offloading all layers (not tuned)".into()
+    }
+}
+
+/// Widgets of the completion group on the AI page
+struct CompletionGroup {
+    group: adw::PreferencesGroup,
+    auto_completion_switch: gtk::Switch,
+    completion_profile_combo: adw::ComboRow,
+    completion_scope_combo: adw::ComboRow,
+    context_strategy_combo: adw::ComboRow,
+    context_lines_spin: gtk::SpinButton,
+    context_prefix_spin: gtk::SpinButton,
+    context_suffix_spin: gtk::SpinButton,
+    fim_template_combo: adw::ComboRow,
+    bulk_insert_spin: gtk::SpinButton,
+    completion_debounce_spin: gtk::SpinButton,
+    completion_max_wait_spin: gtk::SpinButton,
+    trigger_only_switch: gtk::Switch,
+    trigger_chars_row: adw::EntryRow,
+    stop_at_sentence_switch: gtk::Switch,
+    include_filename_switch: gtk::Switch,
+    starter_prompt_row: adw::EntryRow,
+    blocklist_view: gtk::TextView,
+    regenerate_blocked_switch: gtk::Switch,
+    reveal_ghost_switch: gtk::Switch,
+    dismiss_on_click_switch: gtk::Switch,
+    explain_completions_switch: gtk::Switch,
+}
+
+fn build_completion_group(llm: &LlmSettings) -> CompletionGroup {
+    let group = adw::PreferencesGroup::builder()
+        .title("Completion")
+        .description("Control when and where suggestions appear.")
+        .build();
+
+    let auto_completion_row = adw::ActionRow::builder()
+        .title("Suggest While Typing")
+        .subtitle("When off, suggestions only appear on Ctrl+Space")
+        .build();
+    let auto_completion_switch = gtk::Switch::builder()
+        .valign(gtk::Align::Center)
+        .active(llm.auto_completion_enabled)
+        .build();
+    auto_completion_row.add_suffix(&auto_completion_switch);
+    auto_completion_row.set_activatable_widget(Some(&auto_completion_switch));
+    group.add(&auto_completion_row);
+
+    let profile_names: Vec<&'static str> =
+        COMPLETION_PROFILES.iter().map(|(_, name)| *name).collect();
+    let profile_list = gtk::StringList::new(profile_names.as_slice());
+    let profile_row = adw::ComboRow::builder()
+        .title("Profile")
+        .subtitle("Presets adjust triggers, length and stopping together")
+        .model(&profile_list)
+        .selected(completion_profile_index(&llm.completion_profile) as u32)
+        .build();
+    group.add(&profile_row);
+
+    let scope_names: Vec<&'static str> = COMPLETION_SCOPES.iter().map(|(_, name)| *name).collect();
+    let scope_list = gtk::StringList::new(scope_names.as_slice());
+    let scope_row = adw::ComboRow::builder()
+        .title("Scope")
+        .subtitle("With a selection, Ctrl+Space can rewrite it in place")
+        .model(&scope_list)
+        .selected(completion_scope_index(&llm.completion_scope) as u32)
+        .build();
+    group.add(&scope_row);
+
+    let strategy_names: Vec<&'static str> =
+        CONTEXT_STRATEGIES.iter().map(|(_, name)| *name).collect();
+    let strategy_list = gtk::StringList::new(strategy_names.as_slice());
+    let strategy_row = adw::ComboRow::builder()
+        .title("Context Window")
+        .subtitle("How text around the cursor is cut for the prompt")
+        .model(&strategy_list)
+        .selected(context_strategy_index(&llm.context_strategy) as u32)
+        .build();
+    group.add(&strategy_row);
+
+    let (context_lines_row, context_lines_spin) = spin_row(
+        "Context Lines",
+        Some("Full lines kept above and below the cursor"),
+        gtk::Adjustment::new(llm.context_lines as f64, 1.0, 500.0, 1.0, 10.0, 0.0),
+    );
+    // Only meaningful for the line strategy; kept in sync from window.rs
+    context_lines_spin.set_sensitive(llm.context_strategy == ContextStrategy::Lines);
+    group.add(&context_lines_row);
+
+    let (context_prefix_row, context_prefix_spin) = spin_row(
+        "Text Before Cursor",
+        Some("Most characters sent from before the cursor; less is faster on small models"),
+        gtk::Adjustment::new(
+            llm.context_prefix_chars as f64,
+            100.0,
+            100_000.0,
+            100.0,
+            1000.0,
+            0.0,
+        ),
+    );
+    group.add(&context_prefix_row);
+
+    let (context_suffix_row, context_suffix_spin) = spin_row(
+        "Text After Cursor",
+        Some("Most characters sent from after the cursor"),
+        gtk::Adjustment::new(
+            llm.context_suffix_chars as f64,
+            0.0,
+            100_000.0,
+            100.0,
+            1000.0,
+            0.0,
+        ),
+    );
+    group.add(&context_suffix_row);
+
+    let template_names: Vec<&'static str> = FIM_TEMPLATES.iter().map(|(_, name)| *name).collect();
+    let template_list = gtk::StringList::new(template_names.as_slice());
+    let fim_template_row = adw::ComboRow::builder()
+        .title("Prompt Format")
+        .subtitle("Fill-in-the-middle markers the model was trained with")
+        .model(&template_list)
+        .selected(fim_template_index(&llm.fim_template) as u32)
+        .build();
+    group.add(&fim_template_row);
+
+    let (bulk_insert_row, bulk_insert_spin) = spin_row(
+        "Skip Large Pastes",
+        Some("Characters inserted at once before suggestions are skipped (0 = never)"),
+        gtk::Adjustment::new(
+            llm.bulk_insert_chars as f64,
+            0.0,
+            100_000.0,
+            50.0,
+            500.0,
+            0.0,
+        ),
+    );
+    group.add(&bulk_insert_row);
+
+    let (debounce_row, completion_debounce_spin) = spin_row(
+        "Typing Pause (ms)",
+        Some("How long typing has to pause before a suggestion is requested"),
+        gtk::Adjustment::new(
+            llm.completion_debounce_ms as f64,
+            0.0,
+            5_000.0,
+            50.0,
+            250.0,
+            0.0,
+        ),
+    );
+    group.add(&debounce_row);
+
+    let (max_wait_row, completion_max_wait_spin) = spin_row(
+        "Maximum Wait (ms)",
+        Some("Suggest during continuous typing after this long (0 = only after a pause)"),
+        gtk::Adjustment::new(
+            llm.completion_max_wait_ms as f64,
+            0.0,
+            10_000.0,
+            100.0,
+            500.0,
+            0.0,
+        ),
+    );
+    group.add(&max_wait_row);
+
+    let trigger_only_row = adw::ActionRow::builder()
+        .title("Only After Trigger Characters")
+        .subtitle("Suggest only right after typing one of the characters below")
+        .build();
+    let trigger_only_switch = gtk::Switch::builder()
+        .valign(gtk::Align::Center)
+        .active(llm.trigger_only)
+        .build();
+    trigger_only_row.add_suffix(&trigger_only_switch);
+    trigger_only_row.set_activatable_widget(Some(&trigger_only_switch));
+    group.add(&trigger_only_row);
+
+    // Newlines and tabs can't be typed into an entry, so they're shown escaped
+    let trigger_chars_row = adw::EntryRow::builder()
+        .title("Trigger Characters (\\n = newline, \\t = tab)")
+        .text(escape_trigger_chars(&llm.trigger_chars))
+        .sensitive(llm.trigger_only)
+        .build();
+    group.add(&trigger_chars_row);
+
+    let stop_at_sentence_row = adw::ActionRow::builder()
+        .title("Stop at Sentence End")
+        .subtitle("Trim suggestions after the first complete sentence")
+        .build();
+    let stop_at_sentence_switch = gtk::Switch::builder()
+        .valign(gtk::Align::Center)
+        .active(llm.stop_at_sentence_end)
+        .build();
+    stop_at_sentence_row.add_suffix(&stop_at_sentence_switch);
+    stop_at_sentence_row.set_activatable_widget(Some(&stop_at_sentence_switch));
+    group.add(&stop_at_sentence_row);
+
+    let include_filename_row = adw::ActionRow::builder()
+        .title("Include File Name")
+        .subtitle("Tell the model the file name, or the title for prose")
+        .build();
+    let include_filename_switch = gtk::Switch::builder()
+        .valign(gtk::Align::Center)
+        .active(llm.include_filename)
+        .build();
+    include_filename_row.add_suffix(&include_filename_switch);
+    include_filename_row.set_activatable_widget(Some(&include_filename_switch));
+    group.add(&include_filename_row);
+
+    // Used by Ctrl+Space in an empty document to kick off a draft
+    let starter_prompt_row = adw::EntryRow::builder()
+        .title("Starter Prompt for Empty Documents")
+        .text(&llm.starter_prompt)
+        .build();
+    group.add(&starter_prompt_row);
+
+    let blocklist_row = adw::ExpanderRow::builder()
+        .title("Blocked Suggestions")
+        .subtitle("Discard suggestions matching any regular expression (one per line)")
+        .build();
+    let blocklist_view = gtk::TextView::builder()
+        .monospace(true)
+        .wrap_mode(gtk::WrapMode::None)
+        .top_margin(8)
+        .bottom_margin(8)
+        .left_margin(8)
+        .right_margin(8)
+        .height_request(96)
+        .build();
+    blocklist_view
+        .buffer()
+        .set_text(&llm.completion_blocklist.join("\n"));
+    blocklist_row.add_row(&blocklist_view);
+    group.add(&blocklist_row);
+
+    let regenerate_row = adw::ActionRow::builder()
+        .title("Regenerate Blocked Suggestions")
+        .subtitle("Try once more when a suggestion is discarded")
+        .build();
+    let regenerate_blocked_switch = gtk::Switch::builder()
+        .valign(gtk::Align::Center)
+        .active(llm.regenerate_blocked)
+        .build();
+    regenerate_row.add_suffix(&regenerate_blocked_switch);
+    regenerate_row.set_activatable_widget(Some(&regenerate_blocked_switch));
+    group.add(&regenerate_row);
+
+    let reveal_ghost_row = adw::ActionRow::builder()
+        .title("Scroll to Show Suggestions")
+        .subtitle("Bring suggestions that run past the bottom of the view into sight")
+        .build();
+    let reveal_ghost_switch = gtk::Switch::builder()
+        .valign(gtk::Align::Center)
+        .active(llm.reveal_ghost_text)
+        .build();
+    reveal_ghost_row.add_suffix(&reveal_ghost_switch);
+    reveal_ghost_row.set_activatable_widget(Some(&reveal_ghost_switch));
+    group.add(&reveal_ghost_row);
+
+    let dismiss_on_click_row = adw::ActionRow::builder()
+        .title("Dismiss on Click")
+        .subtitle("Clicking elsewhere in the text discards the suggestion")
+        .build();
+    let dismiss_on_click_switch = gtk::Switch::builder()
+        .valign(gtk::Align::Center)
+        .active(llm.dismiss_on_click)
+        .build();
+    dismiss_on_click_row.add_suffix(&dismiss_on_click_switch);
+    dismiss_on_click_row.set_activatable_widget(Some(&dismiss_on_click_switch));
+    group.add(&dismiss_on_click_row);
+
+    let explain_row = adw::ActionRow::builder()
+        .title("Explain Missing Suggestions")
+        .subtitle("Show in the status bar why no suggestion appeared")
+        .build();
+    let explain_completions_switch = gtk::Switch::builder()
+        .valign(gtk::Align::Center)
+        .active(llm.explain_completions)
+        .build();
+    explain_row.add_suffix(&explain_completions_switch);
+    explain_row.set_activatable_widget(Some(&explain_completions_switch));
+    group.add(&explain_row);
+
+    CompletionGroup {
+        group,
+        auto_completion_switch,
+        completion_profile_combo: profile_row,
+        completion_scope_combo: scope_row,
+        context_strategy_combo: strategy_row,
+        context_lines_spin,
+        context_prefix_spin,
+        context_suffix_spin,
+        fim_template_combo: fim_template_row,
+        bulk_insert_spin,
+        completion_debounce_spin,
+        completion_max_wait_spin,
+        trigger_only_switch,
+        trigger_chars_row,
+        stop_at_sentence_switch,
+        include_filename_switch,
+        starter_prompt_row,
+        blocklist_view,
+        regenerate_blocked_switch,
+        reveal_ghost_switch,
+        dismiss_on_click_switch,
+        explain_completions_switch,
+    }
+}
+
+pub(super) fn escape_trigger_chars(chars: &str) -> String {
+    chars.replace('\n', "\\n").replace('\t', "\\t")
+}
+
+pub(super) fn unescape_trigger_chars(text: &str) -> String {
+    text.replace("\\n", "\n").replace("\\t", "\t")
+}
+
+fn spin_row(
+    title: &str,
+    subtitle: Option<&str>,
+    adjustment: gtk::Adjustment,
+) -> (adw::ActionRow, gtk::SpinButton) {
+    let row = adw::ActionRow::builder().title(title).build();
+    if let Some(subtitle) = subtitle {
+        row.set_subtitle(subtitle);
+    }
+    let spin = gtk::SpinButton::builder()
+        .adjustment(&adjustment)
+        .valign(gtk::Align::Center)
+        .build();
+    row.add_suffix(&spin);
+    (row, spin)
+}
+
+/// Subtitle of the loaded model row while no model is in memory
+pub(super) const NO_MODEL_LOADED: &str = "Details appear once a model is loaded";
+
+const COMPLETION_PROFILES: &[(CompletionProfile, &str)] = &[
+    (CompletionProfile::Custom, "Custom"),
+    (
+        CompletionProfile::SentenceDrafting,
+        "Sentence-by-sentence drafting",
+    ),
+];
+
+pub(super) fn completion_profile_index(profile: &CompletionProfile) -> usize {
+    COMPLETION_PROFILES
+        .iter()
+        .position(|(p, _)| p == profile)
+        .unwrap_or(0)
+}
+
+pub(super) fn completion_profile_from_index(idx: u32) -> CompletionProfile {
+    COMPLETION_PROFILES
+        .get(idx as usize)
+        .map(|(profile, _)| *profile)
+        .unwrap_or_default()
+}
+
+const AUTOSAVE_MODES: &[(AutosaveMode, &str)] = &[
+    (AutosaveMode::SwapOnly, "Recovery Snapshot"),
+    (AutosaveMode::WriteThrough, "The File Itself"),
+];
+
+pub(super) fn autosave_mode_index(mode: &AutosaveMode) -> usize {
+    AUTOSAVE_MODES
+        .iter()
+        .position(|(m, _)| m == mode)
+        .unwrap_or(0)
+}
+
+pub(super) fn autosave_mode_from_index(idx: u32) -> AutosaveMode {
+    AUTOSAVE_MODES
+        .get(idx as usize)
+        .map(|(mode, _)| *mode)
+        .unwrap_or_default()
+}
+
+const TAB_ACTIONS: &[(TabAction, &str)] = &[
+    (TabAction::InsertTab, "Indent"),
+    (TabAction::Complete, "Request Completion"),
+];
+
+pub(super) fn tab_action_index(action: &TabAction) -> usize {
+    TAB_ACTIONS
+        .iter()
+        .position(|(a, _)| a == action)
+        .unwrap_or(0)
+}
+
+pub(super) fn tab_action_from_index(idx: u32) -> TabAction {
+    TAB_ACTIONS
+        .get(idx as usize)
+        .map(|(action, _)| *action)
+        .unwrap_or_default()
+}
+
+const COMPLETION_SCOPES: &[(CompletionScope, &str)] = &[
+    (CompletionScope::Cursor, "Insert at Cursor"),
+    (CompletionScope::Selection, "Replace Selection"),
+];
+
+pub(super) fn completion_scope_index(scope: &CompletionScope) -> usize {
+    COMPLETION_SCOPES
+        .iter()
+        .position(|(s, _)| s == scope)
+        .unwrap_or(0)
+}
+
+pub(super) fn completion_scope_from_index(idx: u32) -> CompletionScope {
+    COMPLETION_SCOPES
+        .get(idx as usize)
+        .map(|(scope, _)| *scope)
+        .unwrap_or_default()
+}
+
+const CONTEXT_STRATEGIES: &[(ContextStrategy, &str)] = &[
+    (ContextStrategy::Chars, "Characters"),
+    (ContextStrategy::Lines, "Whole Lines"),
+];
+
+pub(super) fn context_strategy_index(strategy: &ContextStrategy) -> usize {
+    CONTEXT_STRATEGIES
+        .iter()
+        .position(|(s, _)| s == strategy)
+        .unwrap_or(0)
+}
+
+pub(super) fn context_strategy_from_index(idx: u32) -> ContextStrategy {
+    CONTEXT_STRATEGIES
+        .get(idx as usize)
+        .map(|(strategy, _)| *strategy)
+        .unwrap_or_default()
+}
+
+const FIM_TEMPLATES: &[(FimTemplate, &str)] = &[
+    (FimTemplate::Auto, "Automatic"),
+    (FimTemplate::Qwen, "Qwen"),
+    (FimTemplate::DeepSeek, "DeepSeek"),
+    (FimTemplate::CodeLlama, "Code Llama"),
+    (FimTemplate::StarCoder, "StarCoder"),
+    (FimTemplate::None, "None (prefix only)"),
+];
+
+pub(super) fn fim_template_index(template: &FimTemplate) -> usize {
+    FIM_TEMPLATES
+        .iter()
+        .position(|(t, _)| t == template)
+        .unwrap_or(0)
+}
+
+pub(super) fn fim_template_from_index(idx: u32) -> FimTemplate {
+    FIM_TEMPLATES
+        .get(idx as usize)
+        .map(|(template, _)| *template)
+        .unwrap_or_default()
 }
 
 const PROVIDERS: &[(ProviderKind, &str)] = &[
     (ProviderKind::OpenAI, "OpenAI"),
     (ProviderKind::Gemini, "Gemini"),
+    (ProviderKind::Ollama, "Ollama"),
     (ProviderKind::Local, "Local (llama.cpp)"),
 ];
 
@@ -339,21 +1782,149 @@ pub(super) fn provider_from_index(idx: u32) -> ProviderKind {
         .unwrap_or(ProviderKind::OpenAI)
 }
 
-fn build_theming_page() -> adw::PreferencesPage {
+/// Widgets on the Appearance page the window reads and updates
+struct ThemingPage {
+    page: adw::PreferencesPage,
+    follow_system_style_switch: gtk::Switch,
+    dark_style_switch: gtk::Switch,
+    style_scheme_combo: adw::ComboRow,
+    ghost_color_button: gtk::ColorButton,
+    ghost_color_reset_button: gtk::Button,
+}
+
+fn build_theming_page(settings: &Settings) -> ThemingPage {
     let page = adw::PreferencesPage::builder()
         .title("Appearance")
         .icon_name("preferences-desktop-theme-symbolic")
         .build();
     let group = adw::PreferencesGroup::builder().title("Style").build();
-    let theme_switch = gtk::Switch::builder().valign(gtk::Align::Center).build();
+    let follow_system_style_switch = gtk::Switch::builder()
+        .valign(gtk::Align::Center)
+        .active(settings.follow_system_style)
+        .build();
     let theme_row = adw::ActionRow::builder()
         .title("System Code Scheme")
         .subtitle("Inherit light/dark preference")
         .build();
-    theme_row.add_suffix(&theme_switch);
-    theme_row.set_activatable_widget(Some(&theme_switch));
+    theme_row.add_suffix(&follow_system_style_switch);
+    theme_row.set_activatable_widget(Some(&follow_system_style_switch));
     group.add(&theme_row);
 
+    let dark_style_switch = gtk::Switch::builder()
+        .valign(gtk::Align::Center)
+        .active(settings.dark_style)
+        .build();
+    let dark_row = adw::ActionRow::builder()
+        .title("Dark Mode")
+        .subtitle("Used when not following the system")
+        .build();
+    dark_row.add_suffix(&dark_style_switch);
+    dark_row.set_activatable_widget(Some(&dark_style_switch));
+    // Only meaningful while the system preference is ignored
+    follow_system_style_switch
+        .bind_property("active", &dark_row, "sensitive")
+        .invert_boolean()
+        .sync_create()
+        .build();
+    group.add(&dark_row);
+
+    let mut scheme_names = vec!["Automatic".to_string()];
+    let manager = sourceview5::StyleSchemeManager::default();
+    scheme_names.extend(style_scheme_ids().iter().map(|id| {
+        manager
+            .scheme(id)
+            .map(|scheme| scheme.name().to_string())
+            .unwrap_or_else(|| id.clone())
+    }));
+    let scheme_names: Vec<&str> = scheme_names.iter().map(String::as_str).collect();
+    let style_scheme_combo = adw::ComboRow::builder()
+        .title("Syntax Colors")
+        .subtitle("Previewed on the open document as you choose")
+        .model(&gtk::StringList::new(&scheme_names))
+        .selected(style_scheme_index(&settings.style_scheme) as u32)
+        .build();
+    group.add(&style_scheme_combo);
+
     page.add(&group);
-    page
+
+    let suggestions_group = adw::PreferencesGroup::builder()
+        .title("Suggestions")
+        .build();
+    // The real color is set from window.rs, which knows the light/dark default
+    let ghost_color_button = gtk::ColorButton::builder()
+        .valign(gtk::Align::Center)
+        .title("Suggestion Color")
+        .use_alpha(false)
+        .build();
+    let ghost_color_reset_button = gtk::Button::builder()
+        .icon_name("edit-undo-symbolic")
+        .tooltip_text("Use Default Color")
+        .valign(gtk::Align::Center)
+        .sensitive(!settings.ghost_text_color.is_empty())
+        .build();
+    ghost_color_reset_button.add_css_class("flat");
+    let ghost_color_row = adw::ActionRow::builder()
+        .title("Suggestion Color")
+        .subtitle("Color of suggested text before it is accepted")
+        .build();
+    ghost_color_row.add_suffix(&ghost_color_reset_button);
+    ghost_color_row.add_suffix(&ghost_color_button);
+    ghost_color_row.set_activatable_widget(Some(&ghost_color_button));
+    suggestions_group.add(&ghost_color_row);
+    page.add(&suggestions_group);
+
+    ThemingPage {
+        page,
+        follow_system_style_switch,
+        dark_style_switch,
+        style_scheme_combo,
+        ghost_color_button,
+        ghost_color_reset_button,
+    }
+}
+
+/// Row subtitle for the editor font setting
+pub(super) fn font_label(font: &str) -> String {
+    if font.is_empty() {
+        "System monospace".to_string()
+    } else {
+        font.to_string()
+    }
+}
+
+/// Ids of the installed editor color schemes, in the order the picker lists them
+pub(super) fn style_scheme_ids() -> Vec<String> {
+    sourceview5::StyleSchemeManager::default()
+        .scheme_ids()
+        .iter()
+        .map(|id| id.to_string())
+        .collect()
+}
+
+/// Picker position of scheme `id`; an empty or missing id is "Automatic"
+pub(super) fn style_scheme_index(id: &str) -> usize {
+    style_scheme_ids()
+        .iter()
+        .position(|scheme| scheme == id)
+        .map(|idx| idx + 1)
+        .unwrap_or(0)
+}
+
+/// Scheme id at picker position `idx`; empty for "Automatic"
+pub(super) fn style_scheme_from_index(idx: u32) -> String {
+    (idx as usize)
+        .checked_sub(1)
+        .and_then(|idx| style_scheme_ids().into_iter().nth(idx))
+        .unwrap_or_default()
+}
+
+/// `#rrggbb` form of `color`, ignoring alpha
+pub(super) fn color_to_hex(color: &gtk::gdk::RGBA) -> String {
+    let channel = |value: f32| (value.clamp(0.0, 1.0) * 255.0).round() as u8;
+    format!(
+        "#{:02x}{:02x}{:02x}",
+        channel(color.red()),
+        channel(color.green()),
+        channel(color.blue())
+    )
 }
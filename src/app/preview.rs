@@ -0,0 +1,79 @@
+use std::rc::Rc;
+use std::time::Duration;
+
+use gtk4::{glib, prelude::*};
+
+use crate::markdown;
+
+use super::window::AppState;
+
+/// Quiet time after an edit before the preview is rendered again
+const PREVIEW_DELAY: Duration = Duration::from_millis(300);
+
+impl AppState {
+    pub(super) fn hook_markdown_preview(self: &Rc<Self>) {
+        let weak = Rc::downgrade(self);
+        self.preview_toggle.connect_toggled(move |toggle| {
+            if let Some(state) = weak.upgrade() {
+                state.set_markdown_preview(toggle.is_active());
+            }
+        });
+
+        let enabled = self.settings.borrow().markdown_preview;
+        self.preview_toggle.set_active(enabled);
+        self.show_markdown_preview(enabled);
+    }
+
+    fn set_markdown_preview(&self, enabled: bool) {
+        {
+            let mut settings = self.settings.borrow_mut();
+            if settings.markdown_preview == enabled {
+                return;
+            }
+            settings.markdown_preview = enabled;
+            if let Err(err) = settings.save(&self.paths) {
+                log::warn!("Failed to save settings: {err:?}");
+            }
+        }
+        self.show_markdown_preview(enabled);
+    }
+
+    fn show_markdown_preview(&self, visible: bool) {
+        if !visible {
+            if let Some(source) = self.preview_source.borrow_mut().take() {
+                source.remove();
+            }
+        }
+        self.preview_scroller.set_visible(visible);
+        self.update_markdown_preview();
+    }
+
+    /// Render again once typing pauses; rendering a long document on every
+    /// keystroke would make typing lag.
+    pub(super) fn schedule_preview_update(self: &Rc<Self>) {
+        if !self.preview_scroller.is_visible() {
+            return;
+        }
+        if let Some(source) = self.preview_source.borrow_mut().take() {
+            source.remove();
+        }
+        let weak = Rc::downgrade(self);
+        let source = glib::timeout_add_local_once(PREVIEW_DELAY, move || {
+            if let Some(state) = weak.upgrade() {
+                state.preview_source.borrow_mut().take();
+                state.update_markdown_preview();
+            }
+        });
+        self.preview_source.replace(Some(source));
+    }
+
+    /// Render the current tab into the preview pane, if it is showing
+    pub(super) fn update_markdown_preview(&self) {
+        if !self.preview_scroller.is_visible() {
+            return;
+        }
+        let text = self.tab().document.text_without_ghost();
+        self.preview_label
+            .set_markup(&markdown::to_pango_markup(&text));
+    }
+}
@@ -0,0 +1,47 @@
+//! Ctrl+P: print the current tab through the standard print dialog.
+
+use std::rc::Rc;
+
+use gtk4::{self as gtk, prelude::*};
+use sourceview5::prelude::*;
+
+use crate::document::derive_display_name;
+
+use super::window::AppState;
+
+impl AppState {
+    pub(super) fn print_document(self: &Rc<Self>) {
+        let tab = self.tab();
+        self.with_suppressed_completion(|| tab.document.dismiss_ghost_text());
+        if tab.buffer.char_count() == 0 {
+            self.show_toast("Nothing to print");
+            return;
+        }
+        let title = derive_display_name(&tab.file_path.borrow());
+
+        // Takes the view's font, tab width, wrapping and highlighting
+        let compositor = sourceview5::PrintCompositor::from_view(&tab.document.view());
+        compositor.set_print_line_numbers(1);
+        compositor.set_print_header(true);
+        compositor.set_header_format(true, Some(&title), None, Some("Page %N of %Q"));
+
+        let operation = gtk::PrintOperation::new();
+        operation.set_job_name(&title);
+        {
+            let compositor = compositor.clone();
+            operation.connect_begin_print(move |operation, context| {
+                while !compositor.paginate(context) {}
+                operation.set_n_pages(compositor.n_pages());
+            });
+        }
+        operation.connect_draw_page(move |_, context, page| {
+            compositor.draw_page(context, page);
+        });
+
+        if let Err(err) =
+            operation.run(gtk::PrintOperationAction::PrintDialog, Some(&self.window()))
+        {
+            self.present_error("Failed to print", &err.to_string());
+        }
+    }
+}
@@ -1,29 +1,162 @@
-use std::path::Path;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use std::rc::Rc;
 
 use gtk4::{self as gtk, prelude::*};
+use libadwaita as adw;
 
 use super::window::AppState;
 
+/// Resolve symlinks and relative components so one file always maps to one path.
+/// Falls back to the absolute path when the file can't be resolved (e.g. deleted).
+pub(super) fn canonical_path(path: &Path) -> PathBuf {
+    fs::canonicalize(path)
+        .or_else(|_| std::path::absolute(path))
+        .unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// Canonicalize stored recent entries, dropping blanks and duplicates
+pub(super) fn normalize_recent_files(stored: &[String]) -> Vec<PathBuf> {
+    let mut entries: Vec<PathBuf> = Vec::new();
+    for path in stored.iter().filter(|s| !s.is_empty()) {
+        let path = canonical_path(Path::new(path));
+        if !entries.contains(&path) {
+            entries.push(path);
+        }
+    }
+    entries
+}
+
+/// Widget name of the row that empties the list
+pub(super) const CLEAR_RECENT_ROW: &str = "clear-recent";
+
 impl AppState {
     pub(super) fn record_recent_file(&self, path: &Path) {
-        let mut entries = self.recent_entries.borrow_mut();
-        entries.retain(|p| p != path);
-        entries.insert(0, path.to_path_buf());
-        if entries.len() > 10 {
-            entries.truncate(10);
+        let path = canonical_path(path);
+        {
+            let mut entries = self.recent_entries.borrow_mut();
+            entries.retain(|p| *p != path && p.exists());
+            entries.insert(0, path);
+            entries.truncate(self.settings.borrow().max_recent_files);
         }
+        self.save_recent_files();
+        self.refresh_recent_menu();
+    }
+
+    fn save_recent_files(&self) {
+        let entries = self.recent_entries.borrow();
+        let mut settings = self.settings.borrow_mut();
+        settings.recent_files = entries.iter().map(|p| p.display().to_string()).collect();
+        if let Err(err) = settings.save(&self.paths) {
+            log::warn!("Failed to save settings: {err:?}");
+        }
+    }
+
+    /// Lowering the limit drops the oldest entries right away
+    pub(super) fn set_max_recent_files(&self, max: usize) {
         {
             let mut settings = self.settings.borrow_mut();
-            settings.recent_files = entries.iter().map(|p| p.display().to_string()).collect();
-            if let Err(err) = settings.save(&self.paths) {
-                log::warn!("Failed to save settings: {err:?}");
+            if settings.max_recent_files == max {
+                return;
             }
+            settings.max_recent_files = max;
         }
-        drop(entries);
+        self.recent_entries.borrow_mut().truncate(max);
+        self.save_recent_files();
         self.refresh_recent_menu();
     }
 
+    pub(super) fn clear_recent_files(&self) {
+        self.recent_entries.borrow_mut().clear();
+        self.save_recent_files();
+        self.refresh_recent_menu();
+    }
+
+    /// Drop the `idx`-th entry from the list, leaving the file alone
+    pub(super) fn remove_recent_file(&self, idx: usize) {
+        {
+            let mut entries = self.recent_entries.borrow_mut();
+            if idx >= entries.len() {
+                return;
+            }
+            entries.remove(idx);
+        }
+        self.save_recent_files();
+        self.refresh_recent_menu();
+    }
+
+    /// Reopen the file the last session had open, or offer to. Skipped while
+    /// the recovery dialog is up, since a recovered draft may be that file.
+    pub(super) fn restore_last_file(self: &Rc<Self>) {
+        let (path, offset) = {
+            let store = self.window_state.borrow();
+            match &store.last_open_file {
+                Some(path) => (PathBuf::from(path), store.last_cursor_offset),
+                None => return,
+            }
+        };
+        if !path.exists() || self.has_pending_recovery() {
+            return;
+        }
+        if self.settings.borrow().reopen_last_file {
+            self.reopen_at(&path, offset);
+            return;
+        }
+
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| path.display().to_string());
+        let toast = adw::Toast::builder()
+            .title(format!("Reopen {name}?"))
+            .use_markup(false)
+            .button_label("Reopen")
+            .timeout(10)
+            .build();
+        let weak = Rc::downgrade(self);
+        toast.connect_button_clicked(move |_| {
+            if let Some(state) = weak.upgrade() {
+                state.reopen_at(&path, offset);
+            }
+        });
+        self.toast_overlay.add_toast(toast);
+    }
+
+    /// Open `path` and put the cursor back at `offset`
+    fn reopen_at(self: &Rc<Self>, path: &Path, offset: i32) {
+        self.open_path(path);
+        let tab = self.tab();
+        if tab.file_path.borrow().as_deref() != Some(path) {
+            return;
+        }
+        tab.buffer.place_cursor(&tab.buffer.iter_at_offset(offset));
+        // A mark, since the view may not have laid out the text yet
+        tab.document
+            .view()
+            .scroll_to_mark(&tab.buffer.get_insert(), 0.1, false, 0.0, 0.0);
+    }
+
+    /// Open the `idx`-th recent file, asking about unsaved changes first
+    pub(super) fn open_recent(self: &Rc<Self>, idx: usize) {
+        let Some(path) = self.recent_entries.borrow().get(idx).cloned() else {
+            return;
+        };
+        self.open_path(&path);
+    }
+
     pub(super) fn refresh_recent_menu(&self) {
+        // Files deleted or moved since would only fail to open
+        let pruned = {
+            let mut entries = self.recent_entries.borrow_mut();
+            let before = entries.len();
+            entries.retain(|p| p.exists());
+            entries.len() != before
+        };
+        if pruned {
+            self.save_recent_files();
+        }
+
         while let Some(child) = self.recent_list.first_child() {
             self.recent_list.remove(&child);
         }
@@ -44,7 +177,7 @@ impl AppState {
             return;
         }
         // List logic continues
-        for path in entries.iter() {
+        for (idx, path) in entries.iter().enumerate() {
             let display = path
                 .file_name()
                 .and_then(|n| n.to_str())
@@ -66,12 +199,37 @@ impl AppState {
             path_label.add_css_class("dim-label");
             vbox.append(&title_label);
             vbox.append(&path_label);
+            let hbox = gtk::Box::new(gtk::Orientation::Horizontal, 12);
+            vbox.set_hexpand(true);
+            hbox.append(&vbox);
+            if idx < 9 {
+                let shortcut_label = gtk::Label::new(Some(&format!("Ctrl+{}", idx + 1)));
+                shortcut_label.add_css_class("dim-label");
+                shortcut_label.set_margin_end(12);
+                hbox.append(&shortcut_label);
+            }
             let row = gtk::ListBoxRow::builder()
                 .activatable(true)
                 .selectable(false)
+                .tooltip_text("Right-click to remove from the list")
                 .build();
-            row.set_child(Some(&vbox));
+            row.set_child(Some(&hbox));
             self.recent_list.append(&row);
         }
+
+        let clear_label = gtk::Label::builder()
+            .label("Clear Recent")
+            .margin_top(8)
+            .margin_bottom(8)
+            .margin_start(12)
+            .margin_end(12)
+            .build();
+        let clear_row = gtk::ListBoxRow::builder()
+            .name(CLEAR_RECENT_ROW)
+            .activatable(true)
+            .selectable(false)
+            .child(&clear_label)
+            .build();
+        self.recent_list.append(&clear_row);
     }
 }
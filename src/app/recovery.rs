@@ -1,13 +1,17 @@
 use std::cell::RefCell;
+use std::collections::VecDeque;
 use std::fs;
-use std::path::{Path, PathBuf};
+use std::path::PathBuf;
 use std::rc::Rc;
 use std::time::{Duration, Instant, UNIX_EPOCH};
 
-use gtk4::{self as gtk, prelude::*};
+use gtk4::{self as gtk, glib, prelude::*};
 use serde_json;
 
-use super::autosave::AutosaveMetadata;
+use crate::document::{LineEnding, decode_text};
+
+use super::autosave::{AutosaveMetadata, parse_snapshot_name};
+use super::session_lock::LockStatus;
 use super::window::AppState;
 
 #[derive(Debug, Clone)]
@@ -15,10 +19,14 @@ pub(super) struct RecoveryEntry {
     pub(super) swap_path: PathBuf,
     pub(super) meta_path: PathBuf,
     pub(super) metadata: AutosaveMetadata,
+    pub(super) session: String,
+    /// Left by a session that crashed rather than one that wasn't cleaned up
+    pub(super) crashed: bool,
 }
 
 impl AppState {
     pub(super) fn check_recovery_snapshots(self: &Rc<Self>) {
+        self.clear_stale_session_locks();
         let entries = match self.collect_recovery_entries() {
             Ok(entries) => entries,
             Err(err) => {
@@ -29,95 +37,227 @@ impl AppState {
         if entries.is_empty() {
             return;
         }
-        let queue = Rc::new(RefCell::new(entries));
+        let title = if entries[0][0].crashed {
+            "Wispnote didn't shut down cleanly"
+        } else {
+            "Recovered autosave found"
+        };
+        self.notify_desktop(
+            "recovery",
+            title,
+            &format!(
+                "{} unsaved document(s) are waiting to be restored",
+                entries.len()
+            ),
+        );
+        let queue = Rc::new(RefCell::new(VecDeque::from(entries)));
         self.present_next_recovery(queue);
     }
 
-    fn collect_recovery_entries(&self) -> anyhow::Result<Vec<RecoveryEntry>> {
-        let mut entries = Vec::new();
+    /// Whether there are drafts the recovery dialog is offering
+    pub(super) fn has_pending_recovery(&self) -> bool {
+        self.collect_recovery_entries()
+            .is_ok_and(|entries| !entries.is_empty())
+    }
+
+    /// Snapshots left by sessions that are no longer running, one list per
+    /// document with its newest snapshot first. Documents from a crash come
+    /// first, then the rest; each newest first.
+    fn collect_recovery_entries(&self) -> anyhow::Result<Vec<Vec<RecoveryEntry>>> {
+        let mut sessions: Vec<String> = Vec::new();
         for entry in fs::read_dir(&self.paths.autosave_dir)? {
             let entry = entry?;
-            let path = entry.path();
-            if path.extension().and_then(|e| e.to_str()) != Some("swap") {
+            let file_name = entry.file_name();
+            let Some((session, _)) = file_name.to_str().and_then(parse_snapshot_name) else {
+                continue;
+            };
+            if self.session_is_open(session) || sessions.iter().any(|s| s == session) {
                 continue;
             }
-            if self.swap_belongs_to_current_session(&path) {
+            // Another window or instance is still writing these
+            if self.session_lock_status(session) == LockStatus::Running {
                 continue;
             }
-            let meta_path = self.autosave_metadata_path(&path);
-            let metadata = fs::read_to_string(&meta_path)
-                .ok()
-                .and_then(|raw| serde_json::from_str::<AutosaveMetadata>(&raw).ok())
-                .unwrap_or(AutosaveMetadata {
-                    original_path: None,
-                    timestamp: 0,
-                });
-            entries.push(RecoveryEntry {
-                swap_path: path,
-                meta_path,
-                metadata,
-            });
+            sessions.push(session.to_string());
         }
-        entries.sort_by_key(|entry| entry.metadata.timestamp);
-        entries.reverse();
-        Ok(entries)
+
+        let mut documents: Vec<Vec<RecoveryEntry>> = sessions
+            .iter()
+            .map(|session| {
+                let crashed = self.session_lock_status(session) == LockStatus::Crashed;
+                self.session_snapshots(session)
+                    .into_iter()
+                    .map(|path| self.recovery_entry(session, path, crashed))
+                    .collect::<Vec<_>>()
+            })
+            .filter(|snapshots| !snapshots.is_empty())
+            .collect();
+        documents.sort_by_key(|snapshots| {
+            (
+                std::cmp::Reverse(snapshots[0].crashed),
+                std::cmp::Reverse(snapshots[0].metadata.timestamp),
+            )
+        });
+        Ok(documents)
     }
 
-    fn swap_belongs_to_current_session(&self, path: &Path) -> bool {
-        if let Some(stem) = path.file_stem().and_then(|s| s.to_str()) {
-            if let Some(pos) = stem.rfind('-') {
-                let session = &stem[pos + 1..];
-                return session == self.session_token;
-            }
+    fn recovery_entry(&self, session: &str, swap_path: PathBuf, crashed: bool) -> RecoveryEntry {
+        let meta_path = self.autosave_metadata_path(&swap_path);
+        let metadata = fs::read_to_string(&meta_path)
+            .ok()
+            .and_then(|raw| serde_json::from_str::<AutosaveMetadata>(&raw).ok())
+            .unwrap_or_default();
+        RecoveryEntry {
+            swap_path,
+            meta_path,
+            metadata,
+            session: session.to_string(),
+            crashed,
         }
-        false
     }
 
-    fn present_next_recovery(self: &Rc<Self>, entries: Rc<RefCell<Vec<RecoveryEntry>>>) {
-        let entry = match entries.borrow_mut().pop() {
-            Some(e) => e,
+    fn session_is_open(&self, session: &str) -> bool {
+        self.tabs
+            .borrow()
+            .iter()
+            .any(|tab| tab.session_token == session)
+    }
+
+    /// Offer the documents in `queue` one after another, in the order
+    /// [`Self::collect_recovery_entries`] sorted them
+    fn present_next_recovery(self: &Rc<Self>, queue: Rc<RefCell<VecDeque<Vec<RecoveryEntry>>>>) {
+        let snapshots = match queue.borrow_mut().pop_front() {
+            Some(snapshots) => snapshots,
             None => return,
         };
-        let description = entry.metadata.description();
+        let description = snapshots[0].metadata.description();
         let dialog = gtk::MessageDialog::builder()
             .transient_for(&self.window())
             .modal(true)
-            .text("Recovered autosave found")
+            .text(if snapshots[0].crashed {
+                "Unsaved work from a crash"
+            } else {
+                "Recovered autosave found"
+            })
             .secondary_text(&description)
             .build();
         dialog.add_button("Discard", gtk::ResponseType::Reject);
         dialog.add_button("Restore", gtk::ResponseType::Accept);
+        let message_area = dialog.message_area().downcast::<gtk::Box>().ok();
+
+        // The newest snapshot may have caught a cleared or broken buffer
+        let picker = (snapshots.len() > 1).then(|| {
+            let labels: Vec<String> = snapshots
+                .iter()
+                .enumerate()
+                .map(|(index, entry)| entry.metadata.snapshot_label(index))
+                .collect();
+            let labels: Vec<&str> = labels.iter().map(String::as_str).collect();
+            let picker = gtk::DropDown::from_strings(&labels);
+            picker.set_tooltip_text(Some("Snapshot to restore"));
+            if let Some(area) = &message_area {
+                area.append(&picker);
+            }
+            picker
+        });
+
+        let preview = gtk::TextView::builder()
+            .editable(false)
+            .cursor_visible(false)
+            .monospace(true)
+            .top_margin(6)
+            .bottom_margin(6)
+            .left_margin(6)
+            .right_margin(6)
+            .build();
+        let preview_scroller = gtk::ScrolledWindow::builder()
+            .min_content_height(240)
+            .min_content_width(480)
+            .child(&preview)
+            .build();
+        let expander = gtk::Expander::builder()
+            .label("Show changes")
+            .child(&preview_scroller)
+            .build();
+        if let Some(area) = &message_area {
+            area.append(&expander);
+        }
+        let snapshots = Rc::new(snapshots);
+        let refresh_preview = {
+            let snapshots = snapshots.clone();
+            let picker = picker.clone();
+            let expander = expander.clone();
+            move || {
+                // Read only once the user asks to see the changes
+                if !expander.is_expanded() {
+                    return;
+                }
+                let chosen = picker
+                    .as_ref()
+                    .map_or(0, |picker| picker.selected() as usize);
+                if let Some(entry) = snapshots.get(chosen) {
+                    preview.buffer().set_text(&entry.preview());
+                }
+            }
+        };
+        let refresh_preview = Rc::new(refresh_preview);
+        {
+            let refresh_preview = refresh_preview.clone();
+            expander.connect_expanded_notify(move |_| refresh_preview());
+        }
+        if let Some(picker) = &picker {
+            picker.connect_selected_notify(move |_| refresh_preview());
+        }
+
         let weak = Rc::downgrade(self);
         dialog.connect_response(move |dialog, response| {
             if let Some(state) = weak.upgrade() {
-                match response {
-                    gtk::ResponseType::Accept => state.restore_recovery_entry(&entry),
-                    _ => state.discard_recovery_entry(&entry),
+                if response == gtk::ResponseType::Accept {
+                    let chosen = picker
+                        .as_ref()
+                        .map_or(0, |picker| picker.selected() as usize);
+                    if let Some(entry) = snapshots.get(chosen) {
+                        state.restore_recovery_entry(entry);
+                    }
+                }
+                for entry in snapshots.iter() {
+                    state.discard_recovery_entry(entry);
                 }
-                state.present_next_recovery(entries.clone());
+                state.remove_session_lock(&snapshots[0].session);
+                state.present_next_recovery(queue.clone());
             }
             dialog.close();
         });
         dialog.show();
     }
 
-    fn restore_recovery_entry(&self, entry: &RecoveryEntry) {
+    fn restore_recovery_entry(self: &Rc<Self>, entry: &RecoveryEntry) {
         match fs::read_to_string(&entry.swap_path) {
             Ok(contents) => {
-                self.document.buffer().set_text(&contents);
-                self.buffer.set_modified(true);
-                if let Some(path) = entry.metadata.original_path.as_ref().map(PathBuf::from) {
-                    self.file_path.replace(Some(path));
+                // Each recovered draft gets its own tab unless this one is blank
+                let tab = if self.is_pristine() {
+                    self.tab()
                 } else {
-                    self.file_path.replace(None);
+                    self.open_tab()
+                };
+                tab.buffer.set_text(&contents);
+                // Saving writes the file back the way it was
+                if let Some(encoding) = entry.metadata.encoding() {
+                    tab.document.set_encoding(encoding);
                 }
-                self.window().grab_focus();
-                self.last_edit.replace(Some(Instant::now()));
+                tab.document.set_line_ending(entry.metadata.line_ending());
+                tab.document.set_bom(entry.metadata.bom);
+                tab.buffer.set_modified(true);
+                tab.file_path
+                    .replace(entry.metadata.original_path.as_ref().map(PathBuf::from));
+                self.write_session_lock(&tab);
+                self.detect_language(&tab);
+                self.update_title();
+                tab.last_edit.replace(Some(Instant::now()));
                 self.show_toast("Recovered autosave applied");
             }
             Err(err) => self.present_error("Failed to restore", &err.to_string()),
         }
-        self.discard_recovery_entry(entry);
     }
 
     fn discard_recovery_entry(&self, entry: &RecoveryEntry) {
@@ -134,6 +274,51 @@ impl AppState {
     }
 }
 
+impl RecoveryEntry {
+    /// What restoring would change: a diff against the file on disk, or the
+    /// start of the snapshot when there is no file to compare with. The file
+    /// is read the way opening it would, so only real edits show up.
+    fn preview(&self) -> String {
+        let snapshot = match fs::read_to_string(&self.swap_path) {
+            Ok(snapshot) => snapshot,
+            Err(err) => return format!("Failed to read the snapshot: {err}"),
+        };
+        let original = self
+            .metadata
+            .original_path
+            .as_ref()
+            .and_then(|path| fs::read(path).ok())
+            .and_then(|bytes| decode_text(&bytes, self.metadata.encoding()).ok())
+            .map(|(text, _, _)| LineEnding::Lf.apply(&text));
+        recovery_preview(original.as_deref(), &LineEnding::Lf.apply(&snapshot))
+    }
+}
+
+/// Lines of a snapshot shown when there is no file to compare it with
+const PREVIEW_LINES: usize = 200;
+
+fn recovery_preview(original: Option<&str>, snapshot: &str) -> String {
+    let Some(original) = original else {
+        let mut preview: String = snapshot
+            .lines()
+            .take(PREVIEW_LINES)
+            .collect::<Vec<_>>()
+            .join("\n");
+        if snapshot.lines().nth(PREVIEW_LINES).is_some() {
+            preview.push_str("\n…");
+        }
+        return preview;
+    };
+    if original == snapshot {
+        return "The snapshot matches the file on disk.".to_string();
+    }
+    similar::TextDiff::from_lines(original, snapshot)
+        .unified_diff()
+        .context_radius(3)
+        .header("On disk", "Snapshot")
+        .to_string()
+}
+
 impl AutosaveMetadata {
     pub(super) fn description(&self) -> String {
         let location = self.original_path.as_deref().unwrap_or("Untitled document");
@@ -147,4 +332,61 @@ impl AutosaveMetadata {
             }
         }
     }
+
+    /// How the snapshot at `index` (newest first) is listed for picking
+    fn snapshot_label(&self, index: usize) -> String {
+        let saved = glib::DateTime::from_unix_local(self.timestamp as i64)
+            .and_then(|time| time.format("%Y-%m-%d %H:%M:%S"))
+            .ok()
+            .filter(|_| self.timestamp != 0);
+        match (index, saved) {
+            (0, Some(saved)) => format!("{saved} (newest)"),
+            (0, None) => "Newest".to_string(),
+            (_, Some(saved)) => saved.to_string(),
+            (_, None) => format!("Snapshot {}", index + 1),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_recovery_preview_diffs_against_the_file() {
+        let preview = recovery_preview(Some("one\ntwo\nthree\n"), "one\n2\nthree\n");
+        assert!(preview.contains("-two\n"));
+        assert!(preview.contains("+2\n"));
+        assert_eq!(
+            recovery_preview(Some("same\n"), "same\n"),
+            "The snapshot matches the file on disk."
+        );
+    }
+
+    #[test]
+    fn test_preview_reads_the_file_like_opening_it() {
+        let dir = tempfile::tempdir().unwrap();
+        let original = dir.path().join("notes.txt");
+        fs::write(&original, b"\xef\xbb\xbfone\r\ntwo\r\n").unwrap();
+        let swap_path = dir.path().join("snapshot.swp");
+        fs::write(&swap_path, "one\ntwo\n").unwrap();
+        let entry = RecoveryEntry {
+            swap_path,
+            meta_path: dir.path().join("snapshot.meta"),
+            metadata: AutosaveMetadata {
+                original_path: Some(original.display().to_string()),
+                ..AutosaveMetadata::default()
+            },
+            session: String::new(),
+            crashed: false,
+        };
+        assert_eq!(entry.preview(), "The snapshot matches the file on disk.");
+    }
+
+    #[test]
+    fn test_recovery_preview_without_a_file_shows_the_snapshot() {
+        assert_eq!(recovery_preview(None, "draft\ntext\n"), "draft\ntext");
+        let long = "line\n".repeat(PREVIEW_LINES + 5);
+        assert!(recovery_preview(None, &long).ends_with("line\n…"));
+    }
 }
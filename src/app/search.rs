@@ -1,5 +1,9 @@
-use sourceview5::prelude::*;
+use std::rc::Rc;
 
+use gtk4::{self as gtk, glib};
+use sourceview5::{SearchContext, prelude::*};
+
+use super::tabs::TabState;
 use super::window::AppState;
 
 impl AppState {
@@ -10,7 +14,7 @@ impl AppState {
         } else {
             self.search_settings.set_search_text(Some(pattern.as_str()));
         }
-        self.search_context.set_highlight(!pattern.is_empty());
+        self.tab().search_context.set_highlight(!pattern.is_empty());
         self.update_search_feedback();
     }
 
@@ -20,16 +24,107 @@ impl AppState {
             self.match_label.set_text("0 matches");
             return;
         }
-        if let Some(err) = self.search_context.regex_error() {
-            self.match_label
-                .set_text(&format!("Regex error: {}", err.message()));
-            self.status_label
-                .set_text(&format!("Regex error: {}", err.message()));
+        let tab = self.tab();
+        if let Some(err) = tab.search_context.regex_error() {
+            self.show_regex_error(err.message());
+        } else if let Some((start, end)) = self.search_scope(&tab) {
+            // Also waits on the background count, like the whole-buffer case
+            match count_in_range(&tab.search_context, &start, &end) {
+                Some(count) => self
+                    .match_label
+                    .set_text(&format!("{} in selection", count)),
+                None => self.match_label.set_text("Counting…"),
+            }
         } else {
-            let count = self.search_context.occurrences_count();
-            self.match_label
-                .set_text(&format!("{} matches", count.max(0)));
+            // -1 until the background scan of the buffer is done; the
+            // count's notify handler comes back here once it is
+            let count = tab.search_context.occurrences_count();
+            if count < 0 {
+                self.match_label.set_text("Counting…");
+            } else {
+                self.match_label.set_text(&format!("{} matches", count));
+            }
+        }
+    }
+
+    /// Refresh the match count as `tab`'s search context finishes counting,
+    /// instead of waiting on the count while typing.
+    pub(super) fn hook_search_context(self: &Rc<Self>, tab: &TabState) {
+        let weak = Rc::downgrade(self);
+        tab.search_context
+            .connect_occurrences_count_notify(move |context| {
+                if let Some(state) = weak.upgrade() {
+                    if *context == state.tab().search_context {
+                        state.update_search_feedback();
+                    }
+                }
+            });
+    }
+
+    fn show_regex_error(&self, message: &str) {
+        self.match_label
+            .set_text(&format!("Regex error: {}", message));
+        self.status_label
+            .set_text(&format!("Regex error: {}", message));
+    }
+
+    /// The replacement text in the form `SearchContext::replace` expands.
+    /// With regex search on, `$1` and `${1}` work as well as GLib's own `\1`,
+    /// and a reference to a group the pattern lacks is an error rather than
+    /// quietly expanding to nothing.
+    fn regex_replacement(&self) -> Result<String, String> {
+        let replacement = self.replace_entry.text();
+        if !self.search_settings.is_regex_enabled() {
+            return Ok(replacement.to_string());
+        }
+        let replacement = dollar_references_to_glib(&replacement);
+        glib::Regex::check_replacement(replacement.as_str())
+            .map_err(|err| err.message().to_string())?;
+        let pattern = glib::Regex::new(
+            &self.search_entry.text(),
+            glib::RegexCompileFlags::empty(),
+            glib::RegexMatchFlags::empty(),
+        );
+        // A broken pattern is reported by the search itself
+        if let (Ok(Some(pattern)), Some(group)) = (pattern, highest_group_reference(&replacement)) {
+            let groups = pattern.capture_count().max(0) as u32;
+            if group > groups {
+                return Err(format!(
+                    "Replacement refers to group {group}, but the pattern has {groups}"
+                ));
+            }
+        }
+        Ok(replacement)
+    }
+
+    /// Limit searching to the current tab's selection while the
+    /// search-in-selection toggle is on, or lift the limit.
+    pub(super) fn update_search_scope(&self) {
+        let tab = self.tab();
+        if let Some((start, end)) = tab.search_scope.take() {
+            tab.buffer.delete_mark(&start);
+            tab.buffer.delete_mark(&end);
+        }
+        if self.selection_toggle.is_active() {
+            if let Some((start, end)) = tab.buffer.selection_bounds() {
+                // Gravities keep text typed at either edge inside the range
+                let start = tab.buffer.create_mark(None, &start, true);
+                let end = tab.buffer.create_mark(None, &end, false);
+                tab.search_scope.replace(Some((start, end)));
+            }
+        }
+        self.update_search_feedback();
+    }
+
+    /// The range searches in `tab` are limited to, if any
+    fn search_scope(&self, tab: &TabState) -> Option<(gtk::TextIter, gtk::TextIter)> {
+        if !self.selection_toggle.is_active() {
+            return None;
         }
+        tab.search_scope
+            .borrow()
+            .as_ref()
+            .map(|(start, end)| (tab.buffer.iter_at_mark(start), tab.buffer.iter_at_mark(end)))
     }
 
     pub(super) fn find_next_match(&self, forward: bool) {
@@ -37,25 +132,28 @@ impl AppState {
             self.show_search_panel(false);
             return;
         }
-        let insert_mark = self.buffer.get_insert();
-        let mut iter = self.buffer.iter_at_mark(&insert_mark);
+        let tab = self.tab();
+        let insert_mark = tab.buffer.get_insert();
+        let mut iter = tab.buffer.iter_at_mark(&insert_mark);
         if forward {
-            if let Some((_, end)) = self.buffer.selection_bounds() {
+            if let Some((_, end)) = tab.buffer.selection_bounds() {
                 iter = end;
             }
-        } else if let Some((start, _)) = self.buffer.selection_bounds() {
+        } else if let Some((start, _)) = tab.buffer.selection_bounds() {
             iter = start;
         }
 
-        let result = if forward {
-            self.search_context.forward(&iter)
+        let result = if let Some((start, end)) = self.search_scope(&tab) {
+            find_in_range(&tab.search_context, &iter, forward, &start, &end)
+        } else if forward {
+            tab.search_context.forward(&iter)
         } else {
-            self.search_context.backward(&iter)
+            tab.search_context.backward(&iter)
         };
 
         if let Some((match_start, match_end, wrapped)) = result {
-            self.buffer.select_range(&match_start, &match_end);
-            let view = self.document.view();
+            tab.buffer.select_range(&match_start, &match_end);
+            let view = tab.document.view();
             let mut scroll_iter = match_start.clone();
             view.scroll_to_iter(&mut scroll_iter, 0.1, false, 0.0, 0.0);
             if wrapped {
@@ -73,12 +171,19 @@ impl AppState {
             self.show_search_panel(false);
             return;
         }
-        if self.buffer.selection_bounds().is_none() {
+        let replacement = match self.regex_replacement() {
+            Ok(replacement) => replacement,
+            Err(message) => {
+                self.show_regex_error(&message);
+                return;
+            }
+        };
+        let tab = self.tab();
+        if tab.buffer.selection_bounds().is_none() {
             self.find_next_match(true);
         }
-        if let Some((mut start, mut end)) = self.buffer.selection_bounds() {
-            let replacement = self.replace_entry.text();
-            match self
+        if let Some((mut start, mut end)) = tab.buffer.selection_bounds() {
+            match tab
                 .search_context
                 .replace(&mut start, &mut end, replacement.as_str())
             {
@@ -101,12 +206,28 @@ impl AppState {
             self.show_search_panel(false);
             return;
         }
-        let replacement = self.replace_entry.text();
-        let mut iter = self.buffer.start_iter();
+        let replacement = match self.regex_replacement() {
+            Ok(replacement) => replacement,
+            Err(message) => {
+                self.show_regex_error(&message);
+                return;
+            }
+        };
+        let tab = self.tab();
+        let mut iter = match self.search_scope(&tab) {
+            Some((start, _)) => start,
+            None => tab.buffer.start_iter(),
+        };
         let mut count = 0;
-        self.buffer.begin_user_action();
-        while let Some((mut start, mut end, _)) = self.search_context.forward(&iter) {
-            match self
+        tab.buffer.begin_user_action();
+        while let Some((mut start, mut end, wrapped)) = tab.search_context.forward(&iter) {
+            // Replacing moves the end of the range, so it is looked up each time
+            if let Some((_, scope_end)) = self.search_scope(&tab) {
+                if wrapped || end > scope_end {
+                    break;
+                }
+            }
+            match tab
                 .search_context
                 .replace(&mut start, &mut end, replacement.as_str())
             {
@@ -121,19 +242,24 @@ impl AppState {
                 }
             }
         }
-        self.buffer.end_user_action();
+        tab.buffer.end_user_action();
         self.update_search_feedback();
         self.status_label
             .set_text(&format!("Replaced {} matches", count));
     }
 
     pub(super) fn show_search_panel(&self, focus_replace: bool) {
+        let tab = self.tab();
         if !self.search_revealer.reveals_child() {
             self.search_revealer.set_reveal_child(true);
+            // The selection at the time the search opens is the range to search
+            if self.selection_toggle.is_active() {
+                self.update_search_scope();
+            }
         }
-        if self.search_entry.text().is_empty() {
-            if let Some((start, end)) = self.buffer.selection_bounds() {
-                let selection = self.buffer.text(&start, &end, true);
+        if self.search_entry.text().is_empty() && !self.selection_toggle.is_active() {
+            if let Some((start, end)) = tab.buffer.selection_bounds() {
+                let selection = tab.buffer.text(&start, &end, true);
                 if !selection.is_empty() {
                     self.search_entry.set_text(&selection);
                     self.search_entry.select_region(0, -1);
@@ -153,3 +279,240 @@ impl AppState {
         self.window().grab_focus();
     }
 }
+
+/// The next match from `from` that lies within `start..end`, going around
+/// to the other end of the range instead of the document
+fn find_in_range(
+    context: &SearchContext,
+    from: &gtk::TextIter,
+    forward: bool,
+    start: &gtk::TextIter,
+    end: &gtk::TextIter,
+) -> Option<(gtk::TextIter, gtk::TextIter, bool)> {
+    let inside = |found: &(gtk::TextIter, gtk::TextIter, bool)| {
+        !found.2 && found.0 >= *start && found.1 <= *end
+    };
+    let from = from.clone().clamp(start.clone(), end.clone());
+    let around = || {
+        let found = if forward {
+            context.forward(start)
+        } else {
+            context.backward(end)
+        };
+        found.filter(inside).map(|(s, e, _)| (s, e, true))
+    };
+    let found = if forward {
+        context.forward(&from)
+    } else {
+        context.backward(&from)
+    };
+    found.filter(inside).or_else(around)
+}
+
+/// Matches within `start..end`, worked out from where the first and last of
+/// them stand in the context's background count rather than by scanning the
+/// range. `None` until that count is done.
+fn count_in_range(
+    context: &SearchContext,
+    start: &gtk::TextIter,
+    end: &gtk::TextIter,
+) -> Option<usize> {
+    let inside = |found: &(gtk::TextIter, gtk::TextIter, bool)| {
+        !found.2 && found.0 >= *start && found.1 <= *end
+    };
+    let Some(first) = context.forward(start).filter(inside) else {
+        return Some(0);
+    };
+    let mut last = context.backward(end);
+    // A match running past the end of the range doesn't count
+    while let Some(found) = last.clone().filter(|found| !found.2 && found.1 > *end) {
+        last = context.backward(&found.0);
+    }
+    let last = last.filter(inside)?;
+    let first = context.occurrence_position(&first.0, &first.1);
+    let last = context.occurrence_position(&last.0, &last.1);
+    // -1 while counting, 0 if the count doesn't know the match yet
+    (first > 0 && last >= first).then(|| (last - first + 1) as usize)
+}
+
+/// Rewrite `$1` and `${1}` (or `${name}`) references as GLib's `\g<1>`.
+/// `$$` is a literal `$`, and backslash escapes are left for GLib.
+fn dollar_references_to_glib(replacement: &str) -> String {
+    let mut out = String::with_capacity(replacement.len());
+    let mut chars = replacement.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                out.push(c);
+                if let Some(escaped) = chars.next() {
+                    out.push(escaped);
+                }
+            }
+            '$' => match chars.peek() {
+                Some('$') => {
+                    chars.next();
+                    out.push('$');
+                }
+                Some(d) if d.is_ascii_digit() => {
+                    let mut group = String::new();
+                    while let Some(d) = chars.next_if(char::is_ascii_digit) {
+                        group.push(d);
+                    }
+                    out.push_str(&format!("\\g<{group}>"));
+                }
+                Some('{') => {
+                    let rest: String = chars.clone().skip(1).collect();
+                    match rest.find('}') {
+                        Some(len) if len > 0 => {
+                            let name = &rest[..len];
+                            out.push_str(&format!("\\g<{name}>"));
+                            // Past the braces and the name
+                            chars.nth(name.chars().count() + 1);
+                        }
+                        _ => out.push('$'),
+                    }
+                }
+                _ => out.push('$'),
+            },
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// The highest numbered group a GLib replacement string refers to
+fn highest_group_reference(replacement: &str) -> Option<u32> {
+    let mut highest = None;
+    let mut chars = replacement.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            continue;
+        }
+        let mut digits = String::new();
+        match chars.next() {
+            Some(d) if d.is_ascii_digit() => {
+                digits.push(d);
+                if let Some(d) = chars.next_if(char::is_ascii_digit) {
+                    digits.push(d);
+                }
+            }
+            Some('g') if chars.peek() == Some(&'<') => {
+                chars.next();
+                while let Some(c) = chars.next_if(|c| *c != '>') {
+                    digits.push(c);
+                }
+            }
+            _ => continue,
+        }
+        if let Ok(group) = digits.parse::<u32>() {
+            highest = highest.max(Some(group));
+        }
+    }
+    highest
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dollar_references_become_glib_references() {
+        assert_eq!(dollar_references_to_glib("$2 $1"), "\\g<2> \\g<1>");
+        assert_eq!(dollar_references_to_glib("${10}x"), "\\g<10>x");
+        assert_eq!(dollar_references_to_glib("${word}"), "\\g<word>");
+        assert_eq!(dollar_references_to_glib("\\2 \\1"), "\\2 \\1");
+        assert_eq!(dollar_references_to_glib("$$1 costs $"), "$1 costs $");
+        assert_eq!(dollar_references_to_glib("\\$1"), "\\$1");
+    }
+
+    #[test]
+    fn test_highest_group_reference() {
+        assert_eq!(highest_group_reference("\\2 \\1"), Some(2));
+        assert_eq!(highest_group_reference("\\g<12> \\0"), Some(12));
+        assert_eq!(highest_group_reference("\\\\3 \\g<name>"), None);
+        assert_eq!(highest_group_reference("plain"), None);
+    }
+
+    /// A search for `pattern` in `text`, with the background count finished
+    fn counted_search(text: &str, pattern: &str) -> Option<(sourceview5::Buffer, SearchContext)> {
+        if gtk4::init().is_err() {
+            eprintln!("Skipping GTK test due to missing display");
+            return None;
+        }
+        let buffer = sourceview5::Buffer::new(None);
+        buffer.set_text(text);
+        let settings = sourceview5::SearchSettings::new();
+        settings.set_search_text(Some(pattern));
+        let context = SearchContext::new(&buffer, Some(&settings));
+        let main_context = glib::MainContext::default();
+        while context.occurrences_count() < 0 {
+            main_context.iteration(true);
+        }
+        Some((buffer, context))
+    }
+
+    #[test]
+    fn test_find_in_range_wraps_within_the_range() {
+        let Some((buffer, context)) = counted_search("ab ab ab ab", "ab") else {
+            return;
+        };
+        let (start, end) = (buffer.iter_at_offset(3), buffer.iter_at_offset(8));
+        let (found, _, wrapped) =
+            find_in_range(&context, &buffer.iter_at_offset(4), true, &start, &end).unwrap();
+        assert_eq!((found.offset(), wrapped), (6, false));
+        // Past the last match in the range, it goes back to the range's start
+        let (found, _, wrapped) =
+            find_in_range(&context, &buffer.iter_at_offset(8), true, &start, &end).unwrap();
+        assert_eq!((found.offset(), wrapped), (3, true));
+        let (found, _, wrapped) =
+            find_in_range(&context, &buffer.iter_at_offset(3), false, &start, &end).unwrap();
+        assert_eq!((found.offset(), wrapped), (6, true));
+    }
+
+    #[test]
+    fn test_count_in_range() {
+        let Some((buffer, context)) = counted_search("ab ab ab ab", "ab") else {
+            return;
+        };
+        let count = |from, to| {
+            count_in_range(
+                &context,
+                &buffer.iter_at_offset(from),
+                &buffer.iter_at_offset(to),
+            )
+        };
+        assert_eq!(count(0, 11), Some(4));
+        assert_eq!(count(3, 8), Some(2));
+        // Matches cut by either edge are left out
+        assert_eq!(count(1, 7), Some(1));
+        assert_eq!(count(1, 2), Some(0));
+    }
+
+    #[test]
+    fn test_replace_swaps_capture_groups() {
+        if gtk4::init().is_err() {
+            eprintln!("Skipping GTK test due to missing display");
+            return;
+        }
+
+        for replacement in ["\\2 \\1", "$2 $1"] {
+            let buffer = sourceview5::Buffer::new(None);
+            buffer.set_text("hello world");
+            let settings = sourceview5::SearchSettings::new();
+            settings.set_regex_enabled(true);
+            settings.set_search_text(Some(r"(\w+) (\w+)"));
+            let context = SearchContext::new(&buffer, Some(&settings));
+
+            let (mut start, mut end, _) = context.forward(&buffer.start_iter()).unwrap();
+            context
+                .replace(
+                    &mut start,
+                    &mut end,
+                    &dollar_references_to_glib(replacement),
+                )
+                .unwrap();
+            let text = buffer.text(&buffer.start_iter(), &buffer.end_iter(), false);
+            assert_eq!(text, "world hello");
+        }
+    }
+}
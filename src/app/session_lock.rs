@@ -0,0 +1,183 @@
+//! Lock files for the sessions a running window owns, one per tab. A tab's
+//! lock is removed when it closes cleanly, so a lock whose process is gone
+//! means the editor crashed or was killed with that tab open.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use serde_json;
+
+use super::recent;
+use super::tabs::TabState;
+use super::window::AppState;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(super) struct SessionLock {
+    pub(super) pid: u32,
+    pub(super) session: String,
+    /// The file open in the tab, if it has one
+    #[serde(default)]
+    pub(super) path: Option<String>,
+    /// Boot the lock was written in; after a reboot its pid may belong to
+    /// some other process
+    #[serde(default)]
+    pub(super) boot_id: Option<String>,
+}
+
+/// What a session's lock file says about the process that wrote it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum LockStatus {
+    /// Still running, in this process or another one
+    Running,
+    /// Its process is gone without removing the lock
+    Crashed,
+    /// No lock: snapshots that were left behind, not a crash
+    Missing,
+}
+
+impl SessionLock {
+    fn is_alive(&self) -> bool {
+        let same_boot = match (&self.boot_id, boot_id()) {
+            (Some(written), Some(current)) => *written == current,
+            // Locks from older versions, or no way to tell
+            _ => true,
+        };
+        same_boot && process_alive(self.pid)
+    }
+}
+
+impl AppState {
+    fn session_lock_path(&self, session: &str) -> PathBuf {
+        self.paths.autosave_dir.join(format!("{session}.lock"))
+    }
+
+    /// Mark `tab` as owned by this process. Written again whenever the tab's
+    /// file changes, so other instances can tell what it has open.
+    pub(super) fn write_session_lock(&self, tab: &TabState) {
+        let lock = SessionLock {
+            pid: std::process::id(),
+            session: tab.session_token.clone(),
+            path: tab
+                .file_path
+                .borrow()
+                .as_ref()
+                .map(|p| p.display().to_string()),
+            boot_id: boot_id(),
+        };
+        let path = self.session_lock_path(&tab.session_token);
+        let result = serde_json::to_string(&lock)
+            .map_err(anyhow::Error::from)
+            .and_then(|raw| fs::write(&path, raw).map_err(anyhow::Error::from));
+        if let Err(err) = result {
+            log::warn!("Failed to write session lock {:?}: {err:?}", path);
+        }
+    }
+
+    pub(super) fn remove_session_lock(&self, session: &str) {
+        let path = self.session_lock_path(session);
+        if path.exists() {
+            if let Err(err) = fs::remove_file(&path) {
+                log::warn!("Failed to delete session lock {:?}: {:?}", path, err);
+            }
+        }
+    }
+
+    fn read_session_lock(&self, session: &str) -> Option<SessionLock> {
+        let raw = fs::read_to_string(self.session_lock_path(session)).ok()?;
+        serde_json::from_str(&raw).ok()
+    }
+
+    fn session_locks(&self) -> Vec<SessionLock> {
+        let Ok(dir) = fs::read_dir(&self.paths.autosave_dir) else {
+            return Vec::new();
+        };
+        dir.flatten()
+            .filter(|entry| entry.path().extension().is_some_and(|ext| ext == "lock"))
+            .filter_map(|entry| fs::read_to_string(entry.path()).ok())
+            .filter_map(|raw| serde_json::from_str(&raw).ok())
+            .collect()
+    }
+
+    pub(super) fn session_lock_status(&self, session: &str) -> LockStatus {
+        match self.read_session_lock(session) {
+            Some(lock) if lock.is_alive() => LockStatus::Running,
+            Some(_) => LockStatus::Crashed,
+            None => LockStatus::Missing,
+        }
+    }
+
+    /// Drop locks of crashed sessions that left nothing to recover
+    pub(super) fn clear_stale_session_locks(&self) {
+        for lock in self.session_locks() {
+            if !lock.is_alive() && self.session_snapshots(&lock.session).is_empty() {
+                self.remove_session_lock(&lock.session);
+            }
+        }
+    }
+
+    /// Let the user know when another running instance has `path` open too,
+    /// since saving in both would overwrite one another's changes.
+    pub(super) fn warn_if_open_elsewhere(&self, path: &Path) {
+        let target = recent::canonical_path(path);
+        let pid = std::process::id();
+        let elsewhere = self.session_locks().into_iter().any(|lock| {
+            lock.pid != pid
+                && lock.is_alive()
+                && lock
+                    .path
+                    .as_ref()
+                    .is_some_and(|open| recent::canonical_path(Path::new(open)) == target)
+        });
+        if elsewhere {
+            self.show_toast("This file is also open in another Wispnote window");
+        }
+    }
+}
+
+/// Identifies the current boot of the machine, where the kernel exposes one
+fn boot_id() -> Option<String> {
+    fs::read_to_string("/proc/sys/kernel/random/boot_id")
+        .ok()
+        .map(|id| id.trim().to_string())
+}
+
+/// Whether a process with `pid` exists. Without `/proc` there is no cheap way
+/// to tell, so the lock is trusted and the session treated as running.
+fn process_alive(pid: u32) -> bool {
+    if pid == std::process::id() {
+        return true;
+    }
+    let proc_dir = Path::new("/proc");
+    !proc_dir.is_dir() || proc_dir.join(pid.to_string()).exists()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_process_alive() {
+        assert!(process_alive(std::process::id()));
+        if Path::new("/proc").is_dir() {
+            // Above the kernel's pid_max, so never a running process
+            assert!(!process_alive(u32::MAX));
+        }
+    }
+
+    #[test]
+    fn test_lock_from_earlier_boot_is_not_alive() {
+        let lock = SessionLock {
+            pid: std::process::id(),
+            session: "session".to_string(),
+            path: None,
+            boot_id: Some("earlier-boot".to_string()),
+        };
+        assert_eq!(lock.is_alive(), boot_id().is_none());
+        let lock = SessionLock {
+            boot_id: boot_id(),
+            ..lock
+        };
+        assert!(lock.is_alive());
+    }
+}
@@ -0,0 +1,138 @@
+use std::rc::Rc;
+
+use gtk4::{self as gtk, glib, prelude::*};
+
+use crate::llm::LlmManager;
+
+use super::window::AppState;
+
+const COPY_RESPONSE: gtk::ResponseType = gtk::ResponseType::Other(1);
+const INSERT_RESPONSE: gtk::ResponseType = gtk::ResponseType::Other(2);
+
+impl AppState {
+    /// Summarize the selection (or the whole document) with the configured provider
+    pub(super) fn summarize_document(self: &Rc<Self>) {
+        let (text, from_selection) = match self.tab().buffer.selection_bounds() {
+            Some((start, end)) => (
+                self.tab().buffer.text(&start, &end, false).to_string(),
+                true,
+            ),
+            None => (self.tab().document.text_without_ghost(), false),
+        };
+        if text.trim().is_empty() {
+            self.show_toast("Nothing to summarize");
+            return;
+        }
+
+        self.status_label.set_text(if from_selection {
+            "Summarizing selection…"
+        } else {
+            "Summarizing document…"
+        });
+
+        let llm_manager = self.llm_manager.clone();
+        let (tx, rx) = std::sync::mpsc::channel::<anyhow::Result<String>>();
+
+        std::thread::spawn(move || {
+            let result = (|| -> anyhow::Result<String> {
+                let manager = llm_manager
+                    .lock()
+                    .map_err(|e| anyhow::anyhow!("Failed to lock LLM manager: {}", e))?;
+                // Let go of the manager while a remote request is out
+                if let Some(config) = manager.remote_config() {
+                    drop(manager);
+                    return LlmManager::summarize_remote(&config, &text);
+                }
+                manager.summarize(&text)
+            })();
+            let _ = tx.send(result);
+        });
+
+        let weak = Rc::downgrade(self);
+        glib::timeout_add_local(std::time::Duration::from_millis(100), move || {
+            let Some(state) = weak.upgrade() else {
+                return glib::ControlFlow::Break;
+            };
+            match rx.try_recv() {
+                Ok(Ok(summary)) if !summary.is_empty() => {
+                    state.status_label.set_text("");
+                    state.present_summary(&summary);
+                    glib::ControlFlow::Break
+                }
+                Ok(Ok(_)) => {
+                    state.status_label.set_text("");
+                    state.show_toast("The model returned an empty summary");
+                    glib::ControlFlow::Break
+                }
+                Ok(Err(err)) => {
+                    log::warn!("Summary failed: {err}");
+                    state
+                        .status_label
+                        .set_text(&format!("Summary failed: {}", err));
+                    glib::ControlFlow::Break
+                }
+                Err(std::sync::mpsc::TryRecvError::Empty) => glib::ControlFlow::Continue,
+                Err(std::sync::mpsc::TryRecvError::Disconnected) => {
+                    state
+                        .status_label
+                        .set_text("Summary interrupted unexpectedly");
+                    glib::ControlFlow::Break
+                }
+            }
+        });
+    }
+
+    /// Show the summary in a scratch area with options to copy or insert it
+    fn present_summary(self: &Rc<Self>, summary: &str) {
+        let dialog = gtk::Dialog::builder()
+            .transient_for(&self.window())
+            .modal(true)
+            .title("Summary")
+            .default_width(480)
+            .default_height(320)
+            .build();
+        dialog.add_button("Close", gtk::ResponseType::Close);
+        dialog.add_button("Copy", COPY_RESPONSE);
+        dialog.add_button("Insert at Cursor", INSERT_RESPONSE);
+
+        // Editable so the summary can be tweaked before copying or inserting
+        let scratch = gtk::TextView::builder()
+            .wrap_mode(gtk::WrapMode::WordChar)
+            .top_margin(12)
+            .bottom_margin(12)
+            .left_margin(12)
+            .right_margin(12)
+            .build();
+        scratch.buffer().set_text(summary);
+        let scroller = gtk::ScrolledWindow::builder()
+            .hexpand(true)
+            .vexpand(true)
+            .child(&scratch)
+            .build();
+        dialog.content_area().append(&scroller);
+
+        let weak = Rc::downgrade(self);
+        dialog.connect_response(move |dialog, response| {
+            let buffer = scratch.buffer();
+            let text = buffer.text(&buffer.start_iter(), &buffer.end_iter(), false);
+            if let Some(state) = weak.upgrade() {
+                if response == COPY_RESPONSE {
+                    state.window().clipboard().set_text(&text);
+                    state.show_toast("Summary copied to clipboard");
+                } else if response == INSERT_RESPONSE {
+                    state.with_suppressed_completion(|| {
+                        state.tab().document.dismiss_ghost_text();
+                        state.tab().buffer.insert_at_cursor(&text);
+                    });
+                    state
+                        .tab()
+                        .last_edit
+                        .replace(Some(std::time::Instant::now()));
+                    state.update_title();
+                }
+            }
+            dialog.close();
+        });
+        dialog.show();
+    }
+}
@@ -0,0 +1,422 @@
+//! Open documents, one per tab. Each tab owns its buffer and everything tied
+//! to its file; settings, the LLM and the window chrome are shared.
+
+use std::cell::{Cell, RefCell};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::{Instant, SystemTime};
+
+use adw::prelude::*;
+use anyhow::Result;
+use gtk4::glib::Propagation;
+use gtk4::{self as gtk, gio, prelude::*};
+use libadwaita as adw;
+use sourceview5::{SearchContext, SearchSettings};
+use uuid::Uuid;
+
+use crate::document::{Document, derive_display_name};
+
+use super::append_log;
+use super::editor_settings::EDITOR_CSS_CLASS;
+use super::recent;
+use super::window::AppState;
+
+pub(super) struct TabState {
+    /// The tab page's child, which identifies the tab in the `TabView`
+    pub(super) scroller: gtk::ScrolledWindow,
+    pub(super) document: Rc<Document>,
+    pub(super) buffer: sourceview5::Buffer,
+    pub(super) file_path: RefCell<Option<PathBuf>>,
+    pub(super) search_context: SearchContext,
+    /// Start and end of the selection searches are limited to
+    pub(super) search_scope: RefCell<Option<(gtk::TextMark, gtk::TextMark)>>,
+    pub(super) file_monitor: RefCell<Option<gio::FileMonitor>>,
+    /// Set while the monitor is paused: the file's mtime when it was dropped
+    pub(super) paused_monitor_mtime: RefCell<Option<Option<SystemTime>>>,
+    pub(super) external_change_pending: Cell<bool>,
+    pub(super) last_edit: RefCell<Option<Instant>>,
+    pub(super) last_char_count: Cell<i32>,
+    pub(super) last_autosave: Cell<Option<Instant>>,
+    /// The document has lines over `LONG_LINE_CHARS`
+    pub(super) long_lines: Cell<bool>,
+    pub(super) append_lock_tag: gtk::TextTag,
+    pub(super) append_block_open: Cell<bool>,
+    pub(super) append_last_input: Cell<Option<Instant>>,
+    pub(super) append_stamping: Cell<bool>,
+    /// Keeps this tab's autosave files apart from other tabs and windows
+    pub(super) session_token: String,
+}
+
+impl TabState {
+    fn new(search_settings: &SearchSettings) -> Rc<Self> {
+        let document = Document::new();
+        let buffer = document.buffer();
+        let view = document.view();
+        view.add_css_class(EDITOR_CSS_CLASS);
+        let scroller = gtk::ScrolledWindow::builder()
+            .hexpand(true)
+            .vexpand(true)
+            .child(&view)
+            .build();
+        let search_context = SearchContext::new(&buffer, Some(search_settings));
+        search_context.set_highlight(false);
+        let append_lock_tag = append_log::create_lock_tag(&buffer);
+
+        Rc::new(Self {
+            scroller,
+            document,
+            buffer,
+            file_path: RefCell::new(None),
+            search_context,
+            search_scope: RefCell::new(None),
+            file_monitor: RefCell::new(None),
+            paused_monitor_mtime: RefCell::new(None),
+            external_change_pending: Cell::new(false),
+            last_edit: RefCell::new(None),
+            last_char_count: Cell::new(0),
+            last_autosave: Cell::new(None),
+            long_lines: Cell::new(false),
+            append_lock_tag,
+            append_block_open: Cell::new(false),
+            append_last_input: Cell::new(None),
+            append_stamping: Cell::new(false),
+            session_token: Uuid::new_v4().to_string(),
+        })
+    }
+
+    fn is_on(&self, page: &adw::TabPage) -> bool {
+        page.child() == *self.scroller.upcast_ref::<gtk::Widget>()
+    }
+}
+
+impl AppState {
+    /// The tab being edited
+    pub(super) fn tab(&self) -> Rc<TabState> {
+        let tabs = self.tabs.borrow();
+        self.tab_view
+            .selected_page()
+            .and_then(|page| tabs.iter().find(|tab| tab.is_on(&page)))
+            .or_else(|| tabs.first())
+            .cloned()
+            .expect("A window always has at least one tab")
+    }
+
+    pub(super) fn tabs(&self) -> Vec<Rc<TabState>> {
+        self.tabs.borrow().clone()
+    }
+
+    pub(super) fn is_current_tab(&self, tab: &TabState) -> bool {
+        std::ptr::eq(Rc::as_ptr(&self.tab()), tab)
+    }
+
+    fn tab_for_page(&self, page: &adw::TabPage) -> Option<Rc<TabState>> {
+        self.tabs
+            .borrow()
+            .iter()
+            .find(|tab| tab.is_on(page))
+            .cloned()
+    }
+
+    pub(super) fn select_tab(&self, tab: &TabState) {
+        self.tab_view
+            .set_selected_page(&self.tab_view.page(&tab.scroller));
+    }
+
+    /// Add a blank tab and switch to it
+    pub(super) fn open_tab(self: &Rc<Self>) -> Rc<TabState> {
+        let tab = TabState::new(&self.search_settings);
+        self.tabs.borrow_mut().push(tab.clone());
+        self.hook_buffer_signals(&tab);
+        self.hook_append_block(&tab);
+        self.hook_search_context(&tab);
+        self.install_completion_shortcuts(&tab);
+        self.install_zoom_scroll(&tab);
+        self.write_session_lock(&tab);
+
+        let page = self.tab_view.append(&tab.scroller);
+        page.set_title(&derive_display_name(&None));
+        self.tab_view.set_selected_page(&page);
+        self.apply_editor_settings();
+        self.reset_append_lock();
+        tab.document.view().grab_focus();
+        tab
+    }
+
+    /// Open `path` in the current tab if that is blank and in a new tab
+    /// otherwise. A file that is already open is just brought forward.
+    pub(super) fn open_path(self: &Rc<Self>, path: &Path) {
+        let target = recent::canonical_path(path);
+        let open = self.tabs().into_iter().find(|tab| {
+            tab.file_path
+                .borrow()
+                .as_ref()
+                .is_some_and(|open| recent::canonical_path(open) == target)
+        });
+        if let Some(tab) = open {
+            self.select_tab(&tab);
+            return;
+        }
+
+        if !self.is_pristine() {
+            self.open_tab();
+        }
+        if let Err(err) = self.load_document_from_path(path) {
+            self.present_error("Failed to open", &err.to_string());
+        }
+    }
+
+    /// Close the current tab; unsaved changes are asked about first
+    pub(super) fn close_current_tab(&self) {
+        if let Some(page) = self.tab_view.selected_page() {
+            self.tab_view.close_page(&page);
+        }
+    }
+
+    pub(super) fn hook_tab_view(self: &Rc<Self>) {
+        let weak = Rc::downgrade(self);
+        self.tab_view.connect_close_page(move |view, page| {
+            let Some(state) = weak.upgrade() else {
+                return Propagation::Proceed;
+            };
+            // The last tab closes with its window, which asks about every tab
+            if view.n_pages() <= 1 {
+                view.close_page_finish(page, false);
+                state.window().close();
+                return Propagation::Stop;
+            }
+            let modified = state
+                .tab_for_page(page)
+                .is_some_and(|tab| tab.buffer.is_modified());
+            if !modified {
+                return Propagation::Proceed;
+            }
+
+            // Keep the page while asking; agreeing closes it again, unmodified
+            view.close_page_finish(page, false);
+            view.set_selected_page(page);
+            let page = page.clone();
+            state.confirm_unsaved_then(move |st| {
+                st.tab().buffer.set_modified(false);
+                st.tab_view.close_page(&page);
+            });
+            Propagation::Stop
+        });
+
+        let weak = Rc::downgrade(self);
+        self.tab_view.connect_page_detached(move |_, page, _| {
+            let Some(state) = weak.upgrade() else {
+                return;
+            };
+            let Some(tab) = state.tab_for_page(page) else {
+                return;
+            };
+            if state.tabs.borrow().len() <= 1 {
+                return;
+            }
+            state
+                .tabs
+                .borrow_mut()
+                .retain(|open| !Rc::ptr_eq(open, &tab));
+            tab.file_monitor.borrow_mut().take();
+            // Closing drops the tab's changes, so its draft is no longer needed
+            state.remove_autosave_artifacts(&tab);
+            state.remove_session_lock(&tab.session_token);
+        });
+
+        let weak = Rc::downgrade(self);
+        self.tab_view.connect_selected_page_notify(move |_| {
+            if let Some(state) = weak.upgrade() {
+                state.show_current_tab();
+            }
+        });
+    }
+
+    /// Bring the window chrome in line with the tab just switched to
+    fn show_current_tab(&self) {
+        if self.tabs.borrow().is_empty() {
+            return;
+        }
+        // A suggestion belongs to the tab it was made for
+        self.bump_completion_generation();
+        self.cancel_completion_debounce();
+        self.completion_wait_start.set(None);
+        for tab in self.tabs() {
+            self.with_suppressed_completion(|| tab.document.dismiss_ghost_text());
+        }
+
+        self.update_title();
+        self.update_cursor_label();
+        self.update_count_label();
+        self.update_history_buttons();
+        self.update_search_pattern();
+        self.update_markdown_preview();
+        self.tab().document.view().grab_focus();
+    }
+
+    /// Undo and redo buttons follow the current tab's history
+    pub(super) fn update_history_buttons(&self) {
+        let document = self.tab().document.clone();
+        self.undo_button.set_sensitive(document.buffer().can_undo());
+        self.redo_button.set_sensitive(document.can_redo());
+    }
+
+    /// Follow edits in `tab`. Background tabs only change when a file is
+    /// reloaded into them, which doesn't concern the window chrome.
+    pub(super) fn hook_buffer_signals(self: &Rc<Self>, tab: &TabState) {
+        let weak = Rc::downgrade(self);
+        tab.buffer.connect_changed(move |buffer| {
+            if let Some(state) = weak.upgrade() {
+                if *buffer != state.tab().buffer {
+                    return;
+                }
+                // Record the edit first so the title reflects it as unsaved.
+                // Ghost text churn runs suppressed and isn't a user edit.
+                if !state.are_completions_suppressed() {
+                    state.tab().last_edit.replace(Some(Instant::now()));
+                }
+                state.update_title();
+                state.handle_text_change();
+                state.follow_appended_text();
+                state.schedule_count_update();
+                state.schedule_preview_update();
+            }
+        });
+
+        let weak_cursor = Rc::downgrade(self);
+        tab.buffer.connect_mark_set(move |buffer, _iter, mark| {
+            if weak_cursor
+                .upgrade()
+                .is_none_or(|state| *buffer != state.tab().buffer)
+            {
+                return;
+            }
+            // Selecting text switches the counts to the selection
+            if mark.name().as_deref() == Some("selection_bound") {
+                if let Some(state) = weak_cursor.upgrade() {
+                    state.schedule_count_update();
+                }
+            }
+            if mark.name().as_deref() == Some("insert") {
+                if let Some(state) = weak_cursor.upgrade() {
+                    // Ignore cursor moves if we are manipulating ghost text internally
+                    if state.are_completions_suppressed() {
+                        return;
+                    }
+
+                    state.update_cursor_label();
+                }
+            }
+        });
+
+        let weak_modified = Rc::downgrade(self);
+        tab.buffer.connect_modified_changed(move |buffer| {
+            if let Some(state) = weak_modified.upgrade() {
+                if !buffer.is_modified() && *buffer == state.tab().buffer {
+                    state.update_title();
+                }
+            }
+        });
+
+        // Loading a file or undoing everything leaves nothing to step through
+        let weak = Rc::downgrade(self);
+        tab.buffer.connect_can_undo_notify(move |_| {
+            if let Some(state) = weak.upgrade() {
+                state.update_history_buttons();
+            }
+        });
+        let weak = Rc::downgrade(self);
+        tab.buffer.connect_can_redo_notify(move |_| {
+            if let Some(state) = weak.upgrade() {
+                state.update_history_buttons();
+            }
+        });
+    }
+
+    fn new_document(self: &Rc<Self>) -> anyhow::Result<()> {
+        let tab = self.tab();
+        tab.document.clear();
+        tab.file_path.replace(None);
+        self.write_session_lock(&tab);
+        self.detect_language(&tab);
+        self.stop_file_monitor();
+        tab.last_edit.replace(None);
+        self.reset_append_lock();
+        self.detect_long_lines();
+        self.update_title();
+        Ok(())
+    }
+
+    /// An untitled, untouched document that can be replaced without asking
+    pub(super) fn is_pristine(&self) -> bool {
+        let tab = self.tab();
+        tab.file_path.borrow().is_none()
+            && !tab.buffer.is_modified()
+            && tab.buffer.char_count() == 0
+    }
+
+    pub(super) fn load_document_from_path(self: &Rc<Self>, path: &Path) -> Result<()> {
+        let tab = self.tab();
+        self.remove_autosave_artifacts(&tab);
+        tab.document.load_from_path(path)?;
+        // Keep the name the user opened (not the symlink target) but make it
+        // independent of the working directory
+        let path = std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+        tab.file_path.replace(Some(path.clone()));
+        self.write_session_lock(&tab);
+        self.detect_language(&tab);
+        tab.buffer.set_modified(false);
+        self.reset_append_lock();
+        self.detect_long_lines();
+        self.update_title();
+        self.record_recent_file(&path);
+        self.watch_active_file();
+        tab.last_edit.replace(None);
+        self.warn_if_open_elsewhere(&path);
+        Ok(())
+    }
+
+    pub(super) fn confirm_unsaved_then<F>(self: &Rc<Self>, proceed: F)
+    where
+        F: FnOnce(&Rc<Self>) + 'static,
+    {
+        if !self.tab().buffer.is_modified() {
+            proceed(self);
+            return;
+        }
+        let proceed_cell: Rc<RefCell<Option<Box<dyn FnOnce(&Rc<Self>)>>>> =
+            Rc::new(RefCell::new(Some(Box::new(proceed))));
+        let dialog = gtk::MessageDialog::builder()
+            .transient_for(&self.window())
+            .modal(true)
+            .text("Unsaved changes")
+            .secondary_text("Save your changes before continuing?")
+            .build();
+        dialog.add_button("Cancel", gtk::ResponseType::Cancel);
+        dialog.add_button("Discard", gtk::ResponseType::Reject);
+        dialog.add_button("Save", gtk::ResponseType::Accept);
+        let weak = Rc::downgrade(self);
+        let proceed_clone = Rc::clone(&proceed_cell);
+        dialog.connect_response(move |dialog, response| {
+            if let Some(state) = weak.upgrade() {
+                match response {
+                    gtk::ResponseType::Accept => {
+                        state.save_action();
+                        if state.tab().buffer.is_modified() {
+                            return;
+                        }
+                    }
+                    gtk::ResponseType::Reject => {}
+                    _ => {
+                        dialog.close();
+                        return;
+                    }
+                }
+                if let Some(callback) = proceed_clone.borrow_mut().take() {
+                    callback(&state);
+                }
+            }
+            dialog.close();
+        });
+        dialog.show();
+    }
+}
@@ -1,70 +1,176 @@
 use std::cell::{Cell, RefCell};
-use std::path::{Path, PathBuf};
+use std::path::PathBuf;
 use std::rc::Rc;
-use std::sync::{Arc, Mutex, MutexGuard, mpsc};
+use std::sync::atomic::AtomicBool;
+use std::sync::{Arc, Mutex, MutexGuard};
 use std::time::Instant;
 
 use adw::prelude::*;
 use gtk4::gdk;
 use gtk4::gio;
-use gtk4::glib::{self, ControlFlow, Propagation};
+use gtk4::glib::{self, Propagation};
 use gtk4::prelude::*;
 use gtk4::{self as gtk};
 use libadwaita as adw;
-use sourceview5::{SearchContext, SearchSettings, prelude::*};
-use uuid::Uuid;
+use sourceview5::SearchSettings;
 
 use anyhow::Result;
 
-use crate::document::{Document, derive_display_name};
-use crate::llm::{
-    DownloadPhase, DownloadProgress, GpuDevice, HuggingFaceModel, LlmManager, LlmReadiness,
-    LlmSettings, ModelDownloader, ProviderKind,
-};
+use crate::document::{LineEnding, TextCounts, derive_display_name};
+use crate::llm::{GpuDevice, LlmManager, ModelDownloader};
+use crate::logging;
 use crate::paths::AppPaths;
+use crate::secrets;
 use crate::settings::Settings;
 use crate::state_store::WindowState;
 
 use super::autosave::CUSTOM_AUTOSAVE_SENTINEL;
-use super::completion::CompletionTrigger;
+use super::editor_settings::FONT_SCALE_STEP;
 use super::preferences::{self, PreferencesUi};
+use super::recent;
+use super::tabs::TabState;
 
 pub fn build_ui(application: &adw::Application) -> Result<()> {
+    // Only a fresh start picks up where the last session left off
+    let first_window = application.windows().is_empty();
+    let state = build_window(application)?;
+    if first_window {
+        state.restore_last_file();
+    }
+    Ok(())
+}
+
+/// Open files passed on the command line or from a file manager, each in its
+/// own tab of the active window
+pub fn open_files(application: &adw::Application, files: &[gio::File]) {
+    if files.is_empty() {
+        if let Err(err) = build_ui(application) {
+            log::error!("Failed to start UI: {err:?}");
+        }
+        return;
+    }
+
+    let existing = application
+        .active_window()
+        .and_downcast::<adw::ApplicationWindow>()
+        .and_then(|window| state_for_window(&window));
+    let state = match existing {
+        Some(state) => {
+            state.window().present();
+            state
+        }
+        None => match build_window(application) {
+            Ok(state) => state,
+            Err(err) => {
+                log::error!("Failed to start UI: {err:?}");
+                return;
+            }
+        },
+    };
+
+    for file in files {
+        // Going through the path keeps non-UTF-8 file names intact
+        match file.path() {
+            Some(path) => state.open_path(&path),
+            None => state.present_error(
+                "Unsupported file",
+                &format!("{} is not on the local filesystem", file.uri()),
+            ),
+        }
+    }
+}
+
+fn state_for_window(window: &adw::ApplicationWindow) -> Option<Rc<AppState>> {
+    // SAFETY: build_window stores an Rc<AppState> under this key and nothing
+    // else uses it
+    unsafe {
+        window
+            .data::<Rc<AppState>>(APP_STATE_KEY)
+            .map(|state| state.as_ref().clone())
+    }
+}
+
+/// Size of the first monitor, where new windows usually open
+fn monitor_size() -> Option<(i32, i32)> {
+    let monitor = gdk::Display::default()?
+        .monitors()
+        .item(0)?
+        .downcast::<gdk::Monitor>()
+        .ok()?;
+    let geometry = monitor.geometry();
+    Some((geometry.width(), geometry.height()))
+}
+
+/// Key the window's `AppState` is attached under
+const APP_STATE_KEY: &str = "wispnote_app_state";
+
+fn build_window(application: &adw::Application) -> Result<Rc<AppState>> {
     let paths = AppPaths::initialize()?;
-    let settings = Settings::load(&paths)?;
+    let mut settings = Settings::load(&paths)?;
+    logging::set_verbosity(settings.log_verbosity);
+    settings.llm.keyring_api_key = secrets::load_api_key(settings.llm.provider);
+    settings.llm.keyring_hf_token = secrets::load_hf_token();
+    // Earlier versions wrote the token to the config file; move it to the keyring
+    if settings.llm.keyring_hf_token.is_none()
+        && !settings.llm.plaintext_hf_token.is_empty()
+        && secrets::store_hf_token(&settings.llm.plaintext_hf_token).is_ok()
+    {
+        settings.llm.keyring_hf_token = Some(std::mem::take(&mut settings.llm.plaintext_hf_token));
+        if let Err(err) = settings.save(&paths) {
+            log::warn!("Failed to save settings: {err:?}");
+        }
+    }
     let llm_manager = Arc::new(Mutex::new(LlmManager::new(
         settings.llm.clone(),
         paths.models_dir.clone(),
     )));
-    let model_downloader = ModelDownloader::new(paths.models_dir.clone());
+    let model_downloader = ModelDownloader::new(settings.llm.models_dir(&paths.models_dir));
 
-    let document = Document::new();
-    let buffer = document.buffer();
-    let view = document.view();
+    // Carries the editor font from Preferences to every tab's view
+    let editor_css = gtk::CssProvider::new();
+    if let Some(display) = gdk::Display::default() {
+        gtk::style_context_add_provider_for_display(
+            &display,
+            &editor_css,
+            gtk::STYLE_PROVIDER_PRIORITY_APPLICATION,
+        );
+    }
 
     let window_state = WindowState::load(&paths).unwrap_or_else(|err| {
         log::warn!("Failed to load window state: {err:?}");
         WindowState::default()
     });
-    let initial_recent: Vec<PathBuf> = settings
-        .recent_files
-        .iter()
-        .filter_map(|s| {
-            if s.is_empty() {
-                None
-            } else {
-                Some(PathBuf::from(s))
-            }
-        })
-        .collect();
-
-    let header = adw::HeaderBar::builder()
-        .title_widget(&gtk::Label::new(Some("Wispnote")))
-        .build();
+    let mut initial_recent = recent::normalize_recent_files(&settings.recent_files);
+    initial_recent.truncate(settings.max_recent_files);
+
+    // Title with a colored dot showing whether the document is saved,
+    // only autosaved as a draft, or not persisted at all
+    let window_title = adw::WindowTitle::new("Wispnote", "");
+    let modified_dot = gtk::Label::new(Some("●"));
+    let title_box = gtk::Box::new(gtk::Orientation::Horizontal, 6);
+    title_box.append(&modified_dot);
+    title_box.append(&window_title);
+    let header = adw::HeaderBar::builder().title_widget(&title_box).build();
     let new_btn = gtk::Button::from_icon_name("document-new-symbolic");
     new_btn.set_tooltip_text(Some("New window"));
     let open_btn = gtk::Button::from_icon_name("document-open-symbolic");
     open_btn.set_tooltip_text(Some("Open…"));
+    let new_tab_btn = gtk::Button::from_icon_name("tab-new-symbolic");
+    new_tab_btn.set_tooltip_text(Some("New tab"));
+    let undo_btn = gtk::Button::from_icon_name("edit-undo-symbolic");
+    undo_btn.set_tooltip_text(Some("Undo"));
+    undo_btn.set_sensitive(false);
+    let redo_btn = gtk::Button::from_icon_name("edit-redo-symbolic");
+    redo_btn.set_tooltip_text(Some("Redo"));
+    redo_btn.set_sensitive(false);
+    let append_toggle = gtk::ToggleButton::builder()
+        .icon_name("appointment-new-symbolic")
+        .tooltip_text("Append-only log mode")
+        .build();
+    let preview_toggle = gtk::ToggleButton::builder()
+        .icon_name("view-dual-symbolic")
+        .tooltip_text("Markdown preview")
+        .build();
 
     // Main Menu Popover
     let menu_box = gtk::Box::builder()
@@ -106,11 +212,55 @@ pub fn build_ui(application: &adw::Application) -> Result<()> {
         .has_arrow(false)
         .child(&recent_list)
         .build();
+    {
+        // Put keyboard focus on the first entry so arrows + Enter work right away
+        let list = recent_list.clone();
+        recent_popover.connect_show(move |_| {
+            if let Some(row) = list.row_at_index(0) {
+                row.grab_focus();
+            }
+        });
+    }
     recent_btn_inner.connect_clicked(move |btn| {
         recent_popover.set_parent(btn);
         recent_popover.popup();
     });
 
+    let export_btn = gtk::Button::builder()
+        .label("Export as HTML…")
+        .icon_name("document-send-symbolic")
+        .css_classes(["flat"])
+        .halign(gtk::Align::Fill)
+        .build();
+
+    let copy_path_btn = gtk::Button::builder()
+        .label("Copy Full Path")
+        .icon_name("edit-copy-symbolic")
+        .css_classes(["flat"])
+        .halign(gtk::Align::Fill)
+        .build();
+
+    let open_folder_btn = gtk::Button::builder()
+        .label("Open Containing Folder")
+        .icon_name("folder-open-symbolic")
+        .css_classes(["flat"])
+        .halign(gtk::Align::Fill)
+        .build();
+
+    let summarize_btn = gtk::Button::builder()
+        .label("Summarize Document")
+        .icon_name("view-list-bullet-symbolic")
+        .css_classes(["flat"])
+        .halign(gtk::Align::Fill)
+        .build();
+
+    let logs_btn = gtk::Button::builder()
+        .label("Show Logs")
+        .icon_name("utilities-terminal-symbolic")
+        .css_classes(["flat"])
+        .halign(gtk::Align::Fill)
+        .build();
+
     let prefs_button = gtk::Button::builder()
         .label("Preferences")
         .icon_name("emblem-system-symbolic")
@@ -120,9 +270,16 @@ pub fn build_ui(application: &adw::Application) -> Result<()> {
 
     menu_box.append(&save_btn);
     menu_box.append(&save_as_btn);
+    menu_box.append(&export_btn);
+    menu_box.append(&gtk::Separator::new(gtk::Orientation::Horizontal));
+    menu_box.append(&copy_path_btn);
+    menu_box.append(&open_folder_btn);
     menu_box.append(&gtk::Separator::new(gtk::Orientation::Horizontal));
     menu_box.append(&recent_btn_inner);
     menu_box.append(&gtk::Separator::new(gtk::Orientation::Horizontal));
+    menu_box.append(&summarize_btn);
+    menu_box.append(&gtk::Separator::new(gtk::Orientation::Horizontal));
+    menu_box.append(&logs_btn);
     menu_box.append(&prefs_button);
 
     let menu_popover = gtk::Popover::builder()
@@ -138,18 +295,23 @@ pub fn build_ui(application: &adw::Application) -> Result<()> {
 
     header.pack_start(&new_btn);
     header.pack_start(&open_btn);
+    header.pack_start(&new_tab_btn);
+    header.pack_start(&undo_btn);
+    header.pack_start(&redo_btn);
     header.pack_end(&menu_button);
-
-    let scroller = gtk::ScrolledWindow::builder()
-        .hexpand(true)
-        .vexpand(true)
-        .child(&view)
+    header.pack_end(&append_toggle);
+    header.pack_end(&preview_toggle);
+
+    // Pages are added by `open_tab`; the bar only shows with two or more
+    let tab_view = adw::TabView::new();
+    tab_view.set_vexpand(true);
+    let tab_bar = adw::TabBar::builder()
+        .view(&tab_view)
+        .autohide(true)
         .build();
 
     let search_settings = SearchSettings::new();
     search_settings.set_wrap_around(true);
-    let search_context = SearchContext::new(&buffer, Some(&search_settings));
-    search_context.set_highlight(false);
 
     let search_entry = gtk::Entry::builder()
         .placeholder_text("Find…")
@@ -176,6 +338,11 @@ pub fn build_ui(application: &adw::Application) -> Result<()> {
         .tooltip_text("Regular expression")
         .css_classes(["flat"])
         .build();
+    let selection_toggle = gtk::ToggleButton::builder()
+        .icon_name("edit-select-all-symbolic")
+        .tooltip_text("Search in selection")
+        .css_classes(["flat"])
+        .build();
 
     let prev_btn = gtk::Button::builder()
         .icon_name("go-up-symbolic")
@@ -214,6 +381,7 @@ pub fn build_ui(application: &adw::Application) -> Result<()> {
     search_row.append(&case_toggle);
     search_row.append(&word_toggle);
     search_row.append(&regex_toggle);
+    search_row.append(&selection_toggle);
     search_row.append(&gtk::Separator::new(gtk::Orientation::Vertical));
     search_row.append(&prev_btn);
     search_row.append(&next_btn);
@@ -263,6 +431,23 @@ pub fn build_ui(application: &adw::Application) -> Result<()> {
     let status_label = gtk::Label::new(None); // Empty by default
     status_label.set_xalign(0.0);
     let cursor_label = gtk::Label::new(Some("Ln 1, Col 1"));
+    let count_label = gtk::Label::new(None);
+    count_label.add_css_class("dim-label");
+    let line_ending_button = gtk::Button::builder()
+        .label(LineEnding::default().label())
+        .tooltip_text("Line endings used when saving; click to switch")
+        .build();
+    line_ending_button.add_css_class("flat");
+    let language_button = gtk::Button::builder()
+        .label("Plain Text")
+        .tooltip_text("Syntax highlighting; click to choose the language")
+        .build();
+    language_button.add_css_class("flat");
+    let encoding_button = gtk::Button::builder()
+        .label("UTF-8")
+        .tooltip_text("Text encoding; click to convert or reopen the file")
+        .build();
+    encoding_button.add_css_class("flat");
     // Autosave UI removed from status bar
 
     let llm_spinner = gtk::Spinner::new();
@@ -281,6 +466,10 @@ pub fn build_ui(application: &adw::Application) -> Result<()> {
         .build();
     status_box.append(&status_label);
     status_box.append(&cursor_label);
+    status_box.append(&count_label);
+    status_box.append(&language_button);
+    status_box.append(&line_ending_button);
+    status_box.append(&encoding_button);
     status_box.append(&llm_spinner);
     status_box.append(&llm_status_label);
 
@@ -300,8 +489,14 @@ pub fn build_ui(application: &adw::Application) -> Result<()> {
         .margin_top(6)
         .margin_bottom(6)
         .build();
+    let download_cancel_button = gtk::Button::builder()
+        .icon_name("process-stop-symbolic")
+        .tooltip_text("Cancel download")
+        .css_classes(["flat"])
+        .build();
     download_box.append(&download_label);
     download_box.append(&download_progress);
+    download_box.append(&download_cancel_button);
 
     let download_revealer = gtk::Revealer::builder()
         .transition_type(gtk::RevealerTransitionType::SlideDown)
@@ -312,7 +507,34 @@ pub fn build_ui(application: &adw::Application) -> Result<()> {
     let content_column = gtk::Box::builder()
         .orientation(gtk::Orientation::Vertical)
         .build();
-    content_column.append(&scroller);
+    // Shown beside the tabs when the preview toggle is on
+    let preview_label = gtk::Label::builder()
+        .wrap(true)
+        .wrap_mode(gtk::pango::WrapMode::WordChar)
+        .selectable(true)
+        .xalign(0.0)
+        .valign(gtk::Align::Start)
+        .margin_start(12)
+        .margin_end(12)
+        .margin_top(12)
+        .margin_bottom(12)
+        .build();
+    let preview_scroller = gtk::ScrolledWindow::builder()
+        .hscrollbar_policy(gtk::PolicyType::Never)
+        .width_request(240)
+        .child(&preview_label)
+        .visible(false)
+        .build();
+    let editor_paned = gtk::Paned::builder()
+        .orientation(gtk::Orientation::Horizontal)
+        .start_child(&tab_view)
+        .end_child(&preview_scroller)
+        .shrink_end_child(false)
+        .vexpand(true)
+        .build();
+
+    content_column.append(&tab_bar);
+    content_column.append(&editor_paned);
     content_column.append(&search_revealer);
     content_column.append(&download_revealer);
 
@@ -326,11 +548,14 @@ pub fn build_ui(application: &adw::Application) -> Result<()> {
     chrome.append(&overlay);
     chrome.append(&status_box);
 
+    // GTK 4 can't place windows, so only the size and maximized state come back
+    let (default_width, default_height) = window_state.size_within(monitor_size());
     let window = adw::ApplicationWindow::builder()
         .application(application)
         .title("Wispnote")
-        .default_width(window_state.width)
-        .default_height(window_state.height)
+        .default_width(default_width)
+        .default_height(default_height)
+        .maximized(window_state.maximized)
         .content(&chrome)
         .build();
 
@@ -351,49 +576,66 @@ pub fn build_ui(application: &adw::Application) -> Result<()> {
     let state = Rc::new(AppState {
         window: window.downgrade(),
         toast_overlay: overlay.clone(),
-        document,
-        buffer,
-        file_path: RefCell::new(None),
+        tab_view,
+        tabs: RefCell::new(Vec::new()),
+        editor_css,
+        undo_button: undo_btn.clone(),
+        redo_button: redo_btn.clone(),
         status_label,
         cursor_label,
+        count_label,
+        count_source: RefCell::new(None),
+        line_ending_button: line_ending_button.clone(),
+        encoding_button: encoding_button.clone(),
+        language_button: language_button.clone(),
+        copy_path_button: copy_path_btn.clone(),
+        open_folder_button: open_folder_btn.clone(),
         llm_spinner: llm_spinner.clone(),
         llm_status_label: llm_status_label.clone(),
         search_revealer: search_revealer.clone(),
         search_entry: search_entry.clone(),
         replace_entry: replace_entry.clone(),
         match_label: match_label.clone(),
+        selection_toggle: selection_toggle.clone(),
         download_revealer: download_revealer.clone(),
         download_progress: download_progress.clone(),
         download_label: download_label.clone(),
         download_title: RefCell::new(None),
+        download_cancel: RefCell::new(None),
         manual_completion_inflight: Cell::new(false),
         auto_completion_running: Cell::new(false),
         completion_debounce: RefCell::new(None),
+        completion_wait_start: Cell::new(None),
+        idle_unload_source: RefCell::new(None),
         completion_generation: Cell::new(0),
+        completion_cancel: RefCell::new(Arc::new(AtomicBool::new(false))),
         completion_suppression_depth: Cell::new(0),
         last_completion_schedule: Cell::new(None),
         search_settings: search_settings.clone(),
-        search_context: search_context.clone(),
         recent_list: recent_list.clone(),
         recent_entries: RefCell::new(initial_recent),
         autosave_options,
         preferences: preferences_ui,
         llm_manager: Arc::clone(&llm_manager),
-        model_downloader,
+        model_downloader: RefCell::new(model_downloader),
         gpus: detected_gpus,
         paths,
         settings: RefCell::new(settings),
         window_state: RefCell::new(window_state),
         autosave_source: RefCell::new(None),
-        file_monitor: RefCell::new(None),
-        external_change_pending: Cell::new(false),
-        last_edit: RefCell::new(None),
-        last_char_count: Cell::new(0),
-        session_token: Uuid::new_v4().to_string(),
+        monitor_pause_source: RefCell::new(None),
+        window_title,
+        modified_dot,
+        append_toggle,
+        preview_toggle,
+        preview_scroller,
+        preview_label,
+        preview_source: RefCell::new(None),
     });
 
+    state.hook_tab_view();
+    state.open_tab();
     state.initialize();
-    state.install_completion_shortcuts();
     state.refresh_recent_menu();
     state.check_recovery_snapshots();
     state.check_llm_readiness();
@@ -413,56 +655,47 @@ pub fn build_ui(application: &adw::Application) -> Result<()> {
             if idx < 0 {
                 return;
             }
-            if let Some(state) = weak.upgrade() {
-                if let Some(path) = state.recent_entries.borrow().get(idx as usize).cloned() {
-                    state.confirm_unsaved_then(move |st| {
-                        if let Err(err) = st.load_document_from_path(&path) {
-                            st.present_error("Failed to open", &err.to_string());
-                        }
-                    });
-                }
+            if let Some(popover) = row
+                .ancestor(gtk::Popover::static_type())
+                .and_downcast::<gtk::Popover>()
+            {
+                popover.popdown();
             }
-        });
-    }
-
-    {
-        let weak = Rc::downgrade(&state);
-        search_entry.connect_changed(move |_| {
             if let Some(state) = weak.upgrade() {
-                state.update_search_pattern();
+                if row.widget_name() == recent::CLEAR_RECENT_ROW {
+                    state.clear_recent_files();
+                } else {
+                    state.open_recent(idx as usize);
+                }
             }
         });
-    }
 
-    // Autosave status bar logic removed
-
-    {
+        // Right-click removes a single entry
         let weak = Rc::downgrade(&state);
-        let combo = state.preferences.autosave_combo.clone();
-        combo.connect_selected_notify(move |row: &adw::ComboRow| {
-            if let Some(state) = weak.upgrade() {
-                let idx = row.selected() as usize;
-                if let Some((secs, _)) = state.autosave_options.get(idx) {
-                    if *secs == CUSTOM_AUTOSAVE_SENTINEL {
-                        state.prompt_custom_autosave();
-                    } else if *secs != state.settings.borrow().autosave_interval_secs {
-                        state.set_autosave_interval(*secs);
-                    }
-                }
+        let remove_click = gtk::GestureClick::builder()
+            .button(gdk::BUTTON_SECONDARY)
+            .build();
+        remove_click.connect_pressed(move |gesture, _, _, y| {
+            let Some(state) = weak.upgrade() else {
+                return;
+            };
+            let Some(row) = state.recent_list.row_at_y(y as i32) else {
+                return;
+            };
+            if row.widget_name() == recent::CLEAR_RECENT_ROW || !row.is_activatable() {
+                return;
             }
+            gesture.set_state(gtk::EventSequenceState::Claimed);
+            state.remove_recent_file(row.index() as usize);
         });
+        list.add_controller(remove_click);
     }
 
     {
         let weak = Rc::downgrade(&state);
-        let idle_switch = state.preferences.autosave_idle_switch.clone();
-        idle_switch.connect_active_notify(move |switch_widget: &gtk::Switch| {
+        search_entry.connect_changed(move |_| {
             if let Some(state) = weak.upgrade() {
-                let active = switch_widget.is_active();
-                if active == state.settings.borrow().autosave_idle_only {
-                    return;
-                }
-                state.set_autosave_idle_only(active);
+                state.update_search_pattern();
             }
         });
     }
@@ -517,6 +750,15 @@ pub fn build_ui(application: &adw::Application) -> Result<()> {
         });
     }
 
+    {
+        let weak = Rc::downgrade(&state);
+        selection_toggle.connect_toggled(move |_| {
+            if let Some(state) = weak.upgrade() {
+                state.update_search_scope();
+            }
+        });
+    }
+
     {
         let weak = Rc::downgrade(&state);
         prev_btn.connect_clicked(move |_| {
@@ -553,6 +795,15 @@ pub fn build_ui(application: &adw::Application) -> Result<()> {
         });
     }
 
+    {
+        let weak = Rc::downgrade(&state);
+        download_cancel_button.connect_clicked(move |_| {
+            if let Some(state) = weak.upgrade() {
+                state.cancel_download();
+            }
+        });
+    }
+
     {
         let weak = Rc::downgrade(&state);
         close_btn.connect_clicked(move |_| {
@@ -580,8 +831,33 @@ pub fn build_ui(application: &adw::Application) -> Result<()> {
                 state.show_search_panel(true);
                 return Propagation::Stop;
             }
+            if ctrl && shift && (key == gdk::Key::P || key == gdk::Key::p) {
+                state.show_command_palette();
+                return Propagation::Stop;
+            }
+            if ctrl && !shift {
+                // Ctrl+1..Ctrl+9 open the matching recent file
+                if let Some(digit) = key.to_unicode().and_then(|c| c.to_digit(10)) {
+                    if digit >= 1 {
+                        state.open_recent(digit as usize - 1);
+                        return Propagation::Stop;
+                    }
+                }
+            }
             if ctrl {
                 match key {
+                    gdk::Key::plus | gdk::Key::equal | gdk::Key::KP_Add => {
+                        state.zoom_editor(FONT_SCALE_STEP);
+                        return Propagation::Stop;
+                    }
+                    gdk::Key::minus | gdk::Key::KP_Subtract => {
+                        state.zoom_editor(-FONT_SCALE_STEP);
+                        return Propagation::Stop;
+                    }
+                    gdk::Key::_0 | gdk::Key::KP_0 => {
+                        state.reset_editor_zoom();
+                        return Propagation::Stop;
+                    }
                     gdk::Key::f | gdk::Key::F => {
                         state.show_search_panel(false);
                         return Propagation::Stop;
@@ -590,6 +866,19 @@ pub fn build_ui(application: &adw::Application) -> Result<()> {
                         state.show_goto_line_dialog();
                         return Propagation::Stop;
                     }
+                    gdk::Key::p | gdk::Key::P => {
+                        state.print_document();
+                        return Propagation::Stop;
+                    }
+                    // Ctrl+Page Up/Down switching comes with the tab view
+                    gdk::Key::t | gdk::Key::T => {
+                        state.open_tab();
+                        return Propagation::Stop;
+                    }
+                    gdk::Key::w | gdk::Key::W => {
+                        state.close_current_tab();
+                        return Propagation::Stop;
+                    }
                     _ => {}
                 }
             }
@@ -606,8 +895,46 @@ pub fn build_ui(application: &adw::Application) -> Result<()> {
     }
     window.add_controller(key_controller);
 
+    {
+        let weak = Rc::downgrade(&state);
+        line_ending_button.connect_clicked(move |_| {
+            if let Some(state) = weak.upgrade() {
+                state.toggle_line_ending();
+            }
+        });
+    }
+    {
+        let weak = Rc::downgrade(&state);
+        encoding_button.connect_clicked(move |_| {
+            if let Some(state) = weak.upgrade() {
+                state.show_encoding_menu();
+            }
+        });
+    }
+    {
+        let weak = Rc::downgrade(&state);
+        language_button.connect_clicked(move |_| {
+            if let Some(state) = weak.upgrade() {
+                state.show_language_menu();
+            }
+        });
+    }
+
     state.update_search_pattern();
 
+    {
+        let weak = Rc::downgrade(&state);
+        window.connect_is_active_notify(move |win| {
+            if let Some(state) = weak.upgrade() {
+                if win.is_active() {
+                    state.resume_file_monitor();
+                } else {
+                    state.schedule_monitor_pause();
+                }
+            }
+        });
+    }
+
     {
         let weak = Rc::downgrade(&state);
         window.connect_close_request(move |win| {
@@ -616,16 +943,25 @@ pub fn build_ui(application: &adw::Application) -> Result<()> {
                 None => return Propagation::Proceed,
             };
 
-            if !state.buffer.is_modified() {
+            // Each tab with unsaved changes is asked about in turn
+            let Some(modified) = state
+                .tabs()
+                .into_iter()
+                .find(|tab| tab.buffer.is_modified())
+            else {
                 state.persist_window_state();
+                // A clean exit; locks left behind mean a crash
+                for tab in state.tabs() {
+                    state.remove_session_lock(&tab.session_token);
+                }
                 return Propagation::Proceed;
-            }
+            };
+            state.select_tab(&modified);
             let win_clone = win.clone();
             state.confirm_unsaved_then(move |st| {
                 // If the user chose to discard (or saved successfully), we must clear the modified flag
                 // before closing, otherwise the close_request handler will intercept it again.
-                st.buffer.set_modified(false);
-                st.persist_window_state();
+                st.tab().buffer.set_modified(false);
 
                 // Defer the close processing to let the dialog finish completely
                 let win = win_clone.clone();
@@ -648,13 +984,35 @@ pub fn build_ui(application: &adw::Application) -> Result<()> {
         });
     }
 
+    {
+        let weak = Rc::downgrade(&state);
+        new_tab_btn.connect_clicked(move |_| {
+            if let Some(state) = weak.upgrade() {
+                state.open_tab();
+            }
+        });
+    }
+
+    {
+        let weak = Rc::downgrade(&state);
+        undo_btn.connect_clicked(move |_| {
+            if let Some(state) = weak.upgrade() {
+                state.undo();
+            }
+        });
+        let weak = Rc::downgrade(&state);
+        redo_btn.connect_clicked(move |_| {
+            if let Some(state) = weak.upgrade() {
+                state.redo();
+            }
+        });
+    }
+
     {
         let weak = Rc::downgrade(&state);
         open_btn.connect_clicked(move |_| {
             if let Some(state) = weak.upgrade() {
-                state.confirm_unsaved_then(move |st| {
-                    st.open_document_dialog();
-                });
+                state.open_document_dialog();
             }
         });
     }
@@ -677,61 +1035,142 @@ pub fn build_ui(application: &adw::Application) -> Result<()> {
         });
     }
 
+    {
+        let weak = Rc::downgrade(&state);
+        let popover = menu_popover.clone();
+        export_btn.connect_clicked(move |_| {
+            popover.popdown();
+            if let Some(state) = weak.upgrade() {
+                state.export_html_dialog();
+            }
+        });
+    }
+
+    {
+        let weak = Rc::downgrade(&state);
+        let popover = menu_popover.clone();
+        copy_path_btn.connect_clicked(move |_| {
+            popover.popdown();
+            if let Some(state) = weak.upgrade() {
+                state.copy_file_path();
+            }
+        });
+    }
+
+    {
+        let weak = Rc::downgrade(&state);
+        let popover = menu_popover.clone();
+        open_folder_btn.connect_clicked(move |_| {
+            popover.popdown();
+            if let Some(state) = weak.upgrade() {
+                state.open_containing_folder();
+            }
+        });
+    }
+
+    {
+        let weak = Rc::downgrade(&state);
+        let popover = menu_popover.clone();
+        summarize_btn.connect_clicked(move |_| {
+            popover.popdown();
+            if let Some(state) = weak.upgrade() {
+                state.summarize_document();
+            }
+        });
+    }
+
+    {
+        let weak = Rc::downgrade(&state);
+        let popover = menu_popover.clone();
+        logs_btn.connect_clicked(move |_| {
+            popover.popdown();
+            if let Some(state) = weak.upgrade() {
+                state.show_logs();
+            }
+        });
+    }
+
     window.present();
 
     // Keep state alive by attaching it to the window
     unsafe {
-        window.set_data("wispnote_app_state", state.clone());
+        window.set_data(APP_STATE_KEY, state.clone());
     }
 
     // Start loading LLM model in background after window is visible
-    state.preload_llm_model();
+    state.preload_llm_model(false);
 
-    Ok(())
+    Ok(state)
 }
 
 pub(super) struct AppState {
     pub(super) window: glib::WeakRef<adw::ApplicationWindow>,
     pub(super) toast_overlay: adw::ToastOverlay,
-    pub(super) document: Rc<Document>,
-    pub(super) buffer: sourceview5::Buffer,
-    pub(super) file_path: RefCell<Option<PathBuf>>,
+    pub(super) tab_view: adw::TabView,
+    /// Open documents in the order they were opened; see `tab()` for the current one
+    pub(super) tabs: RefCell<Vec<Rc<TabState>>>,
+    pub(super) editor_css: gtk::CssProvider,
+    pub(super) undo_button: gtk::Button,
+    pub(super) redo_button: gtk::Button,
     pub(super) status_label: gtk::Label,
     pub(super) cursor_label: gtk::Label,
+    /// Word, character and line counts of the document or selection
+    count_label: gtk::Label,
+    /// Pending recount, so bursts of edits are counted once
+    count_source: RefCell<Option<glib::SourceId>>,
+    line_ending_button: gtk::Button,
+    pub(super) encoding_button: gtk::Button,
+    pub(super) language_button: gtk::Button,
+    /// Menu actions that need the tab to have a file
+    copy_path_button: gtk::Button,
+    open_folder_button: gtk::Button,
     pub(super) llm_spinner: gtk::Spinner,
     pub(super) llm_status_label: gtk::Label,
     pub(super) search_revealer: gtk::Revealer,
     pub(super) search_entry: gtk::Entry,
     pub(super) replace_entry: gtk::Entry,
     pub(super) match_label: gtk::Label,
+    /// Limits searching to the selection made before searching
+    pub(super) selection_toggle: gtk::ToggleButton,
     pub(super) download_revealer: gtk::Revealer,
     pub(super) download_progress: gtk::ProgressBar,
     pub(super) download_label: gtk::Label,
     pub(super) download_title: RefCell<Option<String>>,
+    /// Cancel flag of the download in progress, if any
+    pub(super) download_cancel: RefCell<Option<Arc<AtomicBool>>>,
     pub(super) manual_completion_inflight: Cell<bool>,
     pub(super) auto_completion_running: Cell<bool>,
     pub(super) completion_debounce: RefCell<Option<glib::SourceId>>,
+    /// When the edits waiting for an automatic completion began
+    pub(super) completion_wait_start: Cell<Option<Instant>>,
+    pub(super) idle_unload_source: RefCell<Option<glib::SourceId>>,
     pub(super) completion_generation: Cell<u64>,
+    /// Cancel token for completion workers started in the current generation
+    pub(super) completion_cancel: RefCell<Arc<AtomicBool>>,
     pub(super) completion_suppression_depth: Cell<u32>,
     pub(super) last_completion_schedule: Cell<Option<std::time::Instant>>,
     pub(super) search_settings: SearchSettings,
-    pub(super) search_context: SearchContext,
     pub(super) recent_list: gtk::ListBox,
     pub(super) recent_entries: RefCell<Vec<PathBuf>>,
     pub(super) autosave_options: Vec<(u64, &'static str)>,
     pub(super) preferences: PreferencesUi,
     pub(super) llm_manager: Arc<Mutex<LlmManager>>,
-    pub(super) model_downloader: ModelDownloader,
+    pub(super) model_downloader: RefCell<ModelDownloader>,
     pub(super) gpus: Vec<GpuDevice>,
     pub(super) paths: AppPaths,
     pub(super) settings: RefCell<Settings>,
     pub(super) window_state: RefCell<WindowState>,
     pub(super) autosave_source: RefCell<Option<glib::SourceId>>,
-    pub(super) file_monitor: RefCell<Option<gio::FileMonitor>>,
-    pub(super) external_change_pending: Cell<bool>,
-    pub(super) last_edit: RefCell<Option<Instant>>,
-    pub(super) last_char_count: Cell<i32>,
-    pub(super) session_token: String,
+    pub(super) monitor_pause_source: RefCell<Option<glib::SourceId>>,
+    pub(super) append_toggle: gtk::ToggleButton,
+    pub(super) preview_toggle: gtk::ToggleButton,
+    /// Markdown preview pane; hidden while the toggle is off
+    pub(super) preview_scroller: gtk::ScrolledWindow,
+    pub(super) preview_label: gtk::Label,
+    /// Pending re-render, so bursts of edits are rendered once
+    pub(super) preview_source: RefCell<Option<glib::SourceId>>,
+    pub(super) window_title: adw::WindowTitle,
+    pub(super) modified_dot: gtk::Label,
 }
 
 impl AppState {
@@ -742,286 +1181,132 @@ impl AppState {
     fn initialize(self: &Rc<Self>) {
         self.update_title();
         self.update_cursor_label();
-        self.hook_buffer_signals();
+        self.update_count_label();
         self.restart_autosave();
         self.apply_editor_settings();
         self.sync_preferences_ui();
         self.sync_llm_preferences();
         self.hook_llm_preferences();
+        self.hook_models_dir();
         self.hook_editor_preferences();
+        self.hook_autosave_preferences();
+        self.hook_append_only();
+        self.hook_markdown_preview();
     }
 
-    fn install_completion_shortcuts(self: &Rc<Self>) {
-        let controller = gtk::EventControllerKey::new();
-        // Set to CAPTURE phase so we intercept Tab before TextView's default handler
-        controller.set_propagation_phase(gtk::PropagationPhase::Capture);
-        let weak = Rc::downgrade(self);
-        controller.connect_key_pressed(move |_, keyval, _, state| {
-            if let Some(app) = weak.upgrade() {
-                if state.contains(gdk::ModifierType::CONTROL_MASK) && keyval == gdk::Key::space {
-                    app.request_llm_completion();
-                    return glib::Propagation::Stop;
-                }
+    pub(super) fn update_title(&self) {
+        let tab = self.tab();
+        let name = derive_display_name(&tab.file_path.borrow());
+        let persist = self.persist_state();
+        let marker = if persist == PersistState::Saved {
+            ""
+        } else {
+            "*"
+        };
+        self.window()
+            .set_title(Some(&format!("Wispnote — {name}{marker}")));
 
-                // Log Tab presses to debug
-                if keyval == gdk::Key::Tab {
-                    log::info!(
-                        "Tab key pressed, ghost_is_active={}",
-                        app.document.ghost_is_active()
-                    );
-                }
+        let (label, css_class) = match persist {
+            PersistState::Saved => ("Saved", "success"),
+            PersistState::DraftAutosaved => ("Autosaved draft — not saved to file", "warning"),
+            PersistState::Unsaved => ("Unsaved changes", "error"),
+        };
+        self.window_title.set_title(&name);
+        self.window_title.set_subtitle(label);
+        for class in ["success", "warning", "error"] {
+            self.modified_dot.remove_css_class(class);
+        }
+        self.modified_dot.add_css_class(css_class);
+        self.modified_dot.set_tooltip_text(Some(label));
+        self.line_ending_button
+            .set_label(tab.document.line_ending().label());
+        self.encoding_button
+            .set_label(tab.document.encoding().name());
+        self.update_language_button();
+        let has_file = tab.file_path.borrow().is_some();
+        self.copy_path_button.set_sensitive(has_file);
+        self.open_folder_button.set_sensitive(has_file);
+
+        let page = self.tab_view.page(&tab.scroller);
+        page.set_title(&format!("{name}{marker}"));
+        let tooltip = tab
+            .file_path
+            .borrow()
+            .as_ref()
+            .map(|path| path.display().to_string());
+        page.set_tooltip(tooltip.as_deref().unwrap_or(""));
 
-                if app.document.ghost_is_active() {
-                    match keyval {
-                        gdk::Key::Tab => {
-                            log::info!("Accepting ghost text completion");
-                            app.accept_current_completion();
-                            return glib::Propagation::Stop;
-                        }
-                        gdk::Key::Escape => {
-                            log::info!("Escape key pressed with active ghost text");
-                            app.cancel_current_completion();
-                            return glib::Propagation::Stop;
-                        }
-                        _ => {
-                            if is_textual_key(keyval, state) {
-                                app.cancel_current_completion();
-                            }
-                        }
-                    }
-                }
+        self.status_label.set_text(&format!(
+            "{}{}",
+            name,
+            match persist {
+                PersistState::Saved => "",
+                PersistState::DraftAutosaved => " • Draft autosaved",
+                PersistState::Unsaved => " • Unsaved",
             }
-
-            glib::Propagation::Proceed
-        });
-        self.document.view().add_controller(controller);
-    }
-
-    fn show_download_banner(&self, title: &str) {
-        self.download_title.replace(Some(title.to_string()));
-        self.download_label
-            .set_text(&format!("{} — preparing", title));
-        self.download_progress.set_fraction(0.0);
-        self.download_progress.set_text(Some("Preparing download…"));
-        self.download_revealer.set_reveal_child(true);
+        ));
     }
 
-    fn update_download_progress(&self, progress: DownloadProgress) {
-        let base = self
-            .download_title
-            .borrow()
-            .clone()
-            .unwrap_or_else(|| "Model download".into());
-
-        match progress.phase {
-            DownloadPhase::Preparing => {
-                self.download_label
-                    .set_text(&format!("{} — preparing", base));
-                self.download_progress.pulse();
-                self.download_progress.set_text(Some("Preparing download…"));
-            }
-            DownloadPhase::VerifyingExisting => {
-                self.download_label
-                    .set_text(&format!("{} — verifying existing file", base));
-                self.update_progress_bar(progress);
-            }
-            DownloadPhase::Downloading => {
-                self.download_label
-                    .set_text(&format!("{} — downloading", base));
-                self.update_progress_bar(progress);
-            }
-            DownloadPhase::Finished => {
-                self.download_label
-                    .set_text(&format!("{} — finishing", base));
-                self.download_progress.set_fraction(1.0);
-                self.download_progress.set_text(Some("Download complete"));
-            }
+    /// Whether the current text is on disk, only in an autosave draft, or nowhere
+    fn persist_state(&self) -> PersistState {
+        let tab = self.tab();
+        if !tab.buffer.is_modified() {
+            return PersistState::Saved;
+        }
+        match (*tab.last_edit.borrow(), tab.last_autosave.get()) {
+            (Some(edit), Some(saved)) if saved >= edit => PersistState::DraftAutosaved,
+            (None, Some(_)) => PersistState::DraftAutosaved,
+            _ => PersistState::Unsaved,
         }
     }
 
-    fn hide_download_banner(&self) {
-        self.download_revealer.set_reveal_child(false);
-        self.download_title.replace(None);
-    }
+    /// Recount shortly, once edits or selection changes settle
+    pub(super) fn schedule_count_update(self: &Rc<Self>) {
+        const COUNT_DELAY_MS: u64 = 250;
 
-    fn update_progress_bar(&self, progress: DownloadProgress) {
-        if let Some(total) = progress.total.filter(|t| *t > 0) {
-            let fraction = (progress.downloaded as f64 / total as f64).clamp(0.0, 1.0);
-            self.download_progress.set_fraction(fraction);
-            self.download_progress.set_text(Some(&format!(
-                "{:.1}% ({} / {})",
-                fraction * 100.0,
-                human_readable_bytes(progress.downloaded),
-                human_readable_bytes(total)
-            )));
-        } else {
-            self.download_progress.pulse();
-            self.download_progress.set_text(Some(&format!(
-                "{} downloaded",
-                human_readable_bytes(progress.downloaded)
-            )));
+        if let Some(source) = self.count_source.borrow_mut().take() {
+            source.remove();
         }
+        let weak = Rc::downgrade(self);
+        let source = glib::timeout_add_local_once(
+            std::time::Duration::from_millis(COUNT_DELAY_MS),
+            move || {
+                if let Some(state) = weak.upgrade() {
+                    state.count_source.borrow_mut().take();
+                    state.update_count_label();
+                }
+            },
+        );
+        self.count_source.replace(Some(source));
     }
 
-    fn hook_buffer_signals(self: &Rc<Self>) {
-        let weak = Rc::downgrade(self);
-        self.buffer.connect_changed(move |_| {
-            if let Some(state) = weak.upgrade() {
-                state.update_title();
-                state.last_edit.replace(Some(Instant::now()));
-                state.handle_text_change();
-            }
-        });
-
-        let weak_cursor = Rc::downgrade(self);
-        self.buffer.connect_mark_set(move |_buf, _iter, mark| {
-            if mark.name().as_deref() == Some("insert") {
-                if let Some(state) = weak_cursor.upgrade() {
-                    // Ignore cursor moves if we are manipulating ghost text internally
-                    if state.are_completions_suppressed() {
-                        return;
-                    }
-
-                    state.update_cursor_label();
-                }
-            }
-        });
-
-        let weak_modified = Rc::downgrade(self);
-        self.buffer.connect_modified_changed(move |buffer| {
-            if let Some(state) = weak_modified.upgrade() {
-                if !buffer.is_modified() {
-                    state.update_title();
-                }
-            }
-        });
-    }
-
-    fn new_document(self: &Rc<Self>) -> anyhow::Result<()> {
-        self.document.clear();
-        self.file_path.replace(None);
-        self.stop_file_monitor();
-        self.last_edit.replace(None);
-        self.update_title();
-        Ok(())
-    }
-
-    fn open_document_dialog(self: &Rc<Self>) {
-        let dialog = gtk::FileChooserDialog::builder()
-            .title("Open File")
-            .transient_for(&self.window())
-            .modal(true)
-            .action(gtk::FileChooserAction::Open)
-            .build();
-        dialog.add_button("Cancel", gtk::ResponseType::Cancel);
-        dialog.add_button("Open", gtk::ResponseType::Accept);
-        Self::attach_file_filters(&dialog);
-        let weak = Rc::downgrade(self);
-        dialog.connect_response(move |dialog, response| {
-            if response == gtk::ResponseType::Accept {
-                if let Some(state) = weak.upgrade() {
-                    if let Some(file) = dialog.file() {
-                        if let Some(path) = file.path() {
-                            if let Err(err) = state.load_document_from_path(&path) {
-                                state.present_error("Failed to open", &err.to_string());
-                            }
-                        } else {
-                            state.present_error(
-                                "Unsupported file",
-                                "Location is not on the local filesystem",
-                            );
-                        }
-                    }
-                }
-            }
-            dialog.close();
-        });
-        dialog.show();
-    }
-
-    fn save_action(self: &Rc<Self>) {
-        if self.file_path.borrow().is_some() {
-            if let Err(err) = self.write_current_file() {
-                self.present_error("Save failed", &err.to_string());
-            }
-        } else {
-            self.save_as_dialog();
-        }
-    }
-
-    fn write_current_file(self: &Rc<Self>) -> anyhow::Result<()> {
-        let path = self
-            .file_path
-            .borrow()
-            .clone()
-            .ok_or_else(|| anyhow::anyhow!("No file selected"))?;
-        self.document.save_to_path(&path)?;
-        self.remove_autosave_artifacts();
-        self.record_recent_file(&path);
-        self.watch_active_file();
-        self.update_title();
-        Ok(())
-    }
-
-    fn save_as_dialog(self: &Rc<Self>) {
-        let dialog = gtk::FileChooserDialog::builder()
-            .title("Save File As")
-            .transient_for(&self.window())
-            .modal(true)
-            .action(gtk::FileChooserAction::Save)
-            .build();
-        dialog.add_button("Cancel", gtk::ResponseType::Cancel);
-        dialog.add_button("Save", gtk::ResponseType::Accept);
-        Self::attach_file_filters(&dialog);
-        let weak = Rc::downgrade(self);
-        dialog.connect_response(move |dialog, response| {
-            if response == gtk::ResponseType::Accept {
-                if let Some(state) = weak.upgrade() {
-                    if let Some(file) = dialog.file() {
-                        if let Some(path) = file.path() {
-                            match state.document.save_to_path(&path) {
-                                Ok(_) => {
-                                    state.file_path.replace(Some(path.clone()));
-                                    state.remove_autosave_artifacts();
-                                    state.record_recent_file(&path);
-                                    state.watch_active_file();
-                                    state.update_title();
-                                    state.run_autosave();
-                                }
-                                Err(err) => state.present_error("Failed to save", &err.to_string()),
-                            }
-                        } else {
-                            state.present_error(
-                                "Unsupported file",
-                                "Location is not on the local filesystem",
-                            );
-                        }
-                    }
-                }
-            }
-            dialog.close();
-        });
-        dialog.show();
-    }
-
-    pub(super) fn update_title(&self) {
-        let name = derive_display_name(&self.file_path.borrow());
-        let marker = if self.buffer.is_modified() { "*" } else { "" };
-        self.window()
-            .set_title(Some(&format!("Wispnote — {name}{marker}")));
-        self.status_label.set_text(&format!(
-            "{}{}",
-            name,
-            if marker.is_empty() {
-                ""
-            } else {
-                " • Unsaved"
-            }
+    pub(super) fn update_count_label(&self) {
+        let (prefix, counts) = match self.tab().buffer.selection_bounds() {
+            Some((start, end)) => (
+                "Selected: ",
+                TextCounts::of(&self.tab().buffer.text(&start, &end, true)),
+            ),
+            None => (
+                "",
+                TextCounts::of(&self.tab().document.text_without_ghost()),
+            ),
+        };
+        let plural = |count: usize, noun: &str| {
+            format!("{count} {noun}{}", if count == 1 { "" } else { "s" })
+        };
+        self.count_label.set_text(&format!(
+            "{prefix}{}, {}, {}",
+            plural(counts.words, "word"),
+            plural(counts.chars, "char"),
+            plural(counts.lines, "line")
         ));
     }
 
-    fn update_cursor_label(&self) {
-        let iter = self.buffer.iter_at_offset(self.buffer.cursor_position());
+    pub(super) fn update_cursor_label(&self) {
+        let iter = self
+            .tab()
+            .buffer
+            .iter_at_offset(self.tab().buffer.cursor_position());
         let line = iter.line() + 1;
         let col = iter.line_offset() + 1;
         self.cursor_label.set_text(&format!("Ln {line}, Col {col}"));
@@ -1040,98 +1325,28 @@ impl AppState {
     }
 
     fn persist_window_state(&self) {
-        let width = self.window().width();
-        let height = self.window().height();
+        let window = self.window();
         let mut store = self.window_state.borrow_mut();
-        store.width = width.max(400);
-        store.height = height.max(300);
-        if let Err(err) = store.save(&self.paths) {
-            log::warn!("Failed to save window state: {err:?}");
-        }
-    }
-
-    fn watch_active_file(self: &Rc<Self>) {
-        self.stop_file_monitor();
-        if let Some(path) = self.file_path.borrow().clone() {
-            let file = gio::File::for_path(&path);
-            match file.monitor_file(gio::FileMonitorFlags::NONE, None::<&gio::Cancellable>) {
-                Ok(monitor) => {
-                    let weak = Rc::downgrade(self);
-                    monitor.connect_changed(move |_, _, _, event| {
-                        if matches!(
-                            event,
-                            gio::FileMonitorEvent::Changed
-                                | gio::FileMonitorEvent::ChangesDoneHint
-                                | gio::FileMonitorEvent::Deleted
-                        ) {
-                            if let Some(state) = weak.upgrade() {
-                                state.handle_external_change();
-                            }
-                        }
-                    });
-                    self.file_monitor.replace(Some(monitor));
-                }
-                Err(err) => log::warn!("Failed to watch file: {err:?}"),
-            }
+        store.maximized = window.is_maximized();
+        // A maximized window's size is the monitor's; keep the size to unmaximize to
+        if !store.maximized {
+            store.width = window.width().max(400);
+            store.height = window.height().max(300);
         }
-    }
-
-    fn stop_file_monitor(&self) {
-        self.file_monitor.borrow_mut().take();
-        self.external_change_pending.set(false);
-    }
-
-    fn handle_external_change(self: &Rc<Self>) {
-        if self.external_change_pending.replace(true) {
-            return;
+        let tab = self.tab();
+        store.last_open_file = tab
+            .file_path
+            .borrow()
+            .as_ref()
+            .map(|path| path.display().to_string());
+        store.last_cursor_offset = tab.buffer.cursor_position();
+        // Keep language choices other windows stored since this one started
+        if let Ok(saved) = WindowState::load(&self.paths) {
+            store.language_overrides = saved.language_overrides;
         }
-        let weak = Rc::downgrade(self);
-        let dialog = gtk::MessageDialog::builder()
-            .transient_for(&self.window())
-            .modal(true)
-            .text("File changed on disk")
-            .secondary_text("The file was modified outside Wispnote. Reload it?")
-            .build();
-        dialog.add_button("Keep My Changes", gtk::ResponseType::Cancel);
-        dialog.add_button("Reload", gtk::ResponseType::Accept);
-        dialog.connect_response(move |dialog: &gtk::MessageDialog, response| {
-            if let Some(state) = weak.upgrade() {
-                if response == gtk::ResponseType::Accept {
-                    state.reload_from_disk();
-                } else {
-                    state.external_change_pending.set(false);
-                }
-            }
-            dialog.close();
-        });
-        dialog.show();
-    }
-
-    fn reload_from_disk(self: &Rc<Self>) {
-        if let Some(path) = self.file_path.borrow().clone() {
-            match self.document.load_from_path(&path) {
-                Ok(_) => {
-                    self.buffer.set_modified(false);
-                    self.update_title();
-                    self.status_label.set_text("Reloaded from disk");
-                    self.watch_active_file();
-                }
-                Err(err) => self.present_error("Failed to reload", &err.to_string()),
-            }
+        if let Err(err) = store.save(&self.paths) {
+            log::warn!("Failed to save window state: {err:?}");
         }
-        self.external_change_pending.set(false);
-    }
-
-    fn load_document_from_path(self: &Rc<Self>, path: &Path) -> Result<()> {
-        self.remove_autosave_artifacts();
-        self.document.load_from_path(path)?;
-        self.file_path.replace(Some(path.to_path_buf()));
-        self.buffer.set_modified(false);
-        self.update_title();
-        self.record_recent_file(path);
-        self.watch_active_file();
-        self.last_edit.replace(None);
-        Ok(())
     }
 
     pub(super) fn show_toast(&self, message: &str) {
@@ -1139,851 +1354,36 @@ impl AppState {
         self.toast_overlay.add_toast(toast);
     }
 
-    fn confirm_unsaved_then<F>(self: &Rc<Self>, proceed: F)
-    where
-        F: FnOnce(&Rc<Self>) + 'static,
-    {
-        if !self.buffer.is_modified() {
-            proceed(self);
-            return;
-        }
-        let proceed_cell: Rc<RefCell<Option<Box<dyn FnOnce(&Rc<Self>)>>>> =
-            Rc::new(RefCell::new(Some(Box::new(proceed))));
-        let dialog = gtk::MessageDialog::builder()
-            .transient_for(&self.window())
-            .modal(true)
-            .text("Unsaved changes")
-            .secondary_text("Save your changes before continuing?")
-            .build();
-        dialog.add_button("Cancel", gtk::ResponseType::Cancel);
-        dialog.add_button("Discard", gtk::ResponseType::Reject);
-        dialog.add_button("Save", gtk::ResponseType::Accept);
-        let weak = Rc::downgrade(self);
-        let proceed_clone = Rc::clone(&proceed_cell);
-        dialog.connect_response(move |dialog, response| {
-            if let Some(state) = weak.upgrade() {
-                match response {
-                    gtk::ResponseType::Accept => {
-                        state.save_action();
-                        if state.buffer.is_modified() {
-                            return;
-                        }
-                    }
-                    gtk::ResponseType::Reject => {}
-                    _ => {
-                        dialog.close();
-                        return;
-                    }
-                }
-                if let Some(callback) = proceed_clone.borrow_mut().take() {
-                    callback(&state);
-                }
-            }
-            dialog.close();
-        });
-        dialog.show();
-    }
-
-    fn show_goto_line_dialog(self: &Rc<Self>) {
-        let dialog = gtk::Dialog::builder()
-            .transient_for(&self.window())
-            .modal(true)
-            .title("Go to Line")
-            .build();
-        dialog.add_button("Cancel", gtk::ResponseType::Cancel);
-        dialog.add_button("Go", gtk::ResponseType::Accept);
-        dialog.set_default_response(gtk::ResponseType::Accept);
-
-        let entry = gtk::Entry::builder()
-            .placeholder_text("Line number")
-            .input_purpose(gtk::InputPurpose::Digits)
-            .activates_default(true)
-            .build();
-        entry.set_margin_top(12);
-        entry.set_margin_bottom(12);
-        entry.set_margin_start(12);
-        entry.set_margin_end(12);
-        dialog.content_area().append(&entry);
-        entry.grab_focus();
-
-        let weak = Rc::downgrade(self);
-        let entry_clone = entry.clone();
-        dialog.connect_response(move |dialog, response| {
-            if response == gtk::ResponseType::Accept {
-                if let Some(state) = weak.upgrade() {
-                    let text = entry_clone.text();
-                    if let Ok(mut line) = text.trim().parse::<i32>() {
-                        if line <= 0 {
-                            line = 1;
-                        }
-                        let total = state.buffer.line_count().max(1);
-                        if line > total {
-                            line = total;
-                        }
-                        if let Some(mut iter) = state.buffer.iter_at_line(line - 1) {
-                            state.buffer.place_cursor(&iter);
-                            let view = state.document.view();
-                            view.scroll_to_iter(&mut iter, 0.1, false, 0.0, 0.0);
-                            state
-                                .status_label
-                                .set_text(&format!("Line {} of {}", line, total));
-                        }
-                    } else {
-                        state.status_label.set_text("Enter a valid line number");
-                    }
-                }
-            }
-            dialog.close();
-        });
-
-        dialog.show();
-    }
-
-    fn sync_llm_preferences(&self) {
-        let (
-            provider,
-            idx,
-            endpoint,
-            override_model,
-            model_path,
-            gpu_idx,
-            gpu_model,
-            cpu_model,
-            max_tokens,
-        ) = {
-            let settings = self.settings.borrow();
-            let provider = settings.llm.provider;
-            let idx = preferences::provider_index(&provider);
-            let endpoint = settings.llm.endpoint.clone();
-            let override_model = settings.llm.override_model_path;
-            let model_path = settings.llm.local_model_path.clone();
-            let gpu_idx = if settings.llm.force_cpu_only {
-                0
-            } else if let Some(ref device) = settings.llm.preferred_device {
-                self.gpus
-                    .iter()
-                    .position(|g| &g.id == device)
-                    .map(|i| i + 1)
-                    .unwrap_or(0)
-            } else {
-                0
-            };
-            let gpu_model = settings.llm.default_gpu_model.clone();
-            let cpu_model = settings.llm.default_cpu_model.clone();
-            let max_tokens = settings.llm.max_completion_tokens;
-            (
-                provider,
-                idx,
-                endpoint,
-                override_model,
-                model_path,
-                gpu_idx,
-                gpu_model,
-                cpu_model,
-                max_tokens,
-            )
-        };
-
-        self.preferences.llm_provider_combo.set_selected(idx as u32);
-        self.preferences
-            .llm_endpoint_row
-            .set_visible(provider != ProviderKind::Local);
-        self.preferences.llm_endpoint_row.set_text(&endpoint);
-        self.preferences
-            .override_model_switch
-            .set_active(override_model);
-        self.preferences.llm_model_row.set_sensitive(override_model);
-        self.preferences.llm_model_row.set_text(&model_path);
-        self.preferences.gpu_combo.set_selected(gpu_idx as u32);
-        self.preferences.gpu_model_row.set_text(&gpu_model);
-        self.preferences.cpu_model_row.set_text(&cpu_model);
-        self.preferences
-            .max_tokens_spin
-            .set_value(max_tokens as f64);
-    }
-
-    fn hook_llm_preferences(self: &Rc<Self>) {
-        let weak = Rc::downgrade(self);
-        self.preferences
-            .llm_provider_combo
-            .connect_selected_notify(move |row| {
-                if let Some(state) = weak.upgrade() {
-                    let provider = preferences::provider_from_index(row.selected());
-                    state.update_llm_provider(provider);
-                }
-            });
-
-        let weak = Rc::downgrade(self);
-        self.preferences
-            .llm_endpoint_row
-            .connect_changed(move |entry: &adw::EntryRow| {
-                if let Some(state) = weak.upgrade() {
-                    state.update_llm_endpoint(entry.text().to_string());
-                }
-            });
-
-        let state = Rc::clone(self);
-        let weak = Rc::downgrade(self);
-        self.preferences
-            .override_model_switch
-            .connect_state_set(move |_, active| {
-                if let Some(state) = weak.upgrade() {
-                    state.update_override_model(active);
-                }
-                Propagation::Proceed
-            });
-
-        let weak = Rc::downgrade(self);
-        self.preferences
-            .llm_model_row
-            .connect_changed(move |entry: &adw::EntryRow| {
-                if let Some(state) = weak.upgrade() {
-                    state.update_llm_local_model(entry.text().to_string());
-                }
-            });
-
-        let weak = Rc::downgrade(self);
-        self.preferences
-            .gpu_combo
-            .connect_selected_notify(move |row| {
-                if let Some(state) = weak.upgrade() {
-                    let idx = row.selected();
-                    state.update_gpu_selection(idx);
-                }
-            });
-
-        let weak = Rc::downgrade(self);
-        self.preferences
-            .gpu_model_row
-            .connect_changed(move |entry: &adw::EntryRow| {
-                if let Some(state) = weak.upgrade() {
-                    state.update_gpu_model(entry.text().to_string());
-                }
-            });
-
-        let weak = Rc::downgrade(self);
-        self.preferences
-            .cpu_model_row
-            .connect_changed(move |entry: &adw::EntryRow| {
-                if let Some(state) = weak.upgrade() {
-                    state.update_cpu_model(entry.text().to_string());
-                }
-            });
-
-        let weak = Rc::downgrade(self);
-        self.preferences
-            .gpu_download_button
-            .connect_clicked(move |_| {
-                if let Some(state) = weak.upgrade() {
-                    let model_ref = state.preferences.gpu_model_row.text().trim().to_string();
-                    if model_ref.is_empty() {
-                        let toast =
-                            adw::Toast::new("Enter a GPU model reference before downloading.");
-                        toast.set_timeout(6);
-                        state.toast_overlay.add_toast(toast);
-                    } else {
-                        state.download_llm_model(model_ref);
-                    }
-                }
-            });
-
-        let weak = Rc::downgrade(self);
-        self.preferences
-            .cpu_download_button
-            .connect_clicked(move |_| {
-                if let Some(state) = weak.upgrade() {
-                    let model_ref = state.preferences.cpu_model_row.text().trim().to_string();
-                    if model_ref.is_empty() {
-                        let toast =
-                            adw::Toast::new("Enter a CPU model reference before downloading.");
-                        toast.set_timeout(6);
-                        state.toast_overlay.add_toast(toast);
-                    } else {
-                        state.download_llm_model(model_ref);
-                    }
-                }
-            });
-
-        let weak = Rc::downgrade(self);
-        self.preferences
-            .max_tokens_spin
-            .connect_value_changed(move |spin| {
-                if let Some(state) = weak.upgrade() {
-                    let value = spin.value() as usize;
-                    state.update_max_completion_tokens(value);
-                }
-            });
-
-        let weak = Rc::downgrade(self);
-        self.preferences
-            .reset_defaults_button
-            .connect_clicked(move |_| {
-                if let Some(state) = weak.upgrade() {
-                    let defaults = LlmSettings::default();
-                    // Updating text triggers the change signals which update settings
-                    state
-                        .preferences
-                        .gpu_model_row
-                        .set_text(&defaults.default_gpu_model);
-                    state
-                        .preferences
-                        .cpu_model_row
-                        .set_text(&defaults.default_cpu_model);
-                    state
-                        .preferences
-                        .max_tokens_spin
-                        .set_value(defaults.max_completion_tokens as f64);
-
-                    let toast = adw::Toast::new("LLM settings reset to defaults.");
-                    toast.set_timeout(3);
-                    state.toast_overlay.add_toast(toast);
-                }
-            });
-    }
-
-    fn update_llm_provider(&self, provider: ProviderKind) {
-        {
-            let mut settings = self.settings.borrow_mut();
-            if settings.llm.provider == provider {
-                return;
-            }
-            settings.llm.provider = provider;
-        }
-        self.save_settings();
-        self.refresh_llm_manager_config();
-        self.sync_llm_preferences();
-    }
-
-    fn update_llm_endpoint(&self, endpoint: String) {
-        {
-            let mut settings = self.settings.borrow_mut();
-            if settings.llm.endpoint == endpoint {
-                return;
-            }
-            settings.llm.endpoint = endpoint;
-        }
-        self.save_settings();
-        self.refresh_llm_manager_config();
-    }
-
-    fn update_llm_local_model(&self, path: String) {
-        {
-            let mut settings = self.settings.borrow_mut();
-            if settings.llm.local_model_path == path {
-                return;
-            }
-            settings.llm.local_model_path = path;
-        }
-        self.save_settings();
-        self.refresh_llm_manager_config();
-    }
-
-    fn update_override_model(&self, active: bool) {
-        {
-            let mut settings = self.settings.borrow_mut();
-            if settings.llm.override_model_path == active {
-                return;
-            }
-            settings.llm.override_model_path = active;
-        }
-        self.save_settings();
-        self.refresh_llm_manager_config();
-        self.sync_llm_preferences();
-    }
-
-    fn update_gpu_selection(&self, idx: u32) {
-        {
-            let mut settings = self.settings.borrow_mut();
-            if idx == 0 {
-                settings.llm.force_cpu_only = true;
-                settings.llm.preferred_device = None;
-            } else {
-                settings.llm.force_cpu_only = false;
-                let gpu_idx = (idx as usize) - 1;
-                if let Some(gpu) = self.gpus.get(gpu_idx) {
-                    settings.llm.preferred_device = Some(gpu.id.clone());
-                }
-            }
-        }
-        self.save_settings();
-        self.refresh_llm_manager_config();
-        self.sync_llm_preferences();
-    }
-
-    fn update_gpu_model(&self, model: String) {
-        {
-            let mut settings = self.settings.borrow_mut();
-            if settings.llm.default_gpu_model == model {
-                return;
-            }
-            settings.llm.default_gpu_model = model;
-        }
-        self.save_settings();
-        self.refresh_llm_manager_config();
-    }
-
-    fn update_cpu_model(&self, model: String) {
-        {
-            let mut settings = self.settings.borrow_mut();
-            if settings.llm.default_cpu_model == model {
-                return;
-            }
-            settings.llm.default_cpu_model = model;
-        }
-        self.save_settings();
-        self.refresh_llm_manager_config();
-    }
-
-    fn update_max_completion_tokens(&self, tokens: usize) {
-        {
-            let mut settings = self.settings.borrow_mut();
-            if settings.llm.max_completion_tokens == tokens {
-                return;
-            }
-            settings.llm.max_completion_tokens = tokens;
-        }
-        self.save_settings();
-        self.refresh_llm_manager_config();
-    }
-
-    fn save_settings(&self) {
-        if let Err(err) = self.settings.borrow().save(&self.paths) {
-            log::warn!("Failed to save settings: {err:?}");
-        }
-    }
-
-    fn apply_editor_settings(&self) {
-        let view = self.document.view();
-        let settings = self.settings.borrow();
-
-        view.set_show_line_marks(settings.show_whitespace);
-
-        if settings.wrap_text {
-            view.set_wrap_mode(gtk::WrapMode::WordChar);
-        } else {
-            view.set_wrap_mode(gtk::WrapMode::None);
-        }
-    }
-
-    fn hook_editor_preferences(self: &Rc<Self>) {
-        let weak = Rc::downgrade(self);
-        self.preferences
-            .whitespace_switch
-            .connect_state_set(move |_, active| {
-                if let Some(state) = weak.upgrade() {
-                    state.set_show_whitespace(active);
-                }
-                Propagation::Proceed
-            });
-
-        let weak = Rc::downgrade(self);
-        self.preferences
-            .wrap_switch
-            .connect_state_set(move |_, active| {
-                if let Some(state) = weak.upgrade() {
-                    state.set_wrap_text(active);
-                }
-                Propagation::Proceed
-            });
-    }
-
-    fn handle_text_change(self: &Rc<Self>) {
-        if self.are_completions_suppressed() {
-            return;
-        }
-
-        // Check for deletions/undo to avoid triggering on backspace or Ctrl+Z
-        let current_count = self.buffer.char_count();
-        let last_count = self.last_char_count.get();
-        self.last_char_count.set(current_count);
-
-        // Only trigger completion on NET INSERTIONS (current > last)
-        // Don't trigger on deletions (current < last) or replacements (current == last)
-        if current_count <= last_count {
-            // User deleted text or replaced - don't trigger auto-completion
-            self.cancel_completion_debounce();
-            self.manual_completion_inflight.set(false);
-            self.with_suppressed_completion(|| self.document.dismiss_ghost_text());
-            return;
-        }
-
-        self.cancel_completion_debounce();
-        self.manual_completion_inflight.set(false);
-        self.with_suppressed_completion(|| self.document.dismiss_ghost_text());
-        let generation = self.bump_completion_generation();
-        self.schedule_auto_completion(generation);
-    }
-
-    pub(super) fn schedule_auto_completion(self: &Rc<Self>, generation: u64) {
-        if self.manual_completion_inflight.get() {
-            return;
-        }
-
-        const DEBOUNCE_MS: u64 = 500;
-
-        // ALWAYS cancel old debounce and schedule new one when content changes
-        self.cancel_completion_debounce();
-
-        let weak = Rc::downgrade(self);
-        let source =
-            glib::timeout_add_local(std::time::Duration::from_millis(DEBOUNCE_MS), move || {
-                if let Some(state) = weak.upgrade() {
-                    // Clear the stored source ID since we're about to complete
-                    // Clear the stored source ID since we're about to complete
-                    state.completion_debounce.borrow_mut().take();
-
-                    if state.manual_completion_inflight.get() {
-                        return ControlFlow::Break;
-                    }
-
-                    state.request_llm_completion_with_generation(
-                        CompletionTrigger::Automatic,
-                        generation,
-                    );
-                }
-                ControlFlow::Break
-            });
-        self.completion_debounce.borrow_mut().replace(source);
-    }
-
-    fn cancel_completion_debounce(&self) {
-        if let Some(source) = self.completion_debounce.borrow_mut().take() {
-            // Ignore errors if source was already removed
-            let _ = source.remove();
-        }
-    }
-
-    pub(super) fn bump_completion_generation(&self) -> u64 {
-        let next = self.completion_generation.get().wrapping_add(1);
-        self.completion_generation.set(next);
-        next
-    }
-
-    fn request_llm_completion(self: &Rc<Self>) {
-        let context = self.completion_context();
-        if context.trim().is_empty() {
-            let toast = adw::Toast::new("Type some text before requesting a completion.");
-            toast.set_timeout(5);
-            self.toast_overlay.add_toast(toast);
-            return;
-        }
-
-        let generation = self.bump_completion_generation();
-        self.request_llm_completion_with_generation(CompletionTrigger::Manual, generation);
-    }
-
-    pub(super) fn completion_context(&self) -> String {
-        const PREFIX_CHARS: usize = 2000;
-        const SUFFIX_CHARS: usize = 1000;
-
-        let buffer = self.document.buffer();
-        let cursor_offset = buffer.cursor_position();
-        let cursor_iter = buffer.iter_at_offset(cursor_offset);
-
-        // Get prefix (text before cursor)
-        let mut prefix_start = cursor_iter.clone();
-        for _ in 0..PREFIX_CHARS {
-            if !prefix_start.backward_char() {
-                break;
-            }
-        }
-        let prefix = buffer.text(&prefix_start, &cursor_iter, true).to_string();
-
-        // Get suffix (text after cursor)
-        let mut suffix_end = cursor_iter.clone();
-        for _ in 0..SUFFIX_CHARS {
-            if !suffix_end.forward_char() {
-                break;
-            }
-        }
-        let suffix = buffer.text(&cursor_iter, &suffix_end, true).to_string();
-
-        // Format as FIM prompt (DeepSeek Coder style)
-        // The model expects: <｜fim▁begin｜>PREFIX<｜fim▁hole｜>SUFFIX<｜fim▁end｜>
-        // Note: ▁ is U+2581 (LOWER ONE EIGHTH BLOCK), not a regular space!
-        // Model will generate what goes in the "hole" (middle)
-        if suffix.is_empty() {
-            // No suffix - just return prefix (end of document, no FIM needed)
-            prefix
-        } else {
-            // FIM format: prefix + hole marker + suffix, all wrapped
-            format!(
-                "<｜fim▁begin｜>{}<｜fim▁hole｜>{}<｜fim▁end｜>",
-                prefix, suffix
-            )
-        }
-    }
-
-    fn accept_current_completion(self: &Rc<Self>) {
-        log::info!("Accepting ghost text completion");
-        let mut accepted = false;
-        self.with_suppressed_completion(|| {
-            accepted = self.document.accept_ghost_text();
-        });
-        if accepted {
-            log::info!("Ghost text accepted successfully");
-            self.status_label.set_text("Completion accepted");
-            // Bump generation to invalidate any in-flight completions, but don't schedule new one
-            // User should continue typing before we offer another suggestion
-            self.bump_completion_generation();
-        } else {
-            log::warn!("No ghost text to accept");
-        }
-    }
-
-    fn cancel_current_completion(&self) {
-        self.with_suppressed_completion(|| self.document.dismiss_ghost_text());
-        self.status_label.set_text("Suggestion dismissed");
-    }
-
-    fn set_show_whitespace(&self, show: bool) {
-        {
-            let mut settings = self.settings.borrow_mut();
-            if settings.show_whitespace == show {
-                return;
-            }
-            settings.show_whitespace = show;
-        }
-        self.save_settings();
-        self.apply_editor_settings();
-    }
-
-    fn set_wrap_text(&self, wrap: bool) {
-        {
-            let mut settings = self.settings.borrow_mut();
-            if settings.wrap_text == wrap {
-                return;
-            }
-            settings.wrap_text = wrap;
-        }
-        self.save_settings();
-        self.apply_editor_settings();
+    /// Toast inside the preferences window, which covers the main overlay while open
+    pub(super) fn show_preferences_toast(&self, message: &str) {
+        let toast = adw::Toast::new(message);
+        self.preferences.window.add_toast(toast);
     }
 
-    fn check_llm_readiness(self: &Rc<Self>) {
-        // Skip if user disabled the check
-        if self.settings.borrow().skip_llm_startup_check {
+    /// Send a desktop notification when enabled and the window is in the background.
+    /// Notifications sharing an `id` replace each other instead of piling up.
+    pub(super) fn notify_desktop(&self, id: &str, title: &str, body: &str) {
+        if !self.settings.borrow().desktop_notifications {
             return;
         }
-
-        let readiness = self
-            .lock_llm_manager()
-            .map(|mgr| mgr.check_readiness())
-            .unwrap_or(LlmReadiness::LocalBackendUnavailable);
-
-        if readiness == LlmReadiness::Ready {
-            // All good, nothing to show
+        let window = self.window();
+        if window.is_active() {
             return;
         }
-
-        self.show_llm_setup_dialog(readiness);
-    }
-
-    fn show_llm_setup_dialog(self: &Rc<Self>, readiness: LlmReadiness) {
-        let dialog = gtk::Dialog::builder()
-            .transient_for(&self.window())
-            .modal(true)
-            .title("LLM Setup")
-            .build();
-
-        let (message, action_label) = match &readiness {
-            LlmReadiness::NeedsDownload { model_ref } => (
-                format!(
-                    "Your LLM configuration uses local inference, but the model is not downloaded yet.\n\n\
-                    Model: {}\n\n\
-                    Would you like to download it now?",
-                    model_ref
-                ),
-                Some("Download Model"),
-            ),
-            LlmReadiness::LocalBackendUnavailable => (
-                "Wispnote could not initialize its bundled llama.cpp backend for local inference.\n\n\
-                (Development build hint) If you're running from source, make sure the llama.cpp shared libraries and GPU/CPU drivers it depends on are available; otherwise, switch to a remote provider in Preferences."
-                    .to_string(),
-                Some("Open Preferences"),
-            ),
-            LlmReadiness::NeedsEndpoint => (
-                "Your LLM provider requires an endpoint URL, but none is configured.\n\n\
-                Please configure your LLM settings in Preferences."
-                    .to_string(),
-                Some("Open Preferences"),
-            ),
-            LlmReadiness::Ready => return, // Should never reach here
-        };
-
-        // Build content
-        let vbox = gtk::Box::new(gtk::Orientation::Vertical, 12);
-        vbox.set_margin_top(12);
-        vbox.set_margin_bottom(12);
-        vbox.set_margin_start(12);
-        vbox.set_margin_end(12);
-
-        let label = gtk::Label::new(Some(&message));
-        label.set_wrap(true);
-        label.set_max_width_chars(50);
-        vbox.append(&label);
-
-        // Add checkbox for "Don't show again"
-        let checkbox = gtk::CheckButton::with_label("Don't show this dialog again");
-        vbox.append(&checkbox);
-
-        dialog.content_area().append(&vbox);
-
-        dialog.add_button("Later", gtk::ResponseType::Cancel);
-
-        if let Some(label) = action_label {
-            dialog.add_button(label, gtk::ResponseType::Accept);
-            dialog.set_default_response(gtk::ResponseType::Accept);
+        if let Some(app) = window.application() {
+            let notification = gio::Notification::new(title);
+            notification.set_body(Some(body));
+            app.send_notification(Some(id), &notification);
         }
-
-        let weak = Rc::downgrade(self);
-        let readiness_clone = readiness.clone();
-        dialog.connect_response(move |dialog, response| {
-            if let Some(state) = weak.upgrade() {
-                // Save "don't show again" preference
-                if checkbox.is_active() {
-                    let mut settings = state.settings.borrow_mut();
-                    settings.skip_llm_startup_check = true;
-                    drop(settings);
-                    state.save_settings();
-                }
-
-                if response == gtk::ResponseType::Accept {
-                    match &readiness_clone {
-                        LlmReadiness::NeedsDownload { model_ref } => {
-                            state.download_llm_model(model_ref.clone());
-                        }
-                        _ => {
-                            // Open preferences
-                            state.preferences.window.present();
-                        }
-                    }
-                }
-            }
-            dialog.close();
-        });
-
-        dialog.show();
-    }
-
-    fn download_llm_model(self: &Rc<Self>, model_ref: String) {
-        let trimmed = model_ref.trim();
-        if trimmed.is_empty() {
-            let toast = adw::Toast::new("Specify a model reference before downloading.");
-            toast.set_timeout(6);
-            self.toast_overlay.add_toast(toast);
-            return;
-        }
-
-        let parsed_model = match HuggingFaceModel::parse(trimmed) {
-            Ok(model) => model,
-            Err(err) => {
-                let error_toast =
-                    adw::Toast::new(&format!("Invalid model reference '{}': {}", trimmed, err));
-                error_toast.set_timeout(10);
-                self.toast_overlay.add_toast(error_toast);
-                self.status_label
-                    .set_text(&format!("Invalid model reference: {}", err));
-                return;
-            }
-        };
-
-        let model_name = parsed_model.filename();
-        self.show_download_banner(&model_name);
-
-        enum DownloadMsg {
-            Progress(DownloadProgress),
-            Finished(anyhow::Result<PathBuf>),
-        }
-
-        let downloader = self.model_downloader.clone();
-        let (sender, receiver) = mpsc::channel::<DownloadMsg>();
-
-        std::thread::spawn(move || {
-            let thread_sender = sender.clone();
-            let result = downloader.download_with_progress(&parsed_model, |progress| {
-                let _ = thread_sender.send(DownloadMsg::Progress(progress));
-            });
-            let _ = thread_sender.send(DownloadMsg::Finished(result));
-        });
-
-        let weak = Rc::downgrade(self);
-        glib::timeout_add_local(std::time::Duration::from_millis(50), move || match receiver
-            .try_recv()
-        {
-            Ok(DownloadMsg::Progress(progress)) => {
-                if let Some(state) = weak.upgrade() {
-                    state.update_download_progress(progress);
-                }
-                ControlFlow::Continue
-            }
-            Ok(DownloadMsg::Finished(result)) => {
-                if let Some(state) = weak.upgrade() {
-                    state.hide_download_banner();
-                    match result {
-                        Ok(path) => {
-                            let success_toast = adw::Toast::new(&format!(
-                                "Model downloaded successfully: {}",
-                                path.display()
-                            ));
-                            success_toast.set_timeout(5);
-                            state.toast_overlay.add_toast(success_toast);
-                            state.status_label.set_text("Model ready for use");
-                        }
-                        Err(err) => {
-                            let error_toast =
-                                adw::Toast::new(&format!("Failed to download model: {}", err));
-                            error_toast.set_timeout(10);
-                            state.toast_overlay.add_toast(error_toast);
-                            state
-                                .status_label
-                                .set_text(&format!("Download failed: {}", err));
-                        }
-                    }
-                }
-                ControlFlow::Break
-            }
-            Err(mpsc::TryRecvError::Empty) => ControlFlow::Continue,
-            Err(mpsc::TryRecvError::Disconnected) => {
-                if let Some(state) = weak.upgrade() {
-                    state.hide_download_banner();
-                    state
-                        .status_label
-                        .set_text("Download interrupted unexpectedly");
-                }
-                ControlFlow::Break
-            }
-        });
-    }
-
-    fn attach_file_filters(dialog: &gtk::FileChooserDialog) {
-        let text_filter = gtk::FileFilter::new();
-        text_filter.set_name(Some("Text files"));
-        text_filter.add_mime_type("text/plain");
-        text_filter.add_pattern("*.txt");
-        dialog.add_filter(&text_filter);
-
-        let md_filter = gtk::FileFilter::new();
-        md_filter.set_name(Some("Markdown"));
-        md_filter.add_mime_type("text/markdown");
-        md_filter.add_pattern("*.md");
-        md_filter.add_pattern("*.markdown");
-        dialog.add_filter(&md_filter);
-
-        let all_filter = gtk::FileFilter::new();
-        all_filter.set_name(Some("All files"));
-        all_filter.add_pattern("*");
-        dialog.add_filter(&all_filter);
-        dialog.set_filter(&text_filter);
     }
 
-    fn refresh_llm_manager_config(&self) {
-        if let Some(mut manager) = self.lock_llm_manager() {
-            manager.update_config(self.settings.borrow().llm.clone());
+    pub(super) fn save_settings(&self) {
+        if let Err(err) = self.settings.borrow().save(&self.paths) {
+            log::warn!("Failed to save settings: {err:?}");
         }
     }
 
-    fn lock_llm_manager(&self) -> Option<MutexGuard<'_, LlmManager>> {
+    pub(super) fn lock_llm_manager(&self) -> Option<MutexGuard<'_, LlmManager>> {
         // Use try_lock to avoid blocking the main thread if the model is loading
         match self.llm_manager.try_lock() {
             Ok(guard) => Some(guard),
@@ -1999,27 +1399,9 @@ impl AppState {
     }
 }
 
-fn human_readable_bytes(bytes: u64) -> String {
-    const UNITS: [&str; 5] = ["B", "KB", "MB", "GB", "TB"];
-    if bytes == 0 {
-        return "0 B".into();
-    }
-    let mut value = bytes as f64;
-    let mut unit = 0;
-    while value >= 1024.0 && unit < UNITS.len() - 1 {
-        value /= 1024.0;
-        unit += 1;
-    }
-    if unit == 0 {
-        format!("{bytes} {}", UNITS[unit])
-    } else {
-        format!("{value:.1} {}", UNITS[unit])
-    }
-}
-
-fn is_textual_key(key: gdk::Key, state: gdk::ModifierType) -> bool {
-    if state.intersects(gdk::ModifierType::CONTROL_MASK | gdk::ModifierType::ALT_MASK) {
-        return false;
-    }
-    key.to_unicode().map(|ch| !ch.is_control()).unwrap_or(false)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PersistState {
+    Saved,
+    DraftAutosaved,
+    Unsaved,
 }
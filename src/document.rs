@@ -1,20 +1,190 @@
 use std::fs;
 use std::path::{Path, PathBuf};
 
-use anyhow::{Context, Result};
+use anyhow::{Context, Result, bail};
+use chardetng::EncodingDetector;
+use encoding_rs::{Encoding, UTF_8, UTF_16BE, UTF_16LE};
 use gtk4::gdk::RGBA;
 use gtk4::pango::Style;
 use gtk4::prelude::*;
 use sourceview5::{Buffer, View};
 
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use std::rc::Rc;
 
+/// Line break style a file is saved with
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LineEnding {
+    #[default]
+    Lf,
+    CrLf,
+}
+
+impl LineEnding {
+    /// The style most line breaks in `text` use; LF when there are none
+    pub fn detect(text: &str) -> LineEnding {
+        let crlf = text.matches("\r\n").count();
+        let lf = text.matches('\n').count() - crlf;
+        if crlf > lf {
+            LineEnding::CrLf
+        } else {
+            LineEnding::Lf
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            LineEnding::Lf => "LF",
+            LineEnding::CrLf => "CRLF",
+        }
+    }
+
+    /// `text` with every line break, LF or CRLF, written in this style
+    pub fn apply(self, text: &str) -> String {
+        let normalized = text.replace("\r\n", "\n");
+        match self {
+            LineEnding::Lf => normalized,
+            LineEnding::CrLf => normalized.replace('\n', "\r\n"),
+        }
+    }
+}
+
+/// Size of a piece of text, for the status bar
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TextCounts {
+    pub words: usize,
+    pub chars: usize,
+    pub lines: usize,
+}
+
+impl TextCounts {
+    pub fn of(text: &str) -> Self {
+        Self {
+            words: text.split_whitespace().count(),
+            chars: text.chars().count(),
+            // An empty document still has one (empty) line
+            lines: text.split('\n').count(),
+        }
+    }
+}
+
+/// Cleanups applied to the text written by a save, leaving the buffer as is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SaveCleanup {
+    /// Add a newline at the end if the text doesn't end with one
+    pub final_newline: bool,
+    /// Strip spaces and tabs from the end of every line
+    pub trim_trailing_whitespace: bool,
+}
+
+impl SaveCleanup {
+    pub fn apply(self, text: &str) -> String {
+        let mut out = if self.trim_trailing_whitespace {
+            text.split('\n')
+                .map(|line| line.trim_end_matches([' ', '\t']))
+                .collect::<Vec<_>>()
+                .join("\n")
+        } else {
+            text.to_string()
+        };
+        if self.final_newline && !out.is_empty() && !out.ends_with('\n') {
+            out.push('\n');
+        }
+        out
+    }
+}
+
+/// Bytes checked for NUL when deciding whether a file is binary
+const BINARY_SNIFF_LEN: usize = 8192;
+
+/// Decode file contents, guessing the encoding unless `encoding` is given.
+/// Returns the text, its encoding and whether the data started with a byte
+/// order mark. Binary data is refused rather than shown as garbage.
+pub fn decode_text(
+    bytes: &[u8],
+    encoding: Option<&'static Encoding>,
+) -> Result<(String, &'static Encoding, bool)> {
+    let bom = Encoding::for_bom(bytes);
+    let encoding = match (encoding, bom) {
+        (Some(encoding), _) => encoding,
+        (None, Some((encoding, _))) => encoding,
+        (None, None) => {
+            // Text never contains NUL; UTF-16 without a BOM is too rare to guess at
+            if bytes.iter().take(BINARY_SNIFF_LEN).any(|&byte| byte == 0) {
+                bail!("This looks like a binary file, not text");
+            }
+            let mut detector = EncodingDetector::new();
+            detector.feed(bytes, true);
+            detector.guess(None, true)
+        }
+    };
+    let (body, has_bom) = match bom {
+        Some((bom_encoding, len)) if bom_encoding == encoding => (&bytes[len..], true),
+        _ => (bytes, false),
+    };
+    let (text, had_errors) = encoding.decode_without_bom_handling(body);
+    if had_errors {
+        log::warn!(
+            "Some bytes are not valid {}; they were replaced",
+            encoding.name()
+        );
+    }
+    Ok((text.into_owned(), encoding, has_bom))
+}
+
+/// Encode `text` for writing in `encoding`, with a byte order mark if `bom`.
+/// Fails when the text has characters the encoding can't represent.
+pub fn encode_text(text: &str, encoding: &'static Encoding, bom: bool) -> Result<Vec<u8>> {
+    // encoding_rs only decodes UTF-16, so it is written by hand
+    if encoding == UTF_16LE || encoding == UTF_16BE {
+        let little_endian = encoding == UTF_16LE;
+        let units = bom.then_some(0xFEFF).into_iter().chain(text.encode_utf16());
+        return Ok(units
+            .flat_map(|unit: u16| {
+                if little_endian {
+                    unit.to_le_bytes()
+                } else {
+                    unit.to_be_bytes()
+                }
+            })
+            .collect());
+    }
+    let (bytes, _, had_errors) = encoding.encode(text);
+    if had_errors {
+        bail!(
+            "Some characters can't be written as {}; choose another encoding",
+            encoding.name()
+        );
+    }
+    let mut out = Vec::with_capacity(bytes.len() + 3);
+    if bom && encoding == UTF_8 {
+        out.extend_from_slice(b"\xEF\xBB\xBF");
+    }
+    out.extend_from_slice(&bytes);
+    Ok(out)
+}
+
 pub struct Document {
     buffer: Buffer,
     view: View,
+    /// Line breaks of the file on disk; the buffer always holds LF
+    line_ending: Cell<LineEnding>,
+    /// Encoding of the file on disk
+    encoding: Cell<&'static Encoding>,
+    /// Whether the file on disk starts with a byte order mark
+    bom: Cell<bool>,
     ghost_tag: gtk4::TextTag,
     ghost_range: RefCell<Option<(gtk4::TextMark, gtk4::TextMark)>>,
+    /// Whether the newest undo step is still the suggestion's insertion, so
+    /// dismissing it can undo that step instead of recording a deletion
+    ghost_undoable: Cell<bool>,
+    /// Redo steps left before the next one would bring back a dismissed
+    /// suggestion; `None` once an edit has cleared the redo history
+    ghost_redo_depth: Cell<Option<u32>>,
+    /// Set while the document changes the buffer itself
+    editing: Cell<bool>,
+    replace_tag: gtk4::TextTag,
+    replace_range: RefCell<Option<(gtk4::TextMark, gtk4::TextMark)>>,
 }
 
 impl Document {
@@ -37,12 +207,76 @@ impl Document {
         ghost_tag.set_property("foreground-rgba", &RGBA::new(0.53, 0.53, 0.53, 1.0));
         tag_table.add(&ghost_tag);
 
-        Rc::new(Self {
+        // Marks the selected text that an accepted rewrite suggestion will replace
+        let replace_tag = gtk4::TextTag::builder()
+            .name("llm-replaced")
+            .strikethrough(true)
+            .build();
+        tag_table.add(&replace_tag);
+
+        let document = Rc::new(Self {
             buffer,
             view,
+            line_ending: Cell::new(LineEnding::default()),
+            encoding: Cell::new(UTF_8),
+            bom: Cell::new(false),
             ghost_tag,
             ghost_range: RefCell::new(None),
-        })
+            ghost_undoable: Cell::new(false),
+            ghost_redo_depth: Cell::new(None),
+            editing: Cell::new(false),
+            replace_tag,
+            replace_range: RefCell::new(None),
+        });
+
+        // An undo can take the suggestion's text out from under its marks;
+        // stop tracking it then instead of leaving an empty ghost behind
+        let weak = Rc::downgrade(&document);
+        document.buffer.connect_delete_range(move |_, start, end| {
+            if let Some(document) = weak.upgrade() {
+                document.forget_deleted_ghost(start, end);
+                document.note_edit();
+            }
+        });
+        let weak = Rc::downgrade(&document);
+        document.buffer.connect_insert_text(move |_, _, _| {
+            if let Some(document) = weak.upgrade() {
+                document.note_edit();
+            }
+        });
+        document
+    }
+
+    /// An edit the document didn't make itself goes on top of the suggestion's
+    /// undo step and clears the redo history
+    fn note_edit(&self) {
+        if !self.editing.get() {
+            self.ghost_undoable.set(false);
+            self.ghost_redo_depth.set(None);
+        }
+    }
+
+    fn while_editing<R>(&self, edit: impl FnOnce() -> R) -> R {
+        let was_editing = self.editing.replace(true);
+        let result = edit();
+        self.editing.set(was_editing);
+        result
+    }
+
+    fn forget_deleted_ghost(&self, start: &gtk4::TextIter, end: &gtk4::TextIter) {
+        let swallowed = self.ghost_range.borrow().as_ref().is_some_and(|(s, e)| {
+            start <= &self.buffer.iter_at_mark(s) && end >= &self.buffer.iter_at_mark(e)
+        });
+        if let (true, Some((start_mark, end_mark))) = (swallowed, self.take_ghost_marks()) {
+            log::debug!("Ghost text was deleted from under its marks");
+            self.buffer.delete_mark(&start_mark);
+            self.buffer.delete_mark(&end_mark);
+        }
+    }
+
+    /// Color the suggestion text is drawn in
+    pub fn set_ghost_color(&self, color: &RGBA) {
+        self.ghost_tag.set_property("foreground-rgba", color);
     }
 
     pub fn view(&self) -> View {
@@ -53,26 +287,86 @@ impl Document {
         self.buffer.clone()
     }
 
+    /// Tag on the text of a showing suggestion
+    pub fn ghost_tag(&self) -> gtk4::TextTag {
+        self.ghost_tag.clone()
+    }
+
     pub fn clear(&self) {
         self.buffer.set_text("");
         self.buffer.set_modified(false);
+        self.line_ending.set(LineEnding::default());
+        self.encoding.set(UTF_8);
+        self.bom.set(false);
     }
 
     pub fn load_from_path(&self, path: &Path) -> Result<()> {
-        let data = fs::read_to_string(path)
-            .with_context(|| format!("Failed to open {}", path.display()))?;
-        self.buffer.set_text(&data);
+        self.load_from_path_with(path, None)
+    }
+
+    /// Load `path`, decoding it as `encoding` instead of the detected one if given
+    pub fn load_from_path_with(
+        &self,
+        path: &Path,
+        encoding: Option<&'static Encoding>,
+    ) -> Result<()> {
+        let bytes = fs::read(path).with_context(|| format!("Failed to open {}", path.display()))?;
+        // The message already says what's wrong; callers show it as is
+        let (data, encoding, bom) = decode_text(&bytes, encoding)?;
+        let line_ending = LineEnding::detect(&data);
+        self.buffer.set_text(&LineEnding::Lf.apply(&data));
         self.buffer.set_modified(false);
+        self.line_ending.set(line_ending);
+        self.encoding.set(encoding);
+        self.bom.set(bom);
         Ok(())
     }
 
-    pub fn save_to_path(&self, path: &Path) -> Result<()> {
-        let text = self.current_text();
-        fs::write(path, text).with_context(|| format!("Failed to save {}", path.display()))?;
+    pub fn save_to_path(&self, path: &Path, cleanup: SaveCleanup) -> Result<()> {
+        let text = cleanup.apply(&self.current_text());
+        let text = self.line_ending.get().apply(&text);
+        let bytes = encode_text(&text, self.encoding.get(), self.bom.get())
+            .with_context(|| format!("Failed to save {}", path.display()))?;
+        fs::write(path, bytes).with_context(|| format!("Failed to save {}", path.display()))?;
         self.buffer.set_modified(false);
         Ok(())
     }
 
+    pub fn line_ending(&self) -> LineEnding {
+        self.line_ending.get()
+    }
+
+    /// Save with `line_ending` from now on; the document needs saving to apply it
+    pub fn set_line_ending(&self, line_ending: LineEnding) {
+        if self.line_ending.replace(line_ending) != line_ending {
+            self.buffer.set_modified(true);
+        }
+    }
+
+    pub fn encoding(&self) -> &'static Encoding {
+        self.encoding.get()
+    }
+
+    /// Save as `encoding` from now on; the document needs saving to apply it
+    pub fn set_encoding(&self, encoding: &'static Encoding) {
+        if self.encoding.replace(encoding) != encoding {
+            // Only the Unicode encodings carry a byte order mark
+            if encoding != UTF_16LE && encoding != UTF_16BE && encoding != UTF_8 {
+                self.bom.set(false);
+            }
+            self.buffer.set_modified(true);
+        }
+    }
+
+    /// Whether saving starts the file with a byte order mark
+    pub fn has_bom(&self) -> bool {
+        self.bom.get()
+    }
+
+    pub fn set_bom(&self, bom: bool) {
+        self.bom.set(bom);
+    }
+
     pub fn current_text(&self) -> String {
         let start = self.buffer.start_iter();
         let end = self.buffer.end_iter();
@@ -85,6 +379,13 @@ impl Document {
             return;
         }
 
+        // A suggestion isn't an edit; keep a clean document clean
+        let was_modified = self.buffer.is_modified();
+        // One undo step, so undoing an accepted suggestion removes all of it
+        // and dismissing one can take that step back
+        self.editing.set(true);
+        self.buffer.begin_user_action();
+
         // Get cursor position using the insert mark (always valid)
         let insert_mark = self.buffer.get_insert();
         let mut insert_iter = self.buffer.iter_at_mark(&insert_mark);
@@ -111,6 +412,118 @@ impl Document {
         self.buffer.place_cursor(&start_iter);
 
         self.ghost_range.replace(Some((start_mark, end_mark)));
+        self.buffer.end_user_action();
+        self.editing.set(false);
+        self.buffer.set_modified(was_modified);
+        self.ghost_undoable.set(true);
+        self.ghost_redo_depth.set(None);
+    }
+
+    /// Show `text` as a rewrite of the current selection. The selection is
+    /// struck through and only removed once the suggestion is accepted.
+    pub fn insert_ghost_replacement(&self, text: &str) {
+        self.dismiss_ghost_text();
+        let Some((start, end)) = self.buffer.selection_bounds() else {
+            self.insert_ghost_text(text);
+            return;
+        };
+        if text.is_empty() {
+            return;
+        }
+
+        // Both marks use left gravity so the ghost text inserted at the end of
+        // the selection lands outside the replaced range.
+        let replace_start = self.buffer.create_mark(None, &start, true);
+        let replace_end = self.buffer.create_mark(None, &end, true);
+        self.buffer.apply_tag(&self.replace_tag, &start, &end);
+
+        self.buffer.place_cursor(&end);
+        self.insert_ghost_text(text);
+        self.replace_range
+            .replace(Some((replace_start, replace_end)));
+    }
+
+    /// Scroll just enough to bring the end of the suggestion into view. A
+    /// suggestion taller than the view is shown from its start instead, so the
+    /// cursor never leaves the screen.
+    pub fn reveal_ghost_text(&self) {
+        let range = self.ghost_range.borrow();
+        let Some((start_mark, end_mark)) = range.as_ref() else {
+            return;
+        };
+        let visible = self.view.visible_rect();
+        let start = self
+            .view
+            .iter_location(&self.buffer.iter_at_mark(start_mark));
+        let end = self.view.iter_location(&self.buffer.iter_at_mark(end_mark));
+        let end_bottom = end.y() + end.height();
+        if end_bottom <= visible.y() + visible.height() {
+            return;
+        }
+        if end_bottom - start.y() > visible.height() {
+            self.view.scroll_to_mark(start_mark, 0.0, true, 0.0, 0.0);
+        } else {
+            self.view.scroll_to_mark(end_mark, 0.0, false, 0.0, 0.0);
+        }
+    }
+
+    /// Whether `iter` falls within the suggestion, ends included
+    pub fn ghost_contains(&self, iter: &gtk4::TextIter) -> bool {
+        self.ghost_range
+            .borrow()
+            .as_ref()
+            .is_some_and(|(start_mark, end_mark)| {
+                let start = self.buffer.iter_at_mark(start_mark);
+                let end = self.buffer.iter_at_mark(end_mark);
+                iter.in_range(&start, &end) || *iter == end
+            })
+    }
+
+    /// The document text without any suggestion in it
+    pub fn text_without_ghost(&self) -> String {
+        let (start, end) = (self.buffer.start_iter(), self.buffer.end_iter());
+        let Some((ghost_start, ghost_end)) = self
+            .ghost_range
+            .borrow()
+            .as_ref()
+            .map(|(s, e)| (self.buffer.iter_at_mark(s), self.buffer.iter_at_mark(e)))
+        else {
+            return self.current_text();
+        };
+        let mut text = self.buffer.text(&start, &ghost_start, true).to_string();
+        text.push_str(&self.buffer.text(&ghost_end, &end, true));
+        text
+    }
+
+    /// Undo the last edit, dropping a showing suggestion first so undo never
+    /// lands on half of it. Returns whether there was anything to undo.
+    pub fn undo(&self) -> bool {
+        self.dismiss_ghost_text();
+        if !self.buffer.can_undo() {
+            return false;
+        }
+        let depth = self.ghost_redo_depth.get();
+        self.ghost_redo_depth.set(depth.map(|depth| depth + 1));
+        self.while_editing(|| self.buffer.undo());
+        true
+    }
+
+    /// Redo the last undone edit; see [`Document::undo`]
+    pub fn redo(&self) -> bool {
+        self.dismiss_ghost_text();
+        if !self.can_redo() {
+            return false;
+        }
+        let depth = self.ghost_redo_depth.get();
+        self.ghost_redo_depth.set(depth.map(|depth| depth - 1));
+        self.while_editing(|| self.buffer.redo());
+        true
+    }
+
+    /// Whether there is an edit to redo. A dismissed suggestion is left at the
+    /// bottom of the redo history, but never brought back.
+    pub fn can_redo(&self) -> bool {
+        self.buffer.can_redo() && self.ghost_redo_depth.get() != Some(0)
     }
 
     pub fn ghost_is_active(&self) -> bool {
@@ -124,7 +537,10 @@ impl Document {
                 log::warn!("Ghost text marks already deleted in accept_ghost_text");
                 return false;
             }
+            // The insertion is the user's edit now
+            self.ghost_undoable.set(false);
 
+            self.buffer.begin_user_action();
             let mut start = self.buffer.iter_at_mark(&start_mark);
             let mut end = self.buffer.iter_at_mark(&end_mark);
             self.buffer
@@ -133,30 +549,183 @@ impl Document {
             self.buffer.place_cursor(&end);
             self.buffer.delete_mark(&start_mark);
             self.buffer.delete_mark(&end_mark);
+            // A rewrite suggestion replaces the original selection on accept
+            self.release_replace_range(true);
+            self.buffer.end_user_action();
+            // The insertion itself didn't count, so mark the accepted text now
+            self.buffer.set_modified(true);
             return true;
         }
         false
     }
 
+    /// Accept the suggestion through its next word and keep the rest as ghost
+    /// text. Accepts everything when only one word is left.
+    pub fn accept_ghost_word(&self) -> bool {
+        self.accept_ghost_prefix(next_word_end)
+    }
+
+    /// Accept the suggestion through its next line break and keep the rest as
+    /// ghost text. Accepts everything when no line break is left.
+    pub fn accept_ghost_line(&self) -> bool {
+        self.accept_ghost_prefix(next_line_end)
+    }
+
+    /// Accept the first `split(ghost)` bytes of the suggestion, moving the
+    /// ghost range to start after them. `None` or a split at the very end
+    /// accepts the whole suggestion, as does a pending rewrite, which can't
+    /// be taken in parts.
+    fn accept_ghost_prefix(&self, split: impl Fn(&str) -> Option<usize>) -> bool {
+        let range = self.ghost_range.borrow();
+        let Some((start_mark, end_mark)) = range.as_ref() else {
+            return false;
+        };
+        if start_mark.is_deleted() || end_mark.is_deleted() {
+            drop(range);
+            return self.accept_ghost_text();
+        }
+        let start = self.buffer.iter_at_mark(start_mark);
+        let end = self.buffer.iter_at_mark(end_mark);
+        let ghost = self.buffer.text(&start, &end, true);
+        let split_at = split(&ghost).filter(|at| *at < ghost.len());
+        let (Some(split_at), false) = (split_at, self.replace_range.borrow().is_some()) else {
+            drop(range);
+            return self.accept_ghost_text();
+        };
+
+        if self.ghost_undoable.get() {
+            // Swap the suggestion for the accepted part, as an edit of its
+            // own, and a new suggestion for the rest. Undo then takes back
+            // just what was accepted.
+            let offset = start.offset();
+            let (accepted, rest) = ghost.split_at(split_at);
+            drop(range);
+            self.dismiss_ghost_text();
+            let mut at = self.buffer.iter_at_offset(offset);
+            self.buffer.begin_user_action();
+            self.buffer.insert(&mut at, accepted);
+            self.buffer.end_user_action();
+            self.buffer.place_cursor(&at);
+            self.insert_ghost_text(rest);
+            self.buffer.set_modified(true);
+            return true;
+        }
+
+        let mut accepted_end = start.clone();
+        accepted_end.forward_chars(ghost[..split_at].chars().count() as i32);
+        self.buffer
+            .remove_tag(&self.ghost_tag, &start, &accepted_end);
+        self.buffer.move_mark(start_mark, &accepted_end);
+        self.buffer.place_cursor(&accepted_end);
+        self.buffer.set_modified(true);
+        true
+    }
+
     pub fn dismiss_ghost_text(&self) {
+        let was_modified = self.buffer.is_modified();
         if let Some((start_mark, end_mark)) = self.take_ghost_marks() {
             // Validate marks are not deleted
             if start_mark.is_deleted() || end_mark.is_deleted() {
                 log::warn!("Ghost text marks already deleted in dismiss_ghost_text");
-                return;
+            } else {
+                if !self.while_editing(|| self.undo_ghost_insertion(&start_mark, &end_mark)) {
+                    // Only reached when something was edited while the
+                    // suggestion showed; the deletion stays in the history
+                    let mut start = self.buffer.iter_at_mark(&start_mark);
+                    let mut end = self.buffer.iter_at_mark(&end_mark);
+                    self.buffer.begin_user_action();
+                    self.buffer.delete(&mut start, &mut end);
+                    self.buffer.end_user_action();
+                }
+                self.buffer.delete_mark(&start_mark);
+                self.buffer.delete_mark(&end_mark);
+                self.buffer.set_modified(was_modified);
             }
+        }
+        self.ghost_undoable.set(false);
+        self.release_replace_range(false);
+    }
 
-            let mut start = self.buffer.iter_at_mark(&start_mark);
-            let mut end = self.buffer.iter_at_mark(&end_mark);
-            self.buffer.delete(&mut start, &mut end);
-            self.buffer.delete_mark(&start_mark);
-            self.buffer.delete_mark(&end_mark);
+    /// Take the suggestion out by undoing its insertion, so neither shows up
+    /// in the undo history. Only possible while that insertion is still the
+    /// newest undo step; returns whether it was.
+    fn undo_ghost_insertion(&self, start_mark: &gtk4::TextMark, end_mark: &gtk4::TextMark) -> bool {
+        if !self.ghost_undoable.replace(false) || !self.buffer.can_undo() {
+            return false;
+        }
+        let ghost_chars = self.buffer.iter_at_mark(end_mark).offset()
+            - self.buffer.iter_at_mark(start_mark).offset();
+        let expected_chars = self.buffer.char_count() - ghost_chars;
+        // Undo moves the cursor to where the text was; keep it where it is
+        let selection = [self.buffer.selection_bound(), self.buffer.get_insert()].map(|mark| {
+            self.buffer
+                .create_mark(None, &self.buffer.iter_at_mark(&mark), true)
+        });
+
+        self.ghost_redo_depth.set(Some(0));
+        self.buffer.undo();
+        let undone = self.buffer.char_count() == expected_chars;
+        if !undone {
+            // The history merged the suggestion into an earlier step
+            self.ghost_redo_depth.set(None);
+            self.buffer.redo();
+        }
+
+        let [bound, insert] = selection.map(|mark| self.buffer.iter_at_mark(&mark));
+        self.buffer.select_range(&insert, &bound);
+        for mark in &selection {
+            self.buffer.delete_mark(mark);
         }
+        undone
     }
 
     fn take_ghost_marks(&self) -> Option<(gtk4::TextMark, gtk4::TextMark)> {
         self.ghost_range.borrow_mut().take()
     }
+
+    /// Drop the pending rewrite range, deleting the original text when the
+    /// rewrite was accepted and just clearing the strikethrough otherwise.
+    fn release_replace_range(&self, delete_text: bool) {
+        let Some((start_mark, end_mark)) = self.replace_range.borrow_mut().take() else {
+            return;
+        };
+        if start_mark.is_deleted() || end_mark.is_deleted() {
+            log::warn!("Replace range marks already deleted");
+            return;
+        }
+        let mut start = self.buffer.iter_at_mark(&start_mark);
+        let mut end = self.buffer.iter_at_mark(&end_mark);
+        if delete_text {
+            self.buffer.delete(&mut start, &mut end);
+        } else {
+            self.buffer.remove_tag(&self.replace_tag, &start, &end);
+        }
+        self.buffer.delete_mark(&start_mark);
+        self.buffer.delete_mark(&end_mark);
+    }
+}
+
+/// Byte offset just past the next word of `text`, including the whitespace
+/// before it. Punctuation counts as a word of its own.
+fn next_word_end(text: &str) -> Option<usize> {
+    let is_word = |c: char| c.is_alphanumeric() || c == '_';
+    let (start, first) = text.char_indices().find(|(_, c)| !c.is_whitespace())?;
+    if !is_word(first) {
+        return Some(start + first.len_utf8());
+    }
+    Some(
+        text[start..]
+            .char_indices()
+            .find(|(_, c)| !is_word(*c))
+            .map_or(text.len(), |(end, _)| start + end),
+    )
+}
+
+/// Byte offset just past the next line break of `text`. Line breaks at the
+/// very start are skipped so a suggestion opening a new line takes that line too.
+fn next_line_end(text: &str) -> Option<usize> {
+    let start = text.len() - text.trim_start_matches('\n').len();
+    text[start..].find('\n').map(|end| start + end + 1)
 }
 
 pub fn derive_display_name(path: &Option<PathBuf>) -> String {
@@ -174,6 +743,81 @@ pub fn derive_display_name(path: &Option<PathBuf>) -> String {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_decode_text_handles_boms_and_binary() {
+        let (text, encoding, bom) = decode_text(b"\xFF\xFEh\x00i\x00", None).unwrap();
+        assert_eq!((text.as_str(), encoding, bom), ("hi", UTF_16LE, true));
+        let (text, encoding, bom) = decode_text(b"\xEF\xBB\xBFhi", None).unwrap();
+        assert_eq!((text.as_str(), encoding, bom), ("hi", UTF_8, true));
+        let (text, _, _) = decode_text(b"caf\xE9", Some(encoding_rs::WINDOWS_1252)).unwrap();
+        assert_eq!(text, "café");
+        assert!(decode_text(b"\x7FELF\x02\x01\x00\x00", None).is_err());
+    }
+
+    #[test]
+    fn test_encode_text_round_trips() {
+        for (encoding, bom) in [
+            (UTF_8, false),
+            (UTF_8, true),
+            (UTF_16LE, true),
+            (UTF_16BE, true),
+            (encoding_rs::WINDOWS_1252, false),
+        ] {
+            let bytes = encode_text("café\n", encoding, bom).unwrap();
+            let (text, decoded_as, had_bom) = decode_text(&bytes, Some(encoding)).unwrap();
+            assert_eq!(
+                (text.as_str(), decoded_as, had_bom),
+                ("café\n", encoding, bom)
+            );
+        }
+        assert!(encode_text("snow ☃", encoding_rs::WINDOWS_1252, false).is_err());
+    }
+
+    #[test]
+    fn test_text_counts() {
+        assert_eq!(
+            TextCounts::of("Hello  world\nsecond line\n"),
+            TextCounts {
+                words: 4,
+                chars: 25,
+                lines: 3,
+            }
+        );
+        assert_eq!(
+            TextCounts::of(""),
+            TextCounts {
+                words: 0,
+                chars: 0,
+                lines: 1,
+            }
+        );
+    }
+
+    #[test]
+    fn test_save_cleanup() {
+        let both = SaveCleanup {
+            final_newline: true,
+            trim_trailing_whitespace: true,
+        };
+        assert_eq!(both.apply("a  \nb\t\n\nc "), "a\nb\n\nc\n");
+        assert_eq!(both.apply(""), "");
+        assert_eq!(SaveCleanup::default().apply("a  "), "a  ");
+        let newline_only = SaveCleanup {
+            final_newline: true,
+            ..SaveCleanup::default()
+        };
+        assert_eq!(newline_only.apply("a \nb"), "a \nb\n");
+    }
+
+    #[test]
+    fn test_line_ending_detect_and_apply() {
+        assert_eq!(LineEnding::detect("a\r\nb\r\nc\n"), LineEnding::CrLf);
+        assert_eq!(LineEnding::detect("a\nb\r\nc\n"), LineEnding::Lf);
+        assert_eq!(LineEnding::detect("no breaks"), LineEnding::Lf);
+        assert_eq!(LineEnding::CrLf.apply("a\nb\r\n"), "a\r\nb\r\n");
+        assert_eq!(LineEnding::Lf.apply("a\r\nb\n"), "a\nb\n");
+    }
+
     #[test]
     fn test_ghost_text_insertion() {
         // Initialize GTK (if possible in this env)
@@ -206,4 +850,117 @@ mod tests {
         let text_after = doc.current_text();
         assert_eq!(text_after, "Hello World");
     }
+
+    #[test]
+    fn test_next_word_end() {
+        assert_eq!(next_word_end(" world, again"), Some(6));
+        assert_eq!(next_word_end(", again"), Some(1));
+        assert_eq!(next_word_end("naïve_name()"), Some("naïve_name".len()));
+        assert_eq!(next_word_end("last"), Some(4));
+        assert_eq!(next_word_end("  "), None);
+    }
+
+    #[test]
+    fn test_next_line_end() {
+        assert_eq!(next_line_end("one\ntwo\n"), Some(4));
+        assert_eq!(next_line_end("\n\none\ntwo"), Some(6));
+        assert_eq!(next_line_end("no break"), None);
+    }
+
+    #[test]
+    fn test_accept_ghost_word_keeps_rest_as_ghost() {
+        if gtk4::init().is_err() {
+            eprintln!("Skipping GTK test due to missing display");
+            return;
+        }
+
+        let doc = Document::new();
+        doc.buffer.set_text("Hello");
+        doc.buffer.place_cursor(&doc.buffer.end_iter());
+
+        doc.insert_ghost_text(" big world");
+        assert!(doc.accept_ghost_word());
+        assert!(doc.ghost_is_active());
+        let cursor = doc.buffer.iter_at_mark(&doc.buffer.get_insert());
+        assert_eq!(cursor.offset(), "Hello big".len() as i32);
+
+        // The last word takes the rest of the suggestion
+        assert!(doc.accept_ghost_word());
+        assert!(!doc.ghost_is_active());
+        assert_eq!(doc.current_text(), "Hello big world");
+    }
+
+    #[test]
+    fn test_undo_after_accepting_ghost_text() {
+        if gtk4::init().is_err() {
+            eprintln!("Skipping GTK test due to missing display");
+            return;
+        }
+
+        let doc = Document::new();
+        doc.buffer.set_text("Hello");
+        doc.buffer.place_cursor(&doc.buffer.end_iter());
+
+        doc.insert_ghost_text(" big world");
+        assert!(doc.accept_ghost_text());
+        assert!(doc.undo());
+        assert_eq!(doc.current_text(), "Hello");
+        assert!(!doc.ghost_is_active());
+
+        // An undo that bypasses the document mustn't strand the ghost marks
+        doc.insert_ghost_text(" World");
+        doc.buffer.undo();
+        assert_eq!(doc.current_text(), "Hello");
+        assert!(!doc.ghost_is_active());
+    }
+
+    #[test]
+    fn test_undo_after_dismissing_ghost_text() {
+        if gtk4::init().is_err() {
+            eprintln!("Skipping GTK test due to missing display");
+            return;
+        }
+
+        let doc = Document::new();
+        doc.buffer.set_text("Hello");
+        doc.buffer.place_cursor(&doc.buffer.end_iter());
+
+        doc.insert_ghost_text(" big world");
+        doc.dismiss_ghost_text();
+        // The dismissed suggestion can't be redone either
+        assert!(!doc.can_redo());
+        assert!(!doc.redo());
+        assert_eq!(doc.current_text(), "Hello");
+
+        doc.buffer.insert_at_cursor("!");
+        assert!(doc.undo());
+        assert_eq!(doc.current_text(), "Hello");
+        assert!(!doc.ghost_is_active());
+        assert!(doc.redo());
+        assert_eq!(doc.current_text(), "Hello!");
+        assert!(!doc.can_redo());
+    }
+
+    #[test]
+    fn test_ghost_text_keeps_clean_buffer_unmodified() {
+        if gtk4::init().is_err() {
+            eprintln!("Skipping GTK test due to missing display");
+            return;
+        }
+
+        let doc = Document::new();
+        doc.buffer.set_text("Hello");
+        doc.buffer.set_modified(false);
+        doc.buffer.place_cursor(&doc.buffer.end_iter());
+
+        doc.insert_ghost_text(" World");
+        assert!(!doc.buffer.is_modified());
+        doc.dismiss_ghost_text();
+        assert!(!doc.buffer.is_modified());
+
+        doc.insert_ghost_text(" World");
+        assert!(doc.accept_ghost_text());
+        assert!(doc.buffer.is_modified());
+        assert_eq!(doc.current_text(), "Hello World");
+    }
 }
@@ -0,0 +1,94 @@
+use std::collections::VecDeque;
+
+/// Small least-recently-used store of finished completions, so returning to
+/// an identical context doesn't run inference again. Entries are keyed by the
+/// exact prompt, so a changed document can't hit a stale entry.
+#[derive(Debug, Default)]
+pub struct CompletionCache {
+    capacity: usize,
+    /// Most recently used last
+    entries: VecDeque<(CacheKey, String)>,
+}
+
+/// The prompt and the token budget it was completed with
+type CacheKey = (String, usize);
+
+impl CompletionCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    /// Change the capacity, dropping the oldest entries if it shrank.
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        while self.entries.len() > capacity {
+            self.entries.pop_front();
+        }
+    }
+
+    pub fn get(&mut self, prompt: &str, max_tokens: usize) -> Option<String> {
+        let idx = self
+            .entries
+            .iter()
+            .position(|((p, n), _)| p == prompt && *n == max_tokens)?;
+        // Move the hit to the back so it's evicted last
+        let entry = self.entries.remove(idx)?;
+        let completion = entry.1.clone();
+        self.entries.push_back(entry);
+        Some(completion)
+    }
+
+    pub fn insert(&mut self, prompt: &str, max_tokens: usize, completion: &str) {
+        if self.capacity == 0 {
+            return;
+        }
+        self.entries
+            .retain(|((p, n), _)| p != prompt || *n != max_tokens);
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries
+            .push_back(((prompt.to_string(), max_tokens), completion.to_string()));
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_evicts_least_recently_used() {
+        let mut cache = CompletionCache::new(2);
+        cache.insert("a", 8, "one");
+        cache.insert("b", 8, "two");
+        // Touch "a" so "b" becomes the oldest
+        assert_eq!(cache.get("a", 8).as_deref(), Some("one"));
+        cache.insert("c", 8, "three");
+        assert_eq!(cache.get("b", 8), None);
+        assert_eq!(cache.get("a", 8).as_deref(), Some("one"));
+        assert_eq!(cache.get("c", 8).as_deref(), Some("three"));
+    }
+
+    #[test]
+    fn test_key_includes_max_tokens() {
+        let mut cache = CompletionCache::new(4);
+        cache.insert("a", 8, "short");
+        assert_eq!(cache.get("a", 32), None);
+        cache.insert("a", 8, "replaced");
+        assert_eq!(cache.get("a", 8).as_deref(), Some("replaced"));
+    }
+
+    #[test]
+    fn test_zero_capacity_disables_cache() {
+        let mut cache = CompletionCache::new(0);
+        cache.insert("a", 8, "one");
+        assert_eq!(cache.get("a", 8), None);
+    }
+}
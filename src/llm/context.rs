@@ -0,0 +1,357 @@
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+/// How the text around the cursor is cut down to the prompt window.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ContextStrategy {
+    /// A fixed number of characters on each side, cut anywhere
+    Chars,
+    /// Whole lines on each side, never splitting a line except to fit the budget
+    Lines,
+}
+
+impl Default for ContextStrategy {
+    fn default() -> Self {
+        ContextStrategy::Chars
+    }
+}
+
+/// Clip the text before the cursor. `before` should start at a line start so the
+/// line strategy can tell full lines from partial ones.
+pub fn clip_prefix(
+    before: &str,
+    strategy: ContextStrategy,
+    max_chars: usize,
+    lines: usize,
+) -> &str {
+    match strategy {
+        ContextStrategy::Chars => last_chars(before, max_chars),
+        ContextStrategy::Lines => {
+            // Keep the current (partial) line plus `lines` full lines above it
+            let start = before
+                .rmatch_indices('\n')
+                .nth(lines)
+                .map(|(idx, _)| idx + 1)
+                .unwrap_or(0);
+            let window = &before[start..];
+            if window.chars().count() <= max_chars {
+                return window;
+            }
+            // Over budget: drop leading lines, keeping the cut on a line start if possible
+            let clipped = last_chars(window, max_chars);
+            match clipped.find('\n') {
+                Some(idx) => &clipped[idx + 1..],
+                None => clipped,
+            }
+        }
+    }
+}
+
+/// Clip the text after the cursor. `after` should end at a line end.
+pub fn clip_suffix(after: &str, strategy: ContextStrategy, max_chars: usize, lines: usize) -> &str {
+    match strategy {
+        ContextStrategy::Chars => first_chars(after, max_chars),
+        ContextStrategy::Lines => {
+            // Keep the rest of the current line plus `lines` full lines below it
+            let end = after
+                .match_indices('\n')
+                .nth(lines)
+                .map(|(idx, _)| idx)
+                .unwrap_or(after.len());
+            let window = &after[..end];
+            if window.chars().count() <= max_chars {
+                return window;
+            }
+            let clipped = first_chars(window, max_chars);
+            match clipped.rfind('\n') {
+                Some(idx) => &clipped[..idx],
+                None => clipped,
+            }
+        }
+    }
+}
+
+/// A one-line hint naming the file, written as a comment in the file's own
+/// language so code models pick up the language and conventions. Prose files
+/// get their title instead. Returns `None` for paths without a file name.
+pub fn file_header(path: &Path) -> Option<String> {
+    let name = path.file_name()?.to_string_lossy();
+    // The parent directory adds useful context ("src/main.rs") without leaking the full path
+    let shown = match path.parent().and_then(Path::file_name) {
+        Some(parent) => format!("{}/{}", parent.to_string_lossy(), name),
+        None => name.to_string(),
+    };
+    let extension = path
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_ascii_lowercase())
+        .unwrap_or_default();
+    let header = match extension.as_str() {
+        "rs" | "c" | "h" | "cc" | "cpp" | "hpp" | "cs" | "java" | "kt" | "go" | "js" | "jsx"
+        | "ts" | "tsx" | "swift" | "scala" | "dart" | "zig" => format!("// file: {shown}\n"),
+        "py" | "sh" | "bash" | "zsh" | "rb" | "pl" | "r" | "toml" | "yaml" | "yml" | "conf"
+        | "ini" | "cmake" => format!("# file: {shown}\n"),
+        "sql" | "lua" | "hs" => format!("-- file: {shown}\n"),
+        "html" | "xml" | "svg" | "ui" => format!("<!-- file: {shown} -->\n"),
+        "css" => format!("/* file: {shown} */\n"),
+        _ => {
+            let title = path.file_stem()?.to_string_lossy();
+            if matches!(extension.as_str(), "md" | "markdown") {
+                format!("<!-- title: {title} -->\n")
+            } else {
+                format!("Title: {title}\n\n")
+            }
+        }
+    };
+    Some(header)
+}
+
+fn last_chars(text: &str, count: usize) -> &str {
+    let start = text
+        .char_indices()
+        .rev()
+        .nth(count.saturating_sub(1))
+        .map(|(idx, _)| idx)
+        .unwrap_or(0);
+    if count == 0 { "" } else { &text[start..] }
+}
+
+fn first_chars(text: &str, count: usize) -> &str {
+    let end = text
+        .char_indices()
+        .nth(count)
+        .map(|(idx, _)| idx)
+        .unwrap_or(text.len());
+    &text[..end]
+}
+
+/// Fill-in-the-middle prompt layout, which differs between model families.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FimTemplate {
+    /// Picked from the model name
+    Auto,
+    /// Qwen Coder: `<|fim_prefix|>`, `<|fim_suffix|>`, `<|fim_middle|>`
+    Qwen,
+    /// DeepSeek Coder: `<｜fim▁begin｜>`, `<｜fim▁hole｜>`, `<｜fim▁end｜>`
+    DeepSeek,
+    /// Code Llama: `<PRE>`, `<SUF>`, `<MID>`
+    CodeLlama,
+    /// StarCoder: `<fim_prefix>`, `<fim_suffix>`, `<fim_middle>`
+    StarCoder,
+    /// The model has no FIM training; only the text before the cursor is sent
+    None,
+}
+
+impl Default for FimTemplate {
+    fn default() -> Self {
+        FimTemplate::Auto
+    }
+}
+
+/// Markers around the prefix and suffix of a fill-in-the-middle prompt
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FimMarkers {
+    /// Opens the prompt; the prefix follows it
+    pub begin: &'static str,
+    /// Ends the prefix; the suffix follows it
+    pub hole: &'static str,
+    /// Ends the suffix; the model writes the middle after it
+    pub end: &'static str,
+}
+
+/// Templates that have markers, in the order prompts are matched against them
+const FIM_FAMILIES: &[FimTemplate] = &[
+    FimTemplate::DeepSeek,
+    FimTemplate::Qwen,
+    FimTemplate::StarCoder,
+    FimTemplate::CodeLlama,
+];
+
+impl FimTemplate {
+    /// Guess the template from a model name or path. Names that match no
+    /// known family get DeepSeek's, which the default models use.
+    pub fn for_model(name: &str) -> FimTemplate {
+        let name = name.to_ascii_lowercase();
+        if name.contains("qwen") {
+            FimTemplate::Qwen
+        } else if name.contains("codellama") || name.contains("code-llama") {
+            FimTemplate::CodeLlama
+        } else if name.contains("starcoder") {
+            FimTemplate::StarCoder
+        } else {
+            FimTemplate::DeepSeek
+        }
+    }
+
+    /// The concrete template; `Auto` is resolved from `model_name`
+    pub fn resolve(self, model_name: &str) -> FimTemplate {
+        match self {
+            FimTemplate::Auto => FimTemplate::for_model(model_name),
+            template => template,
+        }
+    }
+
+    /// The prompt markers, or `None` for `None` and an unresolved `Auto`
+    pub fn markers(self) -> Option<FimMarkers> {
+        let (begin, hole, end) = match self {
+            FimTemplate::Qwen => ("<|fim_prefix|>", "<|fim_suffix|>", "<|fim_middle|>"),
+            FimTemplate::DeepSeek => ("<｜fim▁begin｜>", "<｜fim▁hole｜>", "<｜fim▁end｜>"),
+            FimTemplate::CodeLlama => ("<PRE> ", " <SUF>", " <MID>"),
+            FimTemplate::StarCoder => ("<fim_prefix>", "<fim_suffix>", "<fim_middle>"),
+            FimTemplate::Auto | FimTemplate::None => return None,
+        };
+        Some(FimMarkers { begin, hole, end })
+    }
+
+    /// Special tokens of this family that must never show up in a completion
+    pub fn sentinels(self) -> &'static [&'static str] {
+        match self {
+            FimTemplate::Qwen => &["<|fim_", "<|file_sep|>", "<|repo_name|>"],
+            FimTemplate::DeepSeek => &["<｜fim"],
+            FimTemplate::CodeLlama => &["<PRE>", "<SUF>", "<MID>", "<EOT>"],
+            FimTemplate::StarCoder => &["<fim_", "<file_sep>", "<filename>"],
+            FimTemplate::Auto | FimTemplate::None => &[],
+        }
+    }
+
+    /// Build the prompt for the gap between `prefix` and `suffix`. At the end
+    /// of the document there is nothing to fill around, so the prefix is sent
+    /// as is.
+    pub fn build_prompt(self, prefix: &str, suffix: &str) -> String {
+        match self.markers() {
+            Some(markers) if !suffix.is_empty() => format!(
+                "{}{}{}{}{}",
+                markers.begin, prefix, markers.hole, suffix, markers.end
+            ),
+            _ => prefix.to_string(),
+        }
+    }
+
+    /// The family whose layout `prompt` uses, if it is a FIM prompt
+    pub fn detect(prompt: &str) -> Option<FimTemplate> {
+        FIM_FAMILIES.iter().copied().find(|template| {
+            template
+                .markers()
+                .is_some_and(|markers| prompt.starts_with(markers.begin))
+        })
+    }
+}
+
+/// Split a fill-in-the-middle prompt into the text before and after the gap,
+/// for providers that take the two parts separately. `None` for plain prompts.
+pub fn split_fim_prompt(prompt: &str) -> Option<(&str, &str)> {
+    let markers = FimTemplate::detect(prompt)?.markers()?;
+    let rest = prompt.strip_prefix(markers.begin)?;
+    let (prefix, suffix) = rest.split_once(markers.hole)?;
+    Some((prefix, suffix.strip_suffix(markers.end).unwrap_or(suffix)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chars_strategy_cuts_anywhere() {
+        assert_eq!(
+            clip_prefix("hello world", ContextStrategy::Chars, 5, 0),
+            "world"
+        );
+        assert_eq!(
+            clip_suffix("hello world", ContextStrategy::Chars, 5, 0),
+            "hello"
+        );
+        assert_eq!(clip_prefix("héllo", ContextStrategy::Chars, 4, 0), "éllo");
+        assert_eq!(clip_prefix("abc", ContextStrategy::Chars, 0, 0), "");
+        assert_eq!(clip_suffix("abc", ContextStrategy::Chars, 10, 0), "abc");
+    }
+
+    #[test]
+    fn test_lines_strategy_snaps_prefix_to_line_start() {
+        let before = "one\ntwo\nthree\nfour\nfi";
+        assert_eq!(clip_prefix(before, ContextStrategy::Lines, 100, 0), "fi");
+        assert_eq!(
+            clip_prefix(before, ContextStrategy::Lines, 100, 2),
+            "three\nfour\nfi"
+        );
+        assert_eq!(clip_prefix(before, ContextStrategy::Lines, 100, 10), before);
+        // Budget forces dropping "three" entirely rather than keeping "ee"
+        assert_eq!(
+            clip_prefix(before, ContextStrategy::Lines, 10, 2),
+            "four\nfi"
+        );
+        // A single line longer than the budget falls back to a character cut
+        assert_eq!(clip_prefix("abcdefgh", ContextStrategy::Lines, 3, 2), "fgh");
+    }
+
+    #[test]
+    fn test_lines_strategy_snaps_suffix_to_line_end() {
+        let after = "ne\ntwo\nthree\nfour";
+        assert_eq!(clip_suffix(after, ContextStrategy::Lines, 100, 0), "ne");
+        assert_eq!(
+            clip_suffix(after, ContextStrategy::Lines, 100, 2),
+            "ne\ntwo\nthree"
+        );
+        assert_eq!(clip_suffix(after, ContextStrategy::Lines, 100, 10), after);
+        assert_eq!(clip_suffix(after, ContextStrategy::Lines, 8, 2), "ne\ntwo");
+        assert_eq!(clip_suffix("abcdefgh", ContextStrategy::Lines, 3, 2), "abc");
+    }
+
+    #[test]
+    fn test_file_header_matches_language() {
+        assert_eq!(
+            file_header(Path::new("/home/me/proj/src/main.rs")).as_deref(),
+            Some("// file: src/main.rs\n")
+        );
+        assert_eq!(
+            file_header(Path::new("build.py")).as_deref(),
+            Some("# file: build.py\n")
+        );
+        assert_eq!(
+            file_header(Path::new("/notes/Trip Plan.md")).as_deref(),
+            Some("<!-- title: Trip Plan -->\n")
+        );
+        assert_eq!(
+            file_header(Path::new("/notes/journal.txt")).as_deref(),
+            Some("Title: journal\n\n")
+        );
+        assert_eq!(file_header(Path::new("/")), None);
+    }
+
+    #[test]
+    fn test_split_fim_prompt() {
+        for template in FIM_FAMILIES {
+            let prompt = template.build_prompt("fn main() {", "}");
+            assert_eq!(FimTemplate::detect(&prompt), Some(*template));
+            assert_eq!(split_fim_prompt(&prompt), Some(("fn main() {", "}")));
+        }
+        assert_eq!(split_fim_prompt("plain text"), None);
+        assert_eq!(
+            FimTemplate::None.build_prompt("fn main() {", "}"),
+            "fn main() {"
+        );
+    }
+
+    #[test]
+    fn test_fim_template_for_model() {
+        assert_eq!(
+            FimTemplate::Auto.resolve("Qwen/Qwen2.5-Coder-1.5B-Instruct-GGUF:q4_k_m.gguf"),
+            FimTemplate::Qwen
+        );
+        assert_eq!(
+            FimTemplate::for_model("/models/codellama-7b.Q4_K_M.gguf"),
+            FimTemplate::CodeLlama
+        );
+        assert_eq!(
+            FimTemplate::for_model("starcoder2-3b-Q4_K_M.gguf"),
+            FimTemplate::StarCoder
+        );
+        assert_eq!(
+            FimTemplate::for_model("deepseek-coder-1.3b-instruct.Q4_K_M.gguf"),
+            FimTemplate::DeepSeek
+        );
+        assert_eq!(
+            FimTemplate::StarCoder.resolve("qwen"),
+            FimTemplate::StarCoder
+        );
+    }
+}
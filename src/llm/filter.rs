@@ -0,0 +1,116 @@
+use regex::Regex;
+
+/// User-configured patterns that reject a completion when any of them matches.
+#[derive(Debug, Clone, Default)]
+pub struct CompletionFilter {
+    patterns: Vec<Regex>,
+}
+
+impl CompletionFilter {
+    /// Compile the given patterns, skipping blank lines and logging invalid ones.
+    pub fn new(patterns: &[String]) -> Self {
+        let patterns = patterns
+            .iter()
+            .filter(|pattern| !pattern.trim().is_empty())
+            .filter_map(|pattern| match Regex::new(pattern) {
+                Ok(regex) => Some(regex),
+                Err(err) => {
+                    log::warn!("Ignoring invalid completion filter {:?}: {}", pattern, err);
+                    None
+                }
+            })
+            .collect();
+        Self { patterns }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.patterns.is_empty()
+    }
+
+    /// Return the first pattern that matches `text`, if any.
+    pub fn blocked_by(&self, text: &str) -> Option<&str> {
+        self.patterns
+            .iter()
+            .find(|regex| regex.is_match(text))
+            .map(|regex| regex.as_str())
+    }
+}
+
+/// Cut `text` right after its first sentence-ending punctuation mark
+/// (`.`, `!` or `?` followed by whitespace or the end of the text).
+pub fn truncate_at_sentence_end(text: &str) -> &str {
+    let mut chars = text.char_indices().peekable();
+    while let Some((idx, ch)) = chars.next() {
+        if matches!(ch, '.' | '!' | '?') {
+            match chars.peek() {
+                None => return text,
+                Some((_, next)) if next.is_whitespace() => return &text[..idx + ch.len_utf8()],
+                _ => {}
+            }
+        }
+    }
+    text
+}
+
+/// Whether `text` already holds a whole sentence, so generating more would
+/// only produce text `truncate_at_sentence_end` cuts off. Punctuation at the
+/// very end doesn't count yet: `3.` may still turn out to be `3.50`.
+pub fn has_complete_sentence(text: &str) -> bool {
+    truncate_at_sentence_end(text).len() < text.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_blocks_matching_completion() {
+        let filter = CompletionFilter::new(&["^```".to_string(), "(?i)as an ai".to_string()]);
+        assert_eq!(filter.blocked_by("```rust\nfn main() {}"), Some("^```"));
+        assert_eq!(
+            filter.blocked_by("Sure! As an AI, I cannot"),
+            Some("(?i)as an ai")
+        );
+        assert_eq!(filter.blocked_by("let x = 1;"), None);
+    }
+
+    #[test]
+    fn test_truncate_at_sentence_end() {
+        assert_eq!(
+            truncate_at_sentence_end(" She left. Then it rained."),
+            " She left."
+        );
+        assert_eq!(truncate_at_sentence_end("Really?! Yes"), "Really?!");
+        // A period inside a number doesn't end a sentence
+        assert_eq!(
+            truncate_at_sentence_end("It cost 3.50 dollars"),
+            "It cost 3.50 dollars"
+        );
+        assert_eq!(truncate_at_sentence_end("no punctuation"), "no punctuation");
+    }
+
+    #[test]
+    fn test_has_complete_sentence_stops_streaming_after_the_first_sentence() {
+        let pieces = [" She", " left", ".", " Then", " it", " rained", "."];
+        let mut streamed = String::new();
+        let used = pieces
+            .iter()
+            .take_while(|piece| {
+                streamed.push_str(piece);
+                !has_complete_sentence(&streamed)
+            })
+            .count();
+        // Generation stops on the piece after the period, not at the end
+        assert_eq!(used, 3);
+        assert_eq!(truncate_at_sentence_end(&streamed), " She left.");
+        assert!(!has_complete_sentence("It cost 3."));
+        assert!(!has_complete_sentence("no punctuation"));
+    }
+
+    #[test]
+    fn test_skips_blank_and_invalid_patterns() {
+        let filter = CompletionFilter::new(&["".to_string(), "(unclosed".to_string()]);
+        assert!(filter.is_empty());
+        assert_eq!(filter.blocked_by("anything"), None);
+    }
+}
@@ -0,0 +1,92 @@
+use anyhow::{Context, Result, anyhow};
+use serde_json::json;
+
+use super::{context, http};
+
+/// Google's Generative Language API
+pub const DEFAULT_ENDPOINT: &str = "https://generativelanguage.googleapis.com/v1beta";
+
+/// Used when no model name is configured
+const DEFAULT_MODEL: &str = "gemini-2.0-flash";
+
+/// Complete `prompt` with Gemini's `generateContent`. Gemini has no
+/// fill-in-the-middle mode, so a FIM prompt becomes an instruction to fill the gap.
+pub fn complete(
+    endpoint: &str,
+    model: &str,
+    api_key: &str,
+    prompt: &str,
+    max_tokens: usize,
+) -> Result<String> {
+    let model = model_or_default(model);
+    let url = format!(
+        "{}/models/{}:generateContent",
+        endpoint.trim_end_matches('/'),
+        model
+    );
+    let text = match context::split_fim_prompt(prompt) {
+        Some((prefix, suffix)) => fill_instruction(prefix, suffix),
+        None => prompt.to_string(),
+    };
+    let body = json!({
+        "contents": [{ "role": "user", "parts": [{ "text": text }] }],
+        "generationConfig": { "maxOutputTokens": max_tokens },
+    });
+
+    log::info!("Sending Gemini request to {}", url);
+    let value = http::post_json(&url, &[("x-goog-api-key", api_key.to_string())], &body)
+        .context("Gemini request failed")?;
+    value["candidates"][0]["content"]["parts"][0]["text"]
+        .as_str()
+        .map(str::to_string)
+        .ok_or_else(|| anyhow!("Gemini response contained no text"))
+}
+
+/// Names of the models the API key can use, without the `models/` prefix.
+/// Only the first page is read; the usual models are all on it.
+pub fn list_models(endpoint: &str, api_key: &str) -> Result<Vec<String>> {
+    let url = format!("{}/models?pageSize=1000", endpoint.trim_end_matches('/'));
+    let value = http::get_json(&url, &[("x-goog-api-key", api_key.to_string())])
+        .context("Listing Gemini models failed")?;
+    value["models"]
+        .as_array()
+        .map(|models| {
+            models
+                .iter()
+                .filter_map(|model| model["name"].as_str())
+                .map(|name| name.trim_start_matches("models/").to_string())
+                .collect()
+        })
+        .ok_or_else(|| anyhow!("Gemini model list contained no models"))
+}
+
+/// The model requests go to: the configured one, or the default
+pub fn model_or_default(model: &str) -> &str {
+    if model.is_empty() {
+        DEFAULT_MODEL
+    } else {
+        model
+    }
+}
+
+/// Marks the gap in a fill-in-the-middle instruction
+const GAP_MARKER: &str = "<FILL>";
+
+fn fill_instruction(prefix: &str, suffix: &str) -> String {
+    format!(
+        "Write the text that belongs at {GAP_MARKER} in the document below. Reply with \
+         only that text, without repeating the surrounding text or adding explanations.\n\n\
+         {prefix}{GAP_MARKER}{suffix}"
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fill_instruction_marks_the_gap() {
+        let instruction = fill_instruction("fn main() {", "}");
+        assert!(instruction.ends_with("\n\nfn main() {<FILL>}"));
+    }
+}
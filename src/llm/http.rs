@@ -0,0 +1,69 @@
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use anyhow::{Context, Result, anyhow};
+use serde_json::Value;
+
+/// A server that doesn't answer this fast isn't there
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+/// Generous, since a busy server can take a while to produce a completion
+const READ_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Shared by all providers so connections are reused
+fn agent() -> &'static ureq::Agent {
+    static AGENT: OnceLock<ureq::Agent> = OnceLock::new();
+    AGENT.get_or_init(|| {
+        ureq::AgentBuilder::new()
+            .timeout_connect(CONNECT_TIMEOUT)
+            .timeout_read(READ_TIMEOUT)
+            .build()
+    })
+}
+
+/// POST `body` as JSON with the extra `headers` and parse the JSON reply
+pub fn post_json(url: &str, headers: &[(&str, String)], body: &Value) -> Result<Value> {
+    let mut request = agent().post(url).set("Content-Type", "application/json");
+    for (name, value) in headers {
+        request = request.set(name, value);
+    }
+    read_json(request.send_string(&body.to_string()))
+}
+
+/// GET `url` with the extra `headers` and parse the JSON reply
+pub fn get_json(url: &str, headers: &[(&str, String)]) -> Result<Value> {
+    let mut request = agent().get(url);
+    for (name, value) in headers {
+        request = request.set(name, value);
+    }
+    read_json(request.call())
+}
+
+/// Error responses are turned into the server's own message where it gives one
+fn read_json(result: Result<ureq::Response, ureq::Error>) -> Result<Value> {
+    match result {
+        Ok(response) => {
+            serde_json::from_reader(response.into_reader()).context("Invalid JSON response")
+        }
+        Err(ureq::Error::Status(code, response)) => {
+            let message = serde_json::from_reader::<_, Value>(response.into_reader())
+                .ok()
+                .and_then(|value| error_message(&value))
+                .unwrap_or_else(|| "no details".to_string());
+            let hint = if code == 401 || code == 403 {
+                " (check the API key)"
+            } else {
+                ""
+            };
+            Err(anyhow!("server returned {}{}: {}", code, hint, message))
+        }
+        Err(err) => Err(anyhow!("{}", err)),
+    }
+}
+
+/// OpenAI and Gemini nest the message under `error`; Ollama uses a plain string
+fn error_message(value: &Value) -> Option<String> {
+    value["error"]["message"]
+        .as_str()
+        .or_else(|| value["error"].as_str())
+        .map(str::to_string)
+}
@@ -1,6 +1,9 @@
-use std::fs::{self, File};
+use std::collections::VecDeque;
+use std::fs::{self, File, OpenOptions};
 use std::io::{Read, Write};
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
 
 use anyhow::{Context, Result, anyhow};
 use serde::{Deserialize, Serialize};
@@ -17,10 +20,72 @@ pub struct HuggingFaceModel {
     pub file: String,
 }
 
+/// A model file in the models directory
+#[derive(Debug, Clone)]
+pub struct DownloadedModel {
+    pub path: PathBuf,
+    pub name: String,
+    pub size: u64,
+    /// Checksum recorded in its `.meta.json`; `None` for untracked files
+    pub sha256: Option<String>,
+}
+
+/// A file offered by a Hugging Face repo
+#[derive(Debug, Clone)]
+pub struct RepoFile {
+    pub name: String,
+    pub size: Option<u64>,
+}
+
+/// Returned by [`ModelDownloader::download_with_progress`] when the download
+/// was cancelled; nothing is left behind on disk.
+#[derive(Debug)]
+pub struct DownloadCancelled;
+
+impl std::fmt::Display for DownloadCancelled {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Download cancelled")
+    }
+}
+
+impl std::error::Error for DownloadCancelled {}
+
+/// How often a download is retried after a network error
+const MAX_RETRIES: u32 = 3;
+/// Wait before the first retry; doubles for each one after that
+const RETRY_BASE_DELAY: Duration = Duration::from_secs(1);
+
+/// Result of a download attempt that made it to the end of the file
+struct Fetched {
+    hash_hex: String,
+    expected_hash: Option<String>,
+    downloaded: u64,
+    total: Option<u64>,
+}
+
+enum FetchError {
+    /// Network trouble that may go away by itself
+    Transient(anyhow::Error),
+    Fatal(anyhow::Error),
+}
+
+impl From<anyhow::Error> for FetchError {
+    fn from(err: anyhow::Error) -> Self {
+        FetchError::Fatal(err)
+    }
+}
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum DownloadPhase {
     Preparing,
     VerifyingExisting,
+    /// Picking up an interrupted download; `downloaded` is where it resumes
+    Resuming,
+    /// Waiting to retry after a network error
+    Retrying {
+        attempt: u32,
+        of: u32,
+    },
     Downloading,
     Finished,
 }
@@ -30,6 +95,44 @@ pub struct DownloadProgress {
     pub phase: DownloadPhase,
     pub downloaded: u64,
     pub total: Option<u64>,
+    /// Recent transfer rate in bytes per second, once there is enough to measure
+    pub speed_bps: Option<u64>,
+}
+
+/// How far back [`SpeedMeter`] looks when averaging the transfer rate
+const SPEED_WINDOW: Duration = Duration::from_secs(5);
+
+/// Transfer rate over a rolling window, so a stall shows up within seconds
+struct SpeedMeter {
+    samples: VecDeque<(Instant, u64)>,
+}
+
+impl SpeedMeter {
+    fn new() -> Self {
+        Self {
+            samples: VecDeque::new(),
+        }
+    }
+
+    /// Note that `downloaded` bytes had arrived in total at `at`.
+    fn record(&mut self, at: Instant, downloaded: u64) {
+        self.samples.push_back((at, downloaded));
+        // Keep one sample older than the window so it stays fully covered
+        while self.samples.len() > 2 && at.duration_since(self.samples[1].0) >= SPEED_WINDOW {
+            self.samples.pop_front();
+        }
+    }
+
+    fn bytes_per_second(&self) -> Option<u64> {
+        let (first_at, first) = *self.samples.front()?;
+        let (last_at, last) = *self.samples.back()?;
+        let elapsed = last_at.duration_since(first_at).as_secs_f64();
+        // Too short a span gives wild numbers
+        if elapsed < 0.5 {
+            return None;
+        }
+        Some((last.saturating_sub(first) as f64 / elapsed) as u64)
+    }
 }
 
 impl HuggingFaceModel {
@@ -94,17 +197,39 @@ impl HuggingFaceModel {
             .to_string()
     }
 
+    /// The `owner/repo` part of a model reference, which may omit the file.
+    pub fn repo_of(reference: &str) -> Option<String> {
+        let left = reference.trim().split([':', '@']).next()?;
+        let mut parts = left.split('/').filter(|part| !part.is_empty());
+        let (owner, repo) = (parts.next()?, parts.next()?);
+        Some(format!("{}/{}", owner, repo))
+    }
+
+    /// Every GGUF file in `repo`, in the order Hugging Face lists them.
+    pub fn list_gguf_files(repo: &str, token: Option<&str>) -> Result<Vec<RepoFile>> {
+        let info = fetch_model_info(repo, "main", token, "list repo files")?;
+        Ok(info
+            .siblings
+            .into_iter()
+            .filter(|sibling| sibling.rfilename.to_lowercase().ends_with(".gguf"))
+            .map(|sibling| RepoFile {
+                name: sibling.rfilename,
+                size: sibling.size,
+            })
+            .collect())
+    }
+
     fn needs_filename_resolution(&self) -> bool {
         !self.file.contains('/') && !self.file.contains('.')
     }
 
-    fn materialize_filename(&mut self) -> Result<()> {
+    fn materialize_filename(&mut self, token: Option<&str>) -> Result<()> {
         if !self.needs_filename_resolution() {
             return Ok(());
         }
 
         let alias = self.file.clone();
-        let resolved = resolve_hf_alias(&self.repo, &alias)?;
+        let resolved = resolve_hf_alias(&self.repo, &self.revision, &alias, token)?;
         log::info!(
             "Resolved Hugging Face alias '{}' -> '{}' for repo {}",
             alias,
@@ -119,33 +244,220 @@ impl HuggingFaceModel {
 #[derive(Clone, Debug)]
 pub struct ModelDownloader {
     models_dir: PathBuf,
+    /// Access token for gated or private repos
+    hf_token: Option<String>,
 }
 
 impl ModelDownloader {
     pub fn new(models_dir: PathBuf) -> Self {
-        Self { models_dir }
+        Self {
+            models_dir,
+            hf_token: None,
+        }
+    }
+
+    /// Authenticate Hugging Face requests with `token`; empty means anonymous.
+    pub fn set_hf_token(&mut self, token: &str) {
+        let token = token.trim();
+        self.hf_token = (!token.is_empty()).then(|| token.to_string());
+    }
+
+    pub fn models_dir(&self) -> &Path {
+        &self.models_dir
+    }
+
+    /// Make sure `dir` exists and new files can be written there.
+    pub fn ensure_writable(dir: &Path) -> Result<()> {
+        fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+        let probe = dir.join(".wispnote-write-test");
+        fs::write(&probe, b"").with_context(|| format!("{} is not writable", dir.display()))?;
+        let _ = fs::remove_file(&probe);
+        Ok(())
+    }
+
+    /// GGUF files in the models directory, sorted by name.
+    pub fn list_models(&self) -> Vec<PathBuf> {
+        let mut models: Vec<PathBuf> = fs::read_dir(&self.models_dir)
+            .map(|entries| {
+                entries
+                    .flatten()
+                    .map(|entry| entry.path())
+                    .filter(|path| {
+                        path.is_file()
+                            && path
+                                .extension()
+                                .is_some_and(|ext| ext.eq_ignore_ascii_case("gguf"))
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        models.sort();
+        models
+    }
+
+    /// Models in the models directory with their sizes and recorded checksums.
+    pub fn list_downloaded(&self) -> Vec<DownloadedModel> {
+        self.list_models()
+            .into_iter()
+            .filter_map(|path| {
+                let name = path.file_name()?.to_str()?.to_string();
+                let size = fs::metadata(&path).ok()?.len();
+                let sha256 = fs::read(self.metadata_path(&name))
+                    .ok()
+                    .and_then(|bytes| serde_json::from_slice::<DownloadMetadata>(&bytes).ok())
+                    .map(|metadata| metadata.sha256);
+                Some(DownloadedModel {
+                    path,
+                    name,
+                    size,
+                    sha256,
+                })
+            })
+            .collect()
+    }
+
+    /// Copy a GGUF file from elsewhere into the models directory and record its
+    /// checksum, so it's verified and listed like a downloaded model.
+    pub fn import_model(&self, source: &Path) -> Result<PathBuf> {
+        let filename = source
+            .file_name()
+            .and_then(|name| name.to_str())
+            .ok_or_else(|| anyhow!("Invalid model file name: {}", source.display()))?;
+        fs::create_dir_all(&self.models_dir).context("Failed to create models directory")?;
+        let output_path = self.models_dir.join(filename);
+        let metadata_path = self.metadata_path(filename);
+
+        // Already in place, only the metadata is missing
+        if output_path.exists() && fs::canonicalize(&output_path)? == fs::canonicalize(source)? {
+            let hash_hex = self.compute_sha256_with_progress(&output_path, None)?;
+            self.write_metadata(&metadata_path, &hash_hex, None, None)?;
+            return Ok(output_path);
+        }
+        if output_path.exists() {
+            anyhow::bail!("A model named {} is already in the models folder", filename);
+        }
+
+        let temp_path = output_path.with_extension("tmp");
+        let hash_hex = match copy_and_hash(source, &temp_path) {
+            Ok(hash_hex) => hash_hex,
+            Err(err) => {
+                let _ = fs::remove_file(&temp_path);
+                return Err(err);
+            }
+        };
+        fs::rename(&temp_path, &output_path).context("Failed to rename imported model")?;
+        self.write_metadata(&metadata_path, &hash_hex, None, None)?;
+        log::info!(
+            "Imported model {} as {}",
+            source.display(),
+            output_path.display()
+        );
+        Ok(output_path)
+    }
+
+    /// Link a GGUF file from elsewhere into the models directory and record its
+    /// checksum, so it's verified and listed like a downloaded model without
+    /// taking up the space twice.
+    pub fn register_model(&self, source: &Path) -> Result<PathBuf> {
+        let filename = source
+            .file_name()
+            .and_then(|name| name.to_str())
+            .ok_or_else(|| anyhow!("Invalid model file name: {}", source.display()))?;
+        fs::create_dir_all(&self.models_dir).context("Failed to create models directory")?;
+        let output_path = self.models_dir.join(filename);
+        let source = fs::canonicalize(source)
+            .with_context(|| format!("Failed to read {}", source.display()))?;
+
+        if fs::symlink_metadata(&output_path).is_err() {
+            std::os::unix::fs::symlink(&source, &output_path)
+                .with_context(|| format!("Failed to link {}", output_path.display()))?;
+        } else if fs::canonicalize(&output_path).ok() != Some(source.clone()) {
+            anyhow::bail!("A model named {} is already in the models folder", filename);
+        }
+        let hash_hex = self.compute_sha256_with_progress(&output_path, None)?;
+        self.write_metadata(&self.metadata_path(filename), &hash_hex, None, None)?;
+        log::info!(
+            "Registered model {} as {}",
+            source.display(),
+            output_path.display()
+        );
+        Ok(output_path)
+    }
+
+    /// Delete the model `name` and its metadata from the models directory.
+    pub fn delete_model(&self, name: &str) -> Result<()> {
+        let path = self.models_dir.join(name);
+        fs::remove_file(&path).with_context(|| format!("Failed to delete {}", path.display()))?;
+        let metadata_path = self.metadata_path(name);
+        if metadata_path.exists() {
+            fs::remove_file(&metadata_path)
+                .with_context(|| format!("Failed to delete {}", metadata_path.display()))?;
+        }
+        log::info!("Deleted model {}", path.display());
+        Ok(())
+    }
+
+    /// Move every model and its metadata into `dest`, copying when the two
+    /// directories are on different filesystems. Files already present in
+    /// `dest` are left alone. Returns how many models were moved.
+    pub fn move_models_to(&self, dest: &Path) -> Result<usize> {
+        fs::create_dir_all(dest).with_context(|| format!("Failed to create {}", dest.display()))?;
+        let mut moved = 0;
+        for model in self.list_models() {
+            let Some(filename) = model.file_name().and_then(|name| name.to_str()) else {
+                continue;
+            };
+            let metadata = self.metadata_path(filename);
+            let mut files = vec![model.clone()];
+            if metadata.exists() {
+                files.push(metadata);
+            }
+            for file in files {
+                let Some(name) = file.file_name() else {
+                    continue;
+                };
+                let target = dest.join(name);
+                if target.exists() {
+                    log::warn!("{} already exists, not moving", target.display());
+                    continue;
+                }
+                if fs::rename(&file, &target).is_err() {
+                    fs::copy(&file, &target).with_context(|| {
+                        format!("Failed to copy {} to {}", file.display(), target.display())
+                    })?;
+                    fs::remove_file(&file)
+                        .with_context(|| format!("Failed to remove {}", file.display()))?;
+                }
+            }
+            moved += 1;
+        }
+        Ok(moved)
     }
 
     /// Convenience wrapper that downloads without emitting UI progress.
     pub fn download(&self, model: &HuggingFaceModel) -> Result<PathBuf> {
-        self.download_with_progress(model, |_| {})
+        self.download_with_progress(model, &AtomicBool::new(false), |_| {})
     }
 
+    /// Download `model`, reporting progress as it goes. Setting `cancel` stops
+    /// the transfer, discards the partial file and returns [`DownloadCancelled`].
     pub fn download_with_progress<F>(
         &self,
         model: &HuggingFaceModel,
+        cancel: &AtomicBool,
         mut progress: F,
     ) -> Result<PathBuf>
     where
         F: FnMut(DownloadProgress),
     {
         let mut resolved = model.clone();
-        resolved.materialize_filename()?;
+        resolved.materialize_filename(self.hf_token.as_deref())?;
 
         progress(DownloadProgress {
             phase: DownloadPhase::Preparing,
             downloaded: 0,
             total: None,
+            speed_bps: None,
         });
 
         fs::create_dir_all(&self.models_dir).context("Failed to create models directory")?;
@@ -166,6 +478,7 @@ impl ModelDownloader {
                         phase: DownloadPhase::Finished,
                         downloaded: file_size,
                         total: Some(file_size),
+                        speed_bps: None,
                     });
                     log::info!(
                         "Model already downloaded with matching hash: {}",
@@ -185,21 +498,126 @@ impl ModelDownloader {
         }
 
         let url = resolved.download_url();
-        log::info!("Downloading model from: {}", url);
+        // Bytes kept from an earlier attempt that was interrupted
+        let temp_path = output_path.with_extension("tmp");
+        let lfs_sha256 = match lfs_sha256(&resolved, self.hf_token.as_deref()) {
+            Ok(hash) => hash,
+            Err(err) => {
+                log::warn!("Couldn't look up the LFS checksum: {err:#}");
+                None
+            }
+        };
 
-        // Use ureq for synchronous HTTP download
-        let response = ureq::get(&url)
-            .call()
-            .map_err(|e| anyhow!("Failed to download model: {}", e))?;
+        let mut retries = 0;
+        let Fetched {
+            hash_hex,
+            expected_hash,
+            downloaded: downloaded_bytes,
+            total: total_size,
+        } = loop {
+            match self.fetch(&resolved, &url, &temp_path, cancel, &mut progress) {
+                Ok(fetched) => break fetched,
+                Err(FetchError::Transient(err)) if retries < MAX_RETRIES => {
+                    retries += 1;
+                    let delay = RETRY_BASE_DELAY * 2u32.pow(retries - 1);
+                    log::warn!(
+                        "Download failed ({:#}), retry {}/{} in {:?}",
+                        err,
+                        retries,
+                        MAX_RETRIES,
+                        delay
+                    );
+                    progress(DownloadProgress {
+                        phase: DownloadPhase::Retrying {
+                            attempt: retries,
+                            of: MAX_RETRIES,
+                        },
+                        downloaded: fs::metadata(&temp_path).map(|m| m.len()).unwrap_or(0),
+                        total: None,
+                        speed_bps: None,
+                    });
+                    if sleep_unless_cancelled(delay, cancel) {
+                        let _ = fs::remove_file(&temp_path);
+                        return Err(DownloadCancelled.into());
+                    }
+                }
+                Err(FetchError::Transient(err) | FetchError::Fatal(err)) => return Err(err),
+            }
+        };
+
+        // The LFS pointer has the content hash; the headers aren't always sent
+        match lfs_sha256.as_ref().or(expected_hash.as_ref()) {
+            Some(expected) if expected != &hash_hex => {
+                let _ = fs::remove_file(&temp_path);
+                anyhow::bail!("Hash mismatch: expected {}, got {}", expected, hash_hex);
+            }
+            Some(_) => log::info!("Verified {} (sha256 {})", filename, hash_hex),
+            None => log::warn!(
+                "No checksum published for {}; skipped verifying the download",
+                filename
+            ),
+        }
+
+        // Atomic rename
+        fs::rename(&temp_path, &output_path).context("Failed to rename downloaded model")?;
+
+        self.write_metadata(
+            &metadata_path,
+            &hash_hex,
+            expected_hash.as_deref(),
+            lfs_sha256.as_deref(),
+        )?;
+
+        let final_total = total_size.or(Some(downloaded_bytes));
+        progress(DownloadProgress {
+            phase: DownloadPhase::Finished,
+            downloaded: downloaded_bytes,
+            total: final_total,
+            speed_bps: None,
+        });
+
+        log::info!("Model downloaded to: {}", output_path.display());
+        Ok(output_path)
+    }
+
+    /// One attempt at streaming `url` into `temp_path`, continuing after
+    /// whatever an earlier attempt left there.
+    fn fetch<F>(
+        &self,
+        model: &HuggingFaceModel,
+        url: &str,
+        temp_path: &Path,
+        cancel: &AtomicBool,
+        progress: &mut F,
+    ) -> Result<Fetched, FetchError>
+    where
+        F: FnMut(DownloadProgress),
+    {
+        // Bytes kept from an earlier attempt that was interrupted
+        let partial_len = fs::metadata(temp_path).map(|m| m.len()).unwrap_or(0);
+
+        log::info!("Downloading model from: {}", url);
+        let (response, resume_from) = request_download(url, self.hf_token.as_deref(), partial_len)
+            .map_err(|e| {
+                let transient = is_transient(&e);
+                let err = request_error(e, &model.repo, "download model");
+                if transient {
+                    FetchError::Transient(err)
+                } else {
+                    FetchError::Fatal(err)
+                }
+            })?;
 
         let expected_hash = response
             .header("x-linked-etag")
             .or_else(|| response.header("x-xet-hash"))
             .map(|value| value.trim_matches('"').to_lowercase());
 
+        // A partial response only counts the remaining bytes
         let total_size = response
             .header("content-length")
-            .and_then(|s| s.parse::<u64>().ok());
+            .and_then(|s| s.parse::<u64>().ok())
+            .map(|len| len + resume_from);
 
         log::info!(
             "Download size: {}",
@@ -209,24 +627,50 @@ impl ModelDownloader {
         );
 
         // Write to temp file first, then rename atomically
-        let temp_path = output_path.with_extension("tmp");
-        let mut file = File::create(&temp_path).context("Failed to create temp file")?;
+        let mut hasher = Sha256::new();
+        let mut file = if resume_from > 0 {
+            log::info!("Resuming download at {} bytes", resume_from);
+            progress(DownloadProgress {
+                phase: DownloadPhase::Resuming,
+                downloaded: resume_from,
+                total: total_size,
+                speed_bps: None,
+            });
+            // The hash covers the whole file, so feed it what's already on disk
+            let mut existing = File::open(temp_path).context("Failed to open partial download")?;
+            std::io::copy(&mut existing, &mut hasher).context("Failed to read partial download")?;
+            OpenOptions::new()
+                .append(true)
+                .open(temp_path)
+                .context("Failed to open partial download")?
+        } else {
+            File::create(temp_path).context("Failed to create temp file")?
+        };
 
         let mut reader = response.into_reader();
-        let mut hasher = Sha256::new();
         let mut buffer = [0u8; 1024 * 64];
-        let mut downloaded_bytes: u64 = 0;
+        let mut downloaded_bytes: u64 = resume_from;
+        let mut speed = SpeedMeter::new();
+        speed.record(Instant::now(), downloaded_bytes);
 
         progress(DownloadProgress {
             phase: DownloadPhase::Downloading,
-            downloaded: 0,
+            downloaded: downloaded_bytes,
             total: total_size,
+            speed_bps: None,
         });
 
         loop {
+            if cancel.load(Ordering::Relaxed) {
+                drop(file);
+                let _ = fs::remove_file(temp_path);
+                log::info!("Download of {} cancelled", model.filename());
+                return Err(FetchError::Fatal(DownloadCancelled.into()));
+            }
             let read = reader
                 .read(&mut buffer)
-                .context("Failed to read model bytes")?;
+                .context("Failed to read model bytes")
+                .map_err(FetchError::Transient)?;
             if read == 0 {
                 break;
             }
@@ -234,41 +678,26 @@ impl ModelDownloader {
                 .context("Failed to write model file")?;
             hasher.update(&buffer[..read]);
             downloaded_bytes += read as u64;
+            speed.record(Instant::now(), downloaded_bytes);
             progress(DownloadProgress {
                 phase: DownloadPhase::Downloading,
                 downloaded: downloaded_bytes,
                 total: total_size,
+                speed_bps: speed.bytes_per_second(),
             });
         }
-        let hash_hex = format!("{:x}", hasher.finalize());
-
-        if let Some(ref expected) = expected_hash {
-            if expected != &hash_hex {
-                let _ = fs::remove_file(&temp_path);
-                anyhow::bail!("Hash mismatch: expected {}, got {}", expected, hash_hex);
-            }
-        }
-
-        // Atomic rename
-        fs::rename(&temp_path, &output_path).context("Failed to rename downloaded model")?;
-
-        self.write_metadata(&metadata_path, &hash_hex, expected_hash.as_deref())?;
-
-        let final_total = total_size.or(Some(downloaded_bytes));
-        progress(DownloadProgress {
-            phase: DownloadPhase::Finished,
+        Ok(Fetched {
+            hash_hex: format!("{:x}", hasher.finalize()),
+            expected_hash,
             downloaded: downloaded_bytes,
-            total: final_total,
-        });
-
-        log::info!("Model downloaded to: {}", output_path.display());
-        Ok(output_path)
+            total: total_size,
+        })
     }
 
     /// Lightweight existence check used for readiness/UI; does not hash.
     pub fn path_exists(&self, model: &HuggingFaceModel) -> Option<PathBuf> {
         let mut resolved = model.clone();
-        if let Err(err) = resolved.materialize_filename() {
+        if let Err(err) = resolved.materialize_filename(self.hf_token.as_deref()) {
             log::warn!(
                 "Failed to resolve Hugging Face alias for {}: {}",
                 model.repo,
@@ -290,7 +719,7 @@ impl ModelDownloader {
     /// Get path to a model if it's downloaded, verifying hash matches metadata
     pub fn get_path(&self, model: &HuggingFaceModel) -> Option<PathBuf> {
         let mut resolved = model.clone();
-        if let Err(err) = resolved.materialize_filename() {
+        if let Err(err) = resolved.materialize_filename(self.hf_token.as_deref()) {
             log::warn!(
                 "Failed to resolve Hugging Face alias for {}: {}",
                 model.repo,
@@ -323,16 +752,146 @@ struct ModelInfo {
 #[derive(Deserialize)]
 struct ModelSibling {
     rfilename: String,
+    /// Only reported when asking for `blobs=true`, like `lfs`
+    #[serde(default)]
+    size: Option<u64>,
+    #[serde(default)]
+    lfs: Option<LfsPointer>,
 }
 
-fn resolve_hf_alias(repo: &str, alias: &str) -> Result<String> {
-    let url = format!("https://huggingface.co/api/models/{}", repo);
-    let response = ureq::get(&url)
+#[derive(Deserialize)]
+struct LfsPointer {
+    sha256: String,
+}
+
+/// Repo metadata at `revision`, including file sizes and LFS checksums
+fn fetch_model_info(
+    repo: &str,
+    revision: &str,
+    token: Option<&str>,
+    action: &str,
+) -> Result<ModelInfo> {
+    let url = format!(
+        "https://huggingface.co/api/models/{}/revision/{}?blobs=true",
+        repo, revision
+    );
+    let response = hf_get(&url, token)
         .call()
-        .map_err(|e| anyhow!("Failed to resolve alias '{}': {}", alias, e))?;
+        .map_err(|e| request_error(e, repo, action))?;
+    from_reader(response.into_reader())
+        .map_err(|e| anyhow!("Failed to parse model metadata for {}: {}", repo, e))
+}
 
-    let info: ModelInfo = from_reader(response.into_reader())
-        .map_err(|e| anyhow!("Failed to parse model metadata for {}: {}", repo, e))?;
+/// Copy `source` to `dest`, returning the SHA256 of what was copied
+fn copy_and_hash(source: &Path, dest: &Path) -> Result<String> {
+    let mut reader =
+        File::open(source).with_context(|| format!("Failed to open {}", source.display()))?;
+    let mut file = File::create(dest).context("Failed to create temp file")?;
+    let mut hasher = Sha256::new();
+    let mut buffer = [0u8; 1024 * 64];
+    loop {
+        let read = reader
+            .read(&mut buffer)
+            .context("Failed to read model file")?;
+        if read == 0 {
+            break;
+        }
+        file.write_all(&buffer[..read])
+            .context("Failed to write model file")?;
+        hasher.update(&buffer[..read]);
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// GET request to Hugging Face, authorized when a token is configured
+fn hf_get(url: &str, token: Option<&str>) -> ureq::Request {
+    let request = ureq::get(url);
+    match token {
+        Some(token) => request.set("Authorization", &format!("Bearer {}", token)),
+        None => request,
+    }
+}
+
+/// Explain failed requests; gated and private repos answer 401/403
+fn request_error(err: ureq::Error, repo: &str, action: &str) -> anyhow::Error {
+    match err {
+        ureq::Error::Status(401 | 403, _) => anyhow!(
+            "{} is gated or private, and the Hugging Face token is missing or doesn't have \
+             access to it. Request access on huggingface.co and set a token in Preferences.",
+            repo
+        ),
+        err => anyhow!("Failed to {}: {}", action, err),
+    }
+}
+
+/// Server hiccups and connection problems are worth another try; other
+/// statuses (missing file, no access) won't change by retrying.
+fn is_transient(err: &ureq::Error) -> bool {
+    match err {
+        ureq::Error::Status(code, _) => *code == 429 || *code >= 500,
+        ureq::Error::Transport(_) => true,
+    }
+}
+
+/// Sleep for `delay`, waking early if `cancel` gets set. Returns whether it did.
+fn sleep_unless_cancelled(delay: Duration, cancel: &AtomicBool) -> bool {
+    let deadline = Instant::now() + delay;
+    while Instant::now() < deadline {
+        if cancel.load(Ordering::Relaxed) {
+            return true;
+        }
+        std::thread::sleep(Duration::from_millis(100));
+    }
+    cancel.load(Ordering::Relaxed)
+}
+
+/// Start downloading `url`, continuing after the first `offset` bytes when the
+/// server supports range requests. Returns the response and the offset it
+/// actually continues from, which is 0 when the download has to restart.
+fn request_download(
+    url: &str,
+    token: Option<&str>,
+    offset: u64,
+) -> Result<(ureq::Response, u64), ureq::Error> {
+    if offset > 0 {
+        match hf_get(url, token)
+            .set("Range", &format!("bytes={}-", offset))
+            .call()
+        {
+            Ok(response) if response.status() == 206 => return Ok((response, offset)),
+            Ok(response) => {
+                log::info!("Server doesn't support resuming, restarting download");
+                return Ok((response, 0));
+            }
+            // The partial file is at least as long as the model; start over
+            Err(ureq::Error::Status(416, _)) => {
+                log::info!("Partial download doesn't match the model, restarting");
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    Ok((hf_get(url, token).call()?, 0))
+}
+
+/// SHA256 of `model`'s file from its Git LFS pointer; `None` for files
+/// stored outside LFS.
+fn lfs_sha256(model: &HuggingFaceModel, token: Option<&str>) -> Result<Option<String>> {
+    let info = fetch_model_info(&model.repo, &model.revision, token, "look up checksum")?;
+    Ok(info
+        .siblings
+        .into_iter()
+        .find(|sibling| sibling.rfilename == model.file)
+        .and_then(|sibling| sibling.lfs)
+        .map(|lfs| lfs.sha256.to_lowercase()))
+}
+
+fn resolve_hf_alias(
+    repo: &str,
+    revision: &str,
+    alias: &str,
+    token: Option<&str>,
+) -> Result<String> {
+    let info = fetch_model_info(repo, revision, token, &format!("resolve alias '{}'", alias))?;
 
     let alias_lower = alias.to_lowercase();
 
@@ -369,6 +928,9 @@ fn resolve_hf_alias(repo: &str, alias: &str) -> Result<String> {
 struct DownloadMetadata {
     sha256: String,
     etag: Option<String>,
+    /// Checksum from the repo's Git LFS pointer, when it has one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    lfs_sha256: Option<String>,
 }
 
 impl ModelDownloader {
@@ -381,10 +943,12 @@ impl ModelDownloader {
         metadata_path: &Path,
         sha256_hex: &str,
         etag: Option<&str>,
+        lfs_sha256: Option<&str>,
     ) -> Result<()> {
         let metadata = DownloadMetadata {
             sha256: sha256_hex.to_string(),
             etag: etag.map(|s| s.to_string()),
+            lfs_sha256: lfs_sha256.map(|s| s.to_string()),
         };
         let json = serde_json::to_string_pretty(&metadata)?;
         fs::write(metadata_path, json)
@@ -447,6 +1011,7 @@ impl ModelDownloader {
                 phase: DownloadPhase::VerifyingExisting,
                 downloaded: processed,
                 total: Some(file_size),
+                speed_bps: None,
             });
         }
 
@@ -462,6 +1027,7 @@ impl ModelDownloader {
                     phase: DownloadPhase::VerifyingExisting,
                     downloaded: processed,
                     total: Some(file_size),
+                    speed_bps: None,
                 });
             }
         }
@@ -484,6 +1050,40 @@ mod tests {
         assert_eq!(model.filename(), "file.gguf");
     }
 
+    #[test]
+    fn test_repo_of_reference() {
+        assert_eq!(
+            HuggingFaceModel::repo_of("owner/repo").as_deref(),
+            Some("owner/repo")
+        );
+        assert_eq!(
+            HuggingFaceModel::repo_of("owner/repo@dev:Q4_K_M").as_deref(),
+            Some("owner/repo")
+        );
+        assert_eq!(
+            HuggingFaceModel::repo_of(" owner/repo/path/file.gguf ").as_deref(),
+            Some("owner/repo")
+        );
+        assert_eq!(HuggingFaceModel::repo_of("owner"), None);
+    }
+
+    #[test]
+    fn test_speed_meter_uses_recent_window() {
+        let start = Instant::now();
+        let mut meter = SpeedMeter::new();
+        meter.record(start, 0);
+        assert_eq!(meter.bytes_per_second(), None);
+        // Fast at first, then 1000 B/s for the last ten seconds
+        meter.record(start + Duration::from_secs(1), 1_000_000);
+        for secs in 2..=11 {
+            meter.record(
+                start + Duration::from_secs(secs),
+                1_000_000 + (secs - 1) * 1000,
+            );
+        }
+        assert_eq!(meter.bytes_per_second(), Some(1000));
+    }
+
     #[test]
     fn test_parse_hf_model_with_path() {
         let model = HuggingFaceModel::parse("owner/repo/path/to/file.gguf").unwrap();
@@ -525,12 +1125,95 @@ mod tests {
         let sha = downloader.compute_sha256(&file_path).unwrap();
         let metadata_path = downloader.metadata_path("file.gguf");
         downloader
-            .write_metadata(&metadata_path, &sha, Some("etag"))
+            .write_metadata(&metadata_path, &sha, Some("etag"), None)
             .unwrap();
 
         assert!(downloader.is_downloaded(&model));
     }
 
+    #[test]
+    fn test_move_models_keeps_metadata() {
+        let from = tempdir().unwrap();
+        let to = tempdir().unwrap();
+        let downloader = ModelDownloader::new(from.path().to_path_buf());
+        fs::write(from.path().join("a.gguf"), b"model").unwrap();
+        fs::write(from.path().join("a.gguf.meta.json"), b"{}").unwrap();
+        fs::write(from.path().join("notes.txt"), b"not a model").unwrap();
+
+        assert_eq!(downloader.move_models_to(to.path()).unwrap(), 1);
+        assert!(to.path().join("a.gguf").exists());
+        assert!(to.path().join("a.gguf.meta.json").exists());
+        assert!(from.path().join("notes.txt").exists());
+        assert!(downloader.list_models().is_empty());
+        assert_eq!(
+            ModelDownloader::new(to.path().to_path_buf()).list_models(),
+            vec![to.path().join("a.gguf")]
+        );
+    }
+
+    #[test]
+    fn test_list_and_delete_downloaded() {
+        let dir = tempdir().unwrap();
+        let downloader = ModelDownloader::new(dir.path().to_path_buf());
+        fs::write(dir.path().join("a.gguf"), b"model").unwrap();
+        downloader
+            .write_metadata(&dir.path().join("a.gguf.meta.json"), "abc", None, None)
+            .unwrap();
+        fs::write(dir.path().join("b.gguf"), b"untracked").unwrap();
+        fs::write(dir.path().join("notes.txt"), b"not a model").unwrap();
+
+        let models = downloader.list_downloaded();
+        assert_eq!(models.len(), 2);
+        assert_eq!(models[0].name, "a.gguf");
+        assert_eq!(models[0].size, 5);
+        assert_eq!(models[0].sha256.as_deref(), Some("abc"));
+        assert_eq!(models[1].sha256, None);
+
+        downloader.delete_model("a.gguf").unwrap();
+        assert!(!dir.path().join("a.gguf").exists());
+        assert!(!dir.path().join("a.gguf.meta.json").exists());
+        assert_eq!(downloader.list_downloaded().len(), 1);
+    }
+
+    #[test]
+    fn test_import_model_records_checksum() {
+        let from = tempdir().unwrap();
+        let models = tempdir().unwrap();
+        let downloader = ModelDownloader::new(models.path().to_path_buf());
+        let source = from.path().join("local.gguf");
+        fs::write(&source, b"model").unwrap();
+
+        let imported = downloader.import_model(&source).unwrap();
+        assert_eq!(imported, models.path().join("local.gguf"));
+        assert!(source.exists());
+        let listed = downloader.list_downloaded();
+        assert_eq!(
+            listed[0].sha256.as_deref(),
+            Some(downloader.compute_sha256(&imported).unwrap().as_str())
+        );
+        assert!(downloader.import_model(&source).is_err());
+    }
+
+    #[test]
+    fn test_register_model_links_in_place() {
+        let from = tempdir().unwrap();
+        let models = tempdir().unwrap();
+        let downloader = ModelDownloader::new(models.path().to_path_buf());
+        let source = from.path().join("local.gguf");
+        fs::write(&source, b"model").unwrap();
+
+        let registered = downloader.register_model(&source).unwrap();
+        assert!(fs::symlink_metadata(&registered).unwrap().is_symlink());
+        let listed = downloader.list_downloaded();
+        assert_eq!(listed.len(), 1);
+        assert!(listed[0].sha256.is_some());
+        // Registering again only refreshes the metadata
+        assert_eq!(downloader.register_model(&source).unwrap(), registered);
+
+        downloader.delete_model("local.gguf").unwrap();
+        assert!(source.exists());
+    }
+
     #[test]
     fn test_download_url() {
         let model = HuggingFaceModel::parse("mradermacher/Luau-Qwen3-4B:Q4_K_M.gguf").unwrap();
@@ -1,12 +1,47 @@
 use anyhow::{Result, anyhow};
+use llama_cpp_2::context::LlamaContext;
 use llama_cpp_2::context::params::LlamaContextParams;
 use llama_cpp_2::llama_backend::LlamaBackend;
 use llama_cpp_2::llama_batch::LlamaBatch;
 use llama_cpp_2::model::params::LlamaModelParams;
 use llama_cpp_2::model::{AddBos, LlamaModel, Special};
 use llama_cpp_2::sampling::LlamaSampler;
+use llama_cpp_2::token::LlamaToken;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::mem::ManuallyDrop;
 use std::path::{Path, PathBuf};
-use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use super::context::{FimMarkers, FimTemplate};
+
+/// GGUF versions llama.cpp reads
+const GGUF_VERSIONS: std::ops::RangeInclusive<u32> = 1..=3;
+
+/// Fail early on files llama.cpp can't load whatever the settings: missing,
+/// unreadable, or not GGUF at all. Anything else failing is down to how the
+/// model was loaded.
+pub fn check_model_file(model_path: &Path) -> Result<()> {
+    use std::io::Read;
+
+    if !model_path.exists() {
+        return Err(anyhow!(
+            "Model file does not exist: {}",
+            model_path.display()
+        ));
+    }
+    let mut header = [0u8; 8];
+    std::fs::File::open(model_path)
+        .and_then(|mut file| file.read_exact(&mut header))
+        .map_err(|e| anyhow!("Failed to read model file {}: {}", model_path.display(), e))?;
+    let version = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+    if &header[..4] != b"GGUF" || !GGUF_VERSIONS.contains(&version) {
+        return Err(anyhow!("Not a GGUF model file: {}", model_path.display()));
+    }
+    Ok(())
+}
 
 /// Wrapper for llama.cpp library with in-process inference
 pub struct LlamaCpp {
@@ -31,12 +66,7 @@ impl LlamaCpp {
         n_gpu_layers: Option<i32>,
         main_gpu: Option<i32>,
     ) -> Result<LoadedModel> {
-        if !model_path.exists() {
-            return Err(anyhow!(
-                "Model file does not exist: {}",
-                model_path.display()
-            ));
-        }
+        check_model_file(model_path)?;
 
         let mut params = LlamaModelParams::default();
 
@@ -60,6 +90,7 @@ impl LlamaCpp {
         log::info!("Model loaded successfully");
 
         Ok(LoadedModel {
+            context: Mutex::new(None),
             backend: Arc::clone(&self.backend),
             model: Arc::new(model),
             source_path: model_path.to_path_buf(),
@@ -67,27 +98,306 @@ impl LlamaCpp {
     }
 }
 
+/// Sentinels filtered from plain (non-FIM) prompts, whose family is unknown
+const DEFAULT_SENTINELS: &[&str] = &["<|fim_", "<|file_sep|>", "<｜fim"];
+
+/// Sampler settings for one completion
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SamplingParams {
+    /// 0 means greedy decoding
+    pub temperature: f32,
+    /// Nucleus sampling threshold; 1.0 disables it
+    pub top_p: f32,
+    /// Candidates kept before sampling; 0 disables it
+    pub top_k: i32,
+    /// Discourages the model from looping on recent tokens
+    pub penalties: Option<RepeatPenalty>,
+    /// Fixed seed for reproducible output; `None` picks a random one
+    pub seed: Option<u64>,
+}
+
+/// Penalties applied to tokens that already appeared in the recent output
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RepeatPenalty {
+    /// How many recent tokens are considered
+    pub last_n: i32,
+    /// Divides the likelihood of repeated tokens; 1.0 disables it
+    pub repeat: f32,
+    /// Subtracted once per earlier occurrence
+    pub frequency: f32,
+    /// Subtracted once if the token occurred at all
+    pub presence: f32,
+}
+
+impl SamplingParams {
+    /// Always pick the most likely token
+    pub fn greedy() -> Self {
+        Self {
+            temperature: 0.0,
+            top_p: 1.0,
+            top_k: 0,
+            penalties: None,
+            seed: None,
+        }
+    }
+
+    fn sampler(&self) -> LlamaSampler {
+        let mut samplers = Vec::new();
+        // Penalties go first so they reshape the logits before anything picks a token
+        if let Some(penalty) = self.penalties {
+            samplers.push(LlamaSampler::penalties(
+                penalty.last_n,
+                penalty.repeat,
+                penalty.frequency,
+                penalty.presence,
+            ));
+        }
+        if self.temperature <= 0.0 {
+            samplers.push(LlamaSampler::greedy());
+        } else {
+            samplers.extend([
+                LlamaSampler::top_k(self.top_k),
+                LlamaSampler::top_p(self.top_p, 1),
+                LlamaSampler::temp(self.temperature),
+                LlamaSampler::dist(self.dist_seed()),
+            ]);
+        }
+        LlamaSampler::chain_simple(samplers)
+    }
+
+    /// Seed for the distribution sampler. llama.cpp seeds are 32 bits, so
+    /// larger configured seeds are folded down.
+    fn dist_seed(&self) -> u32 {
+        match self.seed {
+            Some(seed) => (seed ^ (seed >> 32)) as u32,
+            None => {
+                // Picked here rather than by llama.cpp so it can be logged
+                // and reproduced later
+                let seed = RandomState::new().build_hasher().finish() as u32;
+                log::info!("Sampling with random seed {}", seed);
+                seed
+            }
+        }
+    }
+}
+
+/// How long a completion took, split into prompt processing and generation
+#[derive(Debug, Clone, Copy)]
+pub struct GenerationTiming {
+    pub prompt: Duration,
+    pub generation: Duration,
+    pub tokens: usize,
+}
+
+impl GenerationTiming {
+    pub fn tokens_per_second(&self) -> Option<f64> {
+        if self.tokens == 0 || self.generation.is_zero() {
+            None
+        } else {
+            Some(self.tokens as f64 / self.generation.as_secs_f64())
+        }
+    }
+}
+
 /// A loaded model ready for inference
 pub struct LoadedModel {
+    /// Context kept between completions so a shared prompt prefix isn't
+    /// decoded again
+    context: Mutex<Option<ReusableContext>>,
     backend: Arc<LlamaBackend>,
     model: Arc<LlamaModel>,
     pub source_path: PathBuf,
 }
 
+/// GGUF metadata of a loaded model
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModelInfo {
+    pub name: Option<String>,
+    pub architecture: Option<String>,
+    pub parameters: u64,
+    /// Number of transformer layers, the most that can be offloaded
+    pub layers: u32,
+    /// Context length the model was trained with
+    pub context_length: u32,
+    pub quantization: Option<String>,
+    pub size_bytes: u64,
+}
+
+impl ModelInfo {
+    /// One line for the preferences, e.g. "llama · 1.3B parameters · Q4_K_M · 16384-token context"
+    pub fn summary(&self) -> String {
+        let mut parts = Vec::new();
+        if let Some(architecture) = &self.architecture {
+            parts.push(architecture.clone());
+        }
+        if self.parameters > 0 {
+            parts.push(format!("{} parameters", format_parameters(self.parameters)));
+        }
+        if let Some(quantization) = &self.quantization {
+            parts.push(quantization.clone());
+        }
+        if self.context_length > 0 {
+            parts.push(format!("{}-token context", self.context_length));
+        }
+        parts.join(" · ")
+    }
+}
+
+fn format_parameters(count: u64) -> String {
+    match count {
+        0..1_000_000 => count.to_string(),
+        1_000_000..1_000_000_000 => format!("{}M", count / 1_000_000),
+        _ => format!("{:.1}B", count as f64 / 1e9),
+    }
+}
+
+/// Name of a `general.file_type` value, following llama.cpp's `llama_ftype`
+fn quantization_name(file_type: u32) -> Option<&'static str> {
+    Some(match file_type {
+        0 => "F32",
+        1 => "F16",
+        2 => "Q4_0",
+        3 => "Q4_1",
+        7 => "Q8_0",
+        8 => "Q5_0",
+        9 => "Q5_1",
+        10 => "Q2_K",
+        11 => "Q3_K_S",
+        12 => "Q3_K_M",
+        13 => "Q3_K_L",
+        14 => "Q4_K_S",
+        15 => "Q4_K_M",
+        16 => "Q5_K_S",
+        17 => "Q5_K_M",
+        18 => "Q6_K",
+        19 => "IQ2_XXS",
+        20 => "IQ2_XS",
+        21 => "Q2_K_S",
+        22 => "IQ3_XS",
+        23 => "IQ3_XXS",
+        24 => "IQ1_S",
+        25 => "IQ4_NL",
+        26 => "IQ3_S",
+        27 => "IQ3_M",
+        28 => "IQ2_S",
+        29 => "IQ2_M",
+        30 => "IQ4_XS",
+        31 => "IQ1_M",
+        32 => "BF16",
+        _ => return None,
+    })
+}
+
+/// Quantization named in a file like `model.Q4_K_M.gguf`, for files whose
+/// metadata doesn't record it
+fn quantization_from_file_name(path: &Path) -> Option<String> {
+    let stem = path.file_stem()?.to_str()?;
+    stem.rsplit(['.', '-'])
+        .map(str::to_ascii_uppercase)
+        .find(|part| {
+            let level = part.strip_prefix("IQ").or_else(|| part.strip_prefix('Q'));
+            level.is_some_and(|rest| rest.starts_with(|c: char| c.is_ascii_digit()))
+                || matches!(part.as_str(), "F16" | "BF16" | "F32")
+        })
+}
+
+/// A context together with the tokens its KV cache holds. It owns a handle
+/// on the model the context borrows, so it can be stored or moved anywhere.
+struct ReusableContext {
+    /// Borrows `_model`; dropped by hand in `Drop` so it always goes first
+    ctx: ManuallyDrop<LlamaContext<'static>>,
+    _model: Arc<LlamaModel>,
+    n_ctx: u32,
+    /// Tokens decoded into the KV cache, in position order
+    decoded: Vec<LlamaToken>,
+}
+
+impl Drop for ReusableContext {
+    fn drop(&mut self) {
+        // SAFETY: `ctx` isn't used again, and `_model` outlives it because
+        // fields are only dropped after this returns
+        unsafe { ManuallyDrop::drop(&mut self.ctx) };
+    }
+}
+
+// SAFETY: the context is only used by whoever holds the `LoadedModel::context`
+// lock, never from two threads at once
+unsafe impl Send for ReusableContext {}
+
+fn common_prefix_len(a: &[LlamaToken], b: &[LlamaToken]) -> usize {
+    a.iter().zip(b).take_while(|(a, b)| a == b).count()
+}
+
 impl LoadedModel {
     /// Run inference with the loaded model
-    pub fn complete(&self, prompt: &str, max_tokens: usize, temperature: f32) -> Result<String> {
-        // Create context
-        let ctx_params = LlamaContextParams::default().with_n_ctx(std::num::NonZeroU32::new(2048));
+    pub fn complete(
+        &self,
+        prompt: &str,
+        max_tokens: usize,
+        n_ctx: u32,
+        sampling: &SamplingParams,
+    ) -> Result<String> {
+        self.complete_timed(prompt, max_tokens, n_ctx, sampling)
+            .map(|(text, _)| text)
+    }
 
-        let mut ctx = self
-            .model
-            .new_context(&self.backend, ctx_params)
-            .map_err(|e| anyhow!("Failed to create context: {:?}", e))?;
+    /// Like `complete`, also reporting how long each phase took
+    pub fn complete_timed(
+        &self,
+        prompt: &str,
+        max_tokens: usize,
+        n_ctx: u32,
+        sampling: &SamplingParams,
+    ) -> Result<(String, GenerationTiming)> {
+        self.complete_cancellable(prompt, max_tokens, n_ctx, sampling, &AtomicBool::new(false))
+    }
+
+    /// Like `complete_timed`, giving up with an error as soon as `cancel` is set
+    pub fn complete_cancellable(
+        &self,
+        prompt: &str,
+        max_tokens: usize,
+        n_ctx: u32,
+        sampling: &SamplingParams,
+        cancel: &AtomicBool,
+    ) -> Result<(String, GenerationTiming)> {
+        self.complete_streaming(prompt, max_tokens, n_ctx, sampling, cancel, |_| true)
+    }
+
+    /// Like `complete_cancellable`, calling `on_piece` with each decoded piece as
+    /// it is generated. Returning false from `on_piece` ends generation early
+    /// with the text produced so far.
+    pub fn complete_streaming(
+        &self,
+        prompt: &str,
+        max_tokens: usize,
+        n_ctx: u32,
+        sampling: &SamplingParams,
+        cancel: &AtomicBool,
+        mut on_piece: impl FnMut(&str) -> bool,
+    ) -> Result<(String, GenerationTiming)> {
+        let started = Instant::now();
+        // A window past what the model was trained on only wastes memory
+        let n_ctx = match self.model.n_ctx_train() {
+            0 => n_ctx,
+            trained => n_ctx.min(trained),
+        };
+        // Take the context left by the previous completion. It only goes back
+        // after a successful run, so a failure never leaves a half-updated cache.
+        let mut cached = self.context.lock().unwrap().take();
+        if cached.as_ref().is_some_and(|cached| cached.n_ctx != n_ctx) {
+            cached = None;
+        }
+        let mut cached = match cached {
+            Some(cached) => cached,
+            None => self.new_reusable_context(n_ctx)?,
+        };
+        let ctx: &mut LlamaContext = &mut cached.ctx;
 
         // Tokenize prompt - llama-cpp-2's str_to_token has parse_special=true,
         // so special tokens like FIM markers will be parsed correctly
-        let tokens = self
+        let fim = FimTemplate::detect(prompt);
+        let mut tokens = self
             .model
             .str_to_token(prompt, AddBos::Always)
             .map_err(|e| anyhow!("Failed to tokenize prompt: {:?}", e))?;
@@ -97,20 +407,36 @@ impl LoadedModel {
         }
 
         let n_ctx = ctx.n_ctx() as usize;
+        // Leave room for the generated tokens; long prefixes lose their oldest text
+        let budget = n_ctx.saturating_sub(max_tokens);
+        if tokens.len() > budget {
+            let markers = fim.and_then(FimTemplate::markers);
+            self.trim_prompt_prefix(&mut tokens, budget, markers)
+                .map_err(|_| {
+                    // Only reached when the suffix and markers alone overflow the window
+                    anyhow!(
+                        "Prompt too long: {} tokens don't fit a {}-token context even after \
+                     trimming the text before the cursor; raise the context window in Preferences",
+                        tokens.len(),
+                        n_ctx
+                    )
+                })?;
+        }
         let n_prompt = tokens.len();
 
-        if n_prompt >= n_ctx {
-            return Err(anyhow!(
-                "Prompt too long: {} tokens exceeds context size {}",
-                n_prompt,
-                n_ctx
-            ));
-        }
+        // Tokens up to the first difference are already in the KV cache. Edits
+        // or cursor jumps before the reused region shorten the match, so stale
+        // entries are never used. At least one token is decoded to get logits.
+        let reused = common_prefix_len(&cached.decoded, &tokens).min(n_prompt - 1);
+        cached.decoded.truncate(reused);
+        let ctx: &mut LlamaContext = &mut cached.ctx;
+        ctx.clear_kv_cache_seq(Some(0), Some(reused as u32), None)
+            .map_err(|e| anyhow!("Failed to clear stale cache entries: {:?}", e))?;
 
-        // Prepare batch for prompt processing
+        // Prepare batch for the part of the prompt not in the cache
         let mut batch = LlamaBatch::new(n_ctx, 1);
 
-        for (i, &token) in tokens.iter().enumerate() {
+        for (i, &token) in tokens.iter().enumerate().skip(reused) {
             let is_last = i + 1 == tokens.len();
             batch
                 .add(token, i as i32, &[0], is_last)
@@ -120,19 +446,34 @@ impl LoadedModel {
         // Process the prompt
         ctx.decode(&mut batch)
             .map_err(|e| anyhow!("Failed to decode prompt: {:?}", e))?;
+        cached.decoded.extend_from_slice(&tokens[reused..]);
+        let prompt_done = Instant::now();
+        log::info!(
+            "Decoded {} of {} prompt tokens in {:?} ({} reused from the previous completion)",
+            n_prompt - reused,
+            n_prompt,
+            prompt_done.duration_since(started),
+            reused
+        );
 
         // Generate tokens
         let mut result = String::new();
         let mut n_cur = n_prompt;
         let n_max = n_prompt + max_tokens;
 
-        let mut sampler =
-            LlamaSampler::chain_simple([LlamaSampler::temp(temperature), LlamaSampler::greedy()]);
+        let mut sampler = sampling.sampler();
+        let mut cancelled = false;
+        let sentinels = fim.map_or(DEFAULT_SENTINELS, FimTemplate::sentinels);
 
         while n_cur < n_max {
+            if cancel.load(Ordering::Relaxed) {
+                cancelled = true;
+                break;
+            }
+
             // Sample next token
             let logits_index = batch.n_tokens() - 1;
-            let new_token_id = sampler.sample(&ctx, logits_index);
+            let new_token_id = sampler.sample(ctx, logits_index);
             sampler.accept(new_token_id);
 
             // Check for EOS
@@ -153,16 +494,16 @@ impl LoadedModel {
                 }
             };
 
-            // Filter out FIM sentinels if they leak into generation
-            // Supports both Qwen/StarCoder style (<|fim_*|>) and DeepSeek style (<｜fim▁*｜>)
-            if piece.contains("<|fim_")
-                || piece.contains("<|file_sep|>")
-                || piece.contains("<｜fim")
-            {
+            // Filter out the prompt family's FIM sentinels if they leak into generation
+            if sentinels.iter().any(|sentinel| piece.contains(sentinel)) {
                 continue;
             }
 
+            log::trace!("Token {}: {:?}", n_cur - n_prompt, piece);
             result.push_str(&piece);
+            if !on_piece(&piece) {
+                break;
+            }
 
             // Prepare next batch
             batch.clear();
@@ -173,11 +514,217 @@ impl LoadedModel {
             // Decode
             ctx.decode(&mut batch)
                 .map_err(|e| anyhow!("Failed to decode: {:?}", e))?;
+            cached.decoded.push(new_token_id);
 
             n_cur += 1;
         }
 
-        log::debug!("Generated {} tokens", n_cur - n_prompt);
-        Ok(result)
+        let timing = GenerationTiming {
+            prompt: prompt_done.duration_since(started),
+            generation: prompt_done.elapsed(),
+            tokens: n_cur - n_prompt,
+        };
+        log::debug!(
+            "Generated {} tokens in {:?} (prompt {:?})",
+            timing.tokens,
+            timing.generation,
+            timing.prompt
+        );
+        // Everything decoded so far is still valid, so keep the context either way
+        *self.context.lock().unwrap() = Some(cached);
+        if cancelled {
+            log::debug!("Generation cancelled after {} tokens", timing.tokens);
+            return Err(anyhow!(
+                "Request cancelled (generation mismatch mid-stream)"
+            ));
+        }
+        Ok((result, timing))
+    }
+
+    /// Memory taken by the model's weights
+    pub fn size_bytes(&self) -> u64 {
+        self.model.size()
+    }
+
+    /// What the GGUF file says about the model
+    pub fn info(&self) -> ModelInfo {
+        let meta = |key: &str| {
+            self.model
+                .meta_val_str(key)
+                .ok()
+                .filter(|value| !value.is_empty())
+        };
+        let quantization = meta("general.file_type")
+            .and_then(|file_type| file_type.parse().ok())
+            .and_then(quantization_name)
+            .map(str::to_string)
+            .or_else(|| quantization_from_file_name(&self.source_path));
+        ModelInfo {
+            name: meta("general.name"),
+            architecture: meta("general.architecture"),
+            parameters: self.model.n_params(),
+            layers: self.model.n_layer(),
+            context_length: self.model.n_ctx_train(),
+            quantization,
+            size_bytes: self.model.size(),
+        }
+    }
+
+    fn new_reusable_context(&self, n_ctx: u32) -> Result<ReusableContext> {
+        let ctx_params = LlamaContextParams::default().with_n_ctx(std::num::NonZeroU32::new(n_ctx));
+        let ctx = self
+            .model
+            .new_context(&self.backend, ctx_params)
+            .map_err(|e| anyhow!("Failed to create context: {:?}", e))?;
+        // SAFETY: the context borrows the model behind `self.model`. The
+        // `ReusableContext` keeps its own Arc of that model, which stays at a
+        // stable address, and drops the context before letting go of it.
+        let ctx = unsafe { std::mem::transmute::<LlamaContext<'_>, LlamaContext<'static>>(ctx) };
+        Ok(ReusableContext {
+            ctx: ManuallyDrop::new(ctx),
+            _model: self.model.clone(),
+            n_ctx,
+            decoded: Vec::new(),
+        })
+    }
+
+    /// Drop tokens from the start of the prompt's prefix until it is at most
+    /// `budget` tokens long. The BOS token and FIM markers are kept, so a
+    /// fill-in-the-middle prompt stays well formed.
+    fn trim_prompt_prefix(
+        &self,
+        tokens: &mut Vec<LlamaToken>,
+        budget: usize,
+        fim: Option<FimMarkers>,
+    ) -> Result<()> {
+        let excess = tokens.len().saturating_sub(budget);
+        if excess == 0 {
+            return Ok(());
+        }
+        let prefix_start = fim
+            .and_then(|markers| self.find_marker(tokens, markers.begin))
+            .map(|idx| idx + 1)
+            // Keep the BOS token
+            .unwrap_or(1);
+        let prefix_end = fim
+            .and_then(|markers| self.find_marker(tokens, markers.hole))
+            // Keep at least the last token before the cursor
+            .unwrap_or(tokens.len() - 1);
+        if prefix_end.saturating_sub(prefix_start) < excess {
+            return Err(anyhow!("prompt prefix too short to trim"));
+        }
+        log::info!(
+            "Prompt has {} tokens, {} over budget; dropping the oldest prefix text",
+            tokens.len(),
+            excess
+        );
+        tokens.drain(prefix_start..prefix_start + excess);
+        Ok(())
+    }
+
+    /// Position of the first token matching `marker`, when the marker is a
+    /// single special token for this model
+    fn find_marker(&self, tokens: &[LlamaToken], marker: &str) -> Option<usize> {
+        // Code Llama's markers carry a space that isn't part of the token
+        let marker_tokens = self.model.str_to_token(marker.trim(), AddBos::Never).ok()?;
+        let [marker_token] = marker_tokens.as_slice() else {
+            return None;
+        };
+        tokens.iter().position(|token| token == marker_token)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::LlmSettings;
+    use llama_cpp_2::token::data::LlamaTokenData;
+    use llama_cpp_2::token::data_array::LlamaTokenDataArray;
+
+    #[test]
+    fn test_seed_is_folded_to_32_bits() {
+        let mut sampling = SamplingParams::greedy();
+        sampling.seed = Some(42);
+        assert_eq!(sampling.dist_seed(), 42);
+        sampling.seed = Some(1 << 32);
+        assert_eq!(sampling.dist_seed(), 1);
+    }
+
+    #[test]
+    fn test_check_model_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("model.gguf");
+        assert!(check_model_file(&path).is_err());
+
+        std::fs::write(&path, b"<html>404</html>").unwrap();
+        assert!(check_model_file(&path).is_err());
+
+        let mut header = b"GGUF".to_vec();
+        header.extend_from_slice(&3u32.to_le_bytes());
+        std::fs::write(&path, &header).unwrap();
+        assert!(check_model_file(&path).is_ok());
+    }
+
+    #[test]
+    fn test_quantization_from_file_name() {
+        let quant = |name: &str| quantization_from_file_name(Path::new(name));
+        assert_eq!(
+            quant("deepseek-coder-1.3b-instruct.Q4_K_M.gguf").as_deref(),
+            Some("Q4_K_M")
+        );
+        assert_eq!(
+            quant("qwen2.5-coder-1.5b-instruct-q8_0.gguf").as_deref(),
+            Some("Q8_0")
+        );
+        assert_eq!(quant("qwen2-7b.gguf"), None);
+    }
+
+    #[test]
+    fn test_model_info_summary() {
+        let info = ModelInfo {
+            name: None,
+            architecture: Some("llama".into()),
+            parameters: 1_346_471_936,
+            layers: 24,
+            context_length: 16384,
+            quantization: Some("Q4_K_M".into()),
+            size_bytes: 0,
+        };
+        assert_eq!(
+            info.summary(),
+            "llama · 1.3B parameters · Q4_K_M · 16384-token context"
+        );
+    }
+
+    /// The token `sampling` picks from `logits` (token ids are the indices)
+    /// after the model produced `history`
+    fn pick(sampling: &SamplingParams, history: &[i32], logits: &[f32]) -> Option<i32> {
+        let mut sampler = sampling.sampler();
+        sampler.accept_many(history.iter().map(|&id| LlamaToken::new(id)));
+        let mut candidates = LlamaTokenDataArray::from_iter(
+            logits
+                .iter()
+                .enumerate()
+                .map(|(id, &logit)| LlamaTokenData::new(LlamaToken::new(id as i32), logit, 0.0)),
+            false,
+        );
+        sampler.apply(&mut candidates);
+        candidates.selected_token().map(|token| token.0)
+    }
+
+    #[test]
+    fn test_repeat_penalty_breaks_a_loop() {
+        // Token 0 is only slightly ahead and has just been repeated
+        let history = [0; 8];
+        let logits = [2.0, 1.9, 0.0];
+        assert_eq!(pick(&SamplingParams::greedy(), &history, &logits), Some(0));
+
+        let penalized = SamplingParams {
+            penalties: LlmSettings::default().sampling().penalties,
+            ..SamplingParams::greedy()
+        };
+        assert_eq!(pick(&penalized, &history, &logits), Some(1));
+        // Without a recent repeat nothing changes
+        assert_eq!(pick(&penalized, &[2; 8], &logits), Some(0));
     }
 }
@@ -1,12 +1,31 @@
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::AtomicBool;
 use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
 
+pub mod cache;
+pub mod context;
+pub mod filter;
+pub mod gemini;
+mod http;
 pub mod huggingface;
 pub mod llamacpp;
+pub mod ollama;
+pub mod openai;
 
-pub use huggingface::{DownloadPhase, DownloadProgress, HuggingFaceModel, ModelDownloader};
-pub use llamacpp::{LlamaCpp, LoadedModel};
+pub use context::{ContextStrategy, FimTemplate};
+
+use cache::CompletionCache;
+
+pub use huggingface::{
+    DownloadCancelled, DownloadPhase, DownloadProgress, DownloadedModel, HuggingFaceModel,
+    ModelDownloader, RepoFile,
+};
+pub use llamacpp::{
+    GenerationTiming, LlamaCpp, LoadedModel, ModelInfo, RepeatPenalty, SamplingParams,
+};
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum LlmReadiness {
@@ -18,12 +37,15 @@ pub enum LlmReadiness {
     NeedsEndpoint,
     /// Embedded llama backend failed to initialize
     LocalBackendUnavailable,
+    /// Remote provider is configured but a test request to it failed
+    NeedsValidConnection { error: String },
 }
 
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
 pub enum ProviderKind {
     OpenAI,
     Gemini,
+    Ollama,
     Local,
 }
 
@@ -33,13 +55,84 @@ impl Default for ProviderKind {
     }
 }
 
+impl ProviderKind {
+    const REMOTE: [ProviderKind; 3] = [
+        ProviderKind::OpenAI,
+        ProviderKind::Gemini,
+        ProviderKind::Ollama,
+    ];
+
+    /// Endpoint the provider is reached at out of the box
+    pub fn default_endpoint(self) -> Option<&'static str> {
+        match self {
+            ProviderKind::OpenAI => Some(openai::DEFAULT_ENDPOINT),
+            ProviderKind::Gemini => Some(gemini::DEFAULT_ENDPOINT),
+            ProviderKind::Ollama => Some(ollama::DEFAULT_ENDPOINT),
+            ProviderKind::Local => None,
+        }
+    }
+}
+
+/// Which part of the buffer a completion request is anchored on
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum CompletionScope {
+    /// Insert new text at the cursor
+    Cursor,
+    /// Rewrite the selected text, falling back to the cursor without a selection
+    Selection,
+}
+
+/// Named presets that set several completion knobs at once
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CompletionProfile {
+    /// Knobs are set individually
+    Custom,
+    /// Prose drafting: suggest the next sentence after a finished one
+    SentenceDrafting,
+}
+
+impl Default for CompletionProfile {
+    fn default() -> Self {
+        CompletionProfile::Custom
+    }
+}
+
+impl Default for CompletionScope {
+    fn default() -> Self {
+        CompletionScope::Cursor
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LlmSettings {
     pub provider: ProviderKind,
     pub endpoint: String,
+    /// Model name sent to remote providers
+    #[serde(default)]
+    pub remote_model: String,
+    /// Test the remote connection when the app starts
+    #[serde(default)]
+    pub check_connection_on_startup: bool,
+    /// API key kept in the config file, only used when the system keyring
+    /// isn't available
+    #[serde(default, rename = "api_key", skip_serializing_if = "String::is_empty")]
+    pub plaintext_api_key: String,
+    /// API key loaded from the system keyring; never written to the config
+    #[serde(skip)]
+    pub keyring_api_key: Option<String>,
+    /// Hugging Face access token for downloading gated or private models,
+    /// kept in the config file only when the system keyring isn't available
+    #[serde(default, rename = "hf_token", skip_serializing_if = "String::is_empty")]
+    pub plaintext_hf_token: String,
+    /// Hugging Face token loaded from the system keyring; never written to the config
+    #[serde(skip)]
+    pub keyring_hf_token: Option<String>,
     #[serde(default)]
     pub override_model_path: bool,
     pub local_model_path: String,
+    /// Where downloaded models live, instead of the data directory
+    #[serde(default)]
+    pub models_dir_override: Option<PathBuf>,
     #[serde(default)]
     pub preferred_device: Option<String>,
     #[serde(default)]
@@ -48,22 +141,167 @@ pub struct LlmSettings {
     pub default_gpu_model: String,
     #[serde(default = "default_cpu_model")]
     pub default_cpu_model: String,
+    /// GPU layers found to fit by auto-tuning, keyed by device id
+    #[serde(default)]
+    pub tuned_gpu_layers: BTreeMap<String, i32>,
+    /// Layers to offload to the GPU; `ALL_GPU_LAYERS` leaves it to auto-tuning
+    #[serde(default = "default_gpu_layers")]
+    pub gpu_layers: i32,
     #[serde(default = "default_max_completion_tokens")]
     pub max_completion_tokens: usize,
+    /// Tokens the model sees at once, prompt and completion together
+    #[serde(default = "default_context_size")]
+    pub context_size: u32,
+    /// Sampling temperature; 0 picks the most likely token every time
+    #[serde(default = "default_temperature")]
+    pub temperature: f32,
+    #[serde(default = "default_top_p")]
+    pub top_p: f32,
+    #[serde(default = "default_top_k")]
+    pub top_k: i32,
+    /// Penalize recently generated tokens so small models don't loop
+    #[serde(default = "default_use_repeat_penalty")]
+    pub use_repeat_penalty: bool,
+    #[serde(default = "default_repeat_penalty")]
+    pub repeat_penalty: f32,
+    /// Recent tokens the penalties look at
+    #[serde(default = "default_penalty_last_n")]
+    pub penalty_last_n: i32,
+    #[serde(default)]
+    pub frequency_penalty: f32,
+    #[serde(default)]
+    pub presence_penalty: f32,
+    /// Sampling seed, so a completion can be reproduced; `None` is random
+    #[serde(default)]
+    pub seed: Option<u64>,
+    /// Automatic completions get fewer tokens to finish within this many
+    /// milliseconds; 0 always uses the full budget
+    #[serde(default)]
+    pub latency_target_ms: u64,
+    /// Free the local model after this many seconds without a completion
+    /// request; 0 keeps it loaded
+    #[serde(default)]
+    pub unload_after_idle_secs: u64,
+    /// Recent completions kept for identical prompts; 0 disables the cache
+    #[serde(default = "default_completion_cache_size")]
+    pub completion_cache_size: usize,
+    /// Suggest while typing; when off, only manual requests complete
+    #[serde(default = "default_auto_completion_enabled")]
+    pub auto_completion_enabled: bool,
+    /// Pause in typing before an automatic completion is requested
+    #[serde(default = "default_completion_debounce_ms")]
+    pub completion_debounce_ms: u64,
+    /// Request one anyway after this long of continuous typing; 0 waits for a pause
+    #[serde(default)]
+    pub completion_max_wait_ms: u64,
+    #[serde(default)]
+    pub completion_profile: CompletionProfile,
+    #[serde(default)]
+    pub completion_scope: CompletionScope,
+    #[serde(default)]
+    pub context_strategy: ContextStrategy,
+    /// Full lines kept on each side of the cursor by the line strategy
+    #[serde(default = "default_context_lines")]
+    pub context_lines: usize,
+    /// Most characters of text before the cursor sent with a completion
+    #[serde(default = "default_context_prefix_chars")]
+    pub context_prefix_chars: usize,
+    /// Most characters of text after the cursor sent with a completion
+    #[serde(default = "default_context_suffix_chars")]
+    pub context_suffix_chars: usize,
+    /// Fill-in-the-middle prompt layout; `Auto` picks one from the model name
+    #[serde(default)]
+    pub fim_template: FimTemplate,
+    /// Insertions larger than this many characters (e.g. pastes) don't trigger
+    /// auto-completion; 0 disables the check
+    #[serde(default = "default_bulk_insert_chars")]
+    pub bulk_insert_chars: usize,
+    /// Only auto-complete right after typing one of `trigger_chars`
+    #[serde(default)]
+    pub trigger_only: bool,
+    #[serde(default = "default_trigger_chars")]
+    pub trigger_chars: String,
+    /// Cut suggestions after the first complete sentence
+    #[serde(default)]
+    pub stop_at_sentence_end: bool,
+    /// Prepend the file name (or title, for prose) to the prompt
+    #[serde(default)]
+    pub include_filename: bool,
+    /// Prompt sent by a manual completion in an empty document; empty disables it
+    #[serde(default)]
+    pub starter_prompt: String,
+    /// Regular expressions; completions matching any of them are discarded
+    #[serde(default)]
+    pub completion_blocklist: Vec<String>,
+    /// Ask the model once more when a completion is discarded by the blocklist
+    #[serde(default)]
+    pub regenerate_blocked: bool,
+    /// Scroll a suggestion that ends below the visible area into view
+    #[serde(default = "default_reveal_ghost_text")]
+    pub reveal_ghost_text: bool,
+    /// Clicking outside a suggestion dismisses it
+    #[serde(default = "default_dismiss_on_click")]
+    pub dismiss_on_click: bool,
+    /// Say in the status bar why a completion didn't appear
+    #[serde(default)]
+    pub explain_completions: bool,
 }
 
 impl Default for LlmSettings {
     fn default() -> Self {
         Self {
             provider: ProviderKind::Local,
-            endpoint: "https://api.openai.com/v1".into(),
+            endpoint: openai::DEFAULT_ENDPOINT.into(),
+            remote_model: String::new(),
+            check_connection_on_startup: false,
+            plaintext_api_key: String::new(),
+            keyring_api_key: None,
+            plaintext_hf_token: String::new(),
+            keyring_hf_token: None,
             override_model_path: false,
             local_model_path: String::new(),
+            models_dir_override: None,
             preferred_device: None,
             force_cpu_only: false,
             default_gpu_model: default_gpu_model(),
             default_cpu_model: default_cpu_model(),
+            tuned_gpu_layers: BTreeMap::new(),
+            gpu_layers: default_gpu_layers(),
             max_completion_tokens: default_max_completion_tokens(),
+            context_size: default_context_size(),
+            temperature: default_temperature(),
+            top_p: default_top_p(),
+            top_k: default_top_k(),
+            use_repeat_penalty: default_use_repeat_penalty(),
+            repeat_penalty: default_repeat_penalty(),
+            penalty_last_n: default_penalty_last_n(),
+            frequency_penalty: 0.0,
+            presence_penalty: 0.0,
+            seed: None,
+            unload_after_idle_secs: 0,
+            latency_target_ms: 0,
+            completion_cache_size: default_completion_cache_size(),
+            auto_completion_enabled: default_auto_completion_enabled(),
+            completion_debounce_ms: default_completion_debounce_ms(),
+            completion_max_wait_ms: 0,
+            completion_profile: CompletionProfile::default(),
+            completion_scope: CompletionScope::default(),
+            context_strategy: ContextStrategy::default(),
+            context_lines: default_context_lines(),
+            context_prefix_chars: default_context_prefix_chars(),
+            context_suffix_chars: default_context_suffix_chars(),
+            fim_template: FimTemplate::default(),
+            bulk_insert_chars: default_bulk_insert_chars(),
+            trigger_only: false,
+            trigger_chars: default_trigger_chars(),
+            stop_at_sentence_end: false,
+            include_filename: false,
+            starter_prompt: String::new(),
+            completion_blocklist: Vec::new(),
+            regenerate_blocked: false,
+            reveal_ghost_text: default_reveal_ghost_text(),
+            dismiss_on_click: default_dismiss_on_click(),
+            explain_completions: false,
         }
     }
 }
@@ -73,6 +311,29 @@ const DEFAULT_GPU_MODEL: &str =
 const DEFAULT_CPU_MODEL: &str =
     "TheBloke/deepseek-coder-1.3b-instruct-GGUF:deepseek-coder-1.3b-instruct.Q4_K_M.gguf";
 const DEFAULT_MAX_COMPLETION_TOKENS: usize = 32;
+const DEFAULT_COMPLETION_CACHE_SIZE: usize = 32;
+/// Latency-limited completions still get at least this many tokens
+const MIN_ADAPTIVE_TOKENS: usize = 4;
+/// Weight of the newest measurement in the running speed averages
+const SPEED_SMOOTHING: f64 = 0.3;
+const DEFAULT_BULK_INSERT_CHARS: usize = 200;
+const DEFAULT_CONTEXT_LINES: usize = 40;
+const DEFAULT_CONTEXT_PREFIX_CHARS: usize = 2000;
+const DEFAULT_CONTEXT_SUFFIX_CHARS: usize = 1000;
+const DEFAULT_TRIGGER_CHARS: &str = ". \n";
+/// Room for a full sentence of prose
+const SENTENCE_DRAFTING_MAX_TOKENS: usize = 64;
+/// Offload everything; llama.cpp clamps this to the model's layer count
+pub const ALL_GPU_LAYERS: i32 = 999;
+/// Partial offloads tried by auto-tuning after full offload, largest first
+const GPU_LAYER_CANDIDATES: &[i32] = &[64, 48, 40, 32, 24, 16, 12, 8, 4];
+const SUMMARY_MAX_TOKENS: usize = 256;
+const SUMMARY_INSTRUCTION: &str =
+    "Summarize the following document in a few concise sentences. Reply with the summary only.";
+
+fn default_gpu_layers() -> i32 {
+    ALL_GPU_LAYERS
+}
 
 fn default_gpu_model() -> String {
     DEFAULT_GPU_MODEL.to_string()
@@ -86,10 +347,221 @@ fn default_max_completion_tokens() -> usize {
     DEFAULT_MAX_COMPLETION_TOKENS
 }
 
+fn default_auto_completion_enabled() -> bool {
+    true
+}
+
+fn default_completion_debounce_ms() -> u64 {
+    500
+}
+
+fn default_reveal_ghost_text() -> bool {
+    true
+}
+
+fn default_dismiss_on_click() -> bool {
+    true
+}
+
+fn default_context_size() -> u32 {
+    2048
+}
+
+fn default_temperature() -> f32 {
+    0.2
+}
+
+fn default_top_p() -> f32 {
+    0.95
+}
+
+fn default_top_k() -> i32 {
+    40
+}
+
+fn default_use_repeat_penalty() -> bool {
+    true
+}
+
+fn default_repeat_penalty() -> f32 {
+    1.1
+}
+
+fn default_penalty_last_n() -> i32 {
+    64
+}
+
+fn default_completion_cache_size() -> usize {
+    DEFAULT_COMPLETION_CACHE_SIZE
+}
+
+fn default_bulk_insert_chars() -> usize {
+    DEFAULT_BULK_INSERT_CHARS
+}
+
+fn default_trigger_chars() -> String {
+    DEFAULT_TRIGGER_CHARS.to_string()
+}
+
+fn default_context_lines() -> usize {
+    DEFAULT_CONTEXT_LINES
+}
+
+fn default_context_prefix_chars() -> usize {
+    DEFAULT_CONTEXT_PREFIX_CHARS
+}
+
+fn default_context_suffix_chars() -> usize {
+    DEFAULT_CONTEXT_SUFFIX_CHARS
+}
+
+impl LlmSettings {
+    /// Switch to `provider`. An endpoint that is unset or still some
+    /// provider's default becomes the new provider's default; one the user
+    /// entered is kept.
+    pub fn set_provider(&mut self, provider: ProviderKind) {
+        self.provider = provider;
+        let endpoint = self.endpoint.trim();
+        let is_default = endpoint.is_empty()
+            || ProviderKind::REMOTE
+                .iter()
+                .any(|other| other.default_endpoint() == Some(endpoint));
+        if let (true, Some(default)) = (is_default, provider.default_endpoint()) {
+            self.endpoint = default.to_string();
+        }
+    }
+
+    /// Switch to `profile`, overwriting the knobs it controls
+    pub fn apply_profile(&mut self, profile: CompletionProfile) {
+        self.completion_profile = profile;
+        match profile {
+            CompletionProfile::Custom => {}
+            CompletionProfile::SentenceDrafting => {
+                self.trigger_only = true;
+                self.trigger_chars = " ".into();
+                self.max_completion_tokens = SENTENCE_DRAFTING_MAX_TOKENS;
+                self.stop_at_sentence_end = true;
+            }
+        }
+    }
+
+    /// Sampler settings for local completions
+    pub fn sampling(&self) -> SamplingParams {
+        SamplingParams {
+            temperature: self.temperature,
+            top_p: self.top_p,
+            top_k: self.top_k,
+            penalties: self.use_repeat_penalty.then_some(RepeatPenalty {
+                last_n: self.penalty_last_n,
+                repeat: self.repeat_penalty,
+                frequency: self.frequency_penalty,
+                presence: self.presence_penalty,
+            }),
+            seed: self.seed,
+        }
+    }
+
+    /// Delay before an automatic completion when typing started `waited` ago:
+    /// the debounce, cut short once the max wait runs out. A max wait below
+    /// the debounce counts as the debounce.
+    pub fn completion_delay(&self, waited: Duration) -> Duration {
+        let debounce = Duration::from_millis(self.completion_debounce_ms);
+        if self.completion_max_wait_ms == 0 {
+            return debounce;
+        }
+        let max_wait = Duration::from_millis(self.completion_max_wait_ms).max(debounce);
+        debounce.min(max_wait.saturating_sub(waited))
+    }
+
+    /// Name of the model completions come from: the remote model, or the
+    /// local model's path or Hugging Face reference
+    pub fn model_name(&self) -> &str {
+        match self.provider {
+            ProviderKind::Local
+                if self.override_model_path && !self.local_model_path.is_empty() =>
+            {
+                &self.local_model_path
+            }
+            ProviderKind::Local if self.force_cpu_only => &self.default_cpu_model,
+            ProviderKind::Local => &self.default_gpu_model,
+            _ => &self.remote_model,
+        }
+    }
+
+    /// The FIM template to build prompts with, with `Auto` resolved
+    pub fn resolved_fim_template(&self) -> FimTemplate {
+        self.fim_template.resolve(self.model_name())
+    }
+
+    /// API key for the remote provider, preferring the keyring's copy
+    pub fn api_key(&self) -> &str {
+        self.keyring_api_key
+            .as_deref()
+            .unwrap_or(&self.plaintext_api_key)
+    }
+
+    /// Hugging Face token, preferring the keyring's copy
+    pub fn hf_token(&self) -> &str {
+        self.keyring_hf_token
+            .as_deref()
+            .unwrap_or(&self.plaintext_hf_token)
+    }
+
+    /// The models directory in use: the override if set, else `default`
+    pub fn models_dir(&self, default: &Path) -> PathBuf {
+        self.models_dir_override
+            .clone()
+            .unwrap_or_else(|| default.to_path_buf())
+    }
+
+    /// The GPU whose tuned layer count applies, or `None` when running on the CPU
+    pub fn gpu_tuning_key(&self) -> Option<&str> {
+        if self.force_cpu_only {
+            None
+        } else {
+            self.preferred_device.as_deref()
+        }
+    }
+
+    /// Auto-tuned layer count for the selected GPU, if one has been recorded
+    pub fn tuned_layers(&self) -> Option<i32> {
+        self.gpu_tuning_key()
+            .and_then(|key| self.tuned_gpu_layers.get(key).copied())
+    }
+
+    /// Layers to offload: the configured count if one is set, else the
+    /// auto-tuned count, else all of them
+    pub fn offload_layers(&self) -> i32 {
+        if self.gpu_layers != ALL_GPU_LAYERS {
+            self.gpu_layers
+        } else {
+            self.tuned_layers().unwrap_or(ALL_GPU_LAYERS)
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct GpuDevice {
     pub id: String,
     pub name: String,
+    /// Dedicated memory, where the driver reports it
+    pub vram_bytes: Option<u64>,
+    /// Shares system memory with the CPU
+    pub integrated: bool,
+}
+
+impl GpuDevice {
+    /// Name for the accelerator picker, with what tells similar GPUs apart
+    pub fn label(&self) -> String {
+        const GIB: f64 = 1024.0 * 1024.0 * 1024.0;
+        if self.integrated {
+            format!("{} (integrated)", self.name)
+        } else if let Some(vram) = self.vram_bytes {
+            format!("{} ({:.0} GiB VRAM)", self.name, vram as f64 / GIB)
+        } else {
+            self.name.clone()
+        }
+    }
 }
 
 #[allow(dead_code)]
@@ -98,12 +570,76 @@ pub struct LlmManager {
     downloader: ModelDownloader,
     llamacpp: Option<Arc<LlamaCpp>>,
     loaded_model: Arc<Mutex<Option<LoadedModel>>>,
+    /// Layers the last load had to drop to because the configured count didn't fit
+    gpu_layer_fallback: Mutex<Option<i32>>,
+    /// GGUF metadata of every model loaded so far, by file
+    model_info: Mutex<HashMap<ModelFileKey, ModelInfo>>,
+    cache: Mutex<CompletionCache>,
+    /// Used when the settings don't override the models directory
+    default_models_dir: PathBuf,
+    speed: Mutex<Option<SpeedEstimate>>,
+}
+
+/// A model file as it is on disk, so metadata read from a file that has since
+/// been replaced isn't shown for the new one
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct ModelFileKey {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    len: u64,
+}
+
+impl ModelFileKey {
+    fn new(path: &Path) -> Self {
+        let metadata = std::fs::metadata(path).ok();
+        Self {
+            path: path.to_path_buf(),
+            modified: metadata.as_ref().and_then(|meta| meta.modified().ok()),
+            len: metadata.map_or(0, |meta| meta.len()),
+        }
+    }
+}
+
+/// Running averages of how fast the loaded model works
+#[derive(Debug, Clone, Copy)]
+pub struct SpeedEstimate {
+    pub tokens_per_second: f64,
+    /// Time spent before the first token, mostly prompt processing
+    pub prompt_secs: f64,
+}
+
+impl SpeedEstimate {
+    fn record(previous: Option<Self>, timing: &GenerationTiming) -> Option<Self> {
+        let prompt_secs = timing.prompt.as_secs_f64();
+        let Some(tokens_per_second) = timing.tokens_per_second() else {
+            return previous;
+        };
+        let blend = |old: f64, new: f64| old + SPEED_SMOOTHING * (new - old);
+        Some(match previous {
+            Some(prev) => Self {
+                tokens_per_second: blend(prev.tokens_per_second, tokens_per_second),
+                prompt_secs: blend(prev.prompt_secs, prompt_secs),
+            },
+            None => Self {
+                tokens_per_second,
+                prompt_secs,
+            },
+        })
+    }
+
+    /// Tokens that fit in `target` after prompt processing, within `max_tokens`
+    pub fn token_budget(&self, max_tokens: usize, target: Duration) -> usize {
+        let generation_secs = (target.as_secs_f64() - self.prompt_secs).max(0.0);
+        let budget = (generation_secs * self.tokens_per_second) as usize;
+        budget.clamp(MIN_ADAPTIVE_TOKENS.min(max_tokens), max_tokens)
+    }
 }
 
 #[allow(dead_code)]
 impl LlmManager {
-    pub fn new(config: LlmSettings, models_dir: PathBuf) -> Self {
-        let downloader = ModelDownloader::new(models_dir);
+    pub fn new(config: LlmSettings, default_models_dir: PathBuf) -> Self {
+        let mut downloader = ModelDownloader::new(config.models_dir(&default_models_dir));
+        downloader.set_hf_token(config.hf_token());
         let llamacpp = LlamaCpp::new().ok().map(Arc::new);
 
         if llamacpp.is_none() {
@@ -112,11 +648,17 @@ impl LlmManager {
             );
         }
 
+        let cache = Mutex::new(CompletionCache::new(config.completion_cache_size));
         Self {
             config,
             downloader,
             llamacpp,
             loaded_model: Arc::new(Mutex::new(None)),
+            gpu_layer_fallback: Mutex::new(None),
+            model_info: Mutex::new(HashMap::new()),
+            cache,
+            default_models_dir,
+            speed: Mutex::new(None),
         }
     }
 
@@ -125,6 +667,16 @@ impl LlmManager {
     }
 
     pub fn update_config(&mut self, config: LlmSettings) {
+        // Any setting may change what the model would produce
+        let cache = self.cache.get_mut().unwrap();
+        cache.clear();
+        cache.set_capacity(config.completion_cache_size);
+        let models_dir = config.models_dir(&self.default_models_dir);
+        if models_dir != self.downloader.models_dir() {
+            log::info!("Models directory is now {}", models_dir.display());
+            self.downloader = ModelDownloader::new(models_dir);
+        }
+        self.downloader.set_hf_token(config.hf_token());
         self.config = config;
     }
 
@@ -171,28 +723,7 @@ impl LlmManager {
 
         log::debug!("No model loaded, resolving path...");
 
-        // Determine which model to use (this may involve network requests for HF alias resolution)
-        let model_path =
-            if self.config.override_model_path && !self.config.local_model_path.is_empty() {
-                // Use override path
-                PathBuf::from(&self.config.local_model_path)
-            } else {
-                // Use default model based on GPU/CPU selection
-                let model_ref = if self.config.force_cpu_only {
-                    &self.config.default_cpu_model
-                } else {
-                    &self.config.default_gpu_model
-                };
-
-                // Ensure model is downloaded
-                if !self.is_model_downloaded(model_ref) {
-                    log::info!("Model not downloaded, downloading: {}", model_ref);
-                    self.download_model(model_ref)?
-                } else {
-                    self.get_model_path(model_ref)
-                        .ok_or_else(|| anyhow::anyhow!("Model path not found"))?
-                }
-            };
+        let model_path = self.resolve_model_path()?;
 
         // Now check if a model is loaded and if we need to reload (e.g., different path)
         {
@@ -224,21 +755,7 @@ impl LlmManager {
             log::info!("force_cpu_only is true, using CPU");
             (Some(0), None)
         } else {
-            // Use all GPU layers by default when GPU is selected
-            let layers = Some(999); // llama.cpp will use as many as possible
-
-            // Parse the GPU device ID from preferred_device
-            log::info!(
-                "preferred_device setting: {:?}",
-                self.config.preferred_device
-            );
-            let gpu_device = self.config.preferred_device.as_ref().and_then(|s| {
-                let parsed = s.parse::<i32>();
-                log::info!("Parsed GPU device from '{}': {:?}", s, parsed);
-                parsed.ok()
-            });
-
-            (layers, gpu_device)
+            (Some(self.config.offload_layers()), self.main_gpu())
         };
 
         // Load the model
@@ -247,15 +764,175 @@ impl LlmManager {
         } else {
             log::info!("Loading model on CPU: {}", model_path.display());
         }
-        let loaded = llamacpp.load_model(&model_path, n_gpu_layers, main_gpu)?;
+        // A bad file fails the same with any layer count; don't reload it over and over
+        llamacpp::check_model_file(&model_path)?;
+        let mut layers = n_gpu_layers;
+        let loaded = loop {
+            match llamacpp.load_model(&model_path, layers, main_gpu) {
+                Ok(loaded) => break loaded,
+                // llama.cpp doesn't say why a load failed; the file checked out,
+                // so with layers on the GPU it is nearly always memory
+                Err(err) => match layers.and_then(fewer_gpu_layers) {
+                    Some(fewer) => {
+                        log::warn!(
+                            "Loading with {:?} GPU layers failed ({}), retrying with {}",
+                            layers,
+                            err,
+                            fewer
+                        );
+                        layers = Some(fewer);
+                    }
+                    None => return Err(err),
+                },
+            }
+        };
+        if layers != n_gpu_layers {
+            *self.gpu_layer_fallback.lock().unwrap() = layers;
+        }
+        self.record_model_info(&loaded);
 
         *self.loaded_model.lock().unwrap() = Some(loaded);
+        self.cache.lock().unwrap().clear();
+        *self.speed.lock().unwrap() = None;
 
         Ok(())
     }
 
+    /// The GPU layer count the last load fell back to, if the configured one
+    /// didn't fit. Reported once.
+    pub fn take_gpu_layer_fallback(&self) -> Option<i32> {
+        self.gpu_layer_fallback.lock().unwrap().take()
+    }
+
+    /// Determine which model file to use, downloading the default model if needed.
+    /// This may involve network requests for HF alias resolution.
+    fn resolve_model_path(&self) -> anyhow::Result<PathBuf> {
+        if self.config.override_model_path && !self.config.local_model_path.is_empty() {
+            // Use override path
+            return Ok(PathBuf::from(&self.config.local_model_path));
+        }
+
+        // Use default model based on GPU/CPU selection
+        let model_ref = if self.config.force_cpu_only {
+            &self.config.default_cpu_model
+        } else {
+            &self.config.default_gpu_model
+        };
+
+        // Ensure model is downloaded
+        if !self.is_model_downloaded(model_ref) {
+            log::info!("Model not downloaded, downloading: {}", model_ref);
+            self.download_model(model_ref)
+        } else {
+            self.get_model_path(model_ref)
+                .ok_or_else(|| anyhow::anyhow!("Model path not found"))
+        }
+    }
+
+    /// Parse the GPU device ID from preferred_device. An ID that isn't a
+    /// device index leaves the choice to llama.cpp, which picks its first GPU.
+    fn main_gpu(&self) -> Option<i32> {
+        let device = self.config.preferred_device.as_ref()?;
+        match device.parse::<i32>() {
+            Ok(index) => Some(index),
+            Err(_) => {
+                log::warn!(
+                    "Ignoring GPU device '{}': not a device index; using llama.cpp's default GPU",
+                    device
+                );
+                None
+            }
+        }
+    }
+
+    /// Find the largest GPU layer count that loads and runs on the selected GPU.
+    /// The working model stays loaded; the caller is expected to record the
+    /// returned value in the settings.
+    pub fn auto_tune_gpu_layers(&self) -> anyhow::Result<i32> {
+        let llamacpp = self
+            .llamacpp
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("llama.cpp not available"))?;
+        if self.config.gpu_tuning_key().is_none() {
+            return Err(anyhow::anyhow!("Select a GPU accelerator to auto-tune"));
+        }
+
+        let model_path = self.resolve_model_path()?;
+        let main_gpu = self.main_gpu();
+        // Free the current model so each attempt sees the whole card
+        self.unload_model();
+
+        let model_layers = match self.model_info(&model_path) {
+            Some(info) => Some(info.layers),
+            // Never loaded yet; a CPU-only load is enough to read the metadata
+            None => llamacpp
+                .load_model(&model_path, Some(0), main_gpu)
+                .map(|loaded| {
+                    self.record_model_info(&loaded);
+                    loaded.info().layers
+                })
+                .ok(),
+        };
+        for layers in gpu_layer_candidates(model_layers) {
+            log::info!("Auto-tune: trying n_gpu_layers = {}", layers);
+            // Loading can succeed while the context still fails to allocate, so run a token too
+            let attempt = llamacpp
+                .load_model(&model_path, Some(layers), main_gpu)
+                .and_then(|loaded| {
+                    loaded
+                        .complete(
+                            "test",
+                            1,
+                            self.config.context_size,
+                            &SamplingParams::greedy(),
+                        )
+                        .map(|_| loaded)
+                });
+            match attempt {
+                Ok(loaded) => {
+                    log::info!("Auto-tune: n_gpu_layers = {} works", layers);
+                    self.record_model_info(&loaded);
+                    *self.loaded_model.lock().unwrap() = Some(loaded);
+                    return Ok(layers);
+                }
+                Err(err) => log::warn!("Auto-tune: n_gpu_layers = {} failed: {}", layers, err),
+            }
+        }
+
+        Err(anyhow::anyhow!(
+            "The model did not fit on this GPU at any tested layer count"
+        ))
+    }
+
     /// Run inference with the configured model
     pub fn complete(&self, prompt: &str, max_tokens: usize) -> anyhow::Result<String> {
+        self.complete_cancellable(prompt, max_tokens, &AtomicBool::new(false))
+    }
+
+    /// Run inference, giving up with an error as soon as `cancel` is set
+    pub fn complete_cancellable(
+        &self,
+        prompt: &str,
+        max_tokens: usize,
+        cancel: &AtomicBool,
+    ) -> anyhow::Result<String> {
+        self.complete_streaming(prompt, max_tokens, cancel, |_| true)
+    }
+
+    /// Run inference, calling `on_piece` with each decoded piece as it is
+    /// generated. Returning false from `on_piece` stops generating and returns
+    /// what was produced so far; setting `cancel` abandons the request.
+    pub fn complete_streaming(
+        &self,
+        prompt: &str,
+        max_tokens: usize,
+        cancel: &AtomicBool,
+        on_piece: impl FnMut(&str) -> bool,
+    ) -> anyhow::Result<String> {
+        if let Some(config) = self.remote_config() {
+            return Self::complete_remote(&config, prompt, max_tokens, cancel);
+        }
+
         // Ensure model is loaded
 
         self.ensure_model_loaded()?;
@@ -267,13 +944,193 @@ impl LlmManager {
             .ok_or_else(|| anyhow::anyhow!("Model not loaded"))?;
 
         // Run inference
+        let (text, timing) = model.complete_streaming(
+            prompt,
+            max_tokens,
+            self.config.context_size,
+            &self.config.sampling(),
+            cancel,
+            on_piece,
+        )?;
+        let mut speed = self.speed.lock().unwrap();
+        *speed = SpeedEstimate::record(*speed, &timing);
+        Ok(text)
+    }
+
+    /// Measured speed of the loaded model, once it has generated something
+    pub fn speed(&self) -> Option<SpeedEstimate> {
+        *self.speed.lock().unwrap()
+    }
+
+    /// Token budget for an automatic completion: `max_tokens`, reduced so the
+    /// completion should finish within the configured latency target.
+    pub fn adaptive_token_budget(&self, max_tokens: usize) -> usize {
+        if self.config.latency_target_ms == 0 {
+            return max_tokens;
+        }
+        match self.speed() {
+            Some(speed) => speed.token_budget(
+                max_tokens,
+                Duration::from_millis(self.config.latency_target_ms),
+            ),
+            // Nothing measured yet
+            None => max_tokens,
+        }
+    }
+
+    /// Like `complete_streaming`, but returns a recent result for an identical
+    /// prompt without running inference again (or calling `on_piece`).
+    pub fn complete_cached_streaming(
+        &self,
+        prompt: &str,
+        max_tokens: usize,
+        cancel: &AtomicBool,
+        on_piece: impl FnMut(&str) -> bool,
+    ) -> anyhow::Result<String> {
+        if let Some(completion) = self.cached_completion(prompt, max_tokens) {
+            return Ok(completion);
+        }
+        let completion = self.complete_streaming(prompt, max_tokens, cancel, on_piece)?;
+        self.cache_completion(prompt, max_tokens, &completion);
+        Ok(completion)
+    }
+
+    /// A recent result for an identical prompt, if there is one
+    pub fn cached_completion(&self, prompt: &str, max_tokens: usize) -> Option<String> {
+        let completion = self.cache.lock().unwrap().get(prompt, max_tokens)?;
+        log::info!("Completion cache hit");
+        Some(completion)
+    }
+
+    /// A copy of the settings when completions go to a remote provider. Remote
+    /// requests take it instead of the manager, so callers can unlock the
+    /// manager for the length of the request.
+    pub fn remote_config(&self) -> Option<LlmSettings> {
+        (self.config.provider != ProviderKind::Local).then(|| self.config.clone())
+    }
+
+    /// Complete `prompt` with the remote provider in `config`
+    pub fn complete_remote(
+        config: &LlmSettings,
+        prompt: &str,
+        max_tokens: usize,
+        cancel: &AtomicBool,
+    ) -> anyhow::Result<String> {
+        let completion = complete_remote(config, prompt, max_tokens)
+            .unwrap_or_else(|| Err(anyhow::anyhow!("Not a remote provider")))?;
+        // Remote requests can't be interrupted, so cancellation applies to the result
+        if cancel.load(std::sync::atomic::Ordering::Relaxed) {
+            return Err(anyhow::anyhow!("Request cancelled (generation mismatch)"));
+        }
+        Ok(completion)
+    }
 
-        model.complete(prompt, max_tokens, 0.7)
+    /// Store `completion` as the cached result for `prompt`, replacing any earlier one
+    pub fn cache_completion(&self, prompt: &str, max_tokens: usize, completion: &str) {
+        self.cache
+            .lock()
+            .unwrap()
+            .insert(prompt, max_tokens, completion);
+    }
+
+    /// Ask the configured provider for a short summary of `text`
+    pub fn summarize(&self, text: &str) -> anyhow::Result<String> {
+        if let Some(config) = self.remote_config() {
+            return Self::summarize_remote(&config, text);
+        }
+        // Instruction-tuned local models expect an Alpaca-style prompt
+        let prompt = format!(
+            "### Instruction:\n{}\n\n{}\n### Response:\n",
+            SUMMARY_INSTRUCTION, text
+        );
+        self.complete(&prompt, SUMMARY_MAX_TOKENS)
+            .map(|summary| summary.trim().to_string())
+    }
+
+    /// Summarize `text` with the remote provider in `config`; see
+    /// [`LlmManager::remote_config`]
+    pub fn summarize_remote(config: &LlmSettings, text: &str) -> anyhow::Result<String> {
+        match config.provider {
+            ProviderKind::OpenAI => openai::chat(
+                &config.endpoint,
+                &config.remote_model,
+                config.api_key(),
+                SUMMARY_INSTRUCTION,
+                text,
+                SUMMARY_MAX_TOKENS,
+            ),
+            ProviderKind::Gemini => gemini::complete(
+                &config.endpoint,
+                &config.remote_model,
+                config.api_key(),
+                &format!("{SUMMARY_INSTRUCTION}\n\n{text}"),
+                SUMMARY_MAX_TOKENS,
+            )
+            .map(|summary| summary.trim().to_string()),
+            ProviderKind::Ollama => ollama::generate(
+                &config.endpoint,
+                &config.remote_model,
+                Some(SUMMARY_INSTRUCTION),
+                text,
+                None,
+                SUMMARY_MAX_TOKENS,
+            )
+            .map(|summary| summary.trim().to_string()),
+            ProviderKind::Local => Err(anyhow::anyhow!("Not a remote provider")),
+        }
     }
 
     /// Unload the current model
     pub fn unload_model(&self) {
-        *self.loaded_model.lock().unwrap() = None;
+        if let Some(model) = self.loaded_model.lock().unwrap().take() {
+            log::info!(
+                "Unloaded model {}, freeing {} MiB",
+                model.source_path.display(),
+                model.size_bytes() / (1024 * 1024)
+            );
+        }
+        self.cache.lock().unwrap().clear();
+        *self.speed.lock().unwrap() = None;
+    }
+
+    /// Whether a local model is currently in memory
+    pub fn is_model_loaded(&self) -> bool {
+        self.loaded_model.lock().unwrap().is_some()
+    }
+
+    /// GGUF metadata of the model in memory
+    pub fn loaded_model_info(&self) -> Option<ModelInfo> {
+        let path = self.loaded_model_path()?;
+        self.model_info(&path)
+    }
+
+    /// GGUF metadata of `path`, if the file as it is now has been loaded before
+    pub fn model_info(&self, path: &Path) -> Option<ModelInfo> {
+        self.model_info
+            .lock()
+            .unwrap()
+            .get(&ModelFileKey::new(path))
+            .cloned()
+    }
+
+    fn record_model_info(&self, loaded: &LoadedModel) {
+        let mut known = self.model_info.lock().unwrap();
+        known
+            .entry(ModelFileKey::new(&loaded.source_path))
+            .or_insert_with(|| {
+                let info = loaded.info();
+                log::info!("Model metadata: {}", info.summary());
+                info
+            });
+    }
+
+    /// File the model in memory was loaded from
+    pub fn loaded_model_path(&self) -> Option<PathBuf> {
+        self.loaded_model
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map(|model| model.source_path.clone())
     }
 
     /// Check if local inference is available
@@ -320,7 +1177,7 @@ impl LlmManager {
                     }
                 }
             }
-            ProviderKind::OpenAI | ProviderKind::Gemini => {
+            ProviderKind::OpenAI | ProviderKind::Gemini | ProviderKind::Ollama => {
                 // Check if endpoint is configured
                 if self.config.endpoint.is_empty() {
                     LlmReadiness::NeedsEndpoint
@@ -332,43 +1189,82 @@ impl LlmManager {
         }
     }
 
+    /// Ask the remote provider for its model list, so a wrong endpoint, model
+    /// or API key shows up now rather than on the first completion. Listing
+    /// models isn't billed, unlike a completion. Takes the settings instead of
+    /// `&self` so the manager isn't locked while waiting.
+    pub fn test_connection(config: &LlmSettings) -> anyhow::Result<()> {
+        if config.endpoint.trim().is_empty() {
+            return Err(anyhow::anyhow!("No endpoint URL is set"));
+        }
+        let model = config.remote_model.trim();
+        match config.provider {
+            ProviderKind::Local => {
+                Err(anyhow::anyhow!("Local inference has no connection to test"))
+            }
+            // Self-hosted servers often ignore the model, so none is fine
+            ProviderKind::OpenAI => {
+                let models = openai::list_models(&config.endpoint, config.api_key())?;
+                if model.is_empty() {
+                    Ok(())
+                } else {
+                    check_model_listed(&models, model)
+                }
+            }
+            ProviderKind::Gemini => {
+                let models = gemini::list_models(&config.endpoint, config.api_key())?;
+                check_model_listed(&models, gemini::model_or_default(model))
+            }
+            ProviderKind::Ollama => {
+                if model.is_empty() {
+                    return Err(anyhow::anyhow!("Set the Ollama model name in Preferences"));
+                }
+                let models = ollama::list_models(&config.endpoint)?;
+                check_model_listed(&models, model)
+            }
+        }
+    }
+
     /// Detect GPUs via system enumeration
     /// Note: llama.cpp will automatically detect and use GPUs at runtime
     /// This is just for UI display purposes
     pub fn detect_gpus() -> Vec<GpuDevice> {
         use std::fs;
-        use std::path::Path;
         let mut devices = Vec::new();
 
-        // Check for AMD GPUs via /sys/class/drm
         if let Ok(entries) = fs::read_dir("/sys/class/drm") {
             let mut card_count = 0;
             for entry in entries.flatten() {
                 let name = entry.file_name();
                 let name_str = name.to_string_lossy();
                 if name_str.starts_with("card") && !name_str.contains('-') {
-                    // Try to read the device name
-                    let vendor_path = entry.path().join("device/vendor");
-                    let _device_path = entry.path().join("device/device");
-
-                    let vendor = fs::read_to_string(vendor_path).ok();
-                    let device_name = if let Some(v) = vendor {
-                        if v.trim() == "0x1002" {
-                            "AMD GPU".to_string()
-                        } else if v.trim() == "0x10de" {
-                            "NVIDIA GPU".to_string()
-                        } else if v.trim() == "0x8086" {
-                            "Intel GPU".to_string()
-                        } else {
-                            format!("GPU {}", card_count)
+                    let device_dir = entry.path().join("device");
+                    let read = |file: &str| {
+                        fs::read_to_string(device_dir.join(file))
+                            .ok()
+                            .map(|value| value.trim().to_string())
+                            .filter(|value| !value.is_empty())
+                    };
+                    let vendor = read("vendor").unwrap_or_default();
+                    let vram_bytes = read("mem_info_vram_total").and_then(|v| v.parse().ok());
+                    let model = read("product_name").or_else(|| {
+                        read("device").and_then(|device| pci_device_name(&vendor, &device))
+                    });
+                    let device_name = match (model, vendor_name(&vendor)) {
+                        (Some(model), Some(vendor)) if !model.contains(vendor) => {
+                            format!("{vendor} {model}")
                         }
-                    } else {
-                        format!("GPU {}", card_count)
+                        (Some(model), _) => model,
+                        (None, Some(vendor)) => format!("{vendor} GPU"),
+                        (None, None) => format!("GPU {}", card_count),
                     };
+                    let integrated = is_integrated(&vendor, &device_name, vram_bytes);
 
                     devices.push(GpuDevice {
                         id: card_count.to_string(),
                         name: device_name,
+                        vram_bytes,
+                        integrated,
                     });
                     card_count += 1;
                 }
@@ -380,9 +1276,272 @@ impl LlmManager {
             devices.push(GpuDevice {
                 id: "0".to_string(),
                 name: "GPU (detected via /dev/dri)".to_string(),
+                vram_bytes: None,
+                integrated: false,
             });
         }
 
         devices
     }
 }
+
+/// Complete `prompt` with the configured remote provider, or `None` when
+/// completions run locally
+fn complete_remote(
+    config: &LlmSettings,
+    prompt: &str,
+    max_tokens: usize,
+) -> Option<anyhow::Result<String>> {
+    match config.provider {
+        ProviderKind::Local => None,
+        ProviderKind::OpenAI => Some(openai::complete(
+            &config.endpoint,
+            &config.remote_model,
+            config.api_key(),
+            prompt,
+            max_tokens,
+        )),
+        ProviderKind::Gemini => Some(gemini::complete(
+            &config.endpoint,
+            &config.remote_model,
+            config.api_key(),
+            prompt,
+            max_tokens,
+        )),
+        ProviderKind::Ollama => Some(ollama::complete(
+            &config.endpoint,
+            &config.remote_model,
+            prompt,
+            max_tokens,
+        )),
+    }
+}
+
+/// Fail unless `model` is one of `models`. Ollama lists pulled models with a
+/// tag, and a name without one means `latest`.
+fn check_model_listed(models: &[String], model: &str) -> anyhow::Result<()> {
+    let tagged = format!("{model}:latest");
+    if models.iter().any(|name| name == model || *name == tagged) {
+        Ok(())
+    } else {
+        Err(anyhow::anyhow!("The server has no model named \"{model}\""))
+    }
+}
+
+/// Full offload first, then each partial offload that leaves some of the
+/// model's `layers` on the CPU. Any count from the model's depth up is full
+/// offload again, so trying it would repeat the failure.
+fn gpu_layer_candidates(layers: Option<u32>) -> Vec<i32> {
+    let full = layers.map_or(ALL_GPU_LAYERS, |layers| {
+        i32::try_from(layers).unwrap_or(ALL_GPU_LAYERS)
+    });
+    std::iter::once(ALL_GPU_LAYERS)
+        .chain(
+            GPU_LAYER_CANDIDATES
+                .iter()
+                .copied()
+                .filter(|&candidate| candidate < full),
+        )
+        .collect()
+}
+
+/// Next layer count to try after `layers` failed to load, halving down to
+/// the CPU. Counts past any real model's depth start the halving at 64.
+fn fewer_gpu_layers(layers: i32) -> Option<i32> {
+    (layers > 0).then(|| layers.min(128) / 2)
+}
+
+fn vendor_name(vendor_id: &str) -> Option<&'static str> {
+    match vendor_id {
+        "0x1002" => Some("AMD"),
+        "0x10de" => Some("NVIDIA"),
+        "0x8086" => Some("Intel"),
+        _ => None,
+    }
+}
+
+/// Where distributions install the PCI ID database
+const PCI_IDS_PATHS: &[&str] = &[
+    "/usr/share/hwdata/pci.ids",
+    "/usr/share/misc/pci.ids",
+    "/usr/share/pci.ids",
+];
+
+/// Model name of a PCI device, looked up in the system's `pci.ids`
+fn pci_device_name(vendor_id: &str, device_id: &str) -> Option<String> {
+    PCI_IDS_PATHS
+        .iter()
+        .find_map(|path| std::fs::read_to_string(path).ok())
+        .and_then(|db| lookup_pci_device(&db, vendor_id, device_id))
+}
+
+/// Find a device in the `pci.ids` format: vendors start a line, their devices
+/// follow indented by one tab, and subsystems by two.
+fn lookup_pci_device(db: &str, vendor_id: &str, device_id: &str) -> Option<String> {
+    let vendor = vendor_id.trim_start_matches("0x").to_ascii_lowercase();
+    let device = device_id.trim_start_matches("0x").to_ascii_lowercase();
+    let mut in_vendor = false;
+    for line in db.lines() {
+        if line.starts_with('#') || line.is_empty() {
+            continue;
+        }
+        if !line.starts_with('\t') {
+            if in_vendor {
+                return None;
+            }
+            in_vendor = line.starts_with(&vendor);
+            continue;
+        }
+        if !in_vendor || line.starts_with("\t\t") {
+            continue;
+        }
+        if let Some((id, name)) = line[1..].split_once(char::is_whitespace) {
+            if id == device {
+                return Some(name.trim().to_string());
+            }
+        }
+    }
+    None
+}
+
+/// Best guess at whether a GPU shares system memory. Intel only made
+/// integrated GPUs before Arc, and AMD APUs get a small carve-out of VRAM.
+fn is_integrated(vendor_id: &str, name: &str, vram_bytes: Option<u64>) -> bool {
+    const APU_CARVE_OUT_MAX: u64 = 2 * 1024 * 1024 * 1024;
+    match vendor_id {
+        "0x8086" => !name.contains("Arc"),
+        "0x1002" => vram_bytes.is_some_and(|vram| vram <= APU_CARVE_OUT_MAX),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PCI_IDS: &str = "\
+# comment
+1002  Advanced Micro Devices, Inc. [AMD/ATI]
+\t744c  Navi 31 [Radeon RX 7900 XT/7900 XTX/7900M]
+\t\t1002 0e3b  Radeon RX 7900 GRE
+10de  NVIDIA Corporation
+\t2684  AD102 [GeForce RTX 4090]
+";
+
+    #[test]
+    fn test_lookup_pci_device() {
+        assert_eq!(
+            lookup_pci_device(PCI_IDS, "0x10de", "0x2684").as_deref(),
+            Some("AD102 [GeForce RTX 4090]")
+        );
+        assert_eq!(
+            lookup_pci_device(PCI_IDS, "0x1002", "0x744C").as_deref(),
+            Some("Navi 31 [Radeon RX 7900 XT/7900 XTX/7900M]")
+        );
+        // Subsystem ids aren't devices
+        assert_eq!(lookup_pci_device(PCI_IDS, "0x1002", "0x1002"), None);
+        assert_eq!(lookup_pci_device(PCI_IDS, "0x10de", "0x744c"), None);
+    }
+
+    #[test]
+    fn test_gpu_layer_candidates_start_below_the_model_depth() {
+        assert_eq!(
+            gpu_layer_candidates(Some(28)),
+            [ALL_GPU_LAYERS, 24, 16, 12, 8, 4]
+        );
+        assert_eq!(
+            gpu_layer_candidates(Some(32)),
+            [ALL_GPU_LAYERS, 24, 16, 12, 8, 4]
+        );
+        assert_eq!(gpu_layer_candidates(Some(4)), [ALL_GPU_LAYERS]);
+        assert_eq!(gpu_layer_candidates(None)[1..], *GPU_LAYER_CANDIDATES);
+    }
+
+    #[test]
+    fn test_fewer_gpu_layers_halves_down_to_the_cpu() {
+        let mut tried = vec![ALL_GPU_LAYERS];
+        while let Some(fewer) = fewer_gpu_layers(*tried.last().unwrap()) {
+            tried.push(fewer);
+        }
+        assert_eq!(tried, [ALL_GPU_LAYERS, 64, 32, 16, 8, 4, 2, 1, 0]);
+    }
+
+    #[test]
+    fn test_token_budget_boundaries() {
+        let speed = SpeedEstimate {
+            tokens_per_second: 20.0,
+            prompt_secs: 0.5,
+        };
+        let target = Duration::from_millis(1500);
+        // A second left for generating at 20 tokens a second
+        assert_eq!(speed.token_budget(64, target), 20);
+        assert_eq!(speed.token_budget(20, target), 20);
+        assert_eq!(speed.token_budget(8, target), 8);
+        // Prompt processing alone meets or overshoots the target
+        assert_eq!(
+            speed.token_budget(64, Duration::from_millis(500)),
+            MIN_ADAPTIVE_TOKENS
+        );
+        assert_eq!(speed.token_budget(64, Duration::ZERO), MIN_ADAPTIVE_TOKENS);
+        // The floor never exceeds what was asked for
+        assert_eq!(speed.token_budget(2, Duration::ZERO), 2);
+        assert_eq!(speed.token_budget(0, target), 0);
+    }
+
+    #[test]
+    fn test_check_model_listed() {
+        let models = ["llama3:latest".to_string(), "qwen2.5:7b".to_string()];
+        assert!(check_model_listed(&models, "llama3").is_ok());
+        assert!(check_model_listed(&models, "qwen2.5:7b").is_ok());
+        assert!(check_model_listed(&models, "qwen2.5").is_err());
+        assert!(check_model_listed(&[], "gpt-4o").is_err());
+    }
+
+    #[test]
+    fn test_set_provider_replaces_only_default_endpoints() {
+        let mut llm = LlmSettings::default();
+        llm.set_provider(ProviderKind::Ollama);
+        assert_eq!(llm.endpoint, ollama::DEFAULT_ENDPOINT);
+        llm.set_provider(ProviderKind::Gemini);
+        assert_eq!(llm.endpoint, gemini::DEFAULT_ENDPOINT);
+        llm.set_provider(ProviderKind::OpenAI);
+        assert_eq!(llm.endpoint, openai::DEFAULT_ENDPOINT);
+
+        llm.endpoint = "http://gpu-box:8080/v1".into();
+        llm.set_provider(ProviderKind::Ollama);
+        assert_eq!(llm.endpoint, "http://gpu-box:8080/v1");
+    }
+
+    #[test]
+    fn test_offload_layers_prefers_the_configured_count() {
+        let mut llm = LlmSettings {
+            preferred_device: Some("0".into()),
+            ..LlmSettings::default()
+        };
+        assert_eq!(llm.offload_layers(), ALL_GPU_LAYERS);
+        llm.tuned_gpu_layers.insert("0".into(), 24);
+        assert_eq!(llm.offload_layers(), 24);
+        llm.gpu_layers = 12;
+        assert_eq!(llm.offload_layers(), 12);
+    }
+
+    #[test]
+    fn test_gpu_labels() {
+        let discrete = GpuDevice {
+            id: "1".into(),
+            name: "AMD Navi 31".into(),
+            vram_bytes: Some(24 * 1024 * 1024 * 1024),
+            integrated: false,
+        };
+        assert_eq!(discrete.label(), "AMD Navi 31 (24 GiB VRAM)");
+        let integrated = GpuDevice {
+            id: "0".into(),
+            name: "Intel UHD Graphics 770".into(),
+            vram_bytes: None,
+            integrated: true,
+        };
+        assert_eq!(integrated.label(), "Intel UHD Graphics 770 (integrated)");
+        assert!(is_integrated("0x8086", "Intel UHD Graphics 770", None));
+        assert!(!is_integrated("0x8086", "Intel DG2 [Arc A770]", None));
+    }
+}
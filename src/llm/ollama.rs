@@ -0,0 +1,65 @@
+use anyhow::{Context, Result, anyhow};
+use serde_json::json;
+
+use super::{context, http};
+
+/// Where a default Ollama install listens
+pub const DEFAULT_ENDPOINT: &str = "http://localhost:11434";
+
+/// Complete `prompt` with Ollama's `/api/generate`. A fill-in-the-middle prompt
+/// is sent as `prompt` plus `suffix`, which Ollama formats for the model.
+pub fn complete(endpoint: &str, model: &str, prompt: &str, max_tokens: usize) -> Result<String> {
+    match context::split_fim_prompt(prompt) {
+        Some((prefix, suffix)) => generate(endpoint, model, None, prefix, Some(suffix), max_tokens),
+        None => generate(endpoint, model, None, prompt, None, max_tokens),
+    }
+}
+
+/// Names of the models pulled into the server, from `/api/tags`
+pub fn list_models(endpoint: &str) -> Result<Vec<String>> {
+    let url = format!("{}/api/tags", endpoint.trim_end_matches('/'));
+    let value = http::get_json(&url, &[]).context("Listing Ollama models failed")?;
+    value["models"]
+        .as_array()
+        .map(|models| {
+            models
+                .iter()
+                .filter_map(|model| model["name"].as_str().map(str::to_string))
+                .collect()
+        })
+        .ok_or_else(|| anyhow!("Ollama model list contained no models"))
+}
+
+/// Run `/api/generate` without streaming and return the `response` text
+pub fn generate(
+    endpoint: &str,
+    model: &str,
+    system: Option<&str>,
+    prompt: &str,
+    suffix: Option<&str>,
+    max_tokens: usize,
+) -> Result<String> {
+    if model.is_empty() {
+        return Err(anyhow!("Set the Ollama model name in Preferences"));
+    }
+    let url = format!("{}/api/generate", endpoint.trim_end_matches('/'));
+    let mut body = json!({
+        "model": model,
+        "prompt": prompt,
+        "stream": false,
+        "options": { "num_predict": max_tokens },
+    });
+    if let Some(system) = system {
+        body["system"] = json!(system);
+    }
+    if let Some(suffix) = suffix {
+        body["suffix"] = json!(suffix);
+    }
+
+    log::info!("Sending Ollama request to {}", url);
+    let value = http::post_json(&url, &[], &body).context("Ollama request failed")?;
+    value["response"]
+        .as_str()
+        .map(str::to_string)
+        .ok_or_else(|| anyhow!("Ollama response contained no text"))
+}
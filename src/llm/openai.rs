@@ -0,0 +1,97 @@
+use anyhow::{Context, Result, anyhow};
+use serde_json::json;
+
+use super::{context, http};
+
+/// OpenAI's own API; other compatible servers need their URL set
+pub const DEFAULT_ENDPOINT: &str = "https://api.openai.com/v1";
+
+/// Send a single-turn request to an OpenAI-compatible `/chat/completions` endpoint
+/// and return the assistant message text.
+pub fn chat(
+    endpoint: &str,
+    model: &str,
+    api_key: &str,
+    instruction: &str,
+    content: &str,
+    max_tokens: usize,
+) -> Result<String> {
+    let url = format!("{}/chat/completions", endpoint.trim_end_matches('/'));
+    let mut body = json!({
+        "messages": [
+            { "role": "system", "content": instruction },
+            { "role": "user", "content": content },
+        ],
+        "max_tokens": max_tokens,
+    });
+    // Self-hosted OpenAI-compatible servers often ignore the model field
+    if !model.is_empty() {
+        body["model"] = json!(model);
+    }
+
+    log::info!("Sending chat request to {}", url);
+    let value =
+        http::post_json(&url, &auth_headers(api_key), &body).context("Chat request failed")?;
+    value["choices"][0]["message"]["content"]
+        .as_str()
+        .map(|text| text.trim().to_string())
+        .ok_or_else(|| anyhow!("Chat response contained no message"))
+}
+
+/// Complete `prompt` through an OpenAI-compatible `/completions` endpoint. A
+/// fill-in-the-middle prompt is sent as `prompt` plus `suffix` rather than with
+/// the local model's markers.
+pub fn complete(
+    endpoint: &str,
+    model: &str,
+    api_key: &str,
+    prompt: &str,
+    max_tokens: usize,
+) -> Result<String> {
+    let url = format!("{}/completions", endpoint.trim_end_matches('/'));
+    let mut body = json!({ "max_tokens": max_tokens });
+    match context::split_fim_prompt(prompt) {
+        Some((prefix, suffix)) => {
+            body["prompt"] = json!(prefix);
+            body["suffix"] = json!(suffix);
+        }
+        None => body["prompt"] = json!(prompt),
+    }
+    if !model.is_empty() {
+        body["model"] = json!(model);
+    }
+
+    log::info!("Sending completion request to {}", url);
+    let value = http::post_json(&url, &auth_headers(api_key), &body)
+        .context("Completion request failed")?;
+    value["choices"][0]["text"]
+        .as_str()
+        .map(str::to_string)
+        .ok_or_else(|| anyhow!("Completion response contained no text"))
+}
+
+/// Ids of the models the server offers, from `/models`. Listing is free, so
+/// this is how a connection is tested.
+pub fn list_models(endpoint: &str, api_key: &str) -> Result<Vec<String>> {
+    let url = format!("{}/models", endpoint.trim_end_matches('/'));
+    let value = http::get_json(&url, &auth_headers(api_key)).context("Listing models failed")?;
+    value["data"]
+        .as_array()
+        .map(|models| {
+            models
+                .iter()
+                .filter_map(|model| model["id"].as_str().map(str::to_string))
+                .collect()
+        })
+        .ok_or_else(|| anyhow!("Model list response contained no models"))
+}
+
+/// OpenAI-compatible servers take the key as a bearer token; local ones
+/// usually need none
+fn auth_headers(api_key: &str) -> Vec<(&'static str, String)> {
+    if api_key.is_empty() {
+        Vec::new()
+    } else {
+        vec![("Authorization", format!("Bearer {}", api_key))]
+    }
+}
@@ -0,0 +1,187 @@
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+use gtk4::glib;
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use once_cell::sync::{Lazy, OnceCell};
+use serde::{Deserialize, Serialize};
+
+/// Oldest entries are dropped once the buffer holds this many
+const MAX_ENTRIES: usize = 1000;
+
+/// How much the app records for the log viewer, chosen at runtime
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LogVerbosity {
+    Error,
+    Warn,
+    Info,
+    Debug,
+}
+
+impl Default for LogVerbosity {
+    fn default() -> Self {
+        LogVerbosity::Info
+    }
+}
+
+impl LogVerbosity {
+    pub const ALL: [LogVerbosity; 4] = [
+        LogVerbosity::Error,
+        LogVerbosity::Warn,
+        LogVerbosity::Info,
+        LogVerbosity::Debug,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            LogVerbosity::Error => "Errors",
+            LogVerbosity::Warn => "Warnings",
+            LogVerbosity::Info => "Info",
+            LogVerbosity::Debug => "Debug",
+        }
+    }
+
+    fn level_filter(self) -> LevelFilter {
+        match self {
+            LogVerbosity::Error => LevelFilter::Error,
+            LogVerbosity::Warn => LevelFilter::Warn,
+            LogVerbosity::Info => LevelFilter::Info,
+            LogVerbosity::Debug => LevelFilter::Debug,
+        }
+    }
+}
+
+/// Index into `LogVerbosity::ALL` of the current verbosity
+static VERBOSITY: AtomicU8 = AtomicU8::new(2);
+/// The most RUST_LOG asks for on stderr, so the global max never hides it
+static STDERR_MAX: OnceCell<LevelFilter> = OnceCell::new();
+
+#[derive(Debug, Clone)]
+pub struct LogEntry {
+    pub time: String,
+    pub level: Level,
+    pub target: String,
+    pub message: String,
+}
+
+struct LogBuffer {
+    entries: VecDeque<LogEntry>,
+    /// Total entries ever recorded, so viewers can tell when to refresh
+    total: u64,
+}
+
+static BUFFER: Lazy<Mutex<LogBuffer>> = Lazy::new(|| {
+    Mutex::new(LogBuffer {
+        entries: VecDeque::with_capacity(MAX_ENTRIES),
+        total: 0,
+    })
+});
+
+/// Forwards to env_logger for stderr and keeps recent records for the log viewer.
+struct AppLogger {
+    stderr: env_logger::Logger,
+}
+
+impl AppLogger {
+    /// The app's own messages are kept down to the chosen verbosity (info by
+    /// default, since they explain model loading and downloads) even when
+    /// RUST_LOG is unset; other crates never below warn.
+    fn retains(metadata: &Metadata) -> bool {
+        let app_level = verbosity().level_filter();
+        if metadata.target().starts_with(env!("CARGO_CRATE_NAME")) {
+            metadata.level() <= app_level
+        } else {
+            metadata.level() <= app_level.min(LevelFilter::Warn)
+        }
+    }
+}
+
+impl Log for AppLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.stderr.enabled(metadata) || Self::retains(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        if self.stderr.matches(record) {
+            self.stderr.log(record);
+        }
+        if !Self::retains(record.metadata()) {
+            return;
+        }
+
+        let time = glib::DateTime::now_local()
+            .and_then(|now| now.format("%H:%M:%S"))
+            .map(|time| time.to_string())
+            .unwrap_or_default();
+        let entry = LogEntry {
+            time,
+            level: record.level(),
+            target: record.target().to_string(),
+            message: record.args().to_string(),
+        };
+        if let Ok(mut buffer) = BUFFER.lock() {
+            if buffer.entries.len() == MAX_ENTRIES {
+                buffer.entries.pop_front();
+            }
+            buffer.entries.push_back(entry);
+            buffer.total += 1;
+        }
+    }
+
+    fn flush(&self) {
+        self.stderr.flush();
+    }
+}
+
+/// Install the logger. Replaces `env_logger::init()`; RUST_LOG still controls stderr.
+pub fn init() {
+    let stderr = env_logger::Builder::from_default_env().build();
+    let _ = STDERR_MAX.set(stderr.filter());
+    if log::set_boxed_logger(Box::new(AppLogger { stderr })).is_ok() {
+        apply_max_level();
+    }
+}
+
+pub fn verbosity() -> LogVerbosity {
+    LogVerbosity::ALL
+        .get(VERBOSITY.load(Ordering::Relaxed) as usize)
+        .copied()
+        .unwrap_or_default()
+}
+
+/// Change how much is recorded from now on. Stderr still follows RUST_LOG.
+pub fn set_verbosity(verbosity: LogVerbosity) {
+    let index = LogVerbosity::ALL
+        .iter()
+        .position(|v| *v == verbosity)
+        .unwrap_or(2);
+    VERBOSITY.store(index as u8, Ordering::Relaxed);
+    apply_max_level();
+}
+
+fn apply_max_level() {
+    let stderr_max = STDERR_MAX.get().copied().unwrap_or(LevelFilter::Off);
+    log::set_max_level(stderr_max.max(verbosity().level_filter()));
+}
+
+/// Snapshot of the retained entries, oldest first.
+pub fn recent() -> Vec<LogEntry> {
+    BUFFER
+        .lock()
+        .map(|buffer| buffer.entries.iter().cloned().collect())
+        .unwrap_or_default()
+}
+
+/// Number of entries recorded so far, cheap enough to poll.
+pub fn total() -> u64 {
+    BUFFER.lock().map(|buffer| buffer.total).unwrap_or(0)
+}
+
+pub fn clear() {
+    if let Ok(mut buffer) = BUFFER.lock() {
+        buffer.entries.clear();
+        // Bump the total so open viewers redraw
+        buffer.total += 1;
+    }
+}
@@ -1,7 +1,10 @@
 mod app;
 mod document;
 mod llm;
+mod logging;
+mod markdown;
 mod paths;
+mod secrets;
 mod settings;
 mod state_store;
 
@@ -9,18 +12,43 @@ use gtk4::{gio, glib, prelude::*};
 use libadwaita as adw;
 
 fn main() -> glib::ExitCode {
-    env_logger::init();
+    logging::init();
 
     let app = adw::Application::builder()
         .application_id("com.wispnote.Wispnote")
         .flags(gio::ApplicationFlags::HANDLES_OPEN)
         .build();
 
+    app.add_main_option(
+        "config-dir",
+        glib::Char::from(0),
+        glib::OptionFlags::NONE,
+        glib::OptionArg::Filename,
+        "Keep settings, autosaves and models in DIR",
+        Some("DIR"),
+    );
+    app.connect_handle_local_options(|app, options| {
+        if let Ok(Some(dir)) = options.lookup::<std::path::PathBuf>("config-dir") {
+            paths::set_config_dir_override(dir);
+        }
+        // A separate profile gets its own process; handing it to an instance
+        // that is already running would open it with that instance's files
+        if paths::config_dir_override().is_some() {
+            app.set_flags(app.flags() | gio::ApplicationFlags::NON_UNIQUE);
+        }
+        // Carry on with the default handling
+        -1
+    });
+
     app.connect_activate(|application| {
         if let Err(err) = app::build_ui(application) {
             log::error!("Failed to start UI: {err:?}");
         }
     });
 
+    app.connect_open(|application, files, _hint| {
+        app::open_files(application, files);
+    });
+
     app.run()
 }
@@ -0,0 +1,232 @@
+//! Markdown rendered as Pango markup for the preview pane, and as HTML for
+//! export.
+
+use pulldown_cmark::{Event, HeadingLevel, Options, Parser, Tag, html};
+
+const OPTIONS: Options = Options::ENABLE_STRIKETHROUGH.union(Options::ENABLE_TASKLISTS);
+
+/// Render `source` as an HTML fragment, without the surrounding document
+pub fn to_html(source: &str) -> String {
+    let mut out = String::with_capacity(source.len() * 3 / 2);
+    html::push_html(&mut out, Parser::new_ext(source, OPTIONS));
+    out
+}
+
+/// Render `source` as Pango markup, ready for `gtk::Label::set_markup`
+pub fn to_pango_markup(source: &str) -> String {
+    let mut renderer = Renderer {
+        out: String::with_capacity(source.len()),
+        open: Vec::new(),
+        lists: Vec::new(),
+        fresh: true,
+    };
+    for event in Parser::new_ext(source, OPTIONS) {
+        renderer.event(event);
+    }
+    let trimmed = renderer.out.trim_end().len();
+    renderer.out.truncate(trimmed);
+    renderer.out
+}
+
+/// What closing a tag has to undo
+enum Open {
+    Markup(&'static str),
+    List,
+    CodeBlock,
+}
+
+struct Renderer {
+    out: String,
+    /// Open tags, innermost last
+    open: Vec<Open>,
+    /// Next number of each open list; `None` for bulleted lists
+    lists: Vec<Option<u64>>,
+    /// Nothing has been written since the last block break
+    fresh: bool,
+}
+
+impl Renderer {
+    fn event(&mut self, event: Event) {
+        match event {
+            Event::Start(tag) => self.start(tag),
+            Event::End(_) => match self.open.pop() {
+                Some(Open::Markup(closer)) => self.out.push_str(closer),
+                Some(Open::List) => {
+                    self.lists.pop();
+                }
+                Some(Open::CodeBlock) => {
+                    if self.out.ends_with('\n') {
+                        self.out.pop();
+                    }
+                    self.out.push_str("</tt>");
+                }
+                None => {}
+            },
+            Event::Text(text) | Event::Html(text) | Event::InlineHtml(text) => {
+                self.write(&escape(&text))
+            }
+            Event::Code(code) => self.write(&format!("<tt>{}</tt>", escape(&code))),
+            Event::SoftBreak => self.write(" "),
+            Event::HardBreak => self.write("\n"),
+            Event::Rule => {
+                self.break_block(true);
+                self.write("──────────");
+            }
+            Event::TaskListMarker(done) => self.write(if done { "☑ " } else { "☐ " }),
+            _ => {}
+        }
+    }
+
+    fn start(&mut self, tag: Tag) {
+        let open = match tag {
+            Tag::Paragraph => {
+                // Paragraphs of a list item stay on the bullet's line
+                if self.lists.is_empty() {
+                    self.break_block(true);
+                }
+                Open::Markup("")
+            }
+            Tag::Heading { level, .. } => {
+                self.break_block(true);
+                self.out.push_str(heading_span(level));
+                Open::Markup("</span>")
+            }
+            Tag::BlockQuote(_) => {
+                self.break_block(true);
+                self.out.push_str("<i>");
+                Open::Markup("</i>")
+            }
+            Tag::CodeBlock(_) => {
+                self.break_block(true);
+                self.out.push_str("<tt>");
+                Open::CodeBlock
+            }
+            Tag::List(first) => {
+                if self.lists.is_empty() {
+                    self.break_block(true);
+                }
+                self.lists.push(first);
+                Open::List
+            }
+            Tag::Item => {
+                self.break_block(false);
+                let indent = "    ".repeat(self.lists.len().saturating_sub(1));
+                let marker = match self.lists.last_mut() {
+                    Some(Some(number)) => {
+                        *number += 1;
+                        format!("{}. ", *number - 1)
+                    }
+                    _ => "• ".to_string(),
+                };
+                self.write(&format!("{indent}{marker}"));
+                Open::Markup("")
+            }
+            Tag::Emphasis => {
+                self.out.push_str("<i>");
+                Open::Markup("</i>")
+            }
+            Tag::Strong => {
+                self.out.push_str("<b>");
+                Open::Markup("</b>")
+            }
+            Tag::Strikethrough => {
+                self.out.push_str("<s>");
+                Open::Markup("</s>")
+            }
+            Tag::Link { dest_url, .. } => {
+                self.out
+                    .push_str(&format!("<a href=\"{}\">", escape(&dest_url)));
+                Open::Markup("</a>")
+            }
+            // Images show their alt text
+            Tag::Image { .. } => {
+                self.out.push_str("<i>");
+                Open::Markup("</i>")
+            }
+            _ => Open::Markup(""),
+        };
+        self.open.push(open);
+    }
+
+    fn write(&mut self, text: &str) {
+        self.out.push_str(text);
+        self.fresh = false;
+    }
+
+    /// End the current line, leaving a blank line when `blank` is set
+    fn break_block(&mut self, blank: bool) {
+        if self.fresh {
+            return;
+        }
+        self.out.push_str(if blank { "\n\n" } else { "\n" });
+        self.fresh = true;
+    }
+}
+
+fn heading_span(level: HeadingLevel) -> &'static str {
+    match level {
+        HeadingLevel::H1 => "<span size=\"xx-large\" weight=\"bold\">",
+        HeadingLevel::H2 => "<span size=\"x-large\" weight=\"bold\">",
+        HeadingLevel::H3 => "<span size=\"large\" weight=\"bold\">",
+        _ => "<span weight=\"bold\">",
+    }
+}
+
+/// Escape text for Pango markup; the entities are valid HTML as well
+pub fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_headings_and_emphasis() {
+        assert_eq!(
+            to_pango_markup("# Title\n\nSome *em* and **strong**."),
+            "<span size=\"xx-large\" weight=\"bold\">Title</span>\n\n\
+             Some <i>em</i> and <b>strong</b>."
+        );
+    }
+
+    #[test]
+    fn test_markup_characters_are_escaped() {
+        assert_eq!(to_pango_markup("a < b & c"), "a &lt; b &amp; c");
+    }
+
+    #[test]
+    fn test_lists() {
+        assert_eq!(
+            to_pango_markup("- one\n- two\n\n1. first\n2. second"),
+            "• one\n• two\n\n1. first\n2. second"
+        );
+    }
+
+    #[test]
+    fn test_code_block() {
+        assert_eq!(
+            to_pango_markup("```\nlet x = 1;\n```"),
+            "<tt>let x = 1;</tt>"
+        );
+    }
+
+    #[test]
+    fn test_to_html() {
+        assert_eq!(
+            to_html("# Title\n\nSome *em* & more."),
+            "<h1>Title</h1>\n<p>Some <em>em</em> &amp; more.</p>\n"
+        );
+    }
+}
@@ -1,6 +1,29 @@
 use anyhow::{Context, Result};
 use directories::ProjectDirs;
 use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+/// Environment variables that keep every file the app writes under one
+/// folder, for portable setups and isolated test runs. The first one set wins.
+pub const CONFIG_DIR_ENVS: &[&str] = &["GHOSTPAD_CONFIG_DIR", "WISPNOTE_CONFIG_DIR"];
+
+/// Set from `--config-dir`, which wins over the environment variable
+static CONFIG_DIR_OVERRIDE: OnceLock<PathBuf> = OnceLock::new();
+
+pub fn set_config_dir_override(dir: PathBuf) {
+    let _ = CONFIG_DIR_OVERRIDE.set(dir);
+}
+
+/// The folder everything goes in instead of the usual places, if one was given
+pub fn config_dir_override() -> Option<PathBuf> {
+    CONFIG_DIR_OVERRIDE.get().cloned().or_else(|| {
+        CONFIG_DIR_ENVS
+            .iter()
+            .filter_map(std::env::var_os)
+            .find(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+    })
+}
 
 pub struct AppPaths {
     pub config_file: PathBuf,
@@ -11,18 +34,30 @@ pub struct AppPaths {
 
 impl AppPaths {
     pub fn initialize() -> Result<Self> {
+        if let Some(root) = config_dir_override() {
+            return Self::rooted_at(&root);
+        }
         let dirs = ProjectDirs::from("com", "Wispnote", "wispnote")
             .context("Unable to determine XDG directories")?;
-        let config_dir = dirs.config_dir().to_path_buf();
         let data_dir = dirs.data_dir().to_path_buf();
         let state_dir = dirs
             .state_dir()
             .map(Path::to_path_buf)
             .unwrap_or_else(|| data_dir.clone());
+        Self::create(dirs.config_dir(), &data_dir, &state_dir)
+    }
+
+    /// Everything under `root` instead of the XDG directories
+    pub fn rooted_at(root: &Path) -> Result<Self> {
+        let root = std::path::absolute(root).unwrap_or_else(|_| root.to_path_buf());
+        Self::create(&root, &root, &root)
+    }
+
+    fn create(config_dir: &Path, data_dir: &Path, state_dir: &Path) -> Result<Self> {
         let config_file = config_dir.join("config.toml");
         let state_file = state_dir.join("state.json");
-        std::fs::create_dir_all(&config_dir).context("Failed to create config directory")?;
-        std::fs::create_dir_all(&state_dir).context("Failed to create state directory")?;
+        std::fs::create_dir_all(config_dir).context("Failed to create config directory")?;
+        std::fs::create_dir_all(state_dir).context("Failed to create state directory")?;
         let autosave_dir = state_dir.join("autosave");
         std::fs::create_dir_all(&autosave_dir).context("Failed to create autosave directory")?;
         let models_dir = data_dir.join("models");
@@ -35,3 +70,24 @@ impl AppPaths {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rooted_paths_stay_under_the_root() {
+        let root = tempfile::tempdir().unwrap();
+        let paths = AppPaths::rooted_at(root.path()).unwrap();
+        for path in [
+            &paths.config_file,
+            &paths.state_file,
+            &paths.autosave_dir,
+            &paths.models_dir,
+        ] {
+            assert!(path.starts_with(root.path()), "{path:?}");
+        }
+        assert!(paths.autosave_dir.is_dir());
+        assert!(paths.models_dir.is_dir());
+    }
+}
@@ -0,0 +1,80 @@
+//! API keys and the Hugging Face token, kept in the system keyring (Secret
+//! Service) instead of the plaintext config file.
+
+use anyhow::{Context, Result};
+
+use crate::llm::ProviderKind;
+
+const SERVICE: &str = "wispnote";
+
+/// Keyring account an API key is stored under; one per provider
+fn account(provider: ProviderKind) -> &'static str {
+    match provider {
+        ProviderKind::OpenAI => "openai-api-key",
+        ProviderKind::Gemini => "gemini-api-key",
+        ProviderKind::Ollama => "ollama-api-key",
+        ProviderKind::Local => "local-api-key",
+    }
+}
+
+/// Keyring account of the Hugging Face access token
+const HF_TOKEN_ACCOUNT: &str = "huggingface-token";
+
+fn entry(account: &str) -> Result<keyring::Entry> {
+    keyring::Entry::new(SERVICE, account).context("Secret storage is unavailable")
+}
+
+/// The secret stored under `account`, or `None` when there is none or the
+/// keyring can't be reached. `what` names it in log messages.
+fn load(account: &str, what: &str) -> Option<String> {
+    match entry(account).and_then(|entry| Ok(entry.get_password()?)) {
+        Ok(secret) => Some(secret),
+        Err(err) => {
+            if !matches!(
+                err.downcast_ref::<keyring::Error>(),
+                Some(keyring::Error::NoEntry)
+            ) {
+                log::warn!("Failed to read {what} from the keyring: {err:#}");
+            }
+            None
+        }
+    }
+}
+
+/// Store `secret` under `account`; an empty one removes the stored one.
+fn store(account: &str, secret: &str, what: &str) -> Result<()> {
+    let entry = entry(account)?;
+    if secret.is_empty() {
+        match entry.delete_credential() {
+            Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+            Err(err) => {
+                Err(err).with_context(|| format!("Failed to remove {what} from the keyring"))
+            }
+        }
+    } else {
+        entry
+            .set_password(secret)
+            .with_context(|| format!("Failed to save {what} to the keyring"))
+    }
+}
+
+/// The stored key for `provider`, or `None` when there is none or the
+/// keyring can't be reached.
+pub fn load_api_key(provider: ProviderKind) -> Option<String> {
+    load(account(provider), "API key")
+}
+
+/// Store `key` for `provider`; an empty key removes the stored one.
+pub fn store_api_key(provider: ProviderKind, key: &str) -> Result<()> {
+    store(account(provider), key, "API key")
+}
+
+/// The stored Hugging Face token, if there is one and the keyring answers
+pub fn load_hf_token() -> Option<String> {
+    load(HF_TOKEN_ACCOUNT, "Hugging Face token")
+}
+
+/// Store the Hugging Face token; an empty one removes the stored one.
+pub fn store_hf_token(token: &str) -> Result<()> {
+    store(HF_TOKEN_ACCOUNT, token, "Hugging Face token")
+}
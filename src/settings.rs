@@ -4,39 +4,210 @@ use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
 
 use crate::llm::LlmSettings;
+use crate::logging::LogVerbosity;
 use crate::paths::AppPaths;
 
+/// What Tab does when there is no suggestion to accept and no selection
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TabAction {
+    /// Let the view indent, with a tab character or spaces as
+    /// [`Settings::insert_spaces`] says
+    InsertTab,
+    /// Ask for a completion, like Ctrl+Space
+    Complete,
+    /// Written by versions with a Tab setting of its own for spaces; loading
+    /// turns it into `InsertTab` with `insert_spaces` on
+    #[serde(skip_serializing)]
+    InsertSpaces,
+}
+
+impl Default for TabAction {
+    fn default() -> Self {
+        TabAction::InsertTab
+    }
+}
+
+/// Where autosave puts unsaved changes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AutosaveMode {
+    /// Recovery snapshots only; the file itself changes on Save
+    SwapOnly,
+    /// Save files that have a path, keeping the previous version as `<name>.bak`.
+    /// Their changes are on disk, so there is nothing to recover after a crash;
+    /// untitled documents still get snapshots.
+    WriteThrough,
+}
+
+impl Default for AutosaveMode {
+    fn default() -> Self {
+        AutosaveMode::SwapOnly
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Settings {
     pub autosave_interval_secs: u64,
     #[serde(default)]
+    pub autosave_mode: AutosaveMode,
+    /// Recovery snapshots kept per document, newest first
+    #[serde(default = "default_autosave_snapshots")]
+    pub autosave_snapshots: usize,
+    #[serde(default)]
     pub recent_files: Vec<String>,
+    /// Length of the recent files list; 0 stops tracking them
+    #[serde(default = "default_max_recent_files")]
+    pub max_recent_files: usize,
     #[serde(default)]
     pub autosave_idle_only: bool,
     #[serde(default)]
     pub llm: LlmSettings,
+    #[serde(default = "default_show_line_numbers")]
+    pub show_line_numbers: bool,
     #[serde(default)]
     pub show_whitespace: bool,
     #[serde(default = "default_wrap_text")]
     pub wrap_text: bool,
+    /// Columns a tab character spans
+    #[serde(default = "default_tab_width")]
+    pub tab_width: u32,
+    /// Indent with spaces instead of tab characters
+    #[serde(default)]
+    pub insert_spaces: bool,
+    #[serde(default)]
+    pub auto_indent: bool,
+    #[serde(default = "default_highlight_current_line")]
+    pub highlight_current_line: bool,
+    #[serde(default = "default_highlight_matching_brackets")]
+    pub highlight_matching_brackets: bool,
     #[serde(default)]
     pub skip_llm_startup_check: bool,
+    #[serde(default)]
+    pub desktop_notifications: bool,
+    /// Ask before Save As replaces a different existing file
+    #[serde(default = "default_confirm_overwrite")]
+    pub confirm_overwrite: bool,
+    /// End saved files with a newline
+    #[serde(default = "default_ensure_final_newline")]
+    pub ensure_final_newline: bool,
+    /// Strip spaces and tabs from line ends when saving
+    #[serde(default)]
+    pub trim_trailing_whitespace_on_save: bool,
+    /// Journal mode: only the newest block can be edited and blocks are timestamped
+    #[serde(default)]
+    pub append_only: bool,
+    /// Drop the file monitor while the window sits in the background
+    #[serde(default)]
+    pub pause_monitor_in_background: bool,
+    /// Open the last session's file on start instead of offering to
+    #[serde(default)]
+    pub reopen_last_file: bool,
+    #[serde(default)]
+    pub tab_action: TabAction,
+    /// Detail kept for the log viewer
+    #[serde(default)]
+    pub log_verbosity: LogVerbosity,
+    /// Follow the desktop's light/dark preference
+    #[serde(default = "default_follow_system_style")]
+    pub follow_system_style: bool,
+    /// Use the dark style when not following the desktop
+    #[serde(default)]
+    pub dark_style: bool,
+    /// Editor font as a Pango description ("Fira Code 12"); empty uses the system monospace font
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub editor_font: String,
+    /// Editor zoom set with Ctrl+Plus/Minus, applied on top of the font size
+    #[serde(default = "default_font_scale")]
+    pub font_scale: f64,
+    /// Syntax highlighting scheme id; empty picks Adwaita to match the light/dark style
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub style_scheme: String,
+    /// Suggestion text color as `#rrggbb`; empty follows the light/dark default
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub ghost_text_color: String,
+    /// Show the rendered Markdown pane beside the editor
+    #[serde(default)]
+    pub markdown_preview: bool,
+}
+
+fn default_show_line_numbers() -> bool {
+    true
 }
 
 fn default_wrap_text() -> bool {
     true
 }
 
+fn default_tab_width() -> u32 {
+    4
+}
+
+fn default_highlight_current_line() -> bool {
+    true
+}
+
+fn default_highlight_matching_brackets() -> bool {
+    true
+}
+
+fn default_confirm_overwrite() -> bool {
+    true
+}
+
+fn default_ensure_final_newline() -> bool {
+    true
+}
+
+fn default_autosave_snapshots() -> usize {
+    5
+}
+
+fn default_max_recent_files() -> usize {
+    10
+}
+
+fn default_font_scale() -> f64 {
+    1.0
+}
+
+fn default_follow_system_style() -> bool {
+    true
+}
+
 impl Default for Settings {
     fn default() -> Self {
         Self {
             autosave_interval_secs: 60,
+            autosave_mode: AutosaveMode::default(),
+            autosave_snapshots: default_autosave_snapshots(),
+            max_recent_files: default_max_recent_files(),
             recent_files: Vec::new(),
             autosave_idle_only: false,
             llm: LlmSettings::default(),
+            show_line_numbers: true,
             show_whitespace: false,
             wrap_text: true,
+            tab_width: default_tab_width(),
+            insert_spaces: false,
+            auto_indent: false,
+            highlight_current_line: true,
+            highlight_matching_brackets: true,
             skip_llm_startup_check: false,
+            desktop_notifications: false,
+            confirm_overwrite: true,
+            ensure_final_newline: true,
+            trim_trailing_whitespace_on_save: false,
+            append_only: false,
+            pause_monitor_in_background: false,
+            reopen_last_file: false,
+            tab_action: TabAction::default(),
+            log_verbosity: LogVerbosity::default(),
+            follow_system_style: true,
+            dark_style: false,
+            editor_font: String::new(),
+            font_scale: default_font_scale(),
+            style_scheme: String::new(),
+            ghost_text_color: String::new(),
+            markdown_preview: false,
         }
     }
 }
@@ -44,14 +215,42 @@ impl Default for Settings {
 impl Settings {
     pub fn load(paths: &AppPaths) -> Result<Self> {
         if let Ok(raw) = fs::read_to_string(&paths.config_file) {
-            Ok(toml::from_str(&raw).unwrap_or_default())
+            Ok(toml::from_str::<Self>(&raw).unwrap_or_default().migrated())
         } else {
             Ok(Self::default())
         }
     }
 
+    /// Bring values from older or hand-edited config files in line
+    fn migrated(mut self) -> Self {
+        if self.tab_action == TabAction::InsertSpaces {
+            self.tab_action = TabAction::InsertTab;
+            self.insert_spaces = true;
+        }
+        // A zero-width tab would make the tab stop math divide by zero
+        self.tab_width = self.tab_width.max(1);
+        self
+    }
+
     pub fn save(&self, paths: &AppPaths) -> Result<()> {
         let toml = toml::to_string_pretty(self).context("Failed to serialize settings")?;
         fs::write(&paths.config_file, toml).context("Failed to write settings")
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_migrated_merges_tab_spaces_and_clamps_width() {
+        let settings: Settings = toml::from_str(
+            "autosave_interval_secs = 60\ntab_action = \"InsertSpaces\"\ntab_width = 0\n",
+        )
+        .unwrap();
+        let settings = settings.migrated();
+        assert_eq!(settings.tab_action, TabAction::InsertTab);
+        assert!(settings.insert_spaces);
+        assert_eq!(settings.tab_width, 1);
+    }
+}
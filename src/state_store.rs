@@ -1,3 +1,4 @@
+use std::collections::HashMap;
 use std::fs;
 
 use anyhow::{Context, Result};
@@ -6,9 +7,19 @@ use serde::{Deserialize, Serialize};
 use crate::paths::AppPaths;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct WindowState {
+    /// Unmaximized size, kept while the window is maximized
     pub width: i32,
     pub height: i32,
+    pub maximized: bool,
+    /// File open in the window closed last, offered again on the next start
+    pub last_open_file: Option<String>,
+    /// Cursor position in that file, in characters
+    pub last_cursor_offset: i32,
+    /// Highlighting language picked for a file, by canonical path. An empty id
+    /// is plain text.
+    pub language_overrides: HashMap<String, String>,
 }
 
 impl Default for WindowState {
@@ -16,6 +27,10 @@ impl Default for WindowState {
         Self {
             width: 1024,
             height: 720,
+            maximized: false,
+            last_open_file: None,
+            last_cursor_offset: 0,
+            language_overrides: HashMap::new(),
         }
     }
 }
@@ -30,6 +45,16 @@ impl WindowState {
         }
     }
 
+    /// The saved size, shrunk to fit a monitor of `bounds` (width, height)
+    pub fn size_within(&self, bounds: Option<(i32, i32)>) -> (i32, i32) {
+        match bounds {
+            Some((max_width, max_height)) => {
+                (self.width.min(max_width), self.height.min(max_height))
+            }
+            None => (self.width, self.height),
+        }
+    }
+
     pub fn save(&self, paths: &AppPaths) -> Result<()> {
         let data = serde_json::to_string_pretty(self).context("Serialize window state")?;
         fs::write(&paths.state_file, data).context("Write window state")